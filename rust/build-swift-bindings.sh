@@ -0,0 +1,36 @@
+#!/bin/bash
+
+# Builds the Rust library with the UniFFI layer (the `uniffi` feature) and generates the
+# typed Swift bindings for it: spotifly_rust.swift plus the spotifly_rustFFI C module.
+# The library built here contains the C API as well, so it replaces the one from build.sh.
+
+set -e
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+RUST_DIR="$SCRIPT_DIR"
+OUTPUT_DIR="$SCRIPT_DIR/../build/rust"
+TARGET="${TARGET:-aarch64-apple-darwin}"
+
+# Use rustup-installed cargo if available, otherwise use system cargo
+if [ -f "$HOME/.cargo/bin/cargo" ]; then
+    export PATH="$HOME/.cargo/bin:$PATH"
+fi
+
+mkdir -p "$OUTPUT_DIR/lib"
+mkdir -p "$OUTPUT_DIR/swift"
+
+cd "$RUST_DIR"
+
+echo "Building Spotifly Rust library with Swift bindings for $TARGET..."
+cargo build --release --lib --features uniffi --target "$TARGET"
+cp "$RUST_DIR/target/$TARGET/release/libspotifly_rust.a" "$OUTPUT_DIR/lib/"
+
+echo "Generating Swift bindings..."
+cargo run --release --features uniffi-bindgen --bin uniffi-bindgen -- generate \
+    --library "$RUST_DIR/target/$TARGET/release/libspotifly_rust.a" \
+    --language swift \
+    --out-dir "$OUTPUT_DIR/swift"
+
+echo "Build complete!"
+echo "Static library: $OUTPUT_DIR/lib/libspotifly_rust.a"
+echo "Swift bindings: $OUTPUT_DIR/swift"
@@ -31,22 +31,22 @@ cd "$RUST_DIR"
 case "$PLATFORM_NAME" in
     macosx*)
         echo "Building for macOS (aarch64)..."
-        cargo build --release --target aarch64-apple-darwin
+        cargo build --release --lib --target aarch64-apple-darwin
         cp "$RUST_DIR/target/aarch64-apple-darwin/release/libspotifly_rust.a" "$OUTPUT_DIR/lib/"
         ;;
     iphoneos*)
         echo "Building for iOS device (aarch64)..."
-        cargo build --release --target aarch64-apple-ios
+        cargo build --release --lib --target aarch64-apple-ios
         cp "$RUST_DIR/target/aarch64-apple-ios/release/libspotifly_rust.a" "$OUTPUT_DIR/lib/"
         ;;
     iphonesimulator*)
         echo "Building for iOS Simulator (aarch64)..."
-        cargo build --release --target aarch64-apple-ios-sim
+        cargo build --release --lib --target aarch64-apple-ios-sim
         cp "$RUST_DIR/target/aarch64-apple-ios-sim/release/libspotifly_rust.a" "$OUTPUT_DIR/lib/"
         ;;
     *)
         echo "Unknown platform: $PLATFORM_NAME, defaulting to macOS"
-        cargo build --release --target aarch64-apple-darwin
+        cargo build --release --lib --target aarch64-apple-darwin
         cp "$RUST_DIR/target/aarch64-apple-darwin/release/libspotifly_rust.a" "$OUTPUT_DIR/lib/"
         ;;
 esac
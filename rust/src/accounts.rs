@@ -0,0 +1,351 @@
+// Multiple accounts and account switching.
+//
+// The host can add further accounts (e.g. the members of a family plan) with their own
+// token sets, and switch the player between them without cleaning up and initializing it
+// again: the player, output and settings stay, and only the session is replaced (the way
+// a Connect handover replaces it, see connect.rs). The account being switched away from
+// is remembered with its token set and reusable credentials, so switching back needs no
+// new login. Playback stops and the queue is cleared on a switch.
+//
+// Each account has its own librespot cache (credentials and volume) in accounts/<id>
+// inside the cache directory; the account the player was initialized with keeps the
+// cache directory itself. Audio files are shared.
+
+use crate::auth::{self, OAuthResult, OAUTH_RESULT};
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    connect, current_timestamp_ms, events, loading, power, profile, queue_controller, secure_storage,
+    set_connection_state, station, stop_and_drain, stored_credentials, supervisor, to_c_string, token_manager, webapi,
+    update_position, ACCESS_TOKEN, CONNECTION_DISCONNECTED, DURATION_MS, IS_PLAYING, PLAYER, RUNTIME, SESSION,
+};
+use librespot_core::authentication::Credentials;
+use librespot_core::session::Session;
+use librespot_protocol::authentication::AuthenticationType;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::ffi::{c_char, CStr};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+// A token this close to expiry is refreshed before switching to its account
+const EXPIRY_MARGIN_MS: u64 = 60_000;
+
+#[derive(Clone)]
+struct Account {
+    /// Spotify user ID
+    id: String,
+    display_name: Option<String>,
+    client_id: Option<String>,
+    tokens: Option<OAuthResult>,
+    /// Reusable credentials from the account's last session
+    credentials: Option<Credentials>,
+}
+
+#[derive(Default)]
+struct SwitchState {
+    // The account the player was initialized with
+    initial: Option<String>,
+    // The account switched to, None while the initial account is active
+    current: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AccountInfo<'a> {
+    account_id: &'a str,
+    display_name: Option<&'a str>,
+    is_active: bool,
+}
+
+static ACCOUNTS: Lazy<Mutex<Vec<Account>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static SWITCH_STATE: Lazy<Mutex<SwitchState>> = Lazy::new(|| Mutex::new(SwitchState::default()));
+
+/// The librespot cache directory of the active account inside `dir` (see build_cache()).
+pub(crate) fn cache_dir(dir: &Path) -> PathBuf {
+    match &SWITCH_STATE.lock().unwrap().current {
+        // User IDs are mostly alphanumeric; encode the rest so the ID is a single path component
+        Some(id) => dir.join("accounts").join(form_urlencoded::byte_serialize(id.as_bytes()).collect::<String>()),
+        None => dir.to_path_buf(),
+    }
+}
+
+/// Forgets which account is active (on player cleanup). Added accounts are kept.
+pub(crate) fn reset() {
+    *SWITCH_STATE.lock().unwrap() = SwitchState::default();
+}
+
+// Adds an account, or updates it, keeping what the update doesn't know
+fn upsert(mut account: Account) {
+    let mut accounts = ACCOUNTS.lock().unwrap();
+    match accounts.iter_mut().find(|existing| existing.id == account.id) {
+        Some(existing) => {
+            account.display_name = account.display_name.or(existing.display_name.take());
+            account.credentials = account.credentials.or(existing.credentials.take());
+            *existing = account;
+        }
+        None => accounts.push(account),
+    }
+}
+
+// Records the session's account with its current token set, so it can be switched back to
+fn remember(session: &Session) -> Account {
+    let id = session.username();
+    let auth_data = session.auth_data();
+    let account = Account {
+        id: id.clone(),
+        display_name: None,
+        client_id: token_manager::client_id(),
+        tokens: OAUTH_RESULT.lock().unwrap().clone(),
+        credentials: (!auth_data.is_empty()).then(|| Credentials {
+            username: Some(id.clone()),
+            auth_type: AuthenticationType::AUTHENTICATION_STORED_SPOTIFY_CREDENTIALS,
+            auth_data,
+        }),
+    };
+    upsert(account.clone());
+
+    let mut state = SWITCH_STATE.lock().unwrap();
+    if state.current.is_none() && state.initial.is_none() {
+        state.initial = Some(id);
+    }
+    account
+}
+
+// Makes an account's token set the one used for Web API requests, renewal and reconnects
+fn use_tokens(client_id: Option<String>, tokens: Option<OAuthResult>) {
+    *ACCESS_TOKEN.lock().unwrap() = tokens.as_ref().map(|tokens| tokens.access_token.clone());
+    match tokens {
+        Some(tokens) => {
+            secure_storage::save_tokens(client_id.as_deref(), &tokens);
+            token_manager::restore(client_id, tokens);
+        }
+        None => token_manager::reset(),
+    }
+}
+
+fn set_current(id: &str) {
+    let mut state = SWITCH_STATE.lock().unwrap();
+    state.current = (state.initial.as_deref() != Some(id)).then(|| id.to_string());
+}
+
+fn usable(tokens: &OAuthResult) -> bool {
+    current_timestamp_ms() + EXPIRY_MARGIN_MS < tokens.obtained_at_ms + tokens.expires_in * 1000
+}
+
+// Stops playback and empties the queue, which belong to the previous account
+fn clear_playback() {
+    loading::begin_load();
+    station::stop_station();
+    let player = PLAYER.lock().unwrap().clone();
+    if let Some(player) = player {
+        stop_and_drain(&player);
+    }
+    IS_PLAYING.store(false, Ordering::SeqCst);
+
+    queue_controller::edit(|queue, current_idx| {
+        queue.clear();
+        *current_idx = 0;
+    });
+    update_position(0);
+    DURATION_MS.store(0, Ordering::SeqCst);
+    loading::emit_queue_updated(false);
+}
+
+async fn switch_to(account: Account) -> Result<(), Failure> {
+    let mut tokens = account.tokens.clone();
+    if let Some(expired) = tokens.take_if(|tokens| !usable(tokens)) {
+        if let (Some(client_id), Some(refresh_token)) = (&account.client_id, &expired.refresh_token) {
+            match auth::refresh(client_id, refresh_token).await {
+                Ok(result) => tokens = Some(result),
+                Err(e) => log::warn!("Could not refresh the token of account {}: {}", account.id, e),
+            }
+        }
+    }
+
+    stored_credentials::reset();
+    set_current(&account.id);
+    match tokens {
+        Some(tokens) => {
+            use_tokens(account.client_id.clone(), Some(tokens.clone()));
+            connect::take_over(Credentials::with_access_token(&tokens.access_token)).await?;
+        }
+        None => {
+            // Log in with what the account's last session left, and get a token from the new one
+            let credentials = account.credentials.clone()
+                .ok_or_else(|| Failure::new(
+                    SpotiflyError::NotAuthenticated,
+                    "the account's access token has expired; add it again with a fresh token",
+                ))?;
+            use_tokens(account.client_id.clone(), None);
+            connect::take_over(credentials).await?;
+            let session = webapi::current_session()?;
+            if let Err(e) = stored_credentials::renew_token(&session).await {
+                // Playback works without it; only Web API requests fail
+                log::warn!("{}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_string(s: *const c_char) -> Result<Option<String>, ()> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    unsafe { CStr::from_ptr(s).to_str().map(|s| Some(s.to_string())).map_err(|_| ()) }
+}
+
+/// Adds an account the player can switch to with spotifly_switch_account(), or updates
+/// the token set of one added before. The token is checked against the Web API, but the
+/// current session is left alone. With a client ID and refresh token, an expired token is
+/// refreshed when switching to the account.
+/// Returns the account ID (the Spotify user ID); caller must free the string with
+/// spotifly_free_string(). Returns NULL on error (a free account gets SPOTIFLY_ERROR_NOT_PREMIUM).
+///
+/// # Parameters
+/// - access_token: The account's access token
+/// - client_id: The Spotify app's client ID, may be NULL
+/// - refresh_token: Refresh token from the OAuth flow, may be NULL
+/// - expires_in: Lifetime of the access token in seconds
+#[no_mangle]
+pub extern "C" fn spotifly_add_account(
+    access_token: *const c_char,
+    client_id: *const c_char,
+    refresh_token: *const c_char,
+    expires_in: u64,
+) -> *mut c_char {
+    let (access_token, client_id, refresh_token) =
+        match (read_string(access_token), read_string(client_id), read_string(refresh_token)) {
+            (Ok(Some(a)), Ok(c), Ok(r)) => (a, c, r),
+            _ => {
+                error::fail(SpotiflyError::InvalidArgument, "Add account error: invalid or missing access_token");
+                return ptr::null_mut();
+            }
+        };
+
+    let me = match RUNTIME.block_on(profile::token_me(&access_token)) {
+        Ok(me) => me,
+        Err(e) => {
+            error::report("Add account", e);
+            return ptr::null_mut();
+        }
+    };
+    let Some(id) = me.get("id").and_then(Value::as_str).filter(|id| !id.is_empty()) else {
+        error::fail(SpotiflyError::NotAuthenticated, "Add account error: the access token has no user");
+        return ptr::null_mut();
+    };
+    let product = me.get("product").and_then(Value::as_str);
+    if product.is_some_and(|product| product != "premium") {
+        profile::premium_required("Add account", product);
+        return ptr::null_mut();
+    }
+
+    upsert(Account {
+        id: id.to_string(),
+        display_name: me.get("display_name").and_then(Value::as_str).map(str::to_string),
+        client_id,
+        tokens: Some(OAuthResult {
+            access_token,
+            refresh_token,
+            expires_in,
+            obtained_at_ms: current_timestamp_ms(),
+        }),
+        credentials: None,
+    });
+    log::info!("Added account {}", id);
+    to_c_string(id)
+}
+
+/// Switches the player to another account: playback stops, the queue is cleared and the
+/// session is replaced with one for the account, while the player itself stays up. The
+/// account switched away from is remembered, so it can be switched back to. Sends an
+/// AccountChanged event. If the switch fails, the player reconnects the previous account.
+/// Returns 0 on success (also if the account is already active), a negative error code on error.
+///
+/// # Parameters
+/// - account_id: ID returned by spotifly_add_account() or listed by spotifly_get_accounts_json()
+#[no_mangle]
+pub extern "C" fn spotifly_switch_account(account_id: *const c_char) -> i32 {
+    power::note_activity();
+    let id = match read_string(account_id) {
+        Ok(Some(id)) => id,
+        _ => return error::fail(SpotiflyError::InvalidArgument, "Switch account error: invalid account_id"),
+    };
+    let Some(session) = SESSION.lock().unwrap().clone() else {
+        return error::fail(SpotiflyError::NotInitialized, "Switch account error: player not initialized");
+    };
+    if session.username() == id {
+        return 0;
+    }
+    let Some(account) = ACCOUNTS.lock().unwrap().iter().find(|account| account.id == id).cloned() else {
+        return error::fail(SpotiflyError::NotFound, format!("Switch account error: no account {}", id));
+    };
+
+    let previous = remember(&session);
+    clear_playback();
+    match RUNTIME.block_on(switch_to(account)) {
+        Ok(()) => {
+            log::info!("Switched to account {}", id);
+            events::emit(events::EVENT_ACCOUNT_CHANGED, json!({ "account_id": id, "previous": previous.id }));
+            0
+        }
+        Err(e) => {
+            // The supervisor reconnects the previous account
+            set_current(&previous.id);
+            use_tokens(previous.client_id, previous.tokens);
+            set_connection_state(CONNECTION_DISCONNECTED);
+            supervisor::retry_now();
+            error::report("Switch account", e)
+        }
+    }
+}
+
+/// Removes an added account. The active account can't be removed.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - account_id: The account's ID
+#[no_mangle]
+pub extern "C" fn spotifly_remove_account(account_id: *const c_char) -> i32 {
+    let id = match read_string(account_id) {
+        Ok(Some(id)) => id,
+        _ => return error::fail(SpotiflyError::InvalidArgument, "Remove account error: invalid account_id"),
+    };
+    if SESSION.lock().unwrap().as_ref().is_some_and(|session| session.username() == id) {
+        return error::fail(SpotiflyError::InvalidArgument, "Remove account error: the account is active");
+    }
+
+    let mut accounts = ACCOUNTS.lock().unwrap();
+    let count = accounts.len();
+    accounts.retain(|account| account.id != id);
+    if accounts.len() == count {
+        return error::fail(SpotiflyError::NotFound, format!("Remove account error: no account {}", id));
+    }
+    0
+}
+
+/// Returns the known accounts as a JSON array of {account_id, display_name, is_active}:
+/// the added ones and the one the player is logged in with. display_name is null if unknown.
+/// Caller must free the string with spotifly_free_string().
+#[no_mangle]
+pub extern "C" fn spotifly_get_accounts_json() -> *mut c_char {
+    let active = SESSION.lock().unwrap().as_ref().map(Session::username);
+    let accounts = ACCOUNTS.lock().unwrap();
+    let mut list: Vec<AccountInfo> = accounts.iter()
+        .map(|account| AccountInfo {
+            account_id: &account.id,
+            display_name: account.display_name.as_deref(),
+            is_active: active.as_deref() == Some(account.id.as_str()),
+        })
+        .collect();
+    if let Some(active) = active.as_deref().filter(|active| !accounts.iter().any(|account| account.id == *active)) {
+        list.insert(0, AccountInfo { account_id: active, display_name: None, is_active: true });
+    }
+
+    match serde_json::to_string(&list) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,192 @@
+// Audio level and spectrum analysis.
+//
+// For hosts that want a level meter or spectrum display without handling raw PCM.
+// The output feeds every block of samples through here; about 30 times a second the
+// latest window is reduced to RMS/peak levels and a handful of log-spaced FFT bands.
+// Nothing is computed until the host first asks for levels.
+
+use crate::to_c_string;
+use librespot_playback::decoder::AudioPacket;
+use librespot_playback::{NUM_CHANNELS, SAMPLE_RATE};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::ffi::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Frames per FFT window (a power of two)
+const WINDOW_FRAMES: usize = 1024;
+// Frames between updates, for about 30 updates per second
+const UPDATE_INTERVAL_FRAMES: usize = SAMPLE_RATE as usize / 30;
+const BAND_COUNT: usize = 16;
+const LOWEST_BAND_HZ: f32 = 40.0;
+const HIGHEST_BAND_HZ: f32 = 16_000.0;
+// Band levels are scaled from this many dB below full scale (0.0) up to full scale (1.0)
+const BAND_FLOOR_DB: f32 = -60.0;
+// Levels older than this are reported as silence (playback paused or stopped)
+const STALE_AFTER: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Default, Serialize)]
+struct Levels {
+    rms_left: f32,
+    rms_right: f32,
+    peak_left: f32,
+    peak_right: f32,
+    bands: Vec<f32>,
+}
+
+struct Analyzer {
+    // Most recent WINDOW_FRAMES stereo frames
+    window: VecDeque<(f32, f32)>,
+    frames_since_update: usize,
+    levels: Levels,
+    updated_at: Option<Instant>,
+}
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static ANALYZER: Lazy<Mutex<Analyzer>> = Lazy::new(|| {
+    Mutex::new(Analyzer {
+        window: VecDeque::with_capacity(WINDOW_FRAMES),
+        frames_since_update: 0,
+        levels: Levels::default(),
+        updated_at: None,
+    })
+});
+
+/// Adds a block of played samples to the analysis window.
+pub(crate) fn feed(packet: &AudioPacket) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let samples = match packet.samples() {
+        Ok(samples) => samples,
+        Err(_) => return,
+    };
+
+    let mut analyzer = ANALYZER.lock().unwrap();
+    for frame in samples.chunks_exact(NUM_CHANNELS as usize) {
+        if analyzer.window.len() == WINDOW_FRAMES {
+            analyzer.window.pop_front();
+        }
+        analyzer.window.push_back((frame[0] as f32, frame[1] as f32));
+    }
+
+    analyzer.frames_since_update += samples.len() / NUM_CHANNELS as usize;
+    if analyzer.frames_since_update >= UPDATE_INTERVAL_FRAMES && analyzer.window.len() == WINDOW_FRAMES {
+        analyzer.frames_since_update = 0;
+        analyzer.levels = analyze(&analyzer.window);
+        analyzer.updated_at = Some(Instant::now());
+    }
+}
+
+fn analyze(window: &VecDeque<(f32, f32)>) -> Levels {
+    let frames = window.len() as f32;
+    let rms = |channel: fn(&(f32, f32)) -> f32| {
+        (window.iter().map(|f| channel(f).powi(2)).sum::<f32>() / frames).sqrt()
+    };
+    let peak = |channel: fn(&(f32, f32)) -> f32| window.iter().map(|f| channel(f).abs()).fold(0.0, f32::max);
+
+    // Hann-windowed mono mix
+    let mut spectrum: Vec<(f32, f32)> = window.iter().enumerate()
+        .map(|(i, (left, right))| {
+            let hann = 0.5 - 0.5 * (2.0 * PI * i as f32 / (frames - 1.0)).cos();
+            ((left + right) * 0.5 * hann, 0.0)
+        })
+        .collect();
+    fft(&mut spectrum);
+
+    Levels {
+        rms_left: rms(|f| f.0),
+        rms_right: rms(|f| f.1),
+        peak_left: peak(|f| f.0),
+        peak_right: peak(|f| f.1),
+        bands: band_levels(&spectrum),
+    }
+}
+
+// Reduces an FFT to BAND_COUNT log-spaced bands, each the loudest bin in its range,
+// scaled to 0.0..1.0
+fn band_levels(spectrum: &[(f32, f32)]) -> Vec<f32> {
+    let bins = spectrum.len() / 2;
+    let hz_per_bin = SAMPLE_RATE as f32 / spectrum.len() as f32;
+    // A full-scale sine peaks at a quarter of the window length after the Hann window
+    let full_scale = spectrum.len() as f32 / 4.0;
+    let ratio = (HIGHEST_BAND_HZ / LOWEST_BAND_HZ).powf(1.0 / BAND_COUNT as f32);
+
+    (0..BAND_COUNT)
+        .map(|band| {
+            let low_hz = LOWEST_BAND_HZ * ratio.powi(band as i32);
+            let low = ((low_hz / hz_per_bin) as usize).clamp(1, bins - 1);
+            let high = ((low_hz * ratio / hz_per_bin) as usize).clamp(low + 1, bins);
+            let magnitude = spectrum[low..high].iter()
+                .map(|(re, im)| (re * re + im * im).sqrt())
+                .fold(0.0, f32::max);
+            let db = 20.0 * (magnitude / full_scale).max(1e-9).log10();
+            ((db - BAND_FLOOR_DB) / -BAND_FLOOR_DB).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+// In-place iterative radix-2 FFT of (re, im) pairs; the length must be a power of two
+fn fft(data: &mut [(f32, f32)]) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_re, w_im) = ((angle * k as f32).cos(), (angle * k as f32).sin());
+                let (a, b) = (data[start + k], data[start + k + len / 2]);
+                let (t_re, t_im) = (b.0 * w_re - b.1 * w_im, b.0 * w_im + b.1 * w_re);
+                data[start + k] = (a.0 + t_re, a.1 + t_im);
+                data[start + k + len / 2] = (a.0 - t_re, a.1 - t_im);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Returns the current output levels as JSON:
+/// {rms_left, rms_right, peak_left, peak_right, bands: [16 values]}
+/// Levels are linear amplitudes (0.0 = silence, 1.0 = full scale). Bands are log-spaced
+/// from 40 Hz to 16 kHz, scaled from -60 dBFS (0.0) to 0 dBFS (1.0). Values refresh about
+/// 30 times a second while audio plays and are zero otherwise; poll at the display rate.
+/// The first call starts the analysis, so it returns silence.
+/// Caller must free the string with spotifly_free_string().
+#[no_mangle]
+pub extern "C" fn spotifly_get_audio_levels() -> *mut c_char {
+    ACTIVE.store(true, Ordering::Relaxed);
+
+    let levels = {
+        let analyzer = ANALYZER.lock().unwrap();
+        match analyzer.updated_at {
+            Some(updated_at) if updated_at.elapsed() < STALE_AFTER => analyzer.levels.clone(),
+            _ => Levels {
+                bands: vec![0.0; BAND_COUNT],
+                ..Levels::default()
+            },
+        }
+    };
+
+    match serde_json::to_string(&levels) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,184 @@
+// Artist pages: profile, biography, top tracks and discography, and related artists.
+//
+// Most of it comes from the Web API; the biography is only in the artist's metadata.
+
+use crate::search::{album_result, artist_result, AlbumResult};
+use crate::webapi::{self, first_image_url, str_field};
+use crate::error::Failure;
+use crate::{error, links, parse_spotify_uri, power, to_c_string, with_metadata_timeout, QueueItem, RUNTIME};
+use librespot_core::session::Session;
+use librespot_metadata::{Artist, Metadata};
+use serde::Serialize;
+use serde_json::Value;
+use std::ffi::c_char;
+use std::ptr;
+
+// The Web API returns at most 50 albums per page
+const ALBUMS_PAGE_SIZE: usize = 50;
+// Prolific artists appear on thousands of releases; stop after this many
+const MAX_DISCOGRAPHY: usize = 500;
+
+#[derive(Serialize)]
+struct ArtistPage {
+    uri: String,
+    name: String,
+    image_url: String,
+    genres: Vec<String>,
+    followers: u64,
+    biography: Option<String>,
+    top_tracks: Vec<QueueItem>,
+    albums: Vec<AlbumResult>,
+    singles: Vec<AlbumResult>,
+    compilations: Vec<AlbumResult>,
+    appears_on: Vec<AlbumResult>,
+}
+
+// Fetches the artist's releases in all four groups
+async fn fetch_discography(session: &Session, artist_id: &str) -> Result<Vec<Value>, Failure> {
+    let mut releases = Vec::new();
+
+    while releases.len() < MAX_DISCOGRAPHY {
+        let path = format!(
+            "/artists/{}/albums?include_groups=album,single,compilation,appears_on&limit={}&offset={}",
+            artist_id,
+            ALBUMS_PAGE_SIZE,
+            releases.len(),
+        );
+        let page = webapi::get(session, &path).await?;
+        let items = page.get("items").and_then(Value::as_array).cloned().unwrap_or_default();
+        let done = items.len() < ALBUMS_PAGE_SIZE || page.get("next").is_none_or(Value::is_null);
+        releases.extend(items);
+        if done {
+            break;
+        }
+    }
+
+    Ok(releases)
+}
+
+// The artist's biography, if Spotify has one
+async fn fetch_biography(session: &Session, artist_id: &str) -> Option<String> {
+    let artist_uri = parse_spotify_uri(&format!("spotify:artist:{}", artist_id)).ok()?;
+    let artist = with_metadata_timeout("artist", Artist::get(session, &artist_uri)).await.ok()?;
+    artist.biographies.first()
+        .map(|biography| biography.text.clone())
+        .filter(|text| !text.is_empty())
+}
+
+async fn fetch_artist_page(session: &Session, artist_id: &str) -> Result<ArtistPage, Failure> {
+    let artist_path = format!("/artists/{}", artist_id);
+    let top_tracks_path = format!("/artists/{}/top-tracks?market=from_token", artist_id);
+    let (artist, top_tracks, releases, biography) = tokio::join!(
+        webapi::get(session, &artist_path),
+        webapi::get(session, &top_tracks_path),
+        fetch_discography(session, artist_id),
+        fetch_biography(session, artist_id),
+    );
+    let (artist, top_tracks, releases) = (artist?, top_tracks?, releases?);
+
+    let mut page = ArtistPage {
+        uri: str_field(&artist, "uri"),
+        name: str_field(&artist, "name"),
+        image_url: first_image_url(&artist),
+        genres: artist.get("genres")
+            .and_then(Value::as_array)
+            .map(|genres| genres.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            .unwrap_or_default(),
+        followers: artist.pointer("/followers/total").and_then(Value::as_u64).unwrap_or(0),
+        biography,
+        top_tracks: top_tracks.get("tracks").and_then(Value::as_array).into_iter().flatten()
+            .filter_map(webapi::queue_item_from_json)
+            .collect(),
+        albums: Vec::new(),
+        singles: Vec::new(),
+        compilations: Vec::new(),
+        appears_on: Vec::new(),
+    };
+
+    for release in &releases {
+        let group = match release.get("album_group").and_then(Value::as_str) {
+            Some("album") => &mut page.albums,
+            Some("single") => &mut page.singles,
+            Some("compilation") => &mut page.compilations,
+            Some("appears_on") => &mut page.appears_on,
+            _ => continue,
+        };
+        group.extend(album_result(release));
+    }
+
+    Ok(page)
+}
+
+/// Returns an artist page as JSON:
+/// {uri, name, image_url, genres, followers, biography, top_tracks: [queue item],
+/// albums, singles, compilations, appears_on}
+/// Each release list holds {uri, name, artist_name, album_art_url, release_date, total_tracks};
+/// play a release by passing its uri to spotifly_play_track(). biography may be null.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - uri: Spotify artist URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_get_artist_json(uri: *const c_char) -> *mut c_char {
+    power::note_activity();
+    let action = "Get artist";
+    let artist_id = match links::id_arg(uri, "artist", action) {
+        Ok(id) => id,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        fetch_artist_page(&session, &artist_id).await
+    });
+
+    match result {
+        Ok(page) => match serde_json::to_string(&page) {
+            Ok(json_string) => to_c_string(&json_string),
+            Err(_) => ptr::null_mut(),
+        },
+        Err(e) => {
+            error::report(action, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns artists similar to the given one ("Fans also like") as a JSON array of
+/// {uri, name, image_url, genres}.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - artist_uri: Spotify artist URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_get_related_artists(artist_uri: *const c_char) -> *mut c_char {
+    power::note_activity();
+    let action = "Get related artists";
+    let artist_id = match links::id_arg(artist_uri, "artist", action) {
+        Ok(id) => id,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        webapi::get(&session, &format!("/artists/{}/related-artists", artist_id)).await
+    });
+
+    match result {
+        Ok(response) => {
+            let artists: Vec<_> = response.get("artists").and_then(Value::as_array).into_iter().flatten()
+                .filter_map(artist_result)
+                .collect();
+            match serde_json::to_string(&artists) {
+                Ok(json_string) => to_c_string(&json_string),
+                Err(_) => ptr::null_mut(),
+            }
+        }
+        Err(e) => {
+            error::report(action, e);
+            ptr::null_mut()
+        }
+    }
+}
@@ -0,0 +1,388 @@
+// Artwork download.
+//
+// Fetches cover art through the session instead of leaving it to the host, so sandboxed
+// hosts that can't make arbitrary network requests still get images. Images can also be
+// cached on disk (size-capped, least recently used first out) for hosts that want a file.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{artwork_cache_dir, links, parse_spotify_uri, power, to_c_string, webapi, with_metadata_timeout, RUNTIME};
+use bytes::Bytes;
+use http::{Method, Request};
+use librespot_core::session::Session;
+use librespot_core::{FileId, SpotifyUri};
+use librespot_metadata::audio::item::CoverImage;
+use librespot_metadata::image::{Image, ImageSize};
+use librespot_metadata::{Album, Artist, Episode, Metadata, Show, Track};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ffi::{c_char, CStr};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+const IMAGE_URL_PREFIX: &str = "https://i.scdn.co/image/";
+// Target sizes of the small and medium cover URLs (large is the biggest available)
+const SMALL_DIMENSION: u32 = 64;
+const MEDIUM_DIMENSION: u32 = 300;
+
+// Size limit of the artwork cache (0 = unlimited)
+static ARTWORK_CACHE_LIMIT: AtomicU64 = AtomicU64::new(100 * 1024 * 1024);
+
+/// Cover image URLs in three sizes. Empty when no image is available.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct ArtworkUrls {
+    /// About 64 px
+    pub small: String,
+    /// About 300 px
+    pub medium: String,
+    /// The largest available (usually 640 px)
+    pub large: String,
+}
+
+impl ArtworkUrls {
+    // Picks the image closest to each size from (dimension, url) pairs
+    fn from_sized(images: Vec<(u32, String)>) -> Self {
+        let closest = |target: u32| {
+            images.iter()
+                .min_by_key(|(dimension, _)| dimension.abs_diff(target))
+                .map(|(_, url)| url.clone())
+                .unwrap_or_default()
+        };
+        ArtworkUrls {
+            small: closest(SMALL_DIMENSION),
+            medium: closest(MEDIUM_DIMENSION),
+            large: images.iter()
+                .max_by_key(|(dimension, _)| *dimension)
+                .map(|(_, url)| url.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Resolves metadata cover images (file IDs) into i.scdn.co URLs.
+    pub(crate) fn from_images<'a>(images: impl IntoIterator<Item = &'a Image>) -> Self {
+        Self::from_sized(images.into_iter()
+            .filter_map(|image| {
+                let file_id = image.id.to_base16().ok()?;
+                Some((image_dimension(image), format!("{}{}", IMAGE_URL_PREFIX, file_id)))
+            })
+            .collect())
+    }
+
+    /// Reads the cover images of a player audio item.
+    pub(crate) fn from_covers(covers: &[CoverImage]) -> Self {
+        Self::from_sized(covers.iter()
+            .map(|cover| {
+                let dimension = sized_dimension(cover.width, cover.height, cover.size);
+                (dimension, cover.url.clone())
+            })
+            .collect())
+    }
+
+    /// Reads a Web API "images" array ([{"url", "width", "height"}]).
+    pub(crate) fn from_json(images: Option<&Value>) -> Self {
+        Self::from_sized(images.and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|image| {
+                let url = image.get("url")?.as_str()?.to_string();
+                Some((json_image_dimension(image), url))
+            })
+            .collect())
+    }
+}
+
+// Where an image can be downloaded from
+enum ImageSource {
+    File(FileId),
+    Url(String),
+}
+
+// Approximate pixel size of a metadata image (width/height are often missing)
+fn image_dimension(image: &Image) -> u32 {
+    sized_dimension(image.width, image.height, image.size)
+}
+
+fn sized_dimension(width: i32, height: i32, size: ImageSize) -> u32 {
+    if width > 0 || height > 0 {
+        return width.max(height) as u32;
+    }
+    match size {
+        ImageSize::SMALL => 64,
+        ImageSize::DEFAULT => 300,
+        ImageSize::LARGE => 640,
+        ImageSize::XLARGE => 1280,
+    }
+}
+
+fn json_image_dimension(image: &Value) -> u32 {
+    let width = image.get("width").and_then(Value::as_u64).unwrap_or(0);
+    let height = image.get("height").and_then(Value::as_u64).unwrap_or(0);
+    width.max(height) as u32
+}
+
+fn file_images<'a>(images: impl IntoIterator<Item = &'a Image>) -> Vec<(u32, ImageSource)> {
+    images.into_iter()
+        .map(|image| (image_dimension(image), ImageSource::File(image.id)))
+        .collect()
+}
+
+/// Collects the available images for a track, album, artist, episode, show or playlist.
+async fn available_images(session: &Session, uri: &SpotifyUri) -> Result<Vec<(u32, ImageSource)>, Failure> {
+    let images = match uri {
+        SpotifyUri::Track { .. } => {
+            let track = with_metadata_timeout("track", Track::get(session, uri)).await?;
+            file_images(track.album.covers.iter())
+        }
+        SpotifyUri::Album { .. } => {
+            let album = with_metadata_timeout("album", Album::get(session, uri)).await?;
+            file_images(album.covers.iter())
+        }
+        SpotifyUri::Artist { .. } => {
+            let artist = with_metadata_timeout("artist", Artist::get(session, uri)).await?;
+            file_images(artist.portraits.iter().chain(artist.portrait_group.iter()))
+        }
+        SpotifyUri::Episode { .. } => {
+            let episode = with_metadata_timeout("episode", Episode::get(session, uri)).await?;
+            file_images(episode.covers.iter())
+        }
+        SpotifyUri::Show { .. } => {
+            let show = with_metadata_timeout("show", Show::get(session, uri)).await?;
+            file_images(show.covers.iter())
+        }
+        SpotifyUri::Playlist { id, .. } => {
+            // Playlist covers can be generated mosaics, which only the Web API knows about
+            let id = id.to_base62()
+                .map_err(|e| Failure::new(SpotiflyError::InvalidUri, format!("Invalid playlist id: {}", e)))?;
+            let images = webapi::get(session, &format!("/playlists/{}/images", id)).await?;
+            images.as_array().into_iter().flatten()
+                .filter_map(|image| {
+                    let url = image.get("url")?.as_str()?.to_string();
+                    Some((json_image_dimension(image), ImageSource::Url(url)))
+                })
+                .collect()
+        }
+        _ => return Err(Failure::new(SpotiflyError::NotFound, format!("No artwork for {}", uri))),
+    };
+    Ok(images)
+}
+
+// Picks the smallest image at least `max_dimension` pixels wide/high
+// (or the largest available one). 0 picks the largest image.
+async fn select_image(session: &Session, uri: &SpotifyUri, max_dimension: u32) -> Result<ImageSource, Failure> {
+    let mut images = available_images(session, uri).await?;
+    images.sort_by_key(|(dimension, _)| *dimension);
+
+    let index = images.iter()
+        .position(|(dimension, _)| max_dimension > 0 && *dimension >= max_dimension)
+        .or_else(|| images.len().checked_sub(1))
+        .ok_or_else(|| Failure::new(SpotiflyError::NotFound, format!("No artwork for {}", uri)))?;
+    Ok(images.swap_remove(index).1)
+}
+
+async fn download(session: &Session, source: &ImageSource) -> Result<Bytes, Failure> {
+    match source {
+        ImageSource::File(file_id) => session.spclient().get_image(file_id).await
+            .map_err(|e| error::librespot("Failed to download artwork", &e)),
+        ImageSource::Url(url) => {
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri(url.as_str())
+                .body(Bytes::new())
+                .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Invalid artwork URL: {}", e)))?;
+            session.http_client().request_body(request).await
+                .map_err(|e| error::librespot("Failed to download artwork", &e))
+        }
+    }
+}
+
+/// Downloads the smallest image at least `max_dimension` pixels wide/high
+/// (or the largest available one). 0 picks the largest image.
+pub(crate) async fn fetch_artwork(session: &Session, uri: &SpotifyUri, max_dimension: u32) -> Result<Bytes, Failure> {
+    let source = select_image(session, uri, max_dimension).await?;
+    download(session, &source).await
+}
+
+// Cache file name of an image: its file ID, or a hash of its URL
+fn cache_file_name(source: &ImageSource) -> String {
+    match source {
+        ImageSource::File(file_id) => file_id.to_base16().unwrap_or_else(|_| format!("{:?}", file_id)),
+        ImageSource::Url(url) => format!("{:x}", Md5::digest(url.as_bytes())),
+    }
+}
+
+// Deletes the least recently used images until the cache fits its size limit
+fn evict_artwork(dir: &Path, keep: &Path) {
+    let limit = ARTWORK_CACHE_LIMIT.load(Ordering::SeqCst);
+    if limit == 0 {
+        return;
+    }
+
+    let mut files: Vec<(PathBuf, u64, SystemTime)> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(Result::ok)
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let used = metadata.modified().ok()?;
+                metadata.is_file().then(|| (entry.path(), metadata.len(), used))
+            })
+            .collect(),
+        Err(_) => return,
+    };
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+
+    files.sort_by_key(|(_, _, used)| *used);
+    for (path, len, _) in files {
+        if total <= limit {
+            break;
+        }
+        if path != keep && fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+// Returns the cached image file, downloading it first if needed
+async fn cached_artwork(session: &Session, uri: &SpotifyUri, max_dimension: u32) -> Result<PathBuf, Failure> {
+    let dir = artwork_cache_dir().ok_or_else(|| {
+        Failure::new(SpotiflyError::NotInitialized, "No cache directory set (see spotifly_set_cache_dir())")
+    })?;
+    let source = select_image(session, uri, max_dimension).await?;
+    let path = dir.join(cache_file_name(&source));
+
+    if path.is_file() {
+        // Mark as recently used
+        if let Ok(file) = fs::File::options().append(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        return Ok(path);
+    }
+
+    let bytes = download(session, &source).await?;
+    fs::create_dir_all(&dir)
+        .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Failed to create artwork cache: {}", e)))?;
+    // Write to a temporary file first so a reader never sees a partial image
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, &bytes)
+        .and_then(|_| fs::rename(&tmp_path, &path))
+        .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Failed to write artwork cache: {}", e)))?;
+
+    evict_artwork(&dir, &path);
+    Ok(path)
+}
+
+/// Downloads artwork for a track, album, artist, episode, show or playlist.
+/// Returns the encoded image bytes (JPEG or PNG, as served by Spotify) and stores
+/// their length in out_len, or NULL on error.
+/// Caller must free the bytes with spotifly_free_bytes().
+///
+/// # Parameters
+/// - uri: Spotify URI or URL
+/// - max_dimension: Preferred size in pixels; the smallest image at least this large
+///   is returned (or the largest available). 0 = largest available.
+/// - out_len: Receives the number of bytes returned
+#[no_mangle]
+pub extern "C" fn spotifly_get_artwork_bytes(
+    uri: *const c_char,
+    max_dimension: u32,
+    out_len: *mut usize,
+) -> *mut u8 {
+    power::note_activity();
+    if uri.is_null() || out_len.is_null() {
+        error::fail(SpotiflyError::InvalidArgument, "Get artwork error: uri or out_len is null");
+        return ptr::null_mut();
+    }
+
+    let uri_str = unsafe {
+        match CStr::from_ptr(uri).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => {
+                error::fail(SpotiflyError::InvalidArgument, "Get artwork error: invalid uri string");
+                return ptr::null_mut();
+            }
+        }
+    };
+
+    let result: Result<Bytes, Failure> = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        let link = links::resolve_link(&session, &uri_str).await?;
+        let spotify_uri = parse_spotify_uri(&link.uri)?;
+        fetch_artwork(&session, &spotify_uri, max_dimension).await
+    });
+
+    match result {
+        Ok(bytes) => {
+            let boxed: Box<[u8]> = bytes.to_vec().into_boxed_slice();
+            unsafe { *out_len = boxed.len() };
+            Box::into_raw(boxed) as *mut u8
+        }
+        Err(e) => {
+            error::report("Get artwork", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees bytes returned by this library (e.g. from spotifly_get_artwork_bytes()).
+#[no_mangle]
+pub extern "C" fn spotifly_free_bytes(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        unsafe {
+            let _ = Box::from_raw(ptr::slice_from_raw_parts_mut(data, len));
+        }
+    }
+}
+
+/// Downloads artwork for a track, album, artist, episode, show or playlist into the
+/// artwork cache and returns the path of the image file (JPEG or PNG, as served by Spotify).
+/// Cached images are returned without a download. Images are cached in the "artwork"
+/// subdirectory of the cache directory (see spotifly_set_cache_dir()), keyed by image file ID.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error, or if no cache directory is set.
+///
+/// # Parameters
+/// - uri: Spotify URI or URL
+/// - max_dimension: Preferred size in pixels; the smallest image at least this large
+///   is returned (or the largest available). 0 = largest available.
+#[no_mangle]
+pub extern "C" fn spotifly_fetch_artwork(uri: *const c_char, max_dimension: u32) -> *mut c_char {
+    power::note_activity();
+    if uri.is_null() {
+        error::fail(SpotiflyError::InvalidArgument, "Fetch artwork error: uri is null");
+        return ptr::null_mut();
+    }
+
+    let uri_str = unsafe {
+        match CStr::from_ptr(uri).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => {
+                error::fail(SpotiflyError::InvalidArgument, "Fetch artwork error: invalid uri string");
+                return ptr::null_mut();
+            }
+        }
+    };
+
+    let result: Result<PathBuf, Failure> = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        let link = links::resolve_link(&session, &uri_str).await?;
+        let spotify_uri = parse_spotify_uri(&link.uri)?;
+        cached_artwork(&session, &spotify_uri, max_dimension).await
+    });
+
+    match result {
+        Ok(path) => to_c_string(&path.to_string_lossy()),
+        Err(e) => {
+            error::report("Fetch artwork", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Sets the size limit of the artwork cache. When it's exceeded, the least recently
+/// used images are deleted. Default is 100 MB.
+///
+/// # Parameters
+/// - max_bytes: Size limit in bytes, 0 = unlimited
+#[no_mangle]
+pub extern "C" fn spotifly_set_artwork_cache_limit(max_bytes: u64) {
+    ARTWORK_CACHE_LIMIT.store(max_bytes, Ordering::SeqCst);
+}
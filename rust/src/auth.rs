@@ -0,0 +1,154 @@
+// OAuth token renewal.
+//
+// The host runs the browser-based OAuth flow (PKCE) and hands us the access token, or
+// has the crate run it (see oauth.rs). This module keeps the latest token set in
+// OAUTH_RESULT and can renew it from the refresh token through the accounts service,
+// so long sessions don't need the browser.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{current_timestamp_ms, network, secure_storage, to_c_string, ACCESS_TOKEN, RUNTIME};
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::{Method, Request};
+use librespot_core::http_client::HttpClient;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, CStr};
+use std::ptr;
+use std::sync::Mutex;
+
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+
+/// The latest OAuth token set.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct OAuthResult {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Lifetime of the access token in seconds
+    pub expires_in: u64,
+    /// When the access token was issued (ms since epoch)
+    pub obtained_at_ms: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: u64,
+}
+
+pub(crate) static OAUTH_RESULT: Lazy<Mutex<Option<OAuthResult>>> = Lazy::new(|| Mutex::new(None));
+
+
+// Posts a form to the token endpoint and stores the token set it returns.
+// `previous_refresh_token` is kept if the response doesn't rotate it.
+async fn request_token(
+    client_id: &str,
+    body: String,
+    previous_refresh_token: Option<&str>,
+) -> Result<OAuthResult, Failure> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(TOKEN_URL)
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Bytes::from(body))
+        .map_err(|e| Failure::new(SpotiflyError::InvalidArgument, format!("Invalid token request: {}", e)))?;
+
+    // The accounts service is reachable before a session exists, so it gets its own client
+    let response = HttpClient::new(network::proxy().as_ref()).request_body(request).await
+        .map_err(|e| error::librespot("Token request failed", &e))?;
+    let token: TokenResponse = serde_json::from_slice(&response)
+        .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Failed to parse token response: {:?}", e)))?;
+
+    let result = OAuthResult {
+        access_token: token.access_token,
+        // The refresh token is only rotated sometimes; keep the old one otherwise
+        refresh_token: token.refresh_token.or_else(|| previous_refresh_token.map(str::to_string)),
+        expires_in: token.expires_in,
+        obtained_at_ms: current_timestamp_ms(),
+    };
+
+    *ACCESS_TOKEN.lock().unwrap() = Some(result.access_token.clone());
+    *OAUTH_RESULT.lock().unwrap() = Some(result.clone());
+    secure_storage::save_tokens(Some(client_id), &result);
+    Ok(result)
+}
+
+/// Exchanges a refresh token for a new access token and stores the result.
+/// The new access token is used for all Web API requests from then on.
+pub(crate) async fn refresh(client_id: &str, refresh_token: &str) -> Result<OAuthResult, Failure> {
+    let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("grant_type", "refresh_token")
+        .append_pair("refresh_token", refresh_token)
+        .append_pair("client_id", client_id)
+        .finish();
+    request_token(client_id, body, Some(refresh_token)).await
+}
+
+/// Exchanges an authorization code (from the PKCE flow) for a token set and stores it.
+pub(crate) async fn exchange_code(
+    client_id: &str,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: &str,
+) -> Result<OAuthResult, Failure> {
+    let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("grant_type", "authorization_code")
+        .append_pair("code", code)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("client_id", client_id)
+        .append_pair("code_verifier", code_verifier)
+        .finish();
+    request_token(client_id, body, None).await
+}
+
+/// Renews the access token using a refresh token from the OAuth flow.
+/// The result can be read with spotifly_get_oauth_result_json().
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - client_id: The Spotify app's client ID
+/// - refresh_token: Refresh token from the last OAuth flow or refresh
+#[no_mangle]
+pub extern "C" fn spotifly_refresh_access_token(
+    client_id: *const c_char,
+    refresh_token: *const c_char,
+) -> i32 {
+    if client_id.is_null() || refresh_token.is_null() {
+        return error::fail(SpotiflyError::InvalidArgument, "Refresh token error: client_id or refresh_token is null");
+    }
+
+    let (client_id_str, refresh_token_str) = unsafe {
+        match (CStr::from_ptr(client_id).to_str(), CStr::from_ptr(refresh_token).to_str()) {
+            (Ok(c), Ok(r)) => (c.to_string(), r.to_string()),
+            _ => {
+                return error::fail(SpotiflyError::InvalidArgument, "Refresh token error: invalid string");
+            }
+        }
+    };
+
+    match RUNTIME.block_on(refresh(&client_id_str, &refresh_token_str)) {
+        Ok(_) => 0,
+        Err(e) => {
+            error::report("Refresh token", e)
+        }
+    }
+}
+
+/// Returns the latest OAuth token set as JSON:
+/// {"access_token", "refresh_token", "expires_in", "obtained_at_ms"}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL if no token has been obtained yet.
+#[no_mangle]
+pub extern "C" fn spotifly_get_oauth_result_json() -> *mut c_char {
+    let result_guard = OAUTH_RESULT.lock().unwrap();
+    let result = match result_guard.as_ref() {
+        Some(r) => r,
+        None => return ptr::null_mut(),
+    };
+
+    match serde_json::to_string(result) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,99 @@
+// Region-restricted and relinked tracks.
+//
+// A track can be unplayable in the user's country or catalogue while listing alternatives:
+// the same recording under another ID ("relinking"). Queue items are built from the first
+// playable alternative where there is one. Tracks the player still can't load are skipped,
+// with a TrackUnavailable event.
+
+use crate::{
+    connect, events, queue_controller, station, with_metadata_timeout, CURRENT_INDEX, IS_PLAYING, QUEUE,
+};
+use librespot_core::session::{Session, UserData};
+use librespot_metadata::{Metadata, Track};
+use librespot_playback::player::Player;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Catalogue assumed when the account's attributes don't name one
+const DEFAULT_CATALOGUE: &str = "premium";
+
+// Unavailable tracks skipped since something last played; bounds the skipping when a
+// repeating queue has nothing playable
+static SKIPPED_IN_A_ROW: AtomicUsize = AtomicUsize::new(0);
+
+// Whether the track's restrictions allow it in the user's country and catalogue.
+// Mirrors the check the player makes before loading a track.
+fn allowed_for_user(track: &Track, user_data: &UserData) -> bool {
+    let catalogue = user_data.attributes.get("catalogue")
+        .map_or(DEFAULT_CATALOGUE, String::as_str);
+    let country = &user_data.country;
+
+    let restriction = track.restrictions.iter()
+        .find(|restriction| restriction.catalogue_strs.iter().any(|c| c == catalogue));
+    match restriction {
+        Some(restriction) => match (&restriction.countries_allowed, &restriction.countries_forbidden) {
+            (Some(allowed), _) => allowed.contains(country),
+            (None, Some(forbidden)) => !forbidden.contains(country),
+            (None, None) => true,
+        },
+        None => true,
+    }
+}
+
+fn is_playable(track: &Track, user_data: &UserData) -> bool {
+    !track.files.is_empty() && allowed_for_user(track, user_data)
+}
+
+/// Returns the track itself if it's playable for the user, otherwise its first playable
+/// alternative. Falls back to the track if no alternative is playable.
+pub(crate) async fn playable_track(session: &Session, track: Track) -> Track {
+    let user_data = session.user_data();
+    if is_playable(&track, &user_data) || track.alternatives.is_empty() {
+        return track;
+    }
+
+    for alternative_uri in track.alternatives.iter() {
+        if let Ok(alternative) = with_metadata_timeout("track", Track::get(session, alternative_uri)).await {
+            if is_playable(&alternative, &user_data) {
+                log::info!("Playing {} in place of unavailable {}", alternative.id, track.id);
+                return alternative;
+            }
+        }
+    }
+    track
+}
+
+/// Resets the skip count once a track plays.
+pub(crate) fn on_playing() {
+    SKIPPED_IN_A_ROW.store(0, Ordering::SeqCst);
+}
+
+/// Handles a track the player couldn't load. If it's the current queue item, skips to the
+/// next one, or stops (handing over to autoplay) at the end of the queue. Unavailable
+/// preloads are only reported: they're skipped once they become current.
+/// Sends a TrackUnavailable event {uri, skipped}.
+pub(crate) fn on_unavailable(track_uri: &str, player: &Arc<Player>) {
+    log::warn!("Track unavailable: {}", track_uri);
+
+    let is_current = {
+        let queue_guard = QUEUE.lock().unwrap();
+        queue_guard.get(CURRENT_INDEX.load(Ordering::SeqCst))
+            .is_some_and(|item| item.uri == track_uri)
+    };
+    // A Connect client advances its own context
+    let handled_here = is_current && !connect::is_remote_controlled();
+    let skipped = handled_here
+        && SKIPPED_IN_A_ROW.fetch_add(1, Ordering::SeqCst) < QUEUE.lock().unwrap().len()
+        && queue_controller::skip_from(track_uri, player);
+
+    events::emit(events::EVENT_TRACK_UNAVAILABLE, json!({
+        "uri": track_uri,
+        "skipped": skipped,
+    }));
+
+    if handled_here && !skipped {
+        IS_PLAYING.store(false, Ordering::SeqCst);
+        station::on_queue_ended(track_uri);
+    }
+}
@@ -0,0 +1,285 @@
+// Headless command-line player built on the library.
+//
+// `spotifly-cli daemon` runs the engine with the control server (control_server.rs) and
+// the other subcommands drive it over HTTP, so a terminal or a headless Linux box can
+// play without the app. The engine is used through the same C functions the app calls,
+// which makes the CLI a quick way to exercise them end to end.
+//
+//   spotifly-cli login --client-id ID [--redirect-uri URI]   browser login, kept in secure storage
+//   spotifly-cli logout                                       forget the stored login
+//   spotifly-cli daemon [--token TOKEN]                       run the player until interrupted
+//   spotifly-cli play [URI]    pause    next    previous    status    queue
+//
+// Every subcommand that talks to the daemon takes --port (default 7654).
+
+use serde_json::Value;
+use std::env;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::ExitCode;
+use std::ptr;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Links the engine in; the CLI reaches it through its C functions below
+use spotifly_rust as _;
+
+const DEFAULT_PORT: u16 = 7654;
+const DEFAULT_REDIRECT_URI: &str = "http://127.0.0.1:8888/callback";
+// Secure storage service the CLI keeps its login under
+const SERVICE_NAME: &str = "spotifly-cli";
+// TrackChanged, from the event list in spotifly_rust.h
+const EVENT_TRACK_CHANGED: i32 = 4;
+
+type Callback = extern "C" fn(i32, *const c_char, *mut c_void);
+
+extern "C" {
+    fn spotifly_init_player(access_token: *const c_char) -> i32;
+    fn spotifly_enable_secure_storage(service_name: *const c_char) -> i32;
+    fn spotifly_clear_secure_storage() -> i32;
+    fn spotifly_start_oauth_async(
+        client_id: *const c_char,
+        redirect_uri: *const c_char,
+        timeout_secs: u32,
+        callback: Callback,
+        user_data: *mut c_void,
+    ) -> i32;
+    fn spotifly_register_event_callback(callback: Option<Callback>, user_data: *mut c_void);
+    fn spotifly_start_control_server(port: u16) -> i32;
+    fn spotifly_get_last_error_message() -> *mut c_char;
+    fn spotifly_free_string(s: *mut c_char);
+}
+
+// Result code of the OAuth flow, once it finished
+static OAUTH_DONE: (Mutex<Option<i32>>, Condvar) = (Mutex::new(None), Condvar::new());
+
+struct Args {
+    command: String,
+    positional: Vec<String>,
+    port: u16,
+    client_id: Option<String>,
+    redirect_uri: String,
+    token: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut raw = env::args().skip(1);
+    let command = raw.next().ok_or("missing subcommand")?;
+    let mut args = Args {
+        command,
+        positional: Vec::new(),
+        port: DEFAULT_PORT,
+        client_id: None,
+        redirect_uri: DEFAULT_REDIRECT_URI.to_string(),
+        token: env::var("SPOTIFLY_ACCESS_TOKEN").ok(),
+    };
+
+    while let Some(arg) = raw.next() {
+        let mut value = || raw.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--port" => args.port = value()?.parse().map_err(|_| "invalid --port".to_string())?,
+            "--client-id" => args.client_id = Some(value()?),
+            "--redirect-uri" => args.redirect_uri = value()?,
+            "--token" => args.token = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => args.positional.push(arg),
+        }
+    }
+    Ok(args)
+}
+
+fn c_string(s: &str) -> Result<CString, String> {
+    CString::new(s).map_err(|_| format!("invalid argument: {}", s))
+}
+
+// The engine's message for the error it just returned
+fn last_error(code: i32) -> String {
+    unsafe {
+        let message = spotifly_get_last_error_message();
+        if message.is_null() {
+            return format!("error {}", code);
+        }
+        let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+        spotifly_free_string(message);
+        text
+    }
+}
+
+fn check(code: i32) -> Result<(), String> {
+    if code == 0 { Ok(()) } else { Err(last_error(code)) }
+}
+
+fn enable_secure_storage() -> Result<(), String> {
+    let service = c_string(SERVICE_NAME)?;
+    check(unsafe { spotifly_enable_secure_storage(service.as_ptr()) })
+}
+
+extern "C" fn oauth_finished(result: i32, _json: *const c_char, _user_data: *mut c_void) {
+    let (done, finished) = &OAUTH_DONE;
+    *done.lock().unwrap() = Some(result);
+    finished.notify_all();
+}
+
+fn login(args: &Args) -> Result<(), String> {
+    let client_id = c_string(args.client_id.as_deref().ok_or("login needs --client-id")?)?;
+    let redirect_uri = c_string(&args.redirect_uri)?;
+    enable_secure_storage()?;
+
+    println!("Opening the Spotify login page in your browser...");
+    check(unsafe {
+        spotifly_start_oauth_async(client_id.as_ptr(), redirect_uri.as_ptr(), 0, oauth_finished, ptr::null_mut())
+    })?;
+
+    let (done, finished) = &OAUTH_DONE;
+    let result = finished.wait_while(done.lock().unwrap(), |result| result.is_none()).unwrap().unwrap_or(0);
+    check(result)?;
+    println!("Logged in. Start the player with: spotifly-cli daemon");
+    Ok(())
+}
+
+fn logout() -> Result<(), String> {
+    enable_secure_storage()?;
+    check(unsafe { spotifly_clear_secure_storage() })?;
+    println!("Stored login removed");
+    Ok(())
+}
+
+extern "C" fn print_event(code: i32, payload: *const c_char, _user_data: *mut c_void) {
+    if code != EVENT_TRACK_CHANGED || payload.is_null() {
+        return;
+    }
+    let payload = unsafe { CStr::from_ptr(payload) }.to_string_lossy();
+    if let Ok(event) = serde_json::from_str::<Value>(&payload) {
+        println!("Now playing: {}", event["name"].as_str().unwrap_or("unknown"));
+    }
+}
+
+fn daemon(args: &Args) -> Result<(), String> {
+    enable_secure_storage()?;
+    let token = args.token.as_deref().map(c_string).transpose()?;
+    // Without a token the engine logs in with what `login` stored
+    let token_ptr = token.as_ref().map_or(ptr::null(), |token| token.as_ptr());
+    check(unsafe { spotifly_init_player(token_ptr) })
+        .map_err(|e| format!("{} (run spotifly-cli login first, or pass --token)", e))?;
+
+    unsafe { spotifly_register_event_callback(Some(print_event), ptr::null_mut()) };
+    check(unsafe { spotifly_start_control_server(args.port) })?;
+    println!("Player ready, control server on 127.0.0.1:{} (Ctrl-C to quit)", args.port);
+    loop {
+        thread::sleep(Duration::from_secs(3600));
+    }
+}
+
+// Sends a request to the daemon's control server and returns the JSON reply
+fn call(port: u16, method: &str, path: &str, body: Option<Value>) -> Result<Value, String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port))
+        .map_err(|_| format!("no player on port {} (start one with: spotifly-cli daemon)", port))?;
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    // HTTP/1.0 so the server closes the connection after replying
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\nHost: 127.0.0.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method, path, body.len(), body,
+    ).map_err(|e| e.to_string())?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+    let (_, body) = response.split_once("\r\n\r\n").ok_or("malformed response")?;
+    let reply: Value = serde_json::from_str(body).map_err(|e| format!("malformed response: {}", e))?;
+    match reply.get("error").and_then(Value::as_str) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(reply),
+    }
+}
+
+fn format_ms(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+fn status(port: u16) -> Result<(), String> {
+    let now_playing = call(port, "GET", "/now-playing", None)?;
+    if now_playing.is_null() {
+        println!("Nothing playing");
+        return Ok(());
+    }
+    let artists: Vec<&str> = now_playing["artists"].as_array().into_iter().flatten()
+        .filter_map(|artist| artist["name"].as_str())
+        .collect();
+    println!(
+        "{} {} - {}  {} / {}  ({} of {})",
+        if now_playing["is_playing"].as_bool() == Some(true) { "Playing" } else { "Paused " },
+        now_playing["title"].as_str().unwrap_or_default(),
+        artists.join(", "),
+        format_ms(now_playing["position_ms"].as_u64().unwrap_or(0)),
+        format_ms(now_playing["duration_ms"].as_u64().unwrap_or(0)),
+        now_playing["queue_index"].as_u64().unwrap_or(0) + 1,
+        now_playing["queue_length"].as_u64().unwrap_or(0),
+    );
+    Ok(())
+}
+
+fn queue(port: u16) -> Result<(), String> {
+    let queue = call(port, "GET", "/queue", None)?;
+    let current = queue["current_index"].as_u64().unwrap_or(0) as usize;
+    for (index, item) in queue["items"].as_array().into_iter().flatten().enumerate() {
+        println!(
+            "{} {:>4}. {} - {}  {}",
+            if index == current { ">" } else { " " },
+            index + 1,
+            item["track_name"].as_str().unwrap_or_default(),
+            item["artist_name"].as_str().unwrap_or_default(),
+            format_ms(item["duration_ms"].as_u64().unwrap_or(0)),
+        );
+    }
+    Ok(())
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    match args.command.as_str() {
+        "login" => login(args),
+        "logout" => logout(),
+        "daemon" => daemon(args),
+        "play" => {
+            let body = args.positional.first().map(|uri| serde_json::json!({ "uri": uri }));
+            call(args.port, "POST", "/play", body).map(|_| ())
+        }
+        "pause" => call(args.port, "POST", "/pause", None).map(|_| ()),
+        "next" => call(args.port, "POST", "/next", None).map(|_| ()),
+        "previous" => call(args.port, "POST", "/previous", None).map(|_| ()),
+        "status" => status(args.port),
+        "queue" => queue(args.port),
+        other => Err(format!("unknown subcommand {}", other)),
+    }
+}
+
+fn usage() {
+    eprintln!("usage: spotifly-cli <subcommand> [options]");
+    eprintln!("  login --client-id ID [--redirect-uri URI]   log in through the browser");
+    eprintln!("  logout                                      forget the stored login");
+    eprintln!("  daemon [--token TOKEN]                      run the player (SPOTIFLY_ACCESS_TOKEN also works)");
+    eprintln!("  play [URI]                                  play a track, album, playlist... or resume");
+    eprintln!("  pause | next | previous                     control playback");
+    eprintln!("  status | queue                              show what is playing / the queue");
+    eprintln!("options: --port PORT (control server port, default {})", DEFAULT_PORT);
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("spotifly-cli: {}", e);
+            usage();
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("spotifly-cli: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
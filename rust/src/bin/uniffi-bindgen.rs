@@ -0,0 +1,5 @@
+// Generates the Swift bindings for the `uniffi` feature (see build-swift-bindings.sh).
+
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
@@ -0,0 +1,158 @@
+// Browse content via the Web API: featured playlists, categories and new releases.
+//
+// Spotify curates these per market (the token's country), which gives the host a Home
+// or Browse tab beyond the user's own library. Every list is paged the same way:
+// {"items": [...], "total": n, "offset": n}.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::search::album_result;
+use crate::webapi::{self, playlist_summary_from_json, str_field};
+use crate::{power, to_c_string, RUNTIME};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+// The Web API returns at most 50 items per page
+const PAGE_SIZE: u32 = 50;
+
+/// A browse category (e.g. "Pop", "Workout").
+#[derive(Serialize)]
+struct Category {
+    id: String,
+    name: String,
+    icon_url: String,
+}
+
+fn category(category: &Value) -> Option<Category> {
+    Some(Category {
+        id: category.get("id")?.as_str()?.to_string(),
+        name: str_field(category, "name"),
+        icon_url: category.pointer("/icons/0/url").and_then(Value::as_str).unwrap_or_default().to_string(),
+    })
+}
+
+// Fetches a page and converts the list under `key` ({"items": [...], "total": n}).
+// Returns the converted page and the rest of the response.
+async fn fetch_page<T: Serialize>(
+    path: &str,
+    key: &str,
+    offset: u32,
+    limit: u32,
+    convert: fn(&Value) -> Option<T>,
+) -> Result<(Value, Value), Failure> {
+    let session = webapi::current_session()?;
+    let query_string = webapi::query_string([
+        ("limit", limit.clamp(1, PAGE_SIZE).to_string()),
+        ("offset", offset.to_string()),
+    ]);
+    let response = webapi::get(&session, &format!("{}?{}", path, query_string)).await?;
+
+    let list = response.get(key).ok_or_else(|| Failure::new(SpotiflyError::Unknown, "unexpected response"))?;
+    // Items can be null for content that is no longer available
+    let items: Vec<T> = list.get("items").and_then(Value::as_array).into_iter().flatten()
+        .filter(|item| !item.is_null())
+        .filter_map(convert)
+        .collect();
+    let page = json!({
+        "items": items,
+        "total": list.get("total").and_then(Value::as_u64).unwrap_or(0),
+        "offset": offset,
+    });
+    Ok((page, response))
+}
+
+fn page_result(action: &str, result: Result<Value, Failure>) -> *mut c_char {
+    match result {
+        Ok(page) => to_c_string(&page.to_string()),
+        Err(e) => {
+            error::report(action, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns a page of Spotify's featured playlists as JSON:
+/// {"message": headline or null, "items": [playlist summary], "total": n, "offset": n}
+/// Playlist summaries are {uri, name, owner_name, owner_id, image_url, track_count, collaborative}.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - offset: Index of the first playlist
+/// - limit: Maximum number of playlists (1-50)
+#[no_mangle]
+pub extern "C" fn spotifly_get_featured_playlists(offset: u32, limit: u32) -> *mut c_char {
+    power::note_activity();
+    let result = RUNTIME.block_on(async {
+        let (mut page, response) =
+            fetch_page("/browse/featured-playlists", "playlists", offset, limit, playlist_summary_from_json).await?;
+        page["message"] = response.get("message").cloned().unwrap_or(Value::Null);
+        Ok(page)
+    });
+    page_result("Get featured playlists", result)
+}
+
+/// Returns a page of browse categories as JSON:
+/// {"items": [{id, name, icon_url}], "total": n, "offset": n}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - offset: Index of the first category
+/// - limit: Maximum number of categories (1-50)
+#[no_mangle]
+pub extern "C" fn spotifly_get_categories(offset: u32, limit: u32) -> *mut c_char {
+    power::note_activity();
+    let result = RUNTIME.block_on(async {
+        fetch_page("/browse/categories", "categories", offset, limit, category).await.map(|(page, _)| page)
+    });
+    page_result("Get categories", result)
+}
+
+/// Returns a page of a category's playlists as JSON:
+/// {"items": [playlist summary], "total": n, "offset": n}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error (NotFound for an unknown category).
+///
+/// # Parameters
+/// - category_id: Category ID from spotifly_get_categories()
+/// - offset: Index of the first playlist
+/// - limit: Maximum number of playlists (1-50)
+#[no_mangle]
+pub extern "C" fn spotifly_get_category_playlists(category_id: *const c_char, offset: u32, limit: u32) -> *mut c_char {
+    power::note_activity();
+    let action = "Get category playlists";
+    let id = match (!category_id.is_null()).then(|| unsafe { CStr::from_ptr(category_id) }.to_str()) {
+        Some(Ok(id)) if !id.is_empty() => id.to_string(),
+        _ => {
+            error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid category_id", action));
+            return ptr::null_mut();
+        }
+    };
+
+    let result = RUNTIME.block_on(async {
+        // Category IDs are plain words, but don't let one escape the path
+        let id: String = form_urlencoded::byte_serialize(id.as_bytes()).collect();
+        let path = format!("/browse/categories/{}/playlists", id);
+        fetch_page(&path, "playlists", offset, limit, playlist_summary_from_json).await.map(|(page, _)| page)
+    });
+    page_result(action, result)
+}
+
+/// Returns a page of new album releases as JSON:
+/// {"items": [{uri, name, artist_name, album_art_url, release_date, total_tracks}], "total": n, "offset": n}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - offset: Index of the first album
+/// - limit: Maximum number of albums (1-50)
+#[no_mangle]
+pub extern "C" fn spotifly_get_new_releases(offset: u32, limit: u32) -> *mut c_char {
+    power::note_activity();
+    let result = RUNTIME.block_on(async {
+        fetch_page("/browse/new-releases", "albums", offset, limit, album_result).await.map(|(page, _)| page)
+    });
+    page_result("Get new releases", result)
+}
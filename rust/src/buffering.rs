@@ -0,0 +1,172 @@
+// Buffering and stream health.
+//
+// librespot has no buffering events of its own, so they are derived: a track counts as
+// buffering from its Loading event until it is ready (Playing, Paused, Stopped...), and
+// playback counts as stalled (an underrun) when the player stops handing audio to the
+// output while it is playing, which happens when the decoder runs out of downloaded data.
+// Either only becomes a BufferingStarted event {uri, reason, underruns} once it lasts longer
+// than a short grace period, so fast loads and track changes don't flash a spinner; it is
+// followed by BufferingEnded {uri, reason, duration_ms} once audio flows again.
+
+use crate::{events, to_c_string, IS_PLAYING, RUNTIME};
+use librespot_playback::config::AudioFormat;
+use librespot_playback::decoder::AudioPacket;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::ffi::c_char;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// How long loading or a stall lasts before it counts as buffering
+const GRACE_PERIOD: Duration = Duration::from_millis(750);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const REASON_LOADING: &str = "loading";
+const REASON_UNDERRUN: &str = "underrun";
+
+#[derive(Default)]
+struct StreamHealth {
+    uri: String,
+    // Track being loaded, since when
+    loading_since: Option<Instant>,
+    // Last time audio went to the output
+    last_write: Option<Instant>,
+    // Buffering reported to the host: reason and start
+    buffering: Option<(&'static str, Instant)>,
+    // Stalls of the current track
+    underruns: u32,
+    // Audio handed to the output for the current track
+    bytes: u64,
+}
+
+static HEALTH: Lazy<Mutex<StreamHealth>> = Lazy::new(|| Mutex::new(StreamHealth::default()));
+static MONITOR_STARTED: AtomicBool = AtomicBool::new(false);
+
+// Ends buffering, if it was reported, returning the BufferingEnded payload to send
+// (events are sent once HEALTH is unlocked, as the host may query it from the callback)
+fn end_buffering(health: &mut StreamHealth) -> Option<Value> {
+    let (reason, since) = health.buffering.take()?;
+    Some(json!({
+        "uri": health.uri,
+        "reason": reason,
+        "duration_ms": since.elapsed().as_millis() as u64,
+    }))
+}
+
+fn emit_ended(payload: Option<Value>) {
+    if let Some(payload) = payload {
+        events::emit(events::EVENT_BUFFERING_ENDED, payload);
+    }
+}
+
+/// Called when the player starts loading a track.
+pub(crate) fn on_loading(uri: &str) {
+    let ended = {
+        let mut health = HEALTH.lock().unwrap();
+        let ended = end_buffering(&mut health);
+        *health = StreamHealth {
+            uri: uri.to_string(),
+            loading_since: Some(Instant::now()),
+            ..StreamHealth::default()
+        };
+        ended
+    };
+    emit_ended(ended);
+}
+
+/// Called when the player is done loading (the track plays, pauses, stops or is unavailable).
+pub(crate) fn on_ready() {
+    let ended = {
+        let mut health = HEALTH.lock().unwrap();
+        health.loading_since = None;
+        health.last_write = Some(Instant::now());
+        if health.buffering.is_some_and(|(reason, _)| reason == REASON_LOADING) {
+            end_buffering(&mut health)
+        } else {
+            None
+        }
+    };
+    emit_ended(ended);
+}
+
+/// Called when playback stops or pauses, where no audio flowing is expected.
+pub(crate) fn on_idle() {
+    let ended = {
+        let mut health = HEALTH.lock().unwrap();
+        health.loading_since = None;
+        health.last_write = None;
+        end_buffering(&mut health)
+    };
+    emit_ended(ended);
+}
+
+/// Called by the output sink for each packet of audio it receives.
+pub(crate) fn on_write(packet: &AudioPacket, format: AudioFormat) {
+    let bytes = match packet {
+        AudioPacket::Samples(samples) => samples.len() * format.size(),
+        AudioPacket::Raw(bytes) => bytes.len(),
+    };
+    let ended = {
+        let mut health = HEALTH.lock().unwrap();
+        health.bytes += bytes as u64;
+        health.last_write = Some(Instant::now());
+        if health.buffering.is_some_and(|(reason, _)| reason == REASON_UNDERRUN) {
+            end_buffering(&mut health)
+        } else {
+            None
+        }
+    };
+    emit_ended(ended);
+}
+
+/// Starts checking for slow loads and stalls (once).
+pub(crate) fn start_monitor() {
+    if MONITOR_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    RUNTIME.spawn(async {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let started = {
+                let mut health = HEALTH.lock().unwrap();
+                if health.buffering.is_some() {
+                    continue;
+                }
+                let reason = if health.loading_since.is_some_and(|since| since.elapsed() > GRACE_PERIOD) {
+                    REASON_LOADING
+                } else if IS_PLAYING.load(Ordering::SeqCst)
+                    && health.last_write.is_some_and(|last| last.elapsed() > GRACE_PERIOD)
+                {
+                    health.underruns += 1;
+                    REASON_UNDERRUN
+                } else {
+                    continue;
+                };
+                health.buffering = Some((reason, Instant::now()));
+                json!({ "uri": health.uri, "reason": reason, "underruns": health.underruns })
+            };
+            events::emit(events::EVENT_BUFFERING_STARTED, started);
+        }
+    });
+}
+
+/// Returns the health of the current stream as JSON {uri, buffering, reason, underruns,
+/// bytes_buffered}: buffering is true while the track loads or playback is stalled for longer
+/// than a short grace period (reason is then "loading" or "underrun", otherwise null),
+/// underruns counts the stalls of the current track, and bytes_buffered is the audio the
+/// player has decoded and handed to the output for it, in the output format.
+/// Caller must free the string with spotifly_free_string().
+#[no_mangle]
+pub extern "C" fn spotifly_get_stream_health_json() -> *mut c_char {
+    let health = HEALTH.lock().unwrap();
+    let status = json!({
+        "uri": (!health.uri.is_empty()).then_some(&health.uri),
+        "buffering": health.buffering.is_some(),
+        "reason": health.buffering.map(|(reason, _)| reason),
+        "underruns": health.underruns,
+        "bytes_buffered": health.bytes,
+    });
+    to_c_string(&status.to_string())
+}
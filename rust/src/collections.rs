@@ -0,0 +1,221 @@
+// Album and playlist summaries for browse screens.
+//
+// Unlike playing a collection, these don't load metadata per track: everything comes
+// from the Web API's album/playlist object plus its track pages, which are only read for
+// the total duration. The pages are fetched concurrently.
+
+use crate::webapi::{self, first_image_url, str_field};
+use crate::error::Failure;
+use crate::{error, links, power, to_c_string, METADATA_CONCURRENCY, RUNTIME};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use librespot_core::session::Session;
+use serde::Serialize;
+use serde_json::Value;
+use std::ffi::c_char;
+use std::ptr;
+
+// Track page sizes the Web API allows
+const ALBUM_TRACKS_PAGE_SIZE: usize = 50;
+const PLAYLIST_ITEMS_PAGE_SIZE: usize = 100;
+// Only the durations are needed from playlist track pages
+const PLAYLIST_DURATION_FIELDS: &str = "items(track(duration_ms))";
+
+#[derive(Serialize)]
+struct ArtistRef {
+    name: String,
+    uri: String,
+}
+
+#[derive(Serialize)]
+struct AlbumInfo {
+    uri: String,
+    name: String,
+    artist_name: String,
+    artists: Vec<ArtistRef>,
+    image_url: String,
+    release_date: Option<String>,
+    label: Option<String>,
+    total_tracks: u64,
+    duration_ms: u64,
+}
+
+#[derive(Serialize)]
+struct PlaylistInfo {
+    uri: String,
+    name: String,
+    description: String,
+    owner_name: String,
+    owner_id: String,
+    image_url: String,
+    total_tracks: u64,
+    duration_ms: u64,
+    collaborative: bool,
+    public: Option<bool>,
+}
+
+// Sums the durations in a page of items; `pointer` locates the duration in each item
+fn page_duration_ms(page: &Value, pointer: &str) -> u64 {
+    page.get("items").and_then(Value::as_array).into_iter().flatten()
+        .filter_map(|item| item.pointer(pointer).and_then(Value::as_u64))
+        .sum()
+}
+
+// Sums the durations on the track pages from `offset` on; `path` already has a query string
+async fn remaining_duration_ms(
+    session: &Session,
+    path: &str,
+    page_size: usize,
+    offset: usize,
+    total: usize,
+    pointer: &str,
+) -> Result<u64, Failure> {
+    stream::iter((offset..total).step_by(page_size))
+        .map(|offset| {
+            let path = format!("{}&limit={}&offset={}", path, page_size, offset);
+            async move { webapi::get(session, &path).await }
+        })
+        .buffered(METADATA_CONCURRENCY)
+        .try_fold(0, |sum, page| async move { Ok(sum + page_duration_ms(&page, pointer)) })
+        .await
+}
+
+fn string_field(value: &Value, key: &str) -> Option<String> {
+    value.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+async fn fetch_album_info(session: &Session, album_id: &str) -> Result<AlbumInfo, Failure> {
+    let album = webapi::get(session, &format!("/albums/{}", album_id)).await?;
+    let total_tracks = album.get("total_tracks").and_then(Value::as_u64).unwrap_or(0);
+
+    // The album object embeds the first page of tracks
+    let mut duration_ms = album.get("tracks").map_or(0, |page| page_duration_ms(page, "/duration_ms"));
+    duration_ms += remaining_duration_ms(
+        session,
+        &format!("/albums/{}/tracks?", album_id),
+        ALBUM_TRACKS_PAGE_SIZE,
+        ALBUM_TRACKS_PAGE_SIZE,
+        total_tracks as usize,
+        "/duration_ms",
+    ).await?;
+
+    let artists: Vec<ArtistRef> = album.get("artists").and_then(Value::as_array).into_iter().flatten()
+        .map(|artist| ArtistRef {
+            name: str_field(artist, "name"),
+            uri: str_field(artist, "uri"),
+        })
+        .collect();
+
+    Ok(AlbumInfo {
+        uri: str_field(&album, "uri"),
+        name: str_field(&album, "name"),
+        artist_name: artists.iter().map(|artist| artist.name.as_str()).collect::<Vec<_>>().join(", "),
+        artists,
+        image_url: first_image_url(&album),
+        release_date: string_field(&album, "release_date"),
+        label: string_field(&album, "label"),
+        total_tracks,
+        duration_ms,
+    })
+}
+
+async fn fetch_playlist_info(session: &Session, playlist_id: &str) -> Result<PlaylistInfo, Failure> {
+    let fields = format!(
+        "uri,name,description,owner(id,display_name),images,collaborative,public,tracks(total,{})",
+        PLAYLIST_DURATION_FIELDS,
+    );
+    let playlist = webapi::get(
+        session,
+        &format!("/playlists/{}?{}", playlist_id, webapi::query_string([("fields", fields)])),
+    ).await?;
+    let total_tracks = playlist.pointer("/tracks/total").and_then(Value::as_u64).unwrap_or(0);
+
+    // The playlist object embeds the first page of items
+    let mut duration_ms = playlist.get("tracks").map_or(0, |page| page_duration_ms(page, "/track/duration_ms"));
+    duration_ms += remaining_duration_ms(
+        session,
+        &format!(
+            "/playlists/{}/tracks?{}",
+            playlist_id,
+            webapi::query_string([("fields", PLAYLIST_DURATION_FIELDS.to_string())]),
+        ),
+        PLAYLIST_ITEMS_PAGE_SIZE,
+        PLAYLIST_ITEMS_PAGE_SIZE,
+        total_tracks as usize,
+        "/track/duration_ms",
+    ).await?;
+
+    Ok(PlaylistInfo {
+        uri: str_field(&playlist, "uri"),
+        name: str_field(&playlist, "name"),
+        description: str_field(&playlist, "description"),
+        owner_name: playlist.pointer("/owner/display_name").and_then(Value::as_str).unwrap_or_default().to_string(),
+        owner_id: playlist.pointer("/owner/id").and_then(Value::as_str).unwrap_or_default().to_string(),
+        image_url: first_image_url(&playlist),
+        total_tracks,
+        duration_ms,
+        collaborative: playlist.get("collaborative").and_then(Value::as_bool).unwrap_or(false),
+        public: playlist.get("public").and_then(Value::as_bool),
+    })
+}
+
+fn info_json<T: Serialize>(result: Result<T, Failure>, action: &str) -> *mut c_char {
+    match result {
+        Ok(info) => match serde_json::to_string(&info) {
+            Ok(json_string) => to_c_string(&json_string),
+            Err(_) => ptr::null_mut(),
+        },
+        Err(e) => {
+            error::report(action, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns an album's details as JSON, without loading its tracks' metadata:
+/// {uri, name, artist_name, artists: [{name, uri}], image_url, release_date, label,
+/// total_tracks, duration_ms}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - uri: Spotify album URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_get_album_info_json(uri: *const c_char) -> *mut c_char {
+    power::note_activity();
+    let action = "Get album info";
+    let album_id = match links::id_arg(uri, "album", action) {
+        Ok(id) => id,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        fetch_album_info(&session, &album_id).await
+    });
+    info_json(result, action)
+}
+
+/// Returns a playlist's details as JSON, without loading its tracks' metadata:
+/// {uri, name, description, owner_name, owner_id, image_url, total_tracks, duration_ms,
+/// collaborative, public}
+/// total_tracks counts episodes and unavailable tracks too; duration_ms covers the playable ones.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - uri: Spotify playlist URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_get_playlist_info_json(uri: *const c_char) -> *mut c_char {
+    power::note_activity();
+    let action = "Get playlist info";
+    let playlist_id = match links::id_arg(uri, "playlist", action) {
+        Ok(id) => id,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        fetch_playlist_info(&session, &playlist_id).await
+    });
+    info_json(result, action)
+}
@@ -0,0 +1,274 @@
+// Spotify Connect target with zeroconf discovery.
+//
+// Every session is connected through a Spirc, which registers the player as a Connect
+// device of the account over the session's dealer (websocket) connection and applies the
+// commands other Spotify clients send through it (play, pause, skip, seek, volume) to the
+// player. A session re-established after a dropped connection or an idle disconnect gets a
+// new Spirc, so remote commands keep working.
+//
+// Once enabled, the player is also advertised on the local network via mDNS, so official
+// Spotify clients list it in their device picker. When a client hands over playback,
+// discovery yields credentials for that user; the session is re-established with them and
+// a new Spirc drives the player. Tracks started remotely are mirrored into the local queue,
+// and the queue stops auto-advancing (Spirc does that) until the host plays something itself.
+
+use crate::artwork::ArtworkUrls;
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    build_cache, build_session_config, date_string, queue_controller, secure_storage, set_connection_state, QueueItem,
+    CONNECTION_CONNECTED, CURRENT_INDEX, ITEM_TYPE_EPISODE, ITEM_TYPE_TRACK, MIXER, PLAYER, PLAY_STATE_UNPLAYED, QUEUE,
+    RUNTIME, SESSION, SPIRC,
+};
+use futures_util::StreamExt;
+use librespot_connect::{ConnectConfig, Spirc};
+use librespot_core::authentication::Credentials;
+use librespot_core::config::DeviceType;
+use librespot_core::session::Session;
+use librespot_discovery::Discovery;
+use librespot_metadata::audio::{AudioItem, UniqueFields};
+use librespot_playback::mixer::Mixer;
+use once_cell::sync::Lazy;
+use std::ffi::{c_char, CStr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+struct ConnectSettings {
+    name: String,
+    device_type: DeviceType,
+}
+
+static SETTINGS: Lazy<Mutex<ConnectSettings>> = Lazy::new(|| {
+    Mutex::new(ConnectSettings {
+        name: "Spotifly".to_string(),
+        device_type: DeviceType::Computer,
+    })
+});
+// Task yielding credentials from discovery; aborting it unregisters the device
+static DISCOVERY_TASK: Lazy<Mutex<Option<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(None));
+// Set while a Connect client, not the local queue, decides what plays
+static REMOTE_CONTROLLED: AtomicBool = AtomicBool::new(false);
+
+/// Connect device configuration for Spirc, using the name and type set by the host.
+pub(crate) fn connect_config() -> ConnectConfig {
+    let settings = SETTINGS.lock().unwrap();
+    ConnectConfig {
+        name: settings.name.clone(),
+        device_type: settings.device_type,
+        initial_volume: 65535 / 2, // 50% volume
+        ..Default::default()
+    }
+}
+
+/// Stops advertising the device.
+pub(crate) fn stop() {
+    if let Some(task) = DISCOVERY_TASK.lock().unwrap().take() {
+        task.abort();
+    }
+}
+
+/// True while playback is driven by a Connect client.
+pub(crate) fn is_remote_controlled() -> bool {
+    REMOTE_CONTROLLED.load(Ordering::SeqCst)
+}
+
+/// Called when the player starts loading a track. Local loads always point
+/// CURRENT_INDEX at the track first, so anything else was requested remotely.
+pub(crate) fn on_loading(uri: &str) {
+    let queue_guard = QUEUE.lock().unwrap();
+    let is_local = queue_guard.get(CURRENT_INDEX.load(Ordering::SeqCst))
+        .is_some_and(|item| item.uri == uri);
+    REMOTE_CONTROLLED.store(!is_local, Ordering::SeqCst);
+}
+
+/// Mirrors a remotely started track into the queue, so the queue getters
+/// describe what is playing.
+pub(crate) fn on_track_changed(audio_item: &AudioItem) {
+    if !is_remote_controlled() {
+        return;
+    }
+
+    let item = queue_item_from_audio_item(audio_item);
+    queue_controller::post_edit(move |queue, current_idx| {
+        if let Some(index) = queue.iter().position(|queued| queued.uri == item.uri) {
+            *current_idx = index;
+            return;
+        }
+        *queue = vec![item];
+        *current_idx = 0;
+    });
+}
+
+fn queue_item_from_audio_item(audio_item: &AudioItem) -> QueueItem {
+    let (artist_name, artist_id) = match &audio_item.unique_fields {
+        UniqueFields::Track { artists, .. } => (
+            artists.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(", "),
+            artists.first().and_then(|a| a.id.to_id().ok()),
+        ),
+        UniqueFields::Episode { show_name, .. } => (show_name.clone(), None),
+        UniqueFields::Local { artists, .. } => (artists.clone().unwrap_or_default(), None),
+    };
+    let item_type = match audio_item.unique_fields {
+        UniqueFields::Episode { .. } => ITEM_TYPE_EPISODE,
+        _ => ITEM_TYPE_TRACK,
+    };
+    let album_art_urls = ArtworkUrls::from_covers(&audio_item.covers);
+    let (album_name, track_number, disc_number, release_date) = match &audio_item.unique_fields {
+        UniqueFields::Track { album, number, disc_number, .. } => (Some(album.clone()), *number, *disc_number, None),
+        UniqueFields::Local { album, number, disc_number, .. } => {
+            (album.clone(), number.unwrap_or(0), disc_number.unwrap_or(0), None)
+        }
+        UniqueFields::Episode { publish_time, .. } => (None, 0, 0, date_string(publish_time)),
+    };
+
+    QueueItem {
+        uri: audio_item.uri.clone(),
+        item_type,
+        track_name: audio_item.name.clone(),
+        artist_name,
+        album_art_url: album_art_urls.large.clone(),
+        album_art_urls,
+        duration_ms: audio_item.duration_ms,
+        album_id: None,
+        artist_id,
+        external_url: None,
+        play_state: PLAY_STATE_UNPLAYED,
+        last_position_ms: 0,
+        album_name: album_name.filter(|name| !name.is_empty()),
+        track_number,
+        disc_number,
+        explicit: audio_item.is_explicit,
+        release_date,
+    }
+}
+
+/// Connects a new, unconnected `session` with `credentials` through a Spirc that drives
+/// the player and takes remote commands, replacing any previous Spirc. If Spirc can't be
+/// set up, the session is connected directly so basic playback still works, but the
+/// device won't take remote commands.
+pub(crate) async fn connect_session(session: &Session, credentials: Credentials) -> Result<(), Failure> {
+    let player = PLAYER.lock().unwrap().clone()
+        .ok_or_else(|| Failure::new(SpotiflyError::NotInitialized, "Player not initialized"))?;
+    let mixer = MIXER.lock().unwrap().clone()
+        .ok_or_else(|| Failure::new(SpotiflyError::NotInitialized, "Mixer not initialized"))?;
+
+    // The old Spirc is bound to the old session
+    if let Some(spirc) = SPIRC.lock().unwrap().take() {
+        let _ = spirc.shutdown();
+    }
+    player.set_session(session.clone());
+
+    // Spirc::new() connects the session
+    match Spirc::new(connect_config(), session.clone(), credentials.clone(), player, mixer as Arc<dyn Mixer>).await {
+        Ok((spirc, spirc_task)) => {
+            RUNTIME.spawn(spirc_task);
+            *SPIRC.lock().unwrap() = Some(Arc::new(spirc));
+            log::info!("Spirc initialized - Spotify Connect available");
+        }
+        Err(e) => {
+            log::warn!("Spirc init failed: {:?}", e);
+            log::warn!("Falling back to basic playback (Connect won't be available)");
+            session.connect(credentials, true).await
+                .map_err(|e| error::login_failure("Session connect error", &e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Re-establishes the session with `credentials` and lets a new Spirc take control of
+/// the player (when a Connect client hands over playback, or on an account switch).
+pub(crate) async fn take_over(credentials: Credentials) -> Result<(), Failure> {
+    let session = Session::new(build_session_config(), Some(build_cache()?));
+    connect_session(&session, credentials).await?;
+
+    let old_session = SESSION.lock().unwrap().replace(session.clone());
+    if let Some(old_session) = old_session {
+        old_session.shutdown();
+    }
+
+    set_connection_state(CONNECTION_CONNECTED);
+    secure_storage::on_session_connected(&session);
+    Ok(())
+}
+
+/// Advertises the player on the local network as a Spotify Connect device, so
+/// Spotify apps on phones and other computers can hand playback to it.
+/// Remote commands (play, pause, skip, seek, volume) then control this player,
+/// and remotely started tracks are reflected in the queue.
+/// The player must be initialized first. Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - device_name: Name shown in the device picker, NULL = "Spotifly"
+/// - device_type: Icon in the device picker ("computer", "speaker", "tablet",
+///   "smartphone", "tv", "avr", "stb", "audiodongle"...), NULL = "computer"
+#[no_mangle]
+pub extern "C" fn spotifly_enable_connect(device_name: *const c_char, device_type: *const c_char) -> i32 {
+    let read = |s: *const c_char| -> Result<Option<String>, ()> {
+        if s.is_null() {
+            return Ok(None);
+        }
+        unsafe { CStr::from_ptr(s).to_str().map(|s| Some(s.to_string())).map_err(|_| ()) }
+    };
+
+    let (name, type_str) = match (read(device_name), read(device_type)) {
+        (Ok(n), Ok(t)) => (n.unwrap_or_else(|| "Spotifly".to_string()), t),
+        _ => {
+            return error::fail(SpotiflyError::InvalidArgument, "Enable connect error: invalid string");
+        }
+    };
+    let device_type = match type_str {
+        Some(t) => match DeviceType::from_str(&t) {
+            Ok(device_type) => device_type,
+            Err(_) => return error::fail(
+                SpotiflyError::InvalidArgument,
+                format!("Enable connect error: unknown device type {}", t),
+            ),
+        },
+        None => DeviceType::Computer,
+    };
+
+    if PLAYER.lock().unwrap().is_none() {
+        return error::fail(SpotiflyError::NotInitialized, "Enable connect error: player not initialized");
+    }
+
+    *SETTINGS.lock().unwrap() = ConnectSettings {
+        name: name.clone(),
+        device_type,
+    };
+    stop();
+
+    let session_config = build_session_config();
+    let launched = RUNTIME.block_on(async {
+        Discovery::builder(session_config.device_id, session_config.client_id)
+            .name(name.clone())
+            .device_type(device_type)
+            .launch()
+    });
+    let mut discovery = match launched {
+        Ok(discovery) => discovery,
+        Err(e) => {
+            return error::fail(SpotiflyError::Unknown, format!("Enable connect error: {}", e));
+        }
+    };
+
+    let task = RUNTIME.spawn(async move {
+        while let Some(credentials) = discovery.next().await {
+            log::info!("Connect client took over playback");
+            if let Err(e) = take_over(credentials).await {
+                log::error!("Connect takeover error: {}", e);
+            }
+        }
+    });
+    *DISCOVERY_TASK.lock().unwrap() = Some(task);
+
+    log::info!("Advertising Connect device \"{}\"", name);
+    0
+}
+
+/// Stops advertising the player on the local network.
+/// A Connect client that is already controlling playback stays connected.
+#[no_mangle]
+pub extern "C" fn spotifly_disable_connect() {
+    stop();
+}
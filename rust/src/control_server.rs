@@ -0,0 +1,204 @@
+// Local HTTP control server.
+//
+// An optional HTTP/JSON endpoint on the loopback interface that lets scripts, launchers
+// and stream decks drive playback without going through the C API:
+//
+//   GET  /now-playing   the spotifly_get_now_playing_json() document (null if nothing queued)
+//   GET  /queue         {items, total, current_index}
+//   POST /play          {"uri": "..."} plays a track, album, playlist...; no body resumes
+//   POST /pause, /next, /previous
+//   POST /queue         {"uri": "..."} adds a track or episode to the end of the queue
+//   GET  /events        WebSocket stream of player events (see event_stream.rs)
+//
+// Commands answer {"ok": true} or {"error": message, "code": negative error code}.
+// Only local clients can connect, and commands carrying an Origin header are refused so
+// that web pages open in a browser can't send them.
+
+use crate::error::{self, SpotiflyError};
+use crate::{
+    event_stream, now_playing, spotifly_add_to_queue, spotifly_next, spotifly_pause, spotifly_play_track,
+    spotifly_previous, spotifly_resume, CURRENT_INDEX, QUEUE,
+};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::ffi::{c_char, CString};
+use std::io::Read;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// Request bodies are small JSON objects
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+static SERVER: Lazy<Mutex<Option<Arc<Server>>>> = Lazy::new(|| Mutex::new(None));
+
+struct Reply {
+    status: u16,
+    body: Value,
+}
+
+impl Reply {
+    fn ok(body: Value) -> Self {
+        Reply { status: 200, body }
+    }
+
+    fn error(status: u16, code: SpotiflyError, message: &str) -> Self {
+        Reply { status, body: json!({ "error": message, "code": code as i32 }) }
+    }
+}
+
+// Turns the result of an FFI call into a reply
+fn command_reply(result: i32) -> Reply {
+    if result == 0 {
+        return Reply::ok(json!({ "ok": true }));
+    }
+    let status = match result {
+        r if r == SpotiflyError::InvalidArgument as i32 || r == SpotiflyError::InvalidUri as i32 => 400,
+        r if r == SpotiflyError::NotFound as i32 => 404,
+        r if r == SpotiflyError::NotInitialized as i32 => 409,
+        _ => 500,
+    };
+    let message = error::last_message().unwrap_or_else(|| "command failed".to_string());
+    Reply { status, body: json!({ "error": message, "code": result }) }
+}
+
+// The "uri" field of a JSON request body, if there is one
+fn uri_field(request: &mut Request) -> Result<Option<CString>, Reply> {
+    let mut body = String::new();
+    request.as_reader().take(MAX_BODY_BYTES).read_to_string(&mut body)
+        .map_err(|e| Reply::error(400, SpotiflyError::InvalidArgument, &format!("unreadable body: {}", e)))?;
+    if body.trim().is_empty() {
+        return Ok(None);
+    }
+    let value: Value = serde_json::from_str(&body)
+        .map_err(|e| Reply::error(400, SpotiflyError::InvalidArgument, &format!("invalid JSON: {}", e)))?;
+    match value.get("uri") {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(uri)) => CString::new(uri.as_str())
+            .map(Some)
+            .map_err(|_| Reply::error(400, SpotiflyError::InvalidArgument, "invalid uri")),
+        Some(_) => Err(Reply::error(400, SpotiflyError::InvalidArgument, "uri must be a string")),
+    }
+}
+
+fn queue_json() -> Value {
+    let queue_guard = QUEUE.lock().unwrap();
+    json!({
+        "items": &*queue_guard,
+        "total": queue_guard.len(),
+        "current_index": CURRENT_INDEX.load(Ordering::SeqCst),
+    })
+}
+
+fn route(request: &mut Request) -> Reply {
+    let path = request.url().split('?').next().unwrap_or_default().trim_end_matches('/').to_string();
+    let command = |run: extern "C" fn() -> i32| command_reply(run());
+    let with_uri = |uri: &CString, run: extern "C" fn(*const c_char) -> i32| command_reply(run(uri.as_ptr()));
+
+    match (request.method(), path.as_str()) {
+        (Method::Get, "/now-playing") => {
+            let now_playing = now_playing::json().and_then(|json| serde_json::from_str(&json).ok());
+            Reply::ok(now_playing.unwrap_or(Value::Null))
+        }
+        (Method::Get, "/queue") => Reply::ok(queue_json()),
+        (Method::Post, "/play") => match uri_field(request) {
+            Ok(Some(uri)) => with_uri(&uri, spotifly_play_track),
+            Ok(None) => command(spotifly_resume),
+            Err(reply) => reply,
+        },
+        (Method::Post, "/pause") => command(spotifly_pause),
+        (Method::Post, "/next") => command(spotifly_next),
+        (Method::Post, "/previous") => command(spotifly_previous),
+        (Method::Post, "/queue") => match uri_field(request) {
+            Ok(Some(uri)) => with_uri(&uri, spotifly_add_to_queue),
+            Ok(None) => Reply::error(400, SpotiflyError::InvalidArgument, "uri is required"),
+            Err(reply) => reply,
+        },
+        (_, event_stream::PATH) => Reply::error(400, SpotiflyError::InvalidArgument, "WebSocket upgrade required"),
+        (_, "/now-playing" | "/queue" | "/play" | "/pause" | "/next" | "/previous") => {
+            Reply::error(405, SpotiflyError::InvalidArgument, "method not allowed")
+        }
+        _ => Reply::error(404, SpotiflyError::NotFound, "no such route"),
+    }
+}
+
+fn handle(mut request: Request) {
+    let path = request.url().split('?').next().unwrap_or_default();
+    if path == event_stream::PATH && event_stream::is_upgrade(&request) {
+        event_stream::serve(request);
+        return;
+    }
+
+    let reply = if request.headers().iter().any(|header| header.field.equiv("Origin")) {
+        Reply::error(403, SpotiflyError::InvalidArgument, "browser requests are not accepted")
+    } else {
+        route(&mut request)
+    };
+
+    let content_type = Header::from_bytes("Content-Type", "application/json").expect("static header");
+    let response = Response::from_string(reply.body.to_string())
+        .with_status_code(reply.status)
+        .with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        log::warn!("Control server response error: {}", e);
+    }
+}
+
+/// Starts the HTTP control server on 127.0.0.1 (replacing one already running).
+/// Routes: GET /now-playing, GET /queue, POST /play ({"uri"} to play content, no body to
+/// resume), POST /pause, POST /next, POST /previous, POST /queue ({"uri"} to enqueue).
+/// Only local clients can connect; commands from web pages (with an Origin header) are refused.
+///
+/// A WebSocket on /events streams every player event as {"event": name, "code", "data": payload},
+/// a Position message {position_ms, duration_ms} every second while playing, and a NowPlaying
+/// message when it opens. Browsers may connect from local pages, and from local files once
+/// allowed with spotifly_set_event_stream_allow_local_files().
+/// Returns 0 on success, a negative error code on error (e.g. the port is in use).
+///
+/// # Parameters
+/// - port: Port to listen on
+#[no_mangle]
+pub extern "C" fn spotifly_start_control_server(port: u16) -> i32 {
+    if port == 0 {
+        return error::fail(SpotiflyError::InvalidArgument, "Start control server error: port must not be 0");
+    }
+    spotifly_stop_control_server();
+
+    let server = match Server::http(("127.0.0.1", port)) {
+        Ok(server) => Arc::new(server),
+        Err(e) => return error::fail(SpotiflyError::Unknown, format!("Start control server error: {}", e)),
+    };
+
+    let listener = Arc::clone(&server);
+    let spawned = thread::Builder::new()
+        .name("spotifly-control".to_string())
+        .spawn(move || {
+            // recv() fails once the server is unblocked by spotifly_stop_control_server()
+            while let Ok(request) = listener.recv() {
+                // Plays can take seconds to load; don't hold up other requests meanwhile
+                if let Err(e) = thread::Builder::new()
+                    .name("spotifly-control-request".to_string())
+                    .spawn(move || handle(request))
+                {
+                    log::warn!("Control server error: {}", e);
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        return error::fail(SpotiflyError::Unknown, format!("Start control server error: {}", e));
+    }
+
+    *SERVER.lock().unwrap() = Some(server);
+    log::info!("Control server listening on 127.0.0.1:{}", port);
+    0
+}
+
+/// Stops the HTTP control server, if running.
+#[no_mangle]
+pub extern "C" fn spotifly_stop_control_server() {
+    if let Some(server) = SERVER.lock().unwrap().take() {
+        server.unblock();
+        log::info!("Control server stopped");
+    }
+}
@@ -0,0 +1,434 @@
+// Crossfade between queue tracks.
+//
+// librespot's player renders a single stream, so the overlap is assembled around it.
+// Shortly before the crossfade point, a second player on the same session decodes the tail
+// of the current track into memory, at full volume and as fast as it can. The main player's
+// sink finds the audio it is writing in that tail and follows along. At the crossfade point
+// the queue moves on early (the track counts as played to its end), and the sink mixes the
+// rest of the tail into the next track: the tail fades out while the next track fades in.
+// Volume and normalisation are applied before the sink, so the tail is scaled to match
+// what was playing.
+//
+// If the tail isn't ready in time or the sink can't find its place in it (the tail failed
+// to load, or the output was shaped differently, e.g. by the dynamic normaliser limiting
+// near full scale), the track plays to its end and the next one follows without a crossfade.
+
+use crate::{
+    connect, parse_spotify_uri, player_config, queue_controller, spotifly_get_position_ms, trim, PLAYER_BITRATE,
+    RUNTIME, SESSION,
+};
+use librespot_playback::audio_backend::{Sink, SinkResult};
+use librespot_playback::config::PlayerConfig;
+use librespot_playback::convert::Converter;
+use librespot_playback::decoder::AudioPacket;
+use librespot_playback::mixer::softmixer::SoftMixer;
+use librespot_playback::mixer::{Mixer, MixerConfig};
+use librespot_playback::player::{Player, PlayerEvent};
+use librespot_playback::{NUM_CHANNELS, SAMPLE_RATE};
+use once_cell::sync::Lazy;
+use std::f64::consts::FRAC_PI_2;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Longest crossfade the host can configure
+const MAX_CROSSFADE_MS: u32 = 12_000;
+// Capturing starts this long before the crossfade point
+const CAPTURE_AHEAD_MS: u32 = 15_000;
+// The tail starts this long before the crossfade point, so the sink can find its place in it
+const TAIL_LEAD_MS: u32 = 5_000;
+// Decoding a tail takes longer than this only when something is wrong
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+// How far either side of where the player's position puts it the sink looks for its output
+const SEARCH_WINDOW_MS: u32 = 1_000;
+// Output at most this far off the scaled tail (in energy, relative to the output's) is the tail
+const MATCH_TOLERANCE: f64 = 1e-6;
+// Samples compared at each offset before checking a whole packet, to rule most offsets out quickly
+const QUICK_MATCH_LEN: usize = 32;
+// Energy below which a stretch of audio counts as silence
+const SILENCE_ENERGY: f64 = 1e-9;
+const CHANNELS: usize = NUM_CHANNELS as usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    // The capture player is decoding the tail
+    Capturing,
+    // The tail couldn't be captured; the track plays out without a crossfade
+    Unavailable,
+    // Looking for the sink's output in the tail
+    Searching,
+    // The sink's output is the tail, up to `offset`
+    Following,
+    // The queue is moving on; the first packet that isn't the tail starts the mix
+    Armed,
+    // Mixing the tail from `start` on into the next track
+    Mixing { start: usize },
+}
+
+struct Tail {
+    id: u64,
+    uri: String,
+    // Position in the track of the tail's first sample
+    start_ms: u32,
+    samples: Vec<f32>,
+    stage: Stage,
+    // Next sample of the tail, once the output has been found in it
+    offset: usize,
+    // Output level relative to the tail: the volume and normalisation of the main player
+    scale: f64,
+    // The queue was asked to move on
+    requested: bool,
+}
+
+static CROSSFADE_MS: AtomicU32 = AtomicU32::new(0);
+static TAIL: Lazy<Mutex<Option<Tail>>> = Lazy::new(|| Mutex::new(None));
+static NEXT_CAPTURE_ID: AtomicU64 = AtomicU64::new(1);
+
+// Number of interleaved samples in `ms` of audio
+fn samples_for(ms: u32) -> usize {
+    (u64::from(ms) * u64::from(SAMPLE_RATE) / 1000) as usize * CHANNELS
+}
+
+impl Tail {
+    fn new(id: u64, uri: String, start_ms: u32) -> Self {
+        Tail {
+            id,
+            uri,
+            start_ms,
+            samples: Vec::new(),
+            stage: Stage::Capturing,
+            offset: 0,
+            scale: 1.0,
+            requested: false,
+        }
+    }
+
+    fn is_mixing(&self) -> bool {
+        matches!(self.stage, Stage::Armed | Stage::Mixing { .. })
+    }
+
+    // Handles a packet of the main player's output. `expected` is where in the tail the
+    // player's position puts it. Returns true once the tail is used up.
+    fn process(&mut self, out: &mut [f64], expected: usize) -> bool {
+        match self.stage {
+            Stage::Capturing | Stage::Unavailable => false,
+            Stage::Searching => {
+                if let Some((offset, scale)) = self.find(out, expected) {
+                    self.scale = scale;
+                    self.offset = offset + out.len();
+                    self.stage = Stage::Following;
+                }
+                false
+            }
+            Stage::Following | Stage::Armed => match self.scale_at(out, self.offset, true) {
+                Some(scale) => {
+                    self.scale = scale;
+                    self.offset += out.len();
+                    // Played to the end of the tail without moving on
+                    self.offset >= self.samples.len()
+                }
+                None if self.stage == Stage::Armed => {
+                    self.stage = Stage::Mixing { start: self.offset };
+                    self.mix(out)
+                }
+                None => {
+                    // Playback jumped (e.g. a seek)
+                    self.stage = Stage::Searching;
+                    false
+                }
+            },
+            Stage::Mixing { .. } => self.mix(out),
+        }
+    }
+
+    // Where `out` is in the tail, looking around `expected`, and the scale it plays at
+    fn find(&self, out: &[f64], expected: usize) -> Option<(usize, f64)> {
+        let window = samples_for(SEARCH_WINDOW_MS);
+        let first = expected.saturating_sub(window);
+        let last = (expected + window).min(self.samples.len().saturating_sub(out.len()));
+        let quick = &out[..out.len().min(QUICK_MATCH_LEN)];
+        (first..=last)
+            .step_by(CHANNELS)
+            .filter(|&offset| self.scale_at(quick, offset, false).is_some())
+            .find_map(|offset| Some((offset, self.scale_at(out, offset, false)?)))
+    }
+
+    // The scale at which `out` is the tail from `offset` on, if it is. Silence is only
+    // taken for silence (at the last scale) with `allow_silence`, as it matches anywhere.
+    fn scale_at(&self, out: &[f64], offset: usize, allow_silence: bool) -> Option<f64> {
+        let tail = self.samples.get(offset..offset + out.len())?;
+        let (mut dot, mut tail_energy, mut out_energy) = (0.0, 0.0, 0.0);
+        for (&out, &tail) in out.iter().zip(tail) {
+            let tail = f64::from(tail);
+            dot += out * tail;
+            tail_energy += tail * tail;
+            out_energy += out * out;
+        }
+        if tail_energy < SILENCE_ENERGY {
+            return (allow_silence && out_energy < SILENCE_ENERGY).then_some(self.scale);
+        }
+        // Least-squares scale, and what's left of the output after taking the scaled tail out
+        let scale = dot / tail_energy;
+        let error = out_energy - scale * dot;
+        (scale > 0.0 && error <= MATCH_TOLERANCE * out_energy).then_some(scale)
+    }
+
+    // Mixes the tail into `out`, the next track. Returns true once the tail is used up.
+    fn mix(&mut self, out: &mut [f64]) -> bool {
+        let Stage::Mixing { start } = self.stage else {
+            return false;
+        };
+        let length = self.samples.len().saturating_sub(start).max(1) as f64;
+        for (index, sample) in (self.offset..).zip(out.iter_mut()) {
+            let Some(&tail) = self.samples.get(index) else {
+                break;
+            };
+            // Equal-power curves, stepped per frame so both channels get the same gains
+            let progress = ((index - start) / CHANNELS * CHANNELS) as f64 / length;
+            let (fade_in, fade_out) = (progress * FRAC_PI_2).sin_cos();
+            *sample = *sample * fade_in + f64::from(tail) * self.scale * fade_out;
+        }
+        self.offset += out.len();
+        self.offset >= self.samples.len()
+    }
+}
+
+// Collects a player's output
+struct MemorySink(Arc<Mutex<Vec<f32>>>);
+
+impl Sink for MemorySink {
+    fn write(&mut self, packet: AudioPacket, _: &mut Converter) -> SinkResult<()> {
+        if let AudioPacket::Samples(samples) = packet {
+            self.0.lock().unwrap().extend(samples.iter().map(|&sample| sample as f32));
+        }
+        Ok(())
+    }
+}
+
+// Plays `uri` from `start_ms` to its end through a player that writes into memory
+async fn decode(uri: &str, start_ms: u32) -> Result<Vec<f32>, String> {
+    let session = SESSION.lock().unwrap().clone().ok_or("session not initialized")?;
+    let track = parse_spotify_uri(uri).map_err(|e| e.message)?;
+    let mixer = SoftMixer::open(MixerConfig::default()).map_err(|e| format!("Mixer error: {}", e))?;
+    // Full volume; the sink scales the tail to what the main player plays
+    mixer.set_volume(u16::MAX);
+
+    let samples = Arc::new(Mutex::new(Vec::new()));
+    let config = PlayerConfig {
+        position_update_interval: None,
+        ..player_config(PLAYER_BITRATE.load(Ordering::SeqCst))
+    };
+    let sink_samples = Arc::clone(&samples);
+    let player = Player::new(config, session, mixer.get_soft_volume(), move || Box::new(MemorySink(sink_samples)));
+    let mut event_channel = player.get_player_event_channel();
+    player.load(track, true, start_ms);
+
+    let finished = tokio::time::timeout(CAPTURE_TIMEOUT, async {
+        while let Some(event) = event_channel.recv().await {
+            match event {
+                PlayerEvent::EndOfTrack { .. } => return Ok(()),
+                PlayerEvent::Unavailable { .. } => return Err("track unavailable".to_string()),
+                _ => {}
+            }
+        }
+        Err("capture player stopped".to_string())
+    })
+    .await;
+    // Dropping the player joins its thread
+    tokio::task::spawn_blocking(move || drop(player));
+    finished.map_err(|_| "timed out".to_string())??;
+
+    let samples = std::mem::take(&mut *samples.lock().unwrap());
+    Ok(samples)
+}
+
+// Decodes the tail of `uri` between `start_ms` and `end_ms` and hands it to the sink
+async fn capture(id: u64, uri: String, start_ms: u32, end_ms: u32) {
+    let result = decode(&uri, start_ms).await;
+
+    let mut tail_guard = TAIL.lock().unwrap();
+    let Some(tail) = tail_guard.as_mut().filter(|tail| tail.id == id) else {
+        return;
+    };
+    match result {
+        Ok(mut samples) => {
+            samples.truncate(samples_for(end_ms - start_ms));
+            tail.samples = samples;
+            tail.stage = Stage::Searching;
+        }
+        Err(e) => {
+            log::warn!("No crossfade from {}: {}", uri, e);
+            tail.stage = Stage::Unavailable;
+        }
+    }
+}
+
+/// Called on position updates of the main player. Captures the tail of `uri` ahead of its
+/// crossfade point, and asks the queue controller to move on once the point is reached.
+pub(crate) fn on_position(uri: &str, position_ms: u32, duration_ms: u32, player: &Arc<Player>) {
+    let crossfade_ms = CROSSFADE_MS.load(Ordering::SeqCst);
+    // A trimmed track crossfades into the next one from its trimmed end
+    let end_ms = trim::end_ms(uri).map_or(duration_ms, |end_ms| end_ms.min(duration_ms));
+    // Tracks too short to fade in and out again play without a crossfade
+    if crossfade_ms == 0 || end_ms <= 2 * crossfade_ms + CAPTURE_AHEAD_MS || connect::is_remote_controlled() {
+        return;
+    }
+    let crossfade_at = end_ms - crossfade_ms;
+
+    let mut tail_guard = TAIL.lock().unwrap();
+    // Forget the tail of an earlier track, unless it is still being mixed into this one
+    tail_guard.take_if(|tail| tail.uri != uri && !tail.is_mixing());
+    match tail_guard.as_mut() {
+        Some(tail)
+            if tail.uri == uri && position_ms >= crossfade_at && tail.stage == Stage::Following && !tail.requested =>
+        {
+            tail.requested = true;
+            drop(tail_guard);
+            queue_controller::crossfade_from(uri.to_string(), player);
+        }
+        Some(_) => {}
+        None if position_ms + CAPTURE_AHEAD_MS >= crossfade_at && position_ms < crossfade_at => {
+            let id = NEXT_CAPTURE_ID.fetch_add(1, Ordering::SeqCst);
+            let start_ms = crossfade_at.saturating_sub(TAIL_LEAD_MS);
+            *tail_guard = Some(Tail::new(id, uri.to_string(), start_ms));
+            RUNTIME.spawn(capture(id, uri.to_string(), start_ms, end_ms));
+        }
+        None => {}
+    }
+}
+
+/// Called by the sink with each packet the main player writes: follows the tail, and
+/// mixes it into the next track once the queue has moved on.
+pub(crate) fn process(packet: &mut AudioPacket) {
+    let AudioPacket::Samples(samples) = packet else {
+        return;
+    };
+    let mut tail_guard = TAIL.lock().unwrap();
+    let Some(tail) = tail_guard.as_mut() else {
+        return;
+    };
+    let expected = samples_for(spotifly_get_position_ms().saturating_sub(tail.start_ms));
+    if tail.process(samples, expected) {
+        tail_guard.take();
+    }
+}
+
+/// Has the sink mix the tail of `uri` into whatever the player writes next. Called by the
+/// queue controller before it moves on at the crossfade point. Returns false if the sink
+/// has lost its place in the tail (e.g. after a seek); the track then carries on.
+pub(crate) fn arm(uri: &str) -> bool {
+    let mut tail_guard = TAIL.lock().unwrap();
+    let Some(tail) = tail_guard.as_mut().filter(|tail| tail.uri == uri) else {
+        return false;
+    };
+    tail.requested = false;
+    if tail.stage != Stage::Following {
+        return false;
+    }
+    tail.stage = Stage::Armed;
+    true
+}
+
+/// Drops the tail of `uri` unless it is being mixed, so the track plays to its end.
+pub(crate) fn disarm(uri: &str) {
+    TAIL.lock().unwrap().take_if(|tail| tail.uri == uri && !tail.is_mixing());
+}
+
+/// Drops the tail, cutting a running crossfade short (playback stopped).
+pub(crate) fn reset() {
+    TAIL.lock().unwrap().take();
+}
+
+/// Sets the crossfade between queue tracks (0 = off, the default). The end of each track
+/// overlaps the start of the next one, fading out while the next one fades in. The last
+/// track before the queue ends, and tracks shorter than 15 seconds plus twice the crossfade,
+/// play to their end without one.
+///
+/// # Parameters
+/// - duration_ms: Crossfade duration in milliseconds (0-12000)
+#[no_mangle]
+pub extern "C" fn spotifly_set_crossfade_ms(duration_ms: u32) {
+    CROSSFADE_MS.store(duration_ms.min(MAX_CROSSFADE_MS), Ordering::SeqCst);
+    // A tail captured for the old duration doesn't line up with the new crossfade point
+    TAIL.lock().unwrap().take_if(|tail| !tail.is_mixing());
+}
+
+/// Returns the crossfade between queue tracks in milliseconds (0 = off).
+#[no_mangle]
+pub extern "C" fn spotifly_get_crossfade_ms() -> u32 {
+    CROSSFADE_MS.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tail of `frames` stereo frames of a tone that never repeats within it
+    fn tail(frames: usize) -> Tail {
+        let mut tail = Tail::new(1, "spotify:track:a".to_string(), 0);
+        tail.samples = (0..frames * CHANNELS).map(|i| (i as f32 * 0.01).sin() * 0.5 + (i % 7) as f32 * 0.01).collect();
+        tail.stage = Stage::Searching;
+        tail
+    }
+
+    // The tail's samples from `offset` on, played at `scale`
+    fn output(tail: &Tail, offset: usize, len: usize, scale: f64) -> Vec<f64> {
+        tail.samples[offset..offset + len].iter().map(|&s| f64::from(s) * scale).collect()
+    }
+
+    #[test]
+    fn finds_scaled_output_near_the_expected_offset() {
+        let mut tail = tail(10_000);
+        let mut out = output(&tail, 2_000, 512, 0.25);
+        assert!(!tail.process(&mut out, 1_900));
+        assert_eq!(tail.stage, Stage::Following);
+        assert_eq!(tail.offset, 2_512);
+        assert!((tail.scale - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn other_audio_is_not_found() {
+        let mut tail = tail(10_000);
+        let mut out: Vec<f64> = (0..512).map(|i| (i as f64 * 0.3).cos() * 0.4).collect();
+        tail.process(&mut out, 2_000);
+        assert_eq!(tail.stage, Stage::Searching);
+    }
+
+    #[test]
+    fn losing_the_place_searches_again() {
+        let mut tail = tail(10_000);
+        let mut out = output(&tail, 2_000, 512, 0.5);
+        tail.process(&mut out, 2_000);
+        // A seek: the next packet isn't where the tail continues
+        let mut out = output(&tail, 6_000, 512, 0.5);
+        tail.process(&mut out, 2_512);
+        assert_eq!(tail.stage, Stage::Searching);
+    }
+
+    #[test]
+    fn armed_tail_mixes_into_the_next_track_until_used_up() {
+        let mut tail = tail(512);
+        let mut out = output(&tail, 0, 512, 0.5);
+        tail.process(&mut out, 0);
+        tail.stage = Stage::Armed;
+
+        // The next track: a constant level, so the mix is easy to check
+        let mut out = vec![0.2; 1_024];
+        assert!(tail.process(&mut out, 0));
+        assert_eq!(tail.stage, Stage::Mixing { start: 512 });
+        // The first frame is all tail, the next track comes in from silence
+        assert!((out[0] - f64::from(tail.samples[512]) * 0.5).abs() < 1e-9);
+        assert!((out[1] - f64::from(tail.samples[513]) * 0.5).abs() < 1e-9);
+        // Past the end of the tail only the next track is left
+        assert!(out[512..].iter().all(|&sample| sample == 0.2));
+    }
+
+    #[test]
+    fn unarmed_tail_is_used_up_at_its_end() {
+        let mut tail = tail(512);
+        let mut out = output(&tail, 0, 512, 1.0);
+        tail.process(&mut out, 0);
+        let mut out = output(&tail, 512, 512, 1.0);
+        assert!(tail.process(&mut out, 512));
+    }
+}
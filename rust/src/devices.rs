@@ -0,0 +1,146 @@
+// Controlling other Spotify Connect devices via the Web API.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    connect, power, spotifly_get_position_ms, to_c_string, webapi, CURRENT_INDEX, IS_PLAYING,
+    PLAYER, QUEUE, RUNTIME,
+};
+use http::Method;
+use librespot_core::session::Session;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::ffi::{c_char, CStr};
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+// Keeps the play request well below the Web API's body size limit
+const MAX_TRANSFER_URIS: usize = 100;
+
+/// A Connect device the user can play on.
+#[derive(Serialize)]
+struct Device {
+    id: String,
+    name: String,
+    /// "Computer", "Smartphone", "Speaker", ...
+    device_type: String,
+    is_active: bool,
+    /// Restricted devices can't be controlled through the Web API
+    is_restricted: bool,
+    volume_percent: Option<u64>,
+    /// True for this player
+    is_this_device: bool,
+}
+
+async fn fetch_devices(session: &Session) -> Result<Vec<Device>, Failure> {
+    let response = webapi::get(session, "/me/player/devices").await?;
+    let own_id = session.device_id();
+
+    let devices = response.get("devices").and_then(Value::as_array).into_iter().flatten()
+        .filter_map(|device| {
+            let id = device.get("id")?.as_str()?.to_string();
+            Some(Device {
+                is_this_device: id == own_id,
+                id,
+                name: webapi::str_field(device, "name"),
+                device_type: webapi::str_field(device, "type"),
+                is_active: device.get("is_active").and_then(Value::as_bool).unwrap_or(false),
+                is_restricted: device.get("is_restricted").and_then(Value::as_bool).unwrap_or(false),
+                volume_percent: device.get("volume_percent").and_then(Value::as_u64),
+            })
+        })
+        .collect();
+    Ok(devices)
+}
+
+// Hands the local queue (from the current track on) to another device, or moves
+// the Connect context there if a Connect client is driving playback
+async fn transfer(session: &Session, device_id: &str) -> Result<(), Failure> {
+    let queue_uris: Vec<String> = {
+        let queue_guard = QUEUE.lock().unwrap();
+        queue_guard.iter()
+            .skip(CURRENT_INDEX.load(Ordering::SeqCst))
+            .take(MAX_TRANSFER_URIS)
+            .map(|item| item.uri.clone())
+            .collect()
+    };
+
+    if connect::is_remote_controlled() || queue_uris.is_empty() {
+        let body = json!({ "device_ids": [device_id], "play": true });
+        webapi::request(session, Method::PUT, "/me/player", Some(body)).await?;
+    } else {
+        let path = format!("/me/player/play?{}", webapi::query_string([("device_id", device_id.to_string())]));
+        let body = json!({ "uris": queue_uris, "position_ms": spotifly_get_position_ms() });
+        webapi::request(session, Method::PUT, &path, Some(body)).await?;
+    }
+    Ok(())
+}
+
+/// Returns the user's available Spotify Connect devices as a JSON array of
+/// {id, name, device_type, is_active, is_restricted, volume_percent, is_this_device}.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+#[no_mangle]
+pub extern "C" fn spotifly_list_devices() -> *mut c_char {
+    power::note_activity();
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        fetch_devices(&session).await
+    });
+
+    match result {
+        Ok(devices) => match serde_json::to_string(&devices) {
+            Ok(json_string) => to_c_string(&json_string),
+            Err(_) => ptr::null_mut(),
+        },
+        Err(e) => {
+            error::report("List devices", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Transfers playback to another Connect device and pauses this player.
+/// The current queue continues there from the current track and position; if a
+/// Connect client is driving playback, its context moves to the device instead.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - device_id: Device ID from spotifly_list_devices()
+#[no_mangle]
+pub extern "C" fn spotifly_transfer_playback(device_id: *const c_char) -> i32 {
+    power::note_activity();
+    if device_id.is_null() {
+        return error::fail(SpotiflyError::InvalidArgument, "Transfer playback error: device_id is null");
+    }
+
+    let device_id_str = unsafe {
+        match CStr::from_ptr(device_id).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => return error::fail(
+                SpotiflyError::InvalidArgument,
+                "Transfer playback error: invalid device_id string",
+            ),
+        }
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        if session.device_id() == device_id_str {
+            return Err(Failure::new(SpotiflyError::InvalidArgument, "already playing on this device"));
+        }
+        transfer(&session, &device_id_str).await
+    });
+
+    match result {
+        Ok(()) => {
+            if let Some(player) = PLAYER.lock().unwrap().as_ref() {
+                player.pause();
+            }
+            IS_PLAYING.store(false, Ordering::SeqCst);
+            0
+        }
+        Err(e) => {
+            error::report("Transfer playback", e)
+        }
+    }
+}
@@ -0,0 +1,192 @@
+// Resume points for podcast episodes.
+//
+// The position reached in each episode is remembered in the data directory, and an
+// episode that was left part-way starts again from there. Episodes without local progress
+// pick up the resume point saved in the user's Spotify account (from other devices),
+// fetched when they start loading. Spotify has no API for writing resume points, so
+// progress made here only reaches other devices through Spotify's own playback reporting.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    current_timestamp_ms, links, power, spotifly_get_position_ms, storage, to_c_string, webapi, CURRENT_INDEX, QUEUE,
+    RUNTIME,
+};
+use librespot_playback::player::Player;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::ffi::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+const PROGRESS_FILE: &str = "episode_progress.json";
+// Stopping this close to the end counts as having finished the episode
+const FINISHED_MARGIN_MS: u32 = 15_000;
+// How often progress is saved while an episode plays
+const SAVE_INTERVAL_MS: u64 = 10_000;
+// An account resume point only moves playback that hasn't got further than this on its own
+const SYNC_SEEK_WINDOW_MS: u32 = 10_000;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct EpisodeProgress {
+    position_ms: u32,
+    duration_ms: u32,
+    fully_played: bool,
+    updated_at_ms: u64,
+}
+
+static PROGRESS: Lazy<Mutex<HashMap<String, EpisodeProgress>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static LAST_SAVE_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Loads stored episode progress from the data directory.
+pub(crate) fn load() {
+    if let Some(progress) = storage::load_json::<HashMap<String, EpisodeProgress>>(PROGRESS_FILE) {
+        *PROGRESS.lock().unwrap() = progress;
+    }
+}
+
+fn save() {
+    LAST_SAVE_MS.store(current_timestamp_ms(), Ordering::SeqCst);
+    let progress = PROGRESS.lock().unwrap();
+    storage::save_json(PROGRESS_FILE, &*progress);
+}
+
+fn is_episode(uri: &str) -> bool {
+    uri.starts_with("spotify:episode:")
+}
+
+fn record(uri: &str, position_ms: u32, duration_ms: u32, fully_played: bool) {
+    PROGRESS.lock().unwrap().insert(uri.to_string(), EpisodeProgress {
+        position_ms: if fully_played { 0 } else { position_ms },
+        duration_ms,
+        fully_played,
+        updated_at_ms: current_timestamp_ms(),
+    });
+}
+
+/// Where to start an episode left part-way, None for anything else.
+pub(crate) fn resume_ms(uri: &str) -> Option<u32> {
+    PROGRESS.lock().unwrap().get(uri)
+        .filter(|progress| !progress.fully_played && progress.position_ms > 0)
+        .map(|progress| progress.position_ms)
+}
+
+/// Records the position of a playing episode, saving now if `save_now` (e.g. on pause)
+/// or otherwise every few seconds.
+pub(crate) fn on_position(uri: &str, position_ms: u32, duration_ms: u32, save_now: bool) {
+    if !is_episode(uri) || position_ms == 0 {
+        return;
+    }
+    let fully_played = duration_ms > 0 && position_ms.saturating_add(FINISHED_MARGIN_MS) >= duration_ms;
+    record(uri, position_ms, duration_ms, fully_played);
+
+    let since_save_ms = current_timestamp_ms().saturating_sub(LAST_SAVE_MS.load(Ordering::SeqCst));
+    if save_now || since_save_ms >= SAVE_INTERVAL_MS {
+        save();
+    }
+}
+
+/// Marks an episode that played to the end as finished.
+pub(crate) fn on_finished(uri: &str, duration_ms: u32) {
+    if is_episode(uri) {
+        record(uri, 0, duration_ms, true);
+        save();
+    }
+}
+
+// The resume point saved in the user's account: (position, duration, fully played)
+async fn account_resume_point(uri: &str) -> Result<(u32, u32, bool), Failure> {
+    let id = uri.strip_prefix("spotify:episode:")
+        .ok_or_else(|| Failure::new(SpotiflyError::InvalidUri, format!("not an episode URI: {}", uri)))?;
+    let session = webapi::current_session()?;
+    let episode = webapi::get(&session, &format!("/episodes/{}", id)).await?;
+
+    let resume_point = episode.get("resume_point");
+    let position_ms = resume_point
+        .and_then(|r| r.get("resume_position_ms"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    let fully_played = resume_point
+        .and_then(|r| r.get("fully_played"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let duration_ms = episode.get("duration_ms").and_then(Value::as_u64).unwrap_or(0);
+    Ok((position_ms as u32, duration_ms as u32, fully_played))
+}
+
+/// Looks up the account's resume point for an episode without local progress and, if it
+/// is still the current item and hasn't got far, moves playback there.
+pub(crate) fn on_loading(uri: &str, player: Arc<Player>) {
+    if !is_episode(uri) || PROGRESS.lock().unwrap().contains_key(uri) {
+        return;
+    }
+    let uri = uri.to_string();
+
+    RUNTIME.spawn(async move {
+        let (position_ms, duration_ms) = match account_resume_point(&uri).await {
+            Ok((position_ms, duration_ms, false)) if position_ms > 0 => (position_ms, duration_ms),
+            Ok(_) => return,
+            Err(e) => {
+                log::warn!("Could not fetch resume point for {}: {}", uri, e);
+                return;
+            }
+        };
+
+        let is_current = QUEUE.lock().unwrap()
+            .get(CURRENT_INDEX.load(Ordering::SeqCst))
+            .is_some_and(|item| item.uri == uri);
+        // Local playback may have got somewhere in the meantime
+        let unplayed = !PROGRESS.lock().unwrap().contains_key(&uri);
+        if is_current && unplayed && spotifly_get_position_ms() < SYNC_SEEK_WINDOW_MS {
+            log::info!("Resuming {} at {} ms from the account's resume point", uri, position_ms);
+            record(&uri, position_ms, duration_ms, false);
+            player.seek(position_ms);
+        }
+    });
+}
+
+/// Returns the listening progress of a podcast episode as JSON:
+/// {uri, position_ms, duration_ms, fully_played}
+/// position_ms is where playback will resume (0 if unplayed or finished).
+/// Progress made on this device comes first; for episodes never played here, the resume
+/// point saved in the user's Spotify account is fetched.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - episode_uri: Spotify episode URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_get_episode_progress(episode_uri: *const c_char) -> *mut c_char {
+    let id = match links::id_arg(episode_uri, "episode", "Get episode progress") {
+        Ok(id) => id,
+        Err(_) => return ptr::null_mut(),
+    };
+    let uri = format!("spotify:episode:{}", id);
+
+    let local = PROGRESS.lock().unwrap().get(&uri).map(|p| (p.position_ms, p.duration_ms, p.fully_played));
+    let (position_ms, duration_ms, fully_played) = match local {
+        Some(progress) => progress,
+        None => {
+            power::note_activity();
+            match RUNTIME.block_on(account_resume_point(&uri)) {
+                Ok(progress) => progress,
+                Err(e) => {
+                    error::report("Get episode progress", e);
+                    return ptr::null_mut();
+                }
+            }
+        }
+    };
+    let json = json!({
+        "uri": uri,
+        "position_ms": if fully_played { 0 } else { position_ms },
+        "duration_ms": duration_ms,
+        "fully_played": fully_played,
+    });
+    match serde_json::to_string(&json) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,221 @@
+// Ten-band equalizer.
+//
+// A chain of peaking filters (one per octave, 31 Hz to 16 kHz) applied to the samples on
+// their way to the audio output, ahead of the audio tap and level analysis so they see
+// what is actually played. Boosts are offset by an equal preamp cut so they can't clip.
+
+use crate::error::{self, SpotiflyError};
+use crate::to_c_string;
+use librespot_playback::decoder::AudioPacket;
+use librespot_playback::{NUM_CHANNELS, SAMPLE_RATE};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::f64::consts::PI;
+use std::ffi::{c_char, CStr};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const BAND_COUNT: usize = 10;
+const BAND_FREQUENCIES: [f64; BAND_COUNT] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+// Bandwidth of each band, about one octave
+const BAND_Q: f64 = 1.41;
+const MAX_GAIN_DB: f64 = 12.0;
+
+const PRESETS: [(&str, [f64; BAND_COUNT]); 10] = [
+    ("flat", [0.0; BAND_COUNT]),
+    ("bass_boost", [6.0, 5.0, 4.0, 2.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("bass_reducer", [-6.0, -5.0, -4.0, -2.0, -0.5, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("treble_boost", [0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 2.0, 4.0, 5.0, 6.0]),
+    ("vocal", [-2.0, -2.0, -1.0, 1.0, 3.0, 3.5, 3.0, 1.5, 0.0, -1.0]),
+    ("rock", [4.5, 3.5, 2.0, 0.5, -1.0, -0.5, 1.0, 2.5, 3.5, 4.0]),
+    ("pop", [-1.0, 0.0, 1.5, 3.0, 3.5, 3.0, 1.5, 0.0, -0.5, -1.0]),
+    ("electronic", [4.5, 4.0, 1.5, 0.0, -1.5, 1.0, 0.5, 1.0, 4.0, 4.5]),
+    ("acoustic", [4.0, 4.0, 3.0, 1.0, 1.5, 1.5, 3.0, 3.5, 3.0, 2.0]),
+    ("classical", [4.0, 3.0, 2.5, 2.0, -1.0, -1.0, 0.0, 2.0, 3.0, 3.5]),
+];
+
+// A peaking biquad filter (RBJ cookbook), with separate state per channel
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    // (x[n-1], x[n-2], y[n-1], y[n-2]) per channel
+    state: [(f64, f64, f64, f64); NUM_CHANNELS as usize],
+}
+
+impl Biquad {
+    fn peaking(frequency: f64, gain_db: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / SAMPLE_RATE as f64;
+        let alpha = w0.sin() / (2.0 * BAND_Q);
+        let a0 = 1.0 + alpha / a;
+        Biquad {
+            b0: (1.0 + alpha * a) / a0,
+            b1: -2.0 * w0.cos() / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: -2.0 * w0.cos() / a0,
+            a2: (1.0 - alpha / a) / a0,
+            state: Default::default(),
+        }
+    }
+
+    fn process(&mut self, channel: usize, x: f64) -> f64 {
+        let (x1, x2, y1, y2) = self.state[channel];
+        let y = self.b0 * x + self.b1 * x1 + self.b2 * x2 - self.a1 * y1 - self.a2 * y2;
+        self.state[channel] = (x, x1, y, y1);
+        y
+    }
+}
+
+struct Equalizer {
+    gains: [f64; BAND_COUNT],
+    filters: [Biquad; BAND_COUNT],
+    preamp: f64,
+}
+
+impl Equalizer {
+    fn set_gains(&mut self, gains: [f64; BAND_COUNT]) {
+        self.gains = gains;
+        for (band, filter) in self.filters.iter_mut().enumerate() {
+            let state = filter.state;
+            *filter = Biquad::peaking(BAND_FREQUENCIES[band], gains[band]);
+            filter.state = state;
+        }
+        let max_boost = gains.iter().copied().fold(0.0, f64::max);
+        self.preamp = 10f64.powf(-max_boost / 20.0);
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static EQUALIZER: Lazy<Mutex<Equalizer>> = Lazy::new(|| {
+    let mut equalizer = Equalizer {
+        gains: [0.0; BAND_COUNT],
+        filters: [Biquad::default(); BAND_COUNT],
+        preamp: 1.0,
+    };
+    equalizer.set_gains([0.0; BAND_COUNT]);
+    Mutex::new(equalizer)
+});
+
+/// Equalizes a block of samples in place, if the equalizer is enabled.
+pub(crate) fn process(packet: &mut AudioPacket) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let AudioPacket::Samples(samples) = packet else {
+        return;
+    };
+
+    let mut equalizer = EQUALIZER.lock().unwrap();
+    let equalizer = &mut *equalizer;
+    for frame in samples.chunks_exact_mut(NUM_CHANNELS as usize) {
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            let mut x = *sample * equalizer.preamp;
+            for (filter, gain) in equalizer.filters.iter_mut().zip(equalizer.gains) {
+                // Flat bands pass through unchanged
+                if gain != 0.0 {
+                    x = filter.process(channel, x);
+                }
+            }
+            *sample = x;
+        }
+    }
+}
+
+/// Turns the equalizer on or off. It starts off, with all bands flat.
+///
+/// # Parameters
+/// - enabled: true to equalize playback
+#[no_mangle]
+pub extern "C" fn spotifly_set_eq_enabled(enabled: bool) {
+    if enabled && !ENABLED.load(Ordering::SeqCst) {
+        // Don't let filter memory from earlier playback click into the new audio
+        for filter in EQUALIZER.lock().unwrap().filters.iter_mut() {
+            filter.state = Default::default();
+        }
+    }
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Sets the gain of one equalizer band.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - index: Band index, 0-9 (31, 62, 125, 250, 500 Hz, 1, 2, 4, 8, 16 kHz)
+/// - gain_db: Gain in dB, clamped to -12.0..12.0
+#[no_mangle]
+pub extern "C" fn spotifly_set_eq_band(index: u32, gain_db: f32) -> i32 {
+    let index = index as usize;
+    if index >= BAND_COUNT || !gain_db.is_finite() {
+        return error::fail(
+            SpotiflyError::InvalidArgument,
+            format!("Set EQ band error: invalid band {} or gain {}", index, gain_db),
+        );
+    }
+
+    let mut equalizer = EQUALIZER.lock().unwrap();
+    let mut gains = equalizer.gains;
+    gains[index] = (gain_db as f64).clamp(-MAX_GAIN_DB, MAX_GAIN_DB);
+    equalizer.set_gains(gains);
+    0
+}
+
+/// Sets all equalizer bands from a built-in preset (see spotifly_get_eq_json()).
+/// Does not turn the equalizer on.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - preset_name: Preset name, e.g. "flat", "bass_boost", "vocal"
+#[no_mangle]
+pub extern "C" fn spotifly_set_eq_preset(preset_name: *const c_char) -> i32 {
+    let action = "Set EQ preset";
+    if preset_name.is_null() {
+        return error::fail(SpotiflyError::InvalidArgument, format!("{} error: preset_name is null", action));
+    }
+    let name = unsafe {
+        match CStr::from_ptr(preset_name).to_str() {
+            Ok(s) => s,
+            Err(_) => {
+                return error::fail(
+                    SpotiflyError::InvalidArgument,
+                    format!("{} error: invalid preset_name string", action),
+                )
+            }
+        }
+    };
+
+    match PRESETS.iter().find(|(preset, _)| *preset == name) {
+        Some((_, gains)) => {
+            EQUALIZER.lock().unwrap().set_gains(*gains);
+            0
+        }
+        None => error::fail(SpotiflyError::NotFound, format!("{} error: no preset named {}", action, name)),
+    }
+}
+
+/// Returns the equalizer settings as JSON:
+/// {enabled, bands: [{frequency, gain_db}], presets: [names]}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+#[no_mangle]
+pub extern "C" fn spotifly_get_eq_json() -> *mut c_char {
+    let gains = EQUALIZER.lock().unwrap().gains;
+    let bands: Vec<_> = BAND_FREQUENCIES.iter().zip(gains)
+        .map(|(frequency, gain_db)| json!({ "frequency": frequency, "gain_db": gain_db }))
+        .collect();
+    let presets: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+
+    let settings = json!({
+        "enabled": ENABLED.load(Ordering::SeqCst),
+        "bands": bands,
+        "presets": presets,
+    });
+    match serde_json::to_string(&settings) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,178 @@
+// Error codes and the last-error message.
+//
+// Functions that fail return a negative SpotiflyError code (or NULL / false) and record
+// a human-readable message that the host can fetch with spotifly_get_last_error_message().
+// Work that fails further down returns a Failure, which gets its code where it happens:
+// from the check that failed, or for librespot and HTTP errors, from the kind librespot
+// derived from the response's status code. The message is also logged (see logging.rs).
+
+use crate::{logging, to_c_string};
+use librespot_core::error::ErrorKind;
+use once_cell::sync::Lazy;
+use std::ffi::c_char;
+use std::fmt;
+use std::ptr;
+use std::sync::Mutex;
+
+/// Error codes returned by FFI functions. Success is 0.
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SpotiflyError {
+    /// Anything not covered below
+    Unknown = -1,
+    /// The player or session hasn't been initialized
+    NotInitialized = -2,
+    /// A NULL, malformed or out-of-range argument
+    InvalidArgument = -3,
+    /// The string isn't a (supported) Spotify URI or URL
+    InvalidUri = -4,
+    /// Missing, expired or rejected credentials
+    NotAuthenticated = -5,
+    /// The account isn't Spotify Premium
+    NotPremium = -6,
+    /// A request to Spotify failed
+    Network = -7,
+    /// A request to Spotify took too long
+    Timeout = -8,
+    /// The content isn't playable (region or licence restrictions)
+    Unavailable = -9,
+    /// The content doesn't exist
+    NotFound = -10,
+    /// The load was superseded by another one or cancelled by the host
+    Cancelled = -11,
+}
+
+/// A failed operation: the code the host gets for it and a message describing it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Failure {
+    pub(crate) code: SpotiflyError,
+    pub(crate) message: String,
+}
+
+impl Failure {
+    pub(crate) fn new(code: SpotiflyError, message: impl Into<String>) -> Self {
+        Failure { code, message: message.into() }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// librespot only tells why the access point refused a login (its error code) in the message
+const PREMIUM_REQUIRED_REASON: &str = "Premium account required";
+
+static LAST_ERROR: Lazy<Mutex<Option<(SpotiflyError, String)>>> = Lazy::new(|| Mutex::new(None));
+
+/// Maps a librespot error to a code by its kind. librespot derives the kind from the
+/// status code of the HTTP or access point response that failed.
+pub(crate) fn classify(error: &librespot_core::Error) -> SpotiflyError {
+    match error.kind {
+        ErrorKind::Cancelled => SpotiflyError::Cancelled,
+        ErrorKind::DeadlineExceeded => SpotiflyError::Timeout,
+        ErrorKind::NotFound => SpotiflyError::NotFound,
+        ErrorKind::PermissionDenied => SpotiflyError::Unavailable,
+        ErrorKind::Unauthenticated => SpotiflyError::NotAuthenticated,
+        ErrorKind::InvalidArgument | ErrorKind::OutOfRange => SpotiflyError::InvalidArgument,
+        ErrorKind::Unavailable | ErrorKind::ResourceExhausted | ErrorKind::Aborted | ErrorKind::DataLoss => {
+            SpotiflyError::Network
+        }
+        _ => SpotiflyError::Unknown,
+    }
+}
+
+/// A failed librespot call, coded by classify(), with `context` in front of the message.
+pub(crate) fn librespot(context: impl fmt::Display, error: &librespot_core::Error) -> Failure {
+    Failure::new(classify(error), format!("{}: {}", context, error))
+}
+
+/// A failed login to the access point. Refused logins are rejected credentials, or the
+/// account's tier.
+pub(crate) fn login_failure(context: impl fmt::Display, error: &librespot_core::Error) -> Failure {
+    let code = match error.kind {
+        ErrorKind::PermissionDenied if error.error.to_string().ends_with(PREMIUM_REQUIRED_REASON) => {
+            SpotiflyError::NotPremium
+        }
+        ErrorKind::PermissionDenied => SpotiflyError::NotAuthenticated,
+        _ => classify(error),
+    };
+    Failure::new(code, format!("{}: {}", context, error))
+}
+
+/// Records an error with an explicit code, logs it, and returns the code.
+pub(crate) fn fail(code: SpotiflyError, message: impl Into<String>) -> i32 {
+    let message = message.into();
+    logging::install();
+    log::error!("{}", message);
+    *LAST_ERROR.lock().unwrap() = Some((code, message));
+    code as i32
+}
+
+/// Records a failure of `action` (see fail()), e.g. as "Play error: player not initialized".
+pub(crate) fn report(action: &str, failure: Failure) -> i32 {
+    fail(failure.code, format!("{} error: {}", action, failure.message))
+}
+
+/// Message of the most recent error.
+pub(crate) fn last_message() -> Option<String> {
+    LAST_ERROR.lock().unwrap().as_ref().map(|(_, message)| message.clone())
+}
+
+/// Returns the code of the most recent error (0 if there was none).
+#[no_mangle]
+pub extern "C" fn spotifly_get_last_error_code() -> i32 {
+    LAST_ERROR.lock().unwrap().as_ref().map_or(0, |(code, _)| *code as i32)
+}
+
+/// Returns the message of the most recent error, e.g. "Play error: player not initialized".
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL if there was no error.
+#[no_mangle]
+pub extern "C" fn spotifly_get_last_error_message() -> *mut c_char {
+    match last_message() {
+        Some(message) => to_c_string(&message),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Forgets the most recent error.
+#[no_mangle]
+pub extern "C" fn spotifly_clear_last_error() {
+    LAST_ERROR.lock().unwrap().take();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use librespot_core::Error;
+
+    #[test]
+    fn librespot_errors_are_coded_by_kind() {
+        assert_eq!(classify(&Error::not_found("track")), SpotiflyError::NotFound);
+        assert_eq!(classify(&Error::deadline_exceeded("request")), SpotiflyError::Timeout);
+        assert_eq!(classify(&Error::unauthenticated("401")), SpotiflyError::NotAuthenticated);
+        assert_eq!(classify(&Error::unavailable("503")), SpotiflyError::Network);
+        assert_eq!(classify(&Error::internal("bug")), SpotiflyError::Unknown);
+    }
+
+    #[test]
+    fn message_text_does_not_pick_the_code() {
+        let failure = librespot("Failed to load Premium Not Found Mix", &Error::unavailable("connection reset"));
+        assert_eq!(failure.code, SpotiflyError::Network);
+        assert_eq!(failure.message, "Failed to load Premium Not Found Mix: Service unavailable { connection reset }");
+    }
+
+    #[test]
+    fn refused_logins_are_told_apart_by_reason() {
+        let premium = Error::permission_denied("Login failed with reason: Premium account required");
+        assert_eq!(login_failure("Session connect error", &premium).code, SpotiflyError::NotPremium);
+
+        let rejected = Error::permission_denied("Login failed with reason: Bad credentials");
+        assert_eq!(login_failure("Session connect error", &rejected).code, SpotiflyError::NotAuthenticated);
+
+        let offline = Error::unavailable("connection refused");
+        assert_eq!(login_failure("Session connect error", &offline).code, SpotiflyError::Network);
+    }
+}
@@ -0,0 +1,126 @@
+// WebSocket stream of player events.
+//
+// Clients of the control server (control_server.rs) can open a WebSocket on /events to
+// follow the player without polling: every event the host callback gets is pushed as
+// {"event": name, "code": code, "data": payload}, and while playing a Position message
+// {position_ms, duration_ms} follows every second. A NowPlaying message with the
+// spotifly_get_now_playing_json() document is sent when the stream opens.
+//
+// Browsers always send an Origin header with WebSocket requests, so unlike the commands
+// the stream accepts them, but only from pages served from this machine, so arbitrary
+// websites can't follow what is playing. Local files (e.g. an OBS browser source) send
+// Origin "null", as do sandboxed frames on any website, so they are only let in once the
+// host opts in with spotifly_set_event_stream_allow_local_files().
+
+use crate::{events, now_playing, spotifly_get_duration_ms, spotifly_get_position_ms, IS_PLAYING};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+use tiny_http::{Header, Request, Response, StatusCode};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+/// Path of the event stream on the control server.
+pub(crate) const PATH: &str = "/events";
+
+const POSITION_INTERVAL: Duration = Duration::from_secs(1);
+
+static SUBSCRIBERS: Lazy<Mutex<Vec<Sender<String>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+// Accept the "null" origin of local files
+static ALLOW_LOCAL_FILES: AtomicBool = AtomicBool::new(false);
+
+fn message(event: &str, code: i32, data: &Value) -> String {
+    json!({ "event": event, "code": code, "data": data }).to_string()
+}
+
+/// Pushes an event to every open stream.
+pub(crate) fn publish(code: i32, payload: &Value) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        return;
+    }
+    let text = message(events::name(code), code, payload);
+    // Streams that closed have dropped their receiver
+    subscribers.retain(|subscriber| subscriber.send(text.clone()).is_ok());
+}
+
+fn header_value<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Returns true for a WebSocket handshake request.
+pub(crate) fn is_upgrade(request: &Request) -> bool {
+    header_value(request, "Upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+// Pages served from this machine, and local files (whose origin is "null") if allowed
+fn local_origin(origin: &str) -> bool {
+    if origin == "null" {
+        return ALLOW_LOCAL_FILES.load(Ordering::SeqCst);
+    }
+    url::Url::parse(origin).is_ok_and(|url| {
+        matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"))
+    })
+}
+
+/// Completes the WebSocket handshake and streams events until the client goes away.
+/// Runs on the request's own thread.
+pub(crate) fn serve(request: Request) {
+    let key = header_value(&request, "Sec-WebSocket-Key").map(str::to_string);
+    let origin_allowed = header_value(&request, "Origin").is_none_or(local_origin);
+    let Some(key) = key.filter(|_| origin_allowed) else {
+        let status = if origin_allowed { 400 } else { 403 };
+        let _ = request.respond(Response::empty(StatusCode(status)));
+        return;
+    };
+
+    let accept = Header::from_bytes("Sec-WebSocket-Accept", derive_accept_key(key.as_bytes()))
+        .expect("accept key is a valid header value");
+    let stream = request.upgrade("websocket", Response::empty(StatusCode(101)).with_header(accept));
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    let (sender, receiver) = mpsc::channel();
+    SUBSCRIBERS.lock().unwrap().push(sender);
+
+    let now_playing = now_playing::json().and_then(|json| serde_json::from_str(&json).ok());
+    let mut next = Some(message("NowPlaying", 0, &now_playing.unwrap_or(Value::Null)));
+    loop {
+        if let Some(text) = next.take() {
+            if socket.send(Message::text(text)).is_err() {
+                break;
+            }
+        }
+        next = match receiver.recv_timeout(POSITION_INTERVAL) {
+            Ok(text) => Some(text),
+            Err(RecvTimeoutError::Timeout) => IS_PLAYING.load(Ordering::SeqCst).then(|| {
+                message("Position", 0, &json!({
+                    "position_ms": spotifly_get_position_ms(),
+                    "duration_ms": spotifly_get_duration_ms(),
+                }))
+            }),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+    }
+    // Dropping the receiver unsubscribes on the next publish
+    drop(receiver);
+    let _ = socket.close(None);
+    log::info!("Event stream closed");
+}
+
+/// Sets whether browsers may open the event stream from local files (off by default).
+/// Local files send the Origin "null", which sandboxed frames on any website send too, so
+/// allowing them lets any web page follow what is playing; only enable it for setups such
+/// as a file:// overlay in OBS.
+///
+/// # Parameters
+/// - enabled: true to accept the "null" origin
+#[no_mangle]
+pub extern "C" fn spotifly_set_event_stream_allow_local_files(enabled: bool) {
+    ALLOW_LOCAL_FILES.store(enabled, Ordering::SeqCst);
+}
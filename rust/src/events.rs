@@ -0,0 +1,124 @@
+// Player event forwarding to the host app.
+//
+// The host registers a C callback with spotifly_register_event_callback(). Events are
+// delivered as an event code plus a JSON payload, from a background thread: the host
+// is responsible for hopping to its UI thread. The same events also go to WebSocket
+// clients of the control server (see event_stream.rs).
+
+use crate::event_stream;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::ffi::{c_char, c_void, CString};
+use std::sync::Mutex;
+
+pub(crate) const EVENT_PLAYING: i32 = 1;
+pub(crate) const EVENT_PAUSED: i32 = 2;
+pub(crate) const EVENT_STOPPED: i32 = 3;
+pub(crate) const EVENT_TRACK_CHANGED: i32 = 4;
+pub(crate) const EVENT_END_OF_TRACK: i32 = 5;
+pub(crate) const EVENT_SEEKED: i32 = 6;
+pub(crate) const EVENT_VOLUME_CHANGED: i32 = 7;
+pub(crate) const EVENT_CONNECTION_STATE_CHANGED: i32 = 8;
+pub(crate) const EVENT_LOAD_TIMED_OUT: i32 = 9;
+pub(crate) const EVENT_TRACK_UNAVAILABLE: i32 = 10;
+pub(crate) const EVENT_PRIVATE_SESSION_EXPIRED: i32 = 11;
+pub(crate) const EVENT_TOKEN_NEEDED: i32 = 12;
+pub(crate) const EVENT_TOKEN_REFRESHED: i32 = 13;
+pub(crate) const EVENT_LOAD_COMPLETED: i32 = 14;
+pub(crate) const EVENT_QUEUE_UPDATED: i32 = 15;
+pub(crate) const EVENT_OUTPUT_DEVICE_CHANGED: i32 = 16;
+pub(crate) const EVENT_SLEEP_TIMER_EXPIRED: i32 = 17;
+pub(crate) const EVENT_PREMIUM_REQUIRED: i32 = 18;
+pub(crate) const EVENT_ACCOUNT_CHANGED: i32 = 19;
+pub(crate) const EVENT_PREVIEW_ENDED: i32 = 20;
+pub(crate) const EVENT_EXPLICIT_SKIPPED: i32 = 21;
+pub(crate) const EVENT_CONTEXT_UPDATED: i32 = 22;
+pub(crate) const EVENT_DOWNLOAD_PROGRESS: i32 = 23;
+pub(crate) const EVENT_BUFFERING_STARTED: i32 = 24;
+pub(crate) const EVENT_BUFFERING_ENDED: i32 = 25;
+pub(crate) const EVENT_CONNECT_LOST: i32 = 26;
+
+/// Event names by code, as used by the WebSocket event stream.
+pub(crate) fn name(code: i32) -> &'static str {
+    match code {
+        EVENT_PLAYING => "Playing",
+        EVENT_PAUSED => "Paused",
+        EVENT_STOPPED => "Stopped",
+        EVENT_TRACK_CHANGED => "TrackChanged",
+        EVENT_END_OF_TRACK => "EndOfTrack",
+        EVENT_SEEKED => "Seeked",
+        EVENT_VOLUME_CHANGED => "VolumeChanged",
+        EVENT_CONNECTION_STATE_CHANGED => "ConnectionStateChanged",
+        EVENT_LOAD_TIMED_OUT => "LoadTimedOut",
+        EVENT_TRACK_UNAVAILABLE => "TrackUnavailable",
+        EVENT_PRIVATE_SESSION_EXPIRED => "PrivateSessionExpired",
+        EVENT_TOKEN_NEEDED => "TokenNeeded",
+        EVENT_TOKEN_REFRESHED => "TokenRefreshed",
+        EVENT_LOAD_COMPLETED => "LoadCompleted",
+        EVENT_QUEUE_UPDATED => "QueueUpdated",
+        EVENT_OUTPUT_DEVICE_CHANGED => "OutputDeviceChanged",
+        EVENT_SLEEP_TIMER_EXPIRED => "SleepTimerExpired",
+        EVENT_PREMIUM_REQUIRED => "PremiumRequired",
+        EVENT_ACCOUNT_CHANGED => "AccountChanged",
+        EVENT_PREVIEW_ENDED => "PreviewEnded",
+        EVENT_EXPLICIT_SKIPPED => "ExplicitSkipped",
+        EVENT_CONTEXT_UPDATED => "ContextUpdated",
+        EVENT_DOWNLOAD_PROGRESS => "DownloadProgress",
+        EVENT_BUFFERING_STARTED => "BufferingStarted",
+        EVENT_BUFFERING_ENDED => "BufferingEnded",
+        EVENT_CONNECT_LOST => "ConnectLost",
+        _ => "Unknown",
+    }
+}
+
+/// Event callback: (event code, JSON payload, user data).
+/// The payload is only valid for the duration of the call.
+pub type EventCallback = extern "C" fn(i32, *const c_char, *mut c_void);
+
+struct Registration {
+    callback: EventCallback,
+    // Opaque host pointer, passed back untouched
+    user_data: usize,
+}
+
+static CALLBACK: Lazy<Mutex<Option<Registration>>> = Lazy::new(|| Mutex::new(None));
+
+/// Sends an event to the registered callback, if any, and to event stream subscribers.
+pub(crate) fn emit(code: i32, payload: Value) {
+    event_stream::publish(code, &payload);
+
+    let registration = match CALLBACK.lock().unwrap().as_ref() {
+        Some(r) => (r.callback, r.user_data),
+        None => return,
+    };
+
+    // JSON strings escape NUL, so this can't fail in practice
+    let json = match CString::new(payload.to_string()) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+
+    let (callback, user_data) = registration;
+    callback(code, json.as_ptr(), user_data as *mut c_void);
+}
+
+/// Registers a callback for player events, replacing any previous one.
+/// Pass NULL to unregister.
+///
+/// Events are delivered from a background thread as an event code and a JSON payload:
+/// 1 = Playing, 2 = Paused, 3 = Stopped, 4 = TrackChanged, 5 = EndOfTrack, 6 = Seeked,
+/// 7 = VolumeChanged, 8 = ConnectionStateChanged, 9 = LoadTimedOut, 10 = TrackUnavailable,
+/// 11 = PrivateSessionExpired, 12 = TokenNeeded, 13 = TokenRefreshed, 14 = LoadCompleted,
+/// 15 = QueueUpdated, 16 = OutputDeviceChanged, 17 = SleepTimerExpired, 18 = PremiumRequired,
+/// 19 = AccountChanged, 20 = PreviewEnded, 21 = ExplicitSkipped, 22 = ContextUpdated,
+/// 23 = DownloadProgress, 24 = BufferingStarted, 25 = BufferingEnded
+#[no_mangle]
+pub extern "C" fn spotifly_register_event_callback(
+    callback: Option<EventCallback>,
+    user_data: *mut c_void,
+) {
+    *CALLBACK.lock().unwrap() = callback.map(|callback| Registration {
+        callback,
+        user_data: user_data as usize,
+    });
+}
@@ -0,0 +1,59 @@
+// Explicit-content filter.
+//
+// With the filter on, tracks and episodes flagged explicit are left out when a queue is
+// built and passed over when playback moves on, for parents and shared devices. Station
+// and autoplay candidates are filtered silently; anything the listener asked for that
+// gets left out or passed over sends an ExplicitSkipped event {uri}. Items that are
+// already playing keep playing, and jumping to an item plays it regardless.
+
+use crate::error::{Failure, SpotiflyError};
+use crate::{events, QueueItem};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Error message prefix for items left out by the filter.
+pub(crate) const FILTERED: &str = "Explicit content is filtered";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the filter keeps `item` out.
+pub(crate) fn blocks(item: &QueueItem) -> bool {
+    item.explicit && ENABLED.load(Ordering::SeqCst)
+}
+
+/// Tells the host an explicit item was left out or passed over.
+pub(crate) fn on_skipped(uri: &str) {
+    events::emit(events::EVENT_EXPLICIT_SKIPPED, json!({ "uri": uri }));
+}
+
+/// Lets `item` into the queue unless the filter keeps it out (sending ExplicitSkipped).
+pub(crate) fn admit(item: QueueItem) -> Result<QueueItem, Failure> {
+    if blocks(&item) {
+        on_skipped(&item.uri);
+        return Err(Failure::new(SpotiflyError::Unavailable, format!("{}: {}", FILTERED, item.uri)));
+    }
+    Ok(item)
+}
+
+/// Whether a failure is the filter leaving an item out.
+pub(crate) fn is_filtered(failure: &Failure) -> bool {
+    failure.code == SpotiflyError::Unavailable && failure.message.starts_with(FILTERED)
+}
+
+/// Turns the explicit-content filter on or off (off by default). While it is on,
+/// explicit tracks and episodes are left out of new queues and skipped when playback
+/// moves on, each sending an ExplicitSkipped event. Playing a single explicit item fails
+/// with SPOTIFLY_ERROR_UNAVAILABLE.
+///
+/// # Parameters
+/// - enabled: true to filter explicit content
+#[no_mangle]
+pub extern "C" fn spotifly_set_explicit_filter(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Returns true if the explicit-content filter is on.
+#[no_mangle]
+pub extern "C" fn spotifly_get_explicit_filter() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
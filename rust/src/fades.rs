@@ -0,0 +1,171 @@
+// Volume ramps on pause, resume and stop.
+//
+// Rather than cutting the audio off, pausing and stopping first turn the mixer down to
+// silence, and resuming brings it back up from silence. Like fades between tracks, ramps run the
+// mixer below the user's volume, which stays what spotifly_get_volume() reports. After a
+// faded pause the volume is restored once the player has actually paused.
+
+use crate::{MIXER, RUNTIME};
+use librespot_playback::mixer::Mixer;
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_FADE_MS: u32 = 250;
+// Longest ramp the host can configure
+const MAX_FADE_MS: u32 = 1_000;
+const STEP: Duration = Duration::from_millis(10);
+
+struct Ramp {
+    // Volume set by the user; the mixer runs below it while ramping
+    volume: u16,
+    // Ramping down towards a pause or stop (rather than up after a resume)
+    fading_out: bool,
+}
+
+static FADE_MS: AtomicU32 = AtomicU32::new(DEFAULT_FADE_MS);
+// Bumped whenever a ramp starts or is abandoned; a running ramp stops when it changes
+static RAMP_GENERATION: AtomicU64 = AtomicU64::new(0);
+static RAMP: Lazy<Mutex<Option<Ramp>>> = Lazy::new(|| Mutex::new(None));
+
+fn mixer_volume() -> u16 {
+    MIXER.lock().unwrap().as_ref().map(|mixer| mixer.volume()).unwrap_or(0)
+}
+
+fn set_mixer_volume(volume: u16) {
+    if let Some(mixer) = MIXER.lock().unwrap().as_ref() {
+        mixer.set_volume(volume);
+    }
+}
+
+// Steps the mixer from `from` to `to` over `duration`.
+// Returns false if another ramp took over (or the ramp was abandoned) on the way.
+fn ramp(generation: u64, from: u16, to: u16, duration: Duration) -> bool {
+    let steps = (duration.as_millis() / STEP.as_millis()).max(1) as u32;
+    for step in 1..=steps {
+        thread::sleep(STEP);
+        if RAMP_GENERATION.load(Ordering::SeqCst) != generation {
+            return false;
+        }
+        let volume = from as f32 + (to as f32 - from as f32) * step as f32 / steps as f32;
+        set_mixer_volume(volume.round() as u16);
+    }
+    true
+}
+
+fn fade_duration() -> Duration {
+    Duration::from_millis(FADE_MS.load(Ordering::SeqCst).into())
+}
+
+// Starts ramping down: records the user's volume and returns the new ramp's generation
+fn begin_fade_out() -> u64 {
+    let generation = RAMP_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let mut ramp_guard = RAMP.lock().unwrap();
+    let volume = ramp_guard.as_ref().map(|ramp| ramp.volume).unwrap_or_else(mixer_volume);
+    *ramp_guard = Some(Ramp { volume, fading_out: true });
+    generation
+}
+
+/// Whether pause, resume and stop are faded.
+pub(crate) fn enabled() -> bool {
+    FADE_MS.load(Ordering::SeqCst) > 0
+}
+
+/// The user's volume while a ramp is in progress.
+pub(crate) fn user_volume() -> Option<u16> {
+    RAMP.lock().unwrap().as_ref().map(|ramp| ramp.volume)
+}
+
+/// Abandons the running ramp without touching the mixer, e.g. because the user set
+/// a new volume.
+pub(crate) fn cancel() {
+    RAMP_GENERATION.fetch_add(1, Ordering::SeqCst);
+    RAMP.lock().unwrap().take();
+}
+
+/// Abandons the running ramp and restores the user's volume.
+pub(crate) fn reset() {
+    RAMP_GENERATION.fetch_add(1, Ordering::SeqCst);
+    if let Some(ramp) = RAMP.lock().unwrap().take() {
+        set_mixer_volume(ramp.volume);
+    }
+}
+
+/// Fades out in the background, then runs `pause` (unless a resume came first).
+pub(crate) fn fade_out_then(pause: impl FnOnce() + Send + 'static) {
+    let generation = begin_fade_out();
+    let from = mixer_volume();
+    RUNTIME.spawn_blocking(move || {
+        if ramp(generation, from, 0, fade_duration()) {
+            pause();
+        }
+    });
+}
+
+/// Fades out, blocking until the output is silent (before a stop).
+pub(crate) fn fade_out_blocking() {
+    let generation = begin_fade_out();
+    ramp(generation, mixer_volume(), 0, fade_duration());
+}
+
+/// Fades out over `duration`, blocking until the output is silent (e.g. the sleep
+/// timer's long fade). Returns false if interrupted, e.g. by a resume or a volume change.
+pub(crate) fn fade_out_over(duration: Duration) -> bool {
+    let generation = begin_fade_out();
+    ramp(generation, mixer_volume(), 0, duration)
+}
+
+/// Runs `resume` and fades in from silence (or from wherever a pause ramp had got to).
+pub(crate) fn fade_in_after(resume: impl FnOnce()) {
+    let generation = RAMP_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let volume = {
+        let mut ramp_guard = RAMP.lock().unwrap();
+        let interrupted = ramp_guard.as_ref().map(|ramp| ramp.volume);
+        let volume = interrupted.unwrap_or_else(mixer_volume);
+        if interrupted.is_none() {
+            set_mixer_volume(0);
+        }
+        *ramp_guard = Some(Ramp { volume, fading_out: false });
+        volume
+    };
+    resume();
+
+    let from = mixer_volume();
+    RUNTIME.spawn_blocking(move || {
+        if ramp(generation, from, volume, fade_duration()) {
+            RAMP.lock().unwrap().take();
+        }
+    });
+}
+
+/// Restores the volume once the player has paused after a fade-out.
+pub(crate) fn on_paused() {
+    if RAMP.lock().unwrap().as_ref().is_some_and(|ramp| ramp.fading_out) {
+        reset();
+    }
+}
+
+/// Abandons a fade-out when something else starts playback (e.g. a new track loads).
+pub(crate) fn on_playing() {
+    if RAMP.lock().unwrap().as_ref().is_some_and(|ramp| ramp.fading_out) {
+        reset();
+    }
+}
+
+/// Sets how long pause, resume and stop take to fade (0 = off, cutting the audio
+/// immediately; the default is 250 ms).
+///
+/// # Parameters
+/// - duration_ms: Fade duration in milliseconds (0-1000)
+#[no_mangle]
+pub extern "C" fn spotifly_set_pause_fade_ms(duration_ms: u32) {
+    FADE_MS.store(duration_ms.min(MAX_FADE_MS), Ordering::SeqCst);
+}
+
+/// Returns the pause/resume/stop fade duration in milliseconds (0 = off).
+#[no_mangle]
+pub extern "C" fn spotifly_get_pause_fade_ms() -> u32 {
+    FADE_MS.load(Ordering::SeqCst)
+}
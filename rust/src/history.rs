@@ -0,0 +1,121 @@
+// Recently played tracks.
+//
+// A track enters the history once it actually starts playing (not when it's merely
+// loaded or skipped while loading). Replaying a track moves it back to the top, so each
+// URI appears once. The history is capped and persisted to the data directory.
+// Nothing is recorded during a private session.
+
+use crate::artwork::ArtworkUrls;
+use crate::error::{self, SpotiflyError};
+use crate::{current_timestamp_ms, private_session, spotifly_play_track, storage, to_c_string};
+use librespot_metadata::audio::{AudioItem, UniqueFields};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, CString};
+use std::ptr;
+use std::sync::Mutex;
+
+const HISTORY_FILE: &str = "history.json";
+const MAX_ENTRIES: usize = 200;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct HistoryEntry {
+    uri: String,
+    name: String,
+    /// All artists (or the show, for episodes), comma-separated
+    artist_name: String,
+    artwork: ArtworkUrls,
+    duration_ms: u32,
+    played_at_ms: u64,
+}
+
+// Most recent first
+static ENTRIES: Lazy<Mutex<Vec<HistoryEntry>>> = Lazy::new(|| Mutex::new(Vec::new()));
+// Loaded track waiting to start playing
+static PENDING: Lazy<Mutex<Option<HistoryEntry>>> = Lazy::new(|| Mutex::new(None));
+
+/// Loads the stored history from the data directory.
+pub(crate) fn load() {
+    if let Some(entries) = storage::load_json::<Vec<HistoryEntry>>(HISTORY_FILE) {
+        *ENTRIES.lock().unwrap() = entries;
+    }
+}
+
+/// Remembers a newly loaded track; it's recorded once it starts playing.
+pub(crate) fn on_track_changed(audio_item: &AudioItem) {
+    let artist_name = match &audio_item.unique_fields {
+        UniqueFields::Track { artists, .. } => artists.iter()
+            .map(|artist| artist.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        UniqueFields::Episode { show_name, .. } => show_name.clone(),
+        UniqueFields::Local { artists, .. } => artists.clone().unwrap_or_default(),
+    };
+
+    *PENDING.lock().unwrap() = Some(HistoryEntry {
+        uri: audio_item.uri.clone(),
+        name: audio_item.name.clone(),
+        artist_name,
+        artwork: ArtworkUrls::from_covers(&audio_item.covers),
+        duration_ms: audio_item.duration_ms,
+        played_at_ms: 0,
+    });
+}
+
+/// Records the loaded track when playback of it starts.
+pub(crate) fn on_playing(uri: &str) {
+    let mut entry = match PENDING.lock().unwrap().take_if(|entry| entry.uri == uri) {
+        Some(entry) => entry,
+        None => return,
+    };
+    if private_session::is_active() {
+        return;
+    }
+    entry.played_at_ms = current_timestamp_ms();
+
+    let mut entries = ENTRIES.lock().unwrap();
+    entries.retain(|existing| existing.uri != entry.uri);
+    entries.insert(0, entry);
+    entries.truncate(MAX_ENTRIES);
+    storage::save_json(HISTORY_FILE, &*entries);
+}
+
+/// Returns recently played tracks, most recent first, as a JSON array of
+/// {uri, name, artist_name, artwork: {small, medium, large}, duration_ms, played_at_ms}.
+/// played_at_ms is milliseconds since the Unix epoch.
+/// Caller must free the string with spotifly_free_string().
+///
+/// # Parameters
+/// - limit: Maximum number of entries, 0 = all (up to 200 are kept)
+#[no_mangle]
+pub extern "C" fn spotifly_get_history(limit: usize) -> *mut c_char {
+    let entries = ENTRIES.lock().unwrap();
+    let limit = if limit == 0 { entries.len() } else { limit };
+    let page: Vec<&HistoryEntry> = entries.iter().take(limit).collect();
+
+    match serde_json::to_string(&page) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Plays a history entry, replacing the queue with it.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - index: Index into spotifly_get_history() (0 = most recent)
+#[no_mangle]
+pub extern "C" fn spotifly_play_history_item(index: usize) -> i32 {
+    let uri = match ENTRIES.lock().unwrap().get(index) {
+        Some(entry) => entry.uri.clone(),
+        None => return error::fail(
+            SpotiflyError::InvalidArgument,
+            format!("Play history item error: index {} out of bounds", index),
+        ),
+    };
+
+    match CString::new(uri) {
+        Ok(uri) => spotifly_play_track(uri.as_ptr()),
+        Err(_) => error::fail(SpotiflyError::InvalidArgument, "Play history item error: invalid URI"),
+    }
+}
@@ -1,26 +1,102 @@
-use librespot_connect::{ConnectConfig, Spirc};
-use librespot_core::config::DeviceType;
+// FFI entry points take raw C pointers and check them for null before use. Clippy wants
+// every such public function marked `unsafe`, which would only push unsafe blocks into
+// the Rust callers (such as swift_api) without making the C side any safer; the
+// C API itself is unchanged either way. Allowed crate-wide so `clippy -D warnings` can gate CI.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+mod accounts;
+mod analysis;
+mod artists;
+mod artwork;
+mod availability;
+mod buffering;
+mod browse;
+mod auth;
+mod collections;
+mod connect;
+mod control_server;
+mod crossfade;
+mod devices;
+mod episode_progress;
+mod eq;
+mod explicit_filter;
+mod event_stream;
+mod error;
+mod events;
+mod fades;
+mod history;
+mod library;
+mod links;
+mod loading;
+mod logging;
+mod lyrics;
+mod network;
+mod now_playing;
+mod oauth;
+mod offline;
+mod output;
+mod output_format;
+mod paging;
+mod pins;
+mod queue_controller;
+mod playback_state;
+mod playlist_updates;
+mod playlists;
+mod podcasts;
+mod power;
+mod preview;
+mod private_session;
+mod profile;
+mod recommendations;
+mod scrobble;
+mod search;
+mod secure_storage;
+mod sleep_timer;
+mod speed;
+mod station;
+mod stats;
+mod storage;
+mod supervisor;
+#[cfg(feature = "uniffi")]
+mod swift_api;
+mod stored_credentials;
+mod tap;
+mod token_manager;
+mod trim;
+mod webapi;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+use crate::artwork::ArtworkUrls;
+use crate::error::{Failure, SpotiflyError};
+use futures_util::stream::{self, StreamExt};
+use librespot_connect::Spirc;
 use librespot_core::session::Session;
 use librespot_core::SessionConfig;
 use librespot_core::cache::Cache;
+use librespot_core::date::Date;
 use librespot_core::SpotifyUri;
 use librespot_metadata::{Album, Artist, Metadata, Playlist, Track};
-use librespot_playback::audio_backend;
-use librespot_playback::config::{AudioFormat, Bitrate, PlayerConfig};
+use librespot_playback::config::{Bitrate, NormalisationType, PlayerConfig};
 use librespot_playback::mixer::softmixer::SoftMixer;
 use librespot_playback::mixer::{Mixer, MixerConfig};
-use librespot_playback::player::{Player, PlayerEvent};
+use librespot_playback::player::{Player, PlayerEvent, SinkStatus};
 use once_cell::sync::Lazy;
+use serde_json::json;
 use std::ffi::{c_char, CStr, CString};
+use std::path::PathBuf;
 use std::ptr;
-use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU16, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc;
 
 // Global tokio runtime for async operations
 static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    // Everything that does real work starts the runtime, so logging is set up by then
+    logging::install();
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
@@ -33,21 +109,92 @@ static SESSION: Lazy<Mutex<Option<Session>>> = Lazy::new(|| Mutex::new(None));
 static MIXER: Lazy<Mutex<Option<Arc<SoftMixer>>>> = Lazy::new(|| Mutex::new(None));
 static SPIRC: Lazy<Mutex<Option<Arc<Spirc>>>> = Lazy::new(|| Mutex::new(None));
 static IS_PLAYING: AtomicBool = AtomicBool::new(false);
+// Access token from the last init, used to re-establish the session after sleep or network loss
+static ACCESS_TOKEN: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
 static PLAYER_EVENT_TX: Lazy<Mutex<Option<mpsc::UnboundedSender<()>>>> = Lazy::new(|| Mutex::new(None));
 
+// Sink state - true while the audio sink is open and producing output.
+// Updated from the player's sink event callback so stop/cleanup can wait for silence.
+static SINK_RUNNING: Lazy<(Mutex<bool>, Condvar)> = Lazy::new(|| (Mutex::new(false), Condvar::new()));
+const SINK_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Audio output route, see spotifly_notify_output_route_changed()
+const ROUTE_SPEAKERS: u8 = 0;
+const ROUTE_HEADPHONES: u8 = 1;
+const ROUTE_EXTERNAL: u8 = 2;
+static OUTPUT_ROUTE: AtomicU8 = AtomicU8::new(ROUTE_SPEAKERS);
+// Pause when output falls back to the speakers (e.g. headphones unplugged)
+static AUTO_PAUSE_ON_ROUTE_CHANGE: AtomicBool = AtomicBool::new(false);
+
+// Connection state, see spotifly_get_connection_state()
+const CONNECTION_DISCONNECTED: u8 = 0;
+const CONNECTION_CONNECTED: u8 = 1;
+const CONNECTION_SUSPENDED: u8 = 2;
+const CONNECTION_RECONNECTING: u8 = 3;
+const CONNECTION_IDLE: u8 = 4;
+static CONNECTION_STATE: AtomicU8 = AtomicU8::new(CONNECTION_DISCONNECTED);
+
+// Mute state - volume to restore on unmute
+static MUTED: AtomicBool = AtomicBool::new(false);
+static VOLUME_BEFORE_MUTE: AtomicU16 = AtomicU16::new(0);
+
 // Queue state
+// CURRENT_INDEX must only be written while holding the QUEUE lock so that
+// index bookkeeping stays consistent with queue edits and auto-advance.
 static QUEUE: Lazy<Mutex<Vec<QueueItem>>> = Lazy::new(|| Mutex::new(Vec::new()));
 static CURRENT_INDEX: AtomicUsize = AtomicUsize::new(0);
 
+// Repeat mode, see spotifly_set_repeat_mode()
+const REPEAT_OFF: u8 = 0;
+const REPEAT_CONTEXT: u8 = 1;
+const REPEAT_TRACK: u8 = 2;
+static REPEAT_MODE: AtomicU8 = AtomicU8::new(REPEAT_OFF);
+
+// Load watchdog - play request currently loading (NO_PENDING_LOAD if none)
+const NO_PENDING_LOAD: u64 = u64::MAX;
+static PENDING_LOAD_ID: AtomicU64 = AtomicU64::new(NO_PENDING_LOAD);
+// Load timeout in milliseconds (0 = disabled)
+static LOAD_TIMEOUT_MS: AtomicU32 = AtomicU32::new(15_000);
+// Whether to skip to the next queue item when a load times out
+static SKIP_ON_LOAD_TIMEOUT: AtomicBool = AtomicBool::new(false);
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+// Metadata requests in flight at once while loading albums and playlists
+const METADATA_CONCURRENCY: usize = 16;
+
 // Position tracking - updated from player events
 static POSITION_MS: AtomicU32 = AtomicU32::new(0);
 static POSITION_TIMESTAMP_MS: AtomicU64 = AtomicU64::new(0);
+// Duration of the current track - updated from TrackChanged events
+static DURATION_MS: AtomicU32 = AtomicU32::new(0);
 
 // Playback settings (applied on player init)
 // Bitrate: 0 = 96kbps, 1 = 160kbps (default), 2 = 320kbps
 static BITRATE_SETTING: AtomicU8 = AtomicU8::new(1);
+// Bitrate the current player was created with; it is rebuilt to apply a new setting
+static PLAYER_BITRATE: AtomicU8 = AtomicU8::new(1);
+// A load waiting for the player to be rebuilt
+struct PendingLoad {
+    uri: SpotifyUri,
+    start_playing: bool,
+    position_ms: u32,
+}
+// Set while the player is rebuilt for a new bitrate, to the latest load made meanwhile
+static REBUILD_LOAD: Lazy<Mutex<Option<PendingLoad>>> = Lazy::new(|| Mutex::new(None));
 // Gapless playback: true by default (matches librespot default)
 static GAPLESS_SETTING: AtomicBool = AtomicBool::new(true);
+// Volume normalisation: off by default (matches librespot default)
+static NORMALIZATION_SETTING: AtomicBool = AtomicBool::new(false);
+// Normalisation type: 0 = auto (album gain when playing an album), 1 = track, 2 = album
+static NORMALIZATION_TYPE_SETTING: AtomicU8 = AtomicU8::new(0);
+// Normalisation pre-gain in dB, stored as f32 bits
+static NORMALIZATION_PREGAIN_SETTING: AtomicU32 = AtomicU32::new(0);
+const MAX_NORMALIZATION_PREGAIN_DB: f32 = 10.0;
+// Cache for credentials, volume and audio files (None = no caching)
+struct CacheSettings {
+    dir: PathBuf,
+    audio_size_limit: Option<u64>,
+}
+static CACHE_SETTINGS: Lazy<Mutex<Option<CacheSettings>>> = Lazy::new(|| Mutex::new(None));
 
 /// Get current timestamp in milliseconds since UNIX epoch
 fn current_timestamp_ms() -> u64 {
@@ -58,82 +205,296 @@ fn current_timestamp_ms() -> u64 {
 }
 
 /// Update position from player event
-fn update_position(position_ms: u32) {
+pub(crate) fn update_position(position_ms: u32) {
     POSITION_MS.store(position_ms, Ordering::SeqCst);
     POSITION_TIMESTAMP_MS.store(current_timestamp_ms(), Ordering::SeqCst);
 }
 
-#[derive(Clone, serde::Serialize)]
+/// Update the connection state, notifying the host if it changed
+fn set_connection_state(state: u8) {
+    if CONNECTION_STATE.swap(state, Ordering::SeqCst) != state {
+        events::emit(events::EVENT_CONNECTION_STATE_CHANGED, json!({ "state": state }));
+    }
+}
+
+/// Loads and plays a track, starting at its trim point if it has one
+/// (or, for an episode left part-way, where the listener stopped).
+fn load_track(player: &Player, uri: SpotifyUri) {
+    let uri_str = uri.to_string();
+    let start_ms = episode_progress::resume_ms(&uri_str).unwrap_or_else(|| trim::start_ms(&uri_str));
+    load_item(player, uri, true, start_ms);
+}
+
+/// Loads an item into the player. After a bitrate change the player is first rebuilt with
+/// the new setting (in the background), and the item loads into the new player. Loads made
+/// while it is rebuilt wait for it; only the latest one is played.
+pub(crate) fn load_item(player: &Player, uri: SpotifyUri, start_playing: bool, position_ms: u32) {
+    // Held while loading, so no load can reach the old player once a rebuild has started
+    let mut rebuild_load = REBUILD_LOAD.lock().unwrap();
+    let load = PendingLoad { uri, start_playing, position_ms };
+    if rebuild_load.is_some() {
+        *rebuild_load = Some(load);
+        return;
+    }
+    if PLAYER_BITRATE.load(Ordering::SeqCst) == BITRATE_SETTING.load(Ordering::SeqCst) {
+        player.load(load.uri, load.start_playing, load.position_ms);
+        return;
+    }
+    *rebuild_load = Some(load);
+    drop(rebuild_load);
+
+    RUNTIME.spawn(async move {
+        let player = match rebuild_player().await {
+            Ok(player) => Some(player),
+            Err(e) => {
+                log::error!("Failed to apply the new bitrate: {}", e);
+                PLAYER.lock().unwrap().clone()
+            }
+        };
+        let mut rebuild_load = REBUILD_LOAD.lock().unwrap();
+        if let (Some(player), Some(load)) = (player, rebuild_load.take()) {
+            player.load(load.uri, load.start_playing, load.position_ms);
+        }
+    });
+}
+
+// Replaces the player with one created with the current settings. Spirc holds on to the
+// player it was created with, and creating one connects a session, so Connect moves over
+// to the new player on a new session; if that fails, the host gets a ConnectLost event.
+async fn rebuild_player() -> Result<Arc<Player>, Failure> {
+    let session = webapi::current_session()?;
+    let mixer = MIXER.lock().unwrap().clone()
+        .ok_or_else(|| Failure::new(SpotiflyError::NotInitialized, "Mixer not initialized"))?;
+    let old_player = PLAYER.lock().unwrap().clone();
+
+    let player = start_player(&session, &mixer)?;
+    if let Some(old_player) = old_player {
+        old_player.stop();
+    }
+    if let Err(e) = reconnect_session().await {
+        // The old Spirc would keep driving the old player
+        if let Some(spirc) = SPIRC.lock().unwrap().take() {
+            let _ = spirc.shutdown();
+        }
+        log::warn!("Spotify Connect unavailable after the bitrate change: {}", e);
+        events::emit(events::EVENT_CONNECT_LOST, json!({ "reason": e.message }));
+    }
+    Ok(player)
+}
+
+/// Mark a play request as no longer loading
+fn finish_pending_load(play_request_id: u64) {
+    let _ = PENDING_LOAD_ID.compare_exchange(
+        play_request_id,
+        NO_PENDING_LOAD,
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+    );
+}
+
+/// Watch a track load and cancel it if it doesn't start within the load timeout
+/// (e.g. a dead CDN connection). Optionally skips to the next queue item.
+fn start_load_watchdog(play_request_id: u64, track_uri: String, player: Arc<Player>) {
+    PENDING_LOAD_ID.store(play_request_id, Ordering::SeqCst);
+
+    let timeout_ms = LOAD_TIMEOUT_MS.load(Ordering::SeqCst);
+    if timeout_ms == 0 {
+        return;
+    }
+
+    RUNTIME.spawn(async move {
+        tokio::time::sleep(Duration::from_millis(timeout_ms as u64)).await;
+
+        // Still loading the same request?
+        if PENDING_LOAD_ID.compare_exchange(
+            play_request_id,
+            NO_PENDING_LOAD,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ).is_err() {
+            return;
+        }
+
+        log::warn!("Load timed out after {}ms: {}", timeout_ms, track_uri);
+        player.stop();
+        IS_PLAYING.store(false, Ordering::SeqCst);
+        events::emit(events::EVENT_LOAD_TIMED_OUT, json!({
+            "uri": track_uri,
+            "timeout_ms": timeout_ms,
+        }));
+
+        if SKIP_ON_LOAD_TIMEOUT.load(Ordering::SeqCst) {
+            queue_controller::skip(track_uri, &player);
+        }
+    });
+}
+
+// Helper function to bound metadata requests so a dead connection can't hang a load forever
+async fn with_metadata_timeout<T>(
+    what: &str,
+    request: impl std::future::Future<Output = Result<T, librespot_core::Error>>,
+) -> Result<T, Failure> {
+    match tokio::time::timeout(METADATA_TIMEOUT, request).await {
+        Ok(result) => result.map_err(|e| error::librespot(format_args!("Failed to load {}", what), &e)),
+        Err(_) => Err(Failure::new(SpotiflyError::Timeout, format!("Timed out loading {}", what))),
+    }
+}
+
+/// Update sink state from the player's sink event callback
+fn update_sink_status(status: SinkStatus) {
+    let (lock, cvar) = &*SINK_RUNNING;
+    *lock.lock().unwrap() = status == SinkStatus::Running;
+    cvar.notify_all();
+}
+
+/// Block until the sink has drained its buffer and closed, or the timeout expires.
+/// Returns true if the sink is closed.
+fn wait_for_sink_closed(timeout: Duration) -> bool {
+    let (lock, cvar) = &*SINK_RUNNING;
+    let guard = lock.lock().unwrap();
+    let (guard, _) = cvar.wait_timeout_while(guard, timeout, |running| *running).unwrap();
+    !*guard
+}
+
+/// Tells the player the audio output route changed (e.g. headphones plugged or unplugged).
+/// If auto-pause is enabled, playback pauses when output moves from headphones or an
+/// external device to the built-in speakers.
+/// Returns 0 on success, a negative error code for an unknown route.
+///
+/// # Parameters
+/// - route: 0 = built-in speakers, 1 = headphones, 2 = external device (Bluetooth, AirPlay, USB, HDMI)
+#[no_mangle]
+pub extern "C" fn spotifly_notify_output_route_changed(route: u8) -> i32 {
+    if !matches!(route, ROUTE_SPEAKERS | ROUTE_HEADPHONES | ROUTE_EXTERNAL) {
+        return error::fail(SpotiflyError::InvalidArgument, format!("Output route error: unknown route {}", route));
+    }
+
+    let previous = OUTPUT_ROUTE.swap(route, Ordering::SeqCst);
+    let to_speakers = route == ROUTE_SPEAKERS && previous != ROUTE_SPEAKERS;
+    if to_speakers && AUTO_PAUSE_ON_ROUTE_CHANGE.load(Ordering::SeqCst) && IS_PLAYING.load(Ordering::SeqCst) {
+        if let Some(player) = PLAYER.lock().unwrap().as_ref() {
+            log::info!("Output switched to speakers, pausing");
+            player.pause();
+            IS_PLAYING.store(false, Ordering::SeqCst);
+        }
+    }
+    0
+}
+
+/// Enables or disables pausing when output switches to the built-in speakers.
+/// Disabled by default. Takes effect immediately.
+#[no_mangle]
+pub extern "C" fn spotifly_set_auto_pause_on_route_change(enabled: bool) {
+    AUTO_PAUSE_ON_ROUTE_CHANGE.store(enabled, Ordering::SeqCst);
+}
+
+/// Gets the auto-pause-on-route-change setting.
+#[no_mangle]
+pub extern "C" fn spotifly_get_auto_pause_on_route_change() -> bool {
+    AUTO_PAUSE_ON_ROUTE_CHANGE.load(Ordering::SeqCst)
+}
+
+/// Record listening progress on the current queue item, if it is still `track_uri`.
+fn update_play_state(track_uri: &str, position_ms: u32, completed: bool) {
+    let mut queue_guard = QUEUE.lock().unwrap();
+    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+    if let Some(item) = queue_guard.get_mut(current_idx).filter(|item| item.uri == track_uri) {
+        if completed {
+            item.play_state = PLAY_STATE_COMPLETED;
+            item.last_position_ms = 0;
+        } else if position_ms > 0 {
+            item.play_state = PLAY_STATE_PARTIAL;
+            item.last_position_ms = position_ms;
+        }
+    }
+}
+
+/// Counts `track_uri` as played to its end when playback moves on before the end
+/// (at a trimmed end, or at the crossfade point).
+pub(crate) fn end_track_early(track_uri: &str, position_ms: u32) {
+    stats::on_playback_ended(true);
+    update_play_state(track_uri, position_ms, true);
+    events::emit(events::EVENT_END_OF_TRACK, json!({ "uri": track_uri }));
+}
+
+/// Stop the player and wait until buffered audio has been flushed to the output.
+fn stop_and_drain(player: &Player) {
+    player.stop();
+    if !wait_for_sink_closed(SINK_DRAIN_TIMEOUT) {
+        log::warn!("Timed out waiting for audio sink to drain");
+    }
+}
+
+// Listened state of a queue item
+const PLAY_STATE_UNPLAYED: u8 = 0;
+const PLAY_STATE_PARTIAL: u8 = 1;
+const PLAY_STATE_COMPLETED: u8 = 2;
+
+// Kind of content a queue item plays
+const ITEM_TYPE_TRACK: u8 = 0;
+const ITEM_TYPE_EPISODE: u8 = 1;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 struct QueueItem {
     uri: String,
+    // ITEM_TYPE_*
+    item_type: u8,
     track_name: String,
     artist_name: String,
     album_art_url: String,
+    // Cover art in small/medium/large, album_art_url is the large one
+    album_art_urls: ArtworkUrls,
     duration_ms: u32,
     album_id: Option<String>,
     artist_id: Option<String>,
     external_url: Option<String>,
+    // PLAY_STATE_* and where playback last stopped (0 once completed)
+    play_state: u8,
+    last_position_ms: u32,
+    // Details for rich rows; missing from queues saved before they were added
+    #[serde(default)]
+    album_name: Option<String>,
+    // 1-based; 0 if unknown (and for episodes)
+    #[serde(default)]
+    track_number: u32,
+    #[serde(default)]
+    disc_number: u32,
+    #[serde(default)]
+    explicit: bool,
+    // "YYYY-MM-DD" (album release or episode publish date)
+    #[serde(default)]
+    release_date: Option<String>,
 }
 
-// Helper function to convert URL to URI
-fn url_to_uri(input: &str) -> String {
-    // If already a URI, return as-is
-    if input.starts_with("spotify:") {
-        return input.to_string();
-    }
-
-    // If it's a URL, parse it
-    if input.starts_with("http://") || input.starts_with("https://") {
-        if let Some(marker_pos) = input.find("open.spotify.com/") {
-            let after_marker = &input[marker_pos + "open.spotify.com/".len()..];
-            let parts: Vec<&str> = after_marker.split('/').collect();
-
-            // Filter out locale prefixes like "intl-de"
-            let filtered: Vec<&str> = parts.iter()
-                .filter(|p| !p.starts_with("intl-"))
-                .copied()
-                .collect();
-
-            if filtered.len() >= 2 {
-                let content_type = filtered[0];
-                let mut id = filtered[1];
-
-                // Remove query parameters
-                if let Some(query_pos) = id.find('?') {
-                    id = &id[..query_pos];
-                }
-
-                return format!("spotify:{}:{}", content_type, id);
-            }
-        }
-    }
+/// Formats a metadata date as "YYYY-MM-DD". None if Spotify has no date (year 0).
+/// Month and day are 01 when only the year is known.
+fn date_string(date: &Date) -> Option<String> {
+    (date.year() > 0).then(|| format!("{:04}-{:02}-{:02}", date.year(), u8::from(date.month()), date.day()))
+}
 
-    // Return original if can't parse
-    input.to_string()
+// Helper function to hand a string to the host as an owned C string.
+// Interior NUL bytes (which CString can't represent) are replaced with U+FFFD so
+// metadata with unusual bytes is still returned instead of NULL.
+fn to_c_string(s: &str) -> *mut c_char {
+    let sanitized = if s.contains('\0') {
+        s.replace('\0', "\u{FFFD}")
+    } else {
+        s.to_string()
+    };
+    CString::new(sanitized)
+        .expect("string contains no NUL bytes after sanitizing")
+        .into_raw()
 }
 
 // Helper function to parse Spotify URI from string
-fn parse_spotify_uri(uri_str: &str) -> Result<SpotifyUri, String> {
+fn parse_spotify_uri(uri_str: &str) -> Result<SpotifyUri, Failure> {
     SpotifyUri::from_uri(uri_str)
-        .map_err(|e| format!("Invalid Spotify URI: {:?}", e))
-}
-
-// Helper function to extract album art URL from track
-fn get_album_art_url(track: &Track) -> String {
-    // Try to get largest album cover from track metadata
-    track.album.covers.iter()
-        .max_by_key(|img| img.width * img.height)
-        .and_then(|img| {
-            img.id.to_base16().ok().map(|file_id_hex| {
-                format!("https://i.scdn.co/image/{}", file_id_hex)
-            })
-        })
-        .unwrap_or_default()
+        .map_err(|e| Failure::new(SpotiflyError::InvalidUri, format!("Invalid Spotify URI: {:?}", e)))
 }
 
 // Helper function to extract album ID from track
 fn get_album_id(track: &Track) -> Option<String> {
-    Some(track.album.id.to_id().ok()?)
+    track.album.id.to_id().ok()
 }
 
 // Helper function to extract first artist ID from track
@@ -153,126 +514,121 @@ fn get_external_url(uri: &str) -> Option<String> {
     }
 }
 
-// Load album tracks into queue
-async fn load_album(session: &Session, album_uri: SpotifyUri) -> Result<Vec<QueueItem>, String> {
-    let album = Album::get(session, &album_uri).await
-        .map_err(|e| format!("Failed to load album: {:?}", e))?;
-
-    let mut queue_items = Vec::new();
-
-    // Get track URIs from album
-    let track_uris: Vec<SpotifyUri> = album.tracks()
-        .cloned()
-        .collect();
-
-    // Fetch metadata for each track
-    for track_uri in track_uris {
-        if let Ok(track) = Track::get(session, &track_uri).await {
-            let uri_str = track_uri.to_string();
-            let track_name = track.name.clone();
-            let artist_name = track.artists.iter()
-                .map(|a| a.name.clone())
-                .collect::<Vec<_>>()
-                .join(", ");
-            let album_art_url = get_album_art_url(&track);
-            let duration_ms = track.duration as u32;
-
-            queue_items.push(QueueItem {
-                uri: uri_str.clone(),
-                track_name,
-                artist_name,
-                album_art_url,
-                duration_ms,
-                album_id: get_album_id(&track),
-                artist_id: get_artist_id(&track),
-                external_url: get_external_url(&uri_str),
-            });
-        }
+const UNKNOWN_TRACK_NAME: &str = "Unknown Track";
+const UNKNOWN_ARTIST_NAME: &str = "Unknown Artist";
+
+// Helper function to build a queue item from track metadata.
+// Missing names fall back to placeholders so every item has displayable text.
+fn queue_item_from_track(uri_str: &str, track: &Track) -> QueueItem {
+    let track_name = if track.name.trim().is_empty() {
+        UNKNOWN_TRACK_NAME.to_string()
+    } else {
+        track.name.clone()
+    };
+    let artist_name = track.artists.iter()
+        .map(|a| a.name.clone())
+        .filter(|name| !name.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let artist_name = if artist_name.is_empty() {
+        UNKNOWN_ARTIST_NAME.to_string()
+    } else {
+        artist_name
+    };
+
+    let album_art_urls = ArtworkUrls::from_images(track.album.covers.iter());
+    QueueItem {
+        uri: uri_str.to_string(),
+        item_type: ITEM_TYPE_TRACK,
+        track_name,
+        artist_name,
+        album_art_url: album_art_urls.large.clone(),
+        album_art_urls,
+        duration_ms: track.duration as u32,
+        album_id: get_album_id(track),
+        artist_id: get_artist_id(track),
+        external_url: get_external_url(uri_str),
+        play_state: PLAY_STATE_UNPLAYED,
+        last_position_ms: 0,
+        album_name: Some(track.album.name.clone()).filter(|name| !name.trim().is_empty()),
+        track_number: track.number.max(0) as u32,
+        disc_number: track.disc_number.max(0) as u32,
+        explicit: track.is_explicit,
+        release_date: date_string(&track.album.date),
     }
+}
 
-    Ok(queue_items)
-}
-
-// Load playlist tracks into queue
-async fn load_playlist(session: &Session, playlist_uri: SpotifyUri) -> Result<Vec<QueueItem>, String> {
-    let playlist = Playlist::get(session, &playlist_uri).await
-        .map_err(|e| format!("Failed to load playlist: {:?}", e))?;
-
-    let mut queue_items = Vec::new();
-
-    for item_uri in playlist.tracks() {
-        // Only handle track URIs, skip episodes
-        if matches!(item_uri, SpotifyUri::Track { .. }) {
-            let track_uri = item_uri.clone();
-
-            // Fetch track metadata
-            if let Ok(track) = Track::get(session, &track_uri).await {
-                let uri_str = track_uri.to_string();
-                let track_name = track.name.clone();
-                let artist_name = track.artists.iter()
-                    .map(|a| a.name.clone())
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                let album_art_url = get_album_art_url(&track);
-                let duration_ms = track.duration as u32;
-
-                queue_items.push(QueueItem {
-                    uri: uri_str.clone(),
-                    track_name,
-                    artist_name,
-                    album_art_url,
-                    duration_ms,
-                    album_id: get_album_id(&track),
-                    artist_id: get_artist_id(&track),
-                    external_url: get_external_url(&uri_str),
-                });
-            }
+// Load a single track or episode as a queue item
+// (left out with an error if the explicit filter keeps it out)
+async fn load_queue_item(session: &Session, uri_str: &str) -> Result<QueueItem, Failure> {
+    let spotify_uri = parse_spotify_uri(uri_str)?;
+    let item = match spotify_uri {
+        SpotifyUri::Track { .. } => {
+            let track = with_metadata_timeout("track", Track::get(session, &spotify_uri)).await?;
+            let track = availability::playable_track(session, track).await;
+            queue_item_from_track(&track.id.to_string(), &track)
         }
-    }
+        SpotifyUri::Episode { .. } => podcasts::load_episode(session, &spotify_uri).await?,
+        _ => {
+            return Err(Failure::new(
+                SpotiflyError::InvalidUri,
+                format!("Only track and episode URIs can be queued: {}", uri_str),
+            ));
+        }
+    };
+    explicit_filter::admit(item)
+}
+
+/// Loads queue items for many track/episode URIs, with up to METADATA_CONCURRENCY
+/// requests in flight. Results are in the order of `uris`.
+/// Each track's metadata embeds its album and artists, so no further requests are needed.
+async fn load_queue_items(session: &Session, uris: &[String]) -> Vec<Result<QueueItem, Failure>> {
+    stream::iter(uris)
+        .map(|uri| load_queue_item(session, uri))
+        .buffered(METADATA_CONCURRENCY)
+        .collect()
+        .await
+}
+
+// Track URIs of an album, in album order
+pub(crate) async fn album_track_uris(session: &Session, album_uri: &SpotifyUri) -> Result<Vec<String>, Failure> {
+    let album = with_metadata_timeout("album", Album::get(session, album_uri)).await?;
+    Ok(album.tracks().map(|uri| uri.to_string()).collect())
+}
+
+// Track and episode URIs of a playlist, in playlist order
+async fn playlist_item_uris(session: &Session, playlist_uri: &SpotifyUri) -> Result<Vec<String>, Failure> {
+    let playlist = with_metadata_timeout("playlist", Playlist::get(session, playlist_uri)).await?;
 
-    Ok(queue_items)
+    // Local files can't be streamed
+    let (playable, skipped): (Vec<_>, Vec<_>) = playlist.tracks()
+        .partition(|uri| matches!(uri, SpotifyUri::Track { .. } | SpotifyUri::Episode { .. }));
+    if !skipped.is_empty() {
+        log::warn!("Skipping {} local or unsupported items in {}", skipped.len(), playlist_uri);
+    }
+    Ok(playable.into_iter().map(|uri| uri.to_string()).collect())
 }
 
-// Load artist top tracks into queue
-async fn load_artist(session: &Session, artist_uri: SpotifyUri) -> Result<Vec<QueueItem>, String> {
-    let artist = Artist::get(session, &artist_uri).await
-        .map_err(|e| format!("Failed to load artist: {:?}", e))?;
+// Load playlist tracks and episodes into queue
+async fn load_playlist(session: &Session, playlist_uri: SpotifyUri) -> Result<Vec<QueueItem>, Failure> {
+    let item_uris = playlist_item_uris(session, &playlist_uri).await?;
+
+    // Items that fail to load are left out
+    Ok(load_queue_items(session, &item_uris).await.into_iter()
+        .filter_map(|result| result.map_err(|e| log::warn!("Leaving out playlist item: {}", e)).ok())
+        .collect())
+}
 
-    let mut queue_items = Vec::new();
+// Top track URIs of an artist
+async fn artist_track_uris(session: &Session, artist_uri: &SpotifyUri) -> Result<Vec<String>, Failure> {
+    let artist = with_metadata_timeout("artist", Artist::get(session, artist_uri)).await?;
 
     // Get top tracks - artist.top_tracks is a CountryTopTracks iterator
     // Each item has a tracks field which is Tracks(Vec<SpotifyUri>), access with .0
-    let track_uris: Vec<SpotifyUri> = artist.top_tracks
+    Ok(artist.top_tracks
         .iter()
-        .flat_map(|top_track| top_track.tracks.0.clone())
-        .collect();
-
-    // Fetch metadata for each track
-    for track_uri in track_uris {
-        if let Ok(track) = Track::get(session, &track_uri).await {
-            let uri_str = track_uri.to_string();
-            let track_name = track.name.clone();
-            let artist_name = track.artists.iter()
-                .map(|a| a.name.clone())
-                .collect::<Vec<_>>()
-                .join(", ");
-            let album_art_url = get_album_art_url(&track);
-            let duration_ms = track.duration as u32;
-
-            queue_items.push(QueueItem {
-                uri: uri_str.clone(),
-                track_name,
-                artist_name,
-                album_art_url,
-                duration_ms,
-                album_id: get_album_id(&track),
-                artist_id: get_artist_id(&track),
-                external_url: get_external_url(&uri_str),
-            });
-        }
-    }
-
-    Ok(queue_items)
+        .flat_map(|top_track| top_track.tracks.0.iter().map(|uri| uri.to_string()))
+        .collect())
 }
 
 /// Frees a C string allocated by this library.
@@ -287,21 +643,22 @@ pub extern "C" fn spotifly_free_string(s: *mut c_char) {
 
 /// Initializes the player with the given access token.
 /// Must be called before play/pause operations.
-/// Returns 0 on success, -1 on error.
+/// With secure storage enabled, pass NULL to log in with the stored token set or credentials.
+/// Returns 0 on success, a negative error code on error.
+/// A free account gets SPOTIFLY_ERROR_NOT_PREMIUM and a PremiumRequired event.
 #[no_mangle]
 pub extern "C" fn spotifly_init_player(access_token: *const c_char) -> i32 {
     if access_token.is_null() {
-        eprintln!("Player init error: access_token is null");
-        return -1;
+        return secure_storage::init_player();
     }
 
     let token_str = unsafe {
         match CStr::from_ptr(access_token).to_str() {
             Ok(s) => s.to_string(),
-            Err(_) => {
-                eprintln!("Player init error: invalid access_token string");
-                return -1;
-            }
+            Err(_) => return error::fail(
+                SpotiflyError::InvalidArgument,
+                "Player init error: invalid access_token string",
+            ),
         }
     };
 
@@ -314,78 +671,124 @@ pub extern "C" fn spotifly_init_player(access_token: *const c_char) -> i32 {
         }
     }
 
+    // librespot ends the process when it sees a free account, so check before connecting
+    if let Err(product) = RUNTIME.block_on(profile::require_premium(&token_str)) {
+        return profile::premium_required("Player init", product.as_deref());
+    }
+
     let result = RUNTIME.block_on(async {
-        init_player_async(&token_str).await
+        let credentials = librespot_core::authentication::Credentials::with_access_token(&token_str);
+        init_player_async(credentials, Some(&token_str)).await
     });
 
     match result {
         Ok(_) => 0,
+        Err(e) if e.code == SpotiflyError::NotPremium => {
+            // The access point refused the login for the account tier
+            profile::premium_required("Player init", None)
+        }
         Err(e) => {
-            eprintln!("Player init error: {}", e);
-            -1
+            error::report("Player init", e)
         }
     }
 }
 
-async fn init_player_async(access_token: &str) -> Result<(), String> {
-    let session_config = SessionConfig {
+fn build_session_config() -> SessionConfig {
+    let mut config = SessionConfig {
         device_id: format!("spotifly_{}", std::process::id()),
         ..Default::default()
     };
+    network::apply(&mut config);
+    config
+}
 
-    // Create credentials - will be used by Spirc to connect
-    let credentials = librespot_core::authentication::Credentials::with_access_token(access_token);
-
-    let cache = Cache::new(None::<std::path::PathBuf>, None, None, None)
-        .map_err(|e| format!("Cache error: {}", e))?;
-
-    // Create session but DON'T connect yet - let Spirc handle the connection
-    // This is important for Spirc to work properly with OAuth tokens
-    let session = Session::new(session_config, Some(cache));
+/// Directory of the on-disk artwork cache, if a cache directory is set.
+fn artwork_cache_dir() -> Option<PathBuf> {
+    CACHE_SETTINGS.lock().unwrap().as_ref().map(|settings| settings.dir.join("artwork"))
+}
 
-    // Create mixer
-    let mixer_config = MixerConfig::default();
-    let mixer = Arc::new(SoftMixer::open(mixer_config)
-        .map_err(|e| format!("Mixer error: {}", e))?);
+fn build_cache() -> Result<Cache, Failure> {
+    let settings = CACHE_SETTINGS.lock().unwrap();
+    let dir = settings.as_ref().map(|s| &s.dir);
+    let audio_dir = dir.map(|d| d.join("audio"));
+    // Credentials and volume are kept per account; audio files are shared
+    let account_dir = dir.map(|d| accounts::cache_dir(d));
+    let audio_size_limit = settings.as_ref().and_then(|s| s.audio_size_limit);
+    Cache::new(account_dir.as_ref(), account_dir.as_ref(), audio_dir.as_ref(), audio_size_limit)
+        .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Cache error: {}", e)))
+}
 
-    // Store mixer globally
-    {
-        let mut mixer_guard = MIXER.lock().unwrap();
-        *mixer_guard = Some(Arc::clone(&mixer));
+/// Creates a fresh session from the access token (or the stored credentials, or else the
+/// running session's reusable ones), connects it with a new Spirc (so remote commands keep
+/// working) and hands it to the player.
+async fn reconnect_session() -> Result<(), Failure> {
+    let credentials = stored_credentials::session_credentials().or_else(|e| {
+        SESSION.lock().unwrap().as_ref().and_then(stored_credentials::reusable).ok_or(e)
+    })?;
+    let session = Session::new(build_session_config(), Some(build_cache()?));
+    connect::connect_session(&session, credentials).await?;
+
+    let old_session = SESSION.lock().unwrap().replace(session);
+    if let Some(old_session) = old_session {
+        old_session.shutdown();
     }
 
-    // Create player with user settings
-    let bitrate_setting = BITRATE_SETTING.load(Ordering::SeqCst);
+    Ok(())
+}
+
+/// Player settings from the playback settings, at `bitrate_setting`.
+pub(crate) fn player_config(bitrate_setting: u8) -> PlayerConfig {
     let bitrate = match bitrate_setting {
         0 => Bitrate::Bitrate96,
         2 => Bitrate::Bitrate320,
         _ => Bitrate::Bitrate160, // default
     };
     let gapless = GAPLESS_SETTING.load(Ordering::SeqCst);
-
-    let bitrate_kbps = match bitrate_setting {
-        0 => 96,
-        2 => 320,
-        _ => 160,
+    let normalisation = NORMALIZATION_SETTING.load(Ordering::SeqCst);
+    let normalisation_type = match NORMALIZATION_TYPE_SETTING.load(Ordering::SeqCst) {
+        1 => NormalisationType::Track,
+        2 => NormalisationType::Album,
+        _ => NormalisationType::Auto,
     };
-    println!("[Spotifly] Player initialized: bitrate={}kbps, gapless={}", bitrate_kbps, gapless);
+    let normalisation_pregain_db = spotifly_get_normalization_pregain() as f64;
 
-    let player_config = PlayerConfig {
+    PlayerConfig {
         bitrate,
         gapless,
+        normalisation,
+        normalisation_type,
+        normalisation_pregain_db,
         position_update_interval: Some(Duration::from_millis(200)),
+        ditherer: output_format::ditherer(),
         ..PlayerConfig::default()
-    };
-    let audio_format = AudioFormat::default();
+    }
+}
+
+// Creates the player with the user's settings on `session` and starts forwarding its events,
+// replacing the player stored so far (whose event listener is stopped)
+fn start_player(session: &Session, mixer: &Arc<SoftMixer>) -> Result<Arc<Player>, Failure> {
+    let bitrate_setting = BITRATE_SETTING.load(Ordering::SeqCst);
+    let player_config = player_config(bitrate_setting);
+    log::info!(
+        "Player initialized: bitrate={}kbps, gapless={}, normalization={}",
+        spotifly_get_bitrate_kbps(), player_config.gapless, player_config.normalisation,
+    );
+    PLAYER_BITRATE.store(bitrate_setting, Ordering::SeqCst);
+    let audio_format = output_format::audio_format();
 
-    let backend = audio_backend::find(None).ok_or("No audio backend found")?;
+    let backend = output::sink_builder()?;
 
     let player = Player::new(
         player_config,
         session.clone(),
         mixer.get_soft_volume(),
-        move || backend(None, audio_format),
+        move || Box::new(output::SwitchableSink::open(backend, audio_format)),
     );
+    output::start_device_watcher();
+    buffering::start_monitor();
+
+    // Track sink open/close so stop and cleanup can wait for the output to go silent
+    player.set_sink_event_callback(Some(Box::new(update_sink_status)));
 
     // Get event channel from player
     let mut event_channel = player.get_player_event_channel();
@@ -404,44 +807,133 @@ async fn init_player_async(access_token: &str) -> Result<(), String> {
                 }
                 event = event_channel.recv() => {
                     match event {
-                        Some(PlayerEvent::Playing { position_ms, .. }) => {
+                        Some(PlayerEvent::Playing { play_request_id, track_id, position_ms }) => {
+                            finish_pending_load(play_request_id);
+                            buffering::on_ready();
                             IS_PLAYING.store(true, Ordering::SeqCst);
                             update_position(position_ms);
+                            history::on_playing(&track_id.to_string());
+                            preview::on_main_playing();
+                            availability::on_playing();
+                            fades::on_playing();
+                            events::emit(events::EVENT_PLAYING, json!({
+                                "uri": track_id.to_string(),
+                                "position_ms": position_ms,
+                            }));
                         }
-                        Some(PlayerEvent::Paused { position_ms, .. }) => {
+                        Some(PlayerEvent::Paused { play_request_id, track_id, position_ms }) => {
+                            finish_pending_load(play_request_id);
+                            buffering::on_idle();
                             IS_PLAYING.store(false, Ordering::SeqCst);
                             update_position(position_ms);
+                            fades::on_paused();
+                            update_play_state(&track_id.to_string(), position_ms, false);
+                            episode_progress::on_position(
+                                &track_id.to_string(),
+                                position_ms,
+                                DURATION_MS.load(Ordering::SeqCst),
+                                true,
+                            );
+                            events::emit(events::EVENT_PAUSED, json!({
+                                "uri": track_id.to_string(),
+                                "position_ms": position_ms,
+                            }));
                         }
-                        Some(PlayerEvent::PositionChanged { position_ms, .. }) => {
+                        Some(PlayerEvent::PositionChanged { play_request_id, track_id, position_ms }) => {
                             // Periodic position update (every 200ms)
                             update_position(position_ms);
+                            stats::on_position(position_ms, true);
+                            scrobble::on_position(position_ms, true);
+                            let track_uri = track_id.to_string();
+                            update_play_state(&track_uri, position_ms, false);
+                            crossfade::on_position(
+                                &track_uri,
+                                position_ms,
+                                DURATION_MS.load(Ordering::SeqCst),
+                                &player_clone,
+                            );
+                            episode_progress::on_position(
+                                &track_uri,
+                                position_ms,
+                                DURATION_MS.load(Ordering::SeqCst),
+                                false,
+                            );
+
+                            // A trimmed end counts as the end of the track
+                            if trim::end_reached(play_request_id, &track_uri, position_ms) {
+                                end_track_early(&track_uri, position_ms);
+                                queue_controller::track_ended(track_uri, true, &player_clone);
+                            }
                         }
-                        Some(PlayerEvent::Seeked { position_ms, .. }) => {
+                        Some(PlayerEvent::Seeked { track_id, position_ms, .. }) => {
                             update_position(position_ms);
+                            stats::on_position(position_ms, false);
+                            scrobble::on_position(position_ms, false);
+                            update_play_state(&track_id.to_string(), position_ms, false);
+                            events::emit(events::EVENT_SEEKED, json!({
+                                "uri": track_id.to_string(),
+                                "position_ms": position_ms,
+                            }));
                         }
-                        Some(PlayerEvent::Stopped { .. }) => {
+                        Some(PlayerEvent::VolumeChanged { volume }) => {
+                            if volume > 0 {
+                                // Volume raised elsewhere (e.g. from a Connect remote)
+                                MUTED.store(false, Ordering::SeqCst);
+                            }
+                            events::emit(events::EVENT_VOLUME_CHANGED, json!({
+                                "volume": volume,
+                                "muted": MUTED.load(Ordering::SeqCst),
+                            }));
+                        }
+                        Some(PlayerEvent::TrackChanged { audio_item }) => {
+                            DURATION_MS.store(audio_item.duration_ms, Ordering::SeqCst);
+                            stats::on_track_changed(&audio_item);
+                            speed::on_track_changed(&audio_item);
+                            scrobble::on_track_changed(&audio_item);
+                            now_playing::on_track_changed(&audio_item);
+                            history::on_track_changed(&audio_item);
+                            connect::on_track_changed(&audio_item);
+                            events::emit(events::EVENT_TRACK_CHANGED, json!({
+                                "uri": audio_item.uri,
+                                "name": audio_item.name,
+                                "duration_ms": audio_item.duration_ms,
+                                "cover_url": audio_item.covers.first().map(|c| c.url.clone()),
+                            }));
+                        }
+                        Some(PlayerEvent::Stopped { play_request_id, track_id }) => {
+                            finish_pending_load(play_request_id);
+                            buffering::on_idle();
                             IS_PLAYING.store(false, Ordering::SeqCst);
                             update_position(0);
+                            crossfade::reset();
+                            fades::reset();
+                            stats::on_playback_ended(false);
+                            events::emit(events::EVENT_STOPPED, json!({ "uri": track_id.to_string() }));
                         }
-                        Some(PlayerEvent::EndOfTrack { .. }) => {
+                        Some(PlayerEvent::EndOfTrack { play_request_id, track_id }) => {
+                            finish_pending_load(play_request_id);
+                            buffering::on_idle();
                             IS_PLAYING.store(false, Ordering::SeqCst);
                             update_position(0);
-                            // Auto-advance to next track if available
-                            let queue_guard = QUEUE.lock().unwrap();
-                            let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
-                            if current_idx + 1 < queue_guard.len() {
-                                let next_track = queue_guard[current_idx + 1].clone();
-                                drop(queue_guard);
-                                CURRENT_INDEX.store(current_idx + 1, Ordering::SeqCst);
-
-                                // Parse and load next track
-                                if let Ok(spotify_uri) = parse_spotify_uri(&next_track.uri) {
-                                    player_clone.load(spotify_uri, true, 0);
-                                    IS_PLAYING.store(true, Ordering::SeqCst);
-                                }
-                            } else {
-                                drop(queue_guard);
-                            }
+                            stats::on_playback_ended(true);
+                            update_play_state(&track_id.to_string(), 0, true);
+                            episode_progress::on_finished(&track_id.to_string(), DURATION_MS.load(Ordering::SeqCst));
+                            events::emit(events::EVENT_END_OF_TRACK, json!({ "uri": track_id.to_string() }));
+                            queue_controller::track_ended(track_id.to_string(), false, &player_clone);
+                        }
+                        Some(PlayerEvent::Loading { play_request_id, track_id, .. }) => {
+                            buffering::on_loading(&track_id.to_string());
+                            connect::on_loading(&track_id.to_string());
+                            episode_progress::on_loading(&track_id.to_string(), Arc::clone(&player_clone));
+                            start_load_watchdog(play_request_id, track_id.to_string(), Arc::clone(&player_clone));
+                        }
+                        Some(PlayerEvent::TimeToPreloadNextTrack { track_id, .. }) => {
+                            queue_controller::preload(track_id.to_string(), &player_clone);
+                        }
+                        Some(PlayerEvent::Unavailable { play_request_id, track_id }) => {
+                            finish_pending_load(play_request_id);
+                            buffering::on_idle();
+                            queue_controller::track_unavailable(track_id.to_string(), &player_clone);
                         }
                         None => break,
                         _ => {}
@@ -452,332 +944,334 @@ async fn init_player_async(access_token: &str) -> Result<(), String> {
         drop(player_clone);
     });
 
-    // Store session, player, mixer, and event channel first
-    // This ensures basic playback works even if Spirc initialization fails
+    PLAYER.lock().unwrap().replace(Arc::clone(&player));
+    if let Some(old_tx) = PLAYER_EVENT_TX.lock().unwrap().replace(tx) {
+        let _ = old_tx.send(());
+    }
+    Ok(player)
+}
+
+// Sets up the player and connects a session with `credentials` (used by Spirc to connect).
+// `access_token` is the Web API token, if the credentials came with one.
+async fn init_player_async(
+    credentials: librespot_core::authentication::Credentials,
+    access_token: Option<&str>,
+) -> Result<(), Failure> {
+    // The player is stored before the session connects; the supervisor mustn't take that for a lost connection
+    supervisor::stop();
+
+    let session_config = build_session_config();
+
+    let cache = build_cache()?;
+
+    // Create session but DON'T connect yet - let Spirc handle the connection
+    // This is important for Spirc to work properly with OAuth tokens
+    let session = Session::new(session_config, Some(cache));
+
+    // Create mixer
+    let mixer_config = MixerConfig::default();
+    let mixer = Arc::new(SoftMixer::open(mixer_config)
+        .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Mixer error: {}", e)))?);
+
+    // Store mixer globally
     {
-        let mut player_guard = PLAYER.lock().unwrap();
-        *player_guard = Some(Arc::clone(&player));
+        let mut mixer_guard = MIXER.lock().unwrap();
+        *mixer_guard = Some(Arc::clone(&mixer));
     }
+
+    start_player(&session, &mixer)?;
+
+    // Store the session first (the player and mixer are stored already)
+    // This ensures basic playback works even if Spirc initialization fails
     {
         let mut session_guard = SESSION.lock().unwrap();
         *session_guard = Some(session.clone());
     }
-    {
-        let mut tx_guard = PLAYER_EVENT_TX.lock().unwrap();
-        *tx_guard = Some(tx);
+    if let Some(access_token) = access_token {
+        let mut token_guard = ACCESS_TOKEN.lock().unwrap();
+        *token_guard = Some(access_token.to_string());
     }
 
-    // Create Spirc for Spotify Connect support (makes this app appear as a Connect device)
-    // Spirc::new() will connect the session - this is the proper way per librespot examples
-    let connect_config = ConnectConfig {
-        name: "Spotifly".to_string(),
-        device_type: DeviceType::Computer,
-        initial_volume: 65535 / 2, // 50% volume
-        ..Default::default()
-    };
-
-    // Use the SAME credentials for Spirc - don't create new ones
-    // Spirc::new() handles the session connection internally
-    match Spirc::new(
-        connect_config,
-        session.clone(),
-        credentials.clone(), // Clone so we can use it for fallback if needed
-        player,
-        mixer as Arc<dyn Mixer>,
-    )
-    .await
-    {
-        Ok((spirc, spirc_task)) => {
-            // Spawn Spirc background task
-            let spirc_arc = Arc::new(spirc);
-            RUNTIME.spawn(spirc_task);
-
-            let mut spirc_guard = SPIRC.lock().unwrap();
-            *spirc_guard = Some(spirc_arc);
-            println!("[Spotifly] Spirc initialized - Spotify Connect available");
-        }
-        Err(e) => {
-            // Spirc failed - fall back to manual session connection for basic playback
-            eprintln!("Spirc init failed: {:?}", e);
-            eprintln!("[Spotifly] Falling back to basic playback (Connect won't be available)");
+    // Spirc connects the session and makes this app appear as a Connect device
+    connect::connect_session(&session, credentials).await?;
 
-            // Connect session manually so basic playback works
-            if let Err(connect_err) = session.connect(credentials, true).await {
-                return Err(format!("Session connect error: {}", connect_err));
-            }
-        }
-    }
+    set_connection_state(CONNECTION_CONNECTED);
+    secure_storage::on_session_connected(&session);
+    power::start_wake_monitor();
+    supervisor::start();
+    token_manager::start();
 
     Ok(())
 }
 
-/// Plays multiple tracks in sequence.
-/// Returns 0 on success, -1 on error.
+/// Plays multiple tracks (or podcast episodes) in sequence.
+/// With the explicit filter on, explicit items are left out (each sending ExplicitSkipped)
+/// and the first remaining one plays; if none remain, fails with SPOTIFLY_ERROR_UNAVAILABLE.
+/// Returns 0 on success, a negative error code on error.
 ///
 /// # Parameters
 /// - track_uris_json: JSON array of track URIs as a C string (e.g., "[\"spotify:track:xxx\", \"spotify:track:yyy\"]")
 #[no_mangle]
 pub extern "C" fn spotifly_play_tracks(track_uris_json: *const c_char) -> i32 {
+    power::note_activity();
     if track_uris_json.is_null() {
-        eprintln!("Play tracks error: track_uris_json is null");
-        return -1;
+        return error::fail(SpotiflyError::InvalidArgument, "Play tracks error: track_uris_json is null");
     }
 
     let track_uris_str = unsafe {
         match CStr::from_ptr(track_uris_json).to_str() {
             Ok(s) => s.to_string(),
-            Err(_) => {
-                eprintln!("Play tracks error: invalid track_uris_json string");
-                return -1;
-            }
+            Err(_) => return error::fail(
+                SpotiflyError::InvalidArgument,
+                "Play tracks error: invalid track_uris_json string",
+            ),
         }
     };
 
     // Parse JSON array of track URIs
     let track_uris: Vec<String> = match serde_json::from_str(&track_uris_str) {
         Ok(uris) => uris,
-        Err(e) => {
-            eprintln!("Play tracks error: failed to parse JSON: {:?}", e);
-            return -1;
-        }
+        Err(e) => return error::fail(
+            SpotiflyError::InvalidArgument,
+            format!("Play tracks error: failed to parse JSON: {:?}", e),
+        ),
     };
 
     if track_uris.is_empty() {
-        eprintln!("Play tracks error: empty track URIs array");
-        return -1;
+        return error::fail(SpotiflyError::InvalidArgument, "Play tracks error: empty track URIs array");
     }
 
     let player_guard = PLAYER.lock().unwrap();
     let player = match player_guard.as_ref() {
         Some(p) => Arc::clone(p),
-        None => {
-            eprintln!("Play tracks error: player not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Play tracks error: player not initialized"),
     };
     drop(player_guard);
 
     let session_guard = SESSION.lock().unwrap();
     let session = match session_guard.as_ref() {
         Some(s) => s.clone(),
-        None => {
-            eprintln!("Play tracks error: session not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Play tracks error: session not initialized"),
     };
     drop(session_guard);
 
-    let result: Result<(), String> = RUNTIME.block_on(async {
-        let mut queue_items = Vec::new();
-
-        // Load metadata for all tracks
-        for uri_str in &track_uris {
-            let spotify_uri = parse_spotify_uri(uri_str)?;
-
-            match spotify_uri {
-                SpotifyUri::Track { .. } => {
-                    let track = Track::get(&session, &spotify_uri).await
-                        .map_err(|e| format!("Failed to load track {}: {:?}", uri_str, e))?;
-
-                    let track_name = track.name.clone();
-                    let artist_name = track.artists.iter()
-                        .map(|a| a.name.clone())
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    let album_art_url = get_album_art_url(&track);
-                    let duration_ms = track.duration as u32;
-
-                    let queue_item = QueueItem {
-                        uri: uri_str.clone(),
-                        track_name,
-                        artist_name,
-                        album_art_url,
-                        duration_ms,
-                        album_id: get_album_id(&track),
-                        artist_id: get_artist_id(&track),
-                        external_url: get_external_url(&uri_str),
-                    };
-
-                    queue_items.push(queue_item);
-                }
-                _ => {
-                    return Err(format!("Invalid track URI: {}", uri_str));
-                }
-            }
-        }
+    // Explicitly chosen content replaces any running station
+    station::stop_station();
+    player.set_auto_normalise_as_album(false);
+
+    let load_id = loading::begin_load();
+    let result: Result<(), Failure> = RUNTIME.block_on(loading::unless_cancelled(load_id, async {
+        // Load metadata for all tracks; any failure other than the explicit filter
+        // leaving a track out (sending ExplicitSkipped) fails the whole request
+        let mut filtered = 0;
+        let queue_items = load_queue_items(&session, &track_uris).await
+            .into_iter()
+            .filter(|result| {
+                let is_filtered = matches!(result, Err(failure) if explicit_filter::is_filtered(failure));
+                filtered += usize::from(is_filtered);
+                !is_filtered
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         if queue_items.is_empty() {
-            return Err("No valid tracks loaded".to_string());
+            if filtered > 0 {
+                return Err(Failure::new(
+                    SpotiflyError::Unavailable,
+                    format!("{}: all {} tracks", explicit_filter::FILTERED, filtered),
+                ));
+            }
+            return Err(Failure::new(SpotiflyError::Unavailable, "No valid tracks loaded"));
         }
 
-        // Update queue
-        let mut queue_guard = QUEUE.lock().unwrap();
-        queue_guard.clear();
-        queue_guard.extend(queue_items);
-        drop(queue_guard);
-
-        CURRENT_INDEX.store(0, Ordering::SeqCst);
+        // Play the first queue item: the track as relinked for the user, so player events
+        // carry the URI the queue knows it by
+        let first_uri = parse_spotify_uri(&queue_items[0].uri)?;
 
-        // Load and play first track
-        let first_uri = parse_spotify_uri(&track_uris[0])?;
-        player.load(first_uri, true, 0);
+        queue_controller::replace(queue_items).await?;
+        load_track(&player, first_uri);
 
         Ok(())
-    });
+    }));
 
     match result {
         Ok(_) => 0,
         Err(e) => {
-            eprintln!("Play tracks error: {}", e);
-            -1
+            error::report("Play tracks", e)
         }
     }
 }
 
 /// Plays content by its Spotify URI or URL.
-/// Supports tracks, albums, playlists, and artists.
-/// Returns 0 on success, -1 on error.
+/// Supports tracks, albums, playlists, artists, podcast episodes and shows.
+/// Collections start playing once their first track has loaded; the rest of the queue
+/// fills in afterwards, announced by QueueUpdated events.
+/// Returns 0 on success, a negative error code on error.
 #[no_mangle]
 pub extern "C" fn spotifly_play_track(uri_or_url: *const c_char) -> i32 {
+    power::note_activity();
     if uri_or_url.is_null() {
-        eprintln!("Play error: uri_or_url is null");
-        return -1;
+        return error::fail(SpotiflyError::InvalidArgument, "Play error: uri_or_url is null");
     }
 
     let input_str = unsafe {
         match CStr::from_ptr(uri_or_url).to_str() {
             Ok(s) => s.to_string(),
-            Err(_) => {
-                eprintln!("Play error: invalid uri_or_url string");
-                return -1;
-            }
+            Err(_) => return error::fail(SpotiflyError::InvalidArgument, "Play error: invalid uri_or_url string"),
+        }
+    };
+
+    play_content(input_str, None, None)
+}
+
+/// Plays content by its Spotify URI or URL, starting a collection at a given item and
+/// position instead of its first item, e.g. to resume a playlist where it was left.
+/// Without a start item, a collection link's highlighted track (as in share links) is
+/// played first, or else the first item. If the start item can't be played, playback
+/// starts at the next playable item, from its beginning.
+/// Returns 0 on success, a negative error code on error (NotFound if the start item
+/// isn't in the collection).
+///
+/// # Parameters
+/// - uri_or_url: Spotify URI or URL of a track, album, playlist, artist, episode or show
+/// - start_item: Index (e.g. "36") or URI/URL of the item to start at, or NULL.
+///   Ignored for a single track or episode.
+/// - position_ms: Position in the start item to start at, or 0 for its usual start
+///   (the beginning, or where an episode was left off)
+#[no_mangle]
+pub extern "C" fn spotifly_play_context_at(
+    uri_or_url: *const c_char,
+    start_item: *const c_char,
+    position_ms: u32,
+) -> i32 {
+    power::note_activity();
+    let action = "Play context";
+    let input_str = match (!uri_or_url.is_null()).then(|| unsafe { CStr::from_ptr(uri_or_url) }.to_str()) {
+        Some(Ok(s)) => s.to_string(),
+        _ => return error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid uri_or_url", action)),
+    };
+    let start = if start_item.is_null() {
+        None
+    } else {
+        let Ok(start_str) = unsafe { CStr::from_ptr(start_item) }.to_str() else {
+            return error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid start_item string", action));
+        };
+        match start_str.trim().parse::<usize>() {
+            Ok(index) => Some(StartItem::Index(index)),
+            Err(_) => match links::parse_link(start_str) {
+                Some(link) => Some(StartItem::Uri(link.uri)),
+                None => return error::fail(
+                    SpotiflyError::InvalidUri,
+                    format!("{} error: start_item is not an index or URI: {}", action, start_str),
+                ),
+            },
         }
     };
 
-    // Convert URL to URI if needed
-    let uri_str = url_to_uri(&input_str);
+    play_content(input_str, start, (position_ms > 0).then_some(position_ms))
+}
+
+/// The item of a collection to start playing at.
+enum StartItem {
+    Index(usize),
+    Uri(String),
+}
+
+// Where a collection starts: the requested item, else the link's highlighted track
+// (if it is in the collection), else the first item
+fn context_start(uris: &[String], start: Option<&StartItem>, highlight: Option<&str>) -> Result<usize, Failure> {
+    match start {
+        Some(StartItem::Index(index)) if *index < uris.len() => Ok(*index),
+        Some(StartItem::Index(index)) => {
+            Err(Failure::new(
+                SpotiflyError::InvalidArgument,
+                format!("Start item {} not found (the collection has {} items)", index, uris.len()),
+            ))
+        }
+        Some(StartItem::Uri(uri)) => uris.iter()
+            .position(|item_uri| item_uri == uri)
+            .ok_or_else(|| {
+                Failure::new(SpotiflyError::InvalidArgument, format!("Start item {} not found in the collection", uri))
+            }),
+        None => Ok(highlight
+            .and_then(|highlight| uris.iter().position(|uri| uri == highlight))
+            .unwrap_or(0)),
+    }
+}
+
+// Loads `uri` and starts playing it at `position_ms`, or where load_track() would
+pub(crate) fn load_track_from(player: &Player, uri: SpotifyUri, position_ms: Option<u32>) {
+    match position_ms {
+        Some(position_ms) => load_item(player, uri, true, position_ms),
+        None => load_track(player, uri),
+    }
+}
 
+// Plays a track, collection or episode, starting collections at `start` (see
+// spotifly_play_context_at()) and the first item at `position_ms`
+fn play_content(input_str: String, start: Option<StartItem>, position_ms: Option<u32>) -> i32 {
     let player_guard = PLAYER.lock().unwrap();
     let player = match player_guard.as_ref() {
         Some(p) => Arc::clone(p),
-        None => {
-            eprintln!("Play error: player not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Play error: player not initialized"),
     };
     drop(player_guard);
 
     let session_guard = SESSION.lock().unwrap();
     let session = match session_guard.as_ref() {
         Some(s) => s.clone(),
-        None => {
-            eprintln!("Play error: session not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Play error: session not initialized"),
     };
     drop(session_guard);
 
-    let result: Result<(), String> = RUNTIME.block_on(async {
+    // Explicitly chosen content replaces any running station
+    station::stop_station();
+
+    let load_id = loading::begin_load();
+    let result: Result<(), Failure> = RUNTIME.block_on(loading::unless_cancelled(load_id, async {
+        // Convert URL to URI if needed (resolving short links)
+        let link = links::resolve_link(&session, &input_str).await?;
+        let uri_str = link.uri;
+
         // Parse the URI to determine type
         let spotify_uri = parse_spotify_uri(&uri_str)?;
 
-        match spotify_uri {
-            SpotifyUri::Track { .. } => {
-                // Single track - create queue with one item
-                let track = Track::get(&session, &spotify_uri).await
-                    .map_err(|e| format!("Failed to load track: {:?}", e))?;
-
-                let track_name = track.name.clone();
-                let artist_name = track.artists.iter()
-                    .map(|a| a.name.clone())
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                let album_art_url = get_album_art_url(&track);
-                let duration_ms = track.duration as u32;
-
-                let queue_item = QueueItem {
-                    uri: uri_str.clone(),
-                    track_name,
-                    artist_name,
-                    album_art_url,
-                    duration_ms,
-                    album_id: get_album_id(&track),
-                    artist_id: get_artist_id(&track),
-                    external_url: get_external_url(&uri_str),
-                };
+        // Auto normalisation uses album gain while an album plays
+        player.set_auto_normalise_as_album(matches!(spotify_uri, SpotifyUri::Album { .. }));
 
-                let mut queue_guard = QUEUE.lock().unwrap();
-                queue_guard.clear();
-                queue_guard.push(queue_item);
-                drop(queue_guard);
-
-                CURRENT_INDEX.store(0, Ordering::SeqCst);
-                player.load(spotify_uri, true, 0);
+        match spotify_uri {
+            SpotifyUri::Track { .. } | SpotifyUri::Episode { .. } => {
+                // Single item - create queue with one item, playing an unavailable track's
+                // playable alternative in its place
+                let queue_item = load_queue_item(&session, &uri_str).await?;
+                let item_uri = parse_spotify_uri(&queue_item.uri)?;
+
+                queue_controller::replace(vec![queue_item]).await?;
+                load_track_from(&player, item_uri, position_ms);
             }
-            SpotifyUri::Album { .. } => {
-                // Load album tracks
-                let queue_items = load_album(&session, spotify_uri.clone()).await?;
-
-                if queue_items.is_empty() {
-                    return Err("Album has no tracks".to_string());
+            SpotifyUri::Album { .. }
+            | SpotifyUri::Playlist { .. }
+            | SpotifyUri::Artist { .. }
+            | SpotifyUri::Show { .. } => {
+                let uris = match spotify_uri {
+                    SpotifyUri::Album { .. } => album_track_uris(&session, &spotify_uri).await?,
+                    SpotifyUri::Playlist { .. } => playlist_item_uris(&session, &spotify_uri).await?,
+                    SpotifyUri::Artist { .. } => artist_track_uris(&session, &spotify_uri).await?,
+                    _ => podcasts::show_episode_uris(&session, &spotify_uri).await?,
+                };
+                let start_index = context_start(&uris, start.as_ref(), link.highlight.as_deref())?;
+                let playlist_uris = matches!(spotify_uri, SpotifyUri::Playlist { .. }).then(|| uris.clone());
+                loading::play_progressively(&session, &player, uris, start_index, position_ms, load_id).await?;
+                if let Some(playlist_uris) = playlist_uris {
+                    playlist_updates::watch(&session, &uri_str, &playlist_uris, load_id);
                 }
-
-                let mut queue_guard = QUEUE.lock().unwrap();
-                queue_guard.clear();
-                queue_guard.extend(queue_items);
-                drop(queue_guard);
-
-                CURRENT_INDEX.store(0, Ordering::SeqCst);
-
-                // Load first track
-                let first_uri = parse_spotify_uri(&QUEUE.lock().unwrap()[0].uri)?;
-                player.load(first_uri, true, 0);
             }
-            SpotifyUri::Playlist { .. } => {
-                // Load playlist tracks
-                let queue_items = load_playlist(&session, spotify_uri.clone()).await?;
-
-                if queue_items.is_empty() {
-                    return Err("Playlist has no tracks".to_string());
-                }
-
-                let mut queue_guard = QUEUE.lock().unwrap();
-                queue_guard.clear();
-                queue_guard.extend(queue_items);
-                drop(queue_guard);
-
-                CURRENT_INDEX.store(0, Ordering::SeqCst);
-
-                // Load first track
-                let first_uri = parse_spotify_uri(&QUEUE.lock().unwrap()[0].uri)?;
-                player.load(first_uri, true, 0);
+            _ => {
+                return Err(Failure::new(SpotiflyError::InvalidUri, format!("Unsupported URI type: {}", uri_str)));
             }
-            SpotifyUri::Artist { .. } => {
-                // Load artist top tracks
-                let queue_items = load_artist(&session, spotify_uri.clone()).await?;
-
-                if queue_items.is_empty() {
-                    return Err("Artist has no top tracks".to_string());
-                }
-
-                let mut queue_guard = QUEUE.lock().unwrap();
-                queue_guard.clear();
-                queue_guard.extend(queue_items);
-                drop(queue_guard);
+        }
 
-                CURRENT_INDEX.store(0, Ordering::SeqCst);
-
-                // Load first track
-                let first_uri = parse_spotify_uri(&QUEUE.lock().unwrap()[0].uri)?;
-                player.load(first_uri, true, 0);
-            }
-            _ => {
-                return Err(format!("Unsupported URI type: {}", uri_str));
-            }
-        }
-
-        Ok(())
-    });
+        Ok(())
+    }));
 
     match result {
         Ok(_) => {
@@ -785,64 +1279,129 @@ pub extern "C" fn spotifly_play_track(uri_or_url: *const c_char) -> i32 {
             0
         }
         Err(e) => {
-            eprintln!("Play error: {}", e);
-            -1
+            error::report("Play", e)
         }
     }
 }
 
-/// Pauses playback.
-/// Returns 0 on success, -1 on error.
+/// Pauses playback, fading out first unless fades are off (see spotifly_set_pause_fade_ms()).
+/// Returns 0 on success, a negative error code on error.
 #[no_mangle]
 pub extern "C" fn spotifly_pause() -> i32 {
     let player_guard = PLAYER.lock().unwrap();
     match player_guard.as_ref() {
         Some(player) => {
-            player.pause();
-            IS_PLAYING.store(false, Ordering::SeqCst);
+            if IS_PLAYING.swap(false, Ordering::SeqCst) && fades::enabled() {
+                let player = Arc::clone(player);
+                fades::fade_out_then(move || player.pause());
+            } else {
+                player.pause();
+            }
             0
         }
         None => {
-            eprintln!("Pause error: player not initialized");
-            -1
+            error::fail(SpotiflyError::NotInitialized, "Pause error: player not initialized")
         }
     }
 }
 
-/// Resumes playback.
-/// Returns 0 on success, -1 on error.
+/// Resumes playback, fading in unless fades are off.
+/// Returns 0 on success, a negative error code on error.
 #[no_mangle]
 pub extern "C" fn spotifly_resume() -> i32 {
+    power::note_activity();
     let player_guard = PLAYER.lock().unwrap();
     match player_guard.as_ref() {
         Some(player) => {
-            player.play();
-            IS_PLAYING.store(true, Ordering::SeqCst);
+            if !IS_PLAYING.swap(true, Ordering::SeqCst) && fades::enabled() {
+                fades::fade_in_after(|| player.play());
+            } else {
+                player.play();
+            }
             0
         }
         None => {
-            eprintln!("Resume error: player not initialized");
-            -1
+            error::fail(SpotiflyError::NotInitialized, "Resume error: player not initialized")
         }
     }
 }
 
-/// Stops playback completely.
-/// Returns 0 on success, -1 on error.
+/// Stops playback completely, fading out first unless fades are off.
+/// Blocks until buffered audio has been flushed and the output is silent.
+/// Returns 0 on success, a negative error code on error.
 #[no_mangle]
 pub extern "C" fn spotifly_stop() -> i32 {
     let player_guard = PLAYER.lock().unwrap();
-    match player_guard.as_ref() {
-        Some(player) => {
-            player.stop();
-            IS_PLAYING.store(false, Ordering::SeqCst);
-            0
-        }
-        None => {
-            eprintln!("Stop error: player not initialized");
-            -1
-        }
+    let player = match player_guard.as_ref() {
+        Some(p) => Arc::clone(p),
+        None => return error::fail(SpotiflyError::NotInitialized, "Stop error: player not initialized"),
+    };
+    drop(player_guard);
+
+    if IS_PLAYING.load(Ordering::SeqCst) && fades::enabled() {
+        fades::fade_out_blocking();
     }
+    stop_and_drain(&player);
+    fades::reset();
+    IS_PLAYING.store(false, Ordering::SeqCst);
+    0
+}
+
+/// Stops playback, waits for the output to go silent and tears down the player,
+/// Connect and session. The queue is cleared.
+/// spotifly_init_player() must be called again before further playback.
+/// Returns 0 on success, a negative error code if the player was not initialized.
+#[no_mangle]
+pub extern "C" fn spotifly_cleanup_player() -> i32 {
+    let player = PLAYER.lock().unwrap().take();
+    let player = match player {
+        Some(p) => p,
+        None => return error::fail(SpotiflyError::NotInitialized, "Cleanup error: player not initialized"),
+    };
+
+    supervisor::stop();
+    stop_and_drain(&player);
+    IS_PLAYING.store(false, Ordering::SeqCst);
+    stats::on_playback_ended(false);
+    stats::flush();
+    preview::reset();
+    crossfade::reset();
+    playlist_updates::reset();
+    buffering::on_idle();
+
+    // Stop the event listener task
+    if let Some(tx) = PLAYER_EVENT_TX.lock().unwrap().take() {
+        let _ = tx.send(());
+    }
+
+    if let Some(spirc) = SPIRC.lock().unwrap().take() {
+        let _ = spirc.shutdown();
+    }
+
+    if let Some(session) = SESSION.lock().unwrap().take() {
+        session.shutdown();
+    }
+
+    MIXER.lock().unwrap().take();
+    connect::stop();
+    station::stop_station();
+    ACCESS_TOKEN.lock().unwrap().take();
+    token_manager::reset();
+    stored_credentials::reset();
+    accounts::reset();
+    set_connection_state(CONNECTION_DISCONNECTED);
+
+    queue_controller::edit(|queue, current_idx| {
+        queue.clear();
+        *current_idx = 0;
+    });
+
+    update_position(0);
+    DURATION_MS.store(0, Ordering::SeqCst);
+
+    // Dropping the last reference joins the player thread and closes the sink
+    drop(player);
+    0
 }
 
 /// Returns 1 if currently playing, 0 otherwise.
@@ -851,6 +1410,31 @@ pub extern "C" fn spotifly_is_playing() -> i32 {
     if IS_PLAYING.load(Ordering::SeqCst) { 1 } else { 0 }
 }
 
+/// Sets the track load timeout in milliseconds (0 disables the watchdog).
+/// If a track hasn't started playing within this time the load is cancelled.
+/// Default is 15000 ms.
+#[no_mangle]
+pub extern "C" fn spotifly_set_load_timeout_ms(timeout_ms: u32) {
+    LOAD_TIMEOUT_MS.store(timeout_ms, Ordering::SeqCst);
+}
+
+/// Sets whether a timed-out load skips to the next track in the queue.
+/// Disabled by default.
+#[no_mangle]
+pub extern "C" fn spotifly_set_skip_on_load_timeout(enabled: bool) {
+    SKIP_ON_LOAD_TIMEOUT.store(enabled, Ordering::SeqCst);
+}
+
+/// Returns the session connection state.
+/// 0 = disconnected, 1 = connected, 2 = suspended (system asleep), 3 = reconnecting,
+/// 4 = idle (disconnected after inactivity, reconnects on the next command)
+/// A lost connection is retried automatically with growing pauses (state 3 meanwhile),
+/// and the current track resumes at its last position once it is back.
+#[no_mangle]
+pub extern "C" fn spotifly_get_connection_state() -> u8 {
+    CONNECTION_STATE.load(Ordering::SeqCst)
+}
+
 /// Returns the current playback position in milliseconds.
 /// If playing, interpolates from last known position.
 /// Returns 0 if not playing or no position available.
@@ -876,154 +1460,117 @@ pub extern "C" fn spotifly_get_position_ms() -> u32 {
     }
 }
 
-/// Skips to the next track in the queue.
-/// Returns 0 on success, -1 on error or if at end of queue.
+/// Skips to the next track in the queue (wrapping to the start when repeating, a random
+/// upcoming track when shuffling).
+/// Returns 0 on success, a negative error code on error or if at end of queue.
 #[no_mangle]
 pub extern "C" fn spotifly_next() -> i32 {
-    let queue_guard = QUEUE.lock().unwrap();
-    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
-
-    if current_idx + 1 >= queue_guard.len() {
-        drop(queue_guard);
-        eprintln!("Next error: already at last track");
-        return -1;
-    }
-
-    let next_track = queue_guard[current_idx + 1].clone();
-    drop(queue_guard);
-
-    CURRENT_INDEX.store(current_idx + 1, Ordering::SeqCst);
-
+    power::note_activity();
     let player_guard = PLAYER.lock().unwrap();
     let player = match player_guard.as_ref() {
         Some(p) => Arc::clone(p),
-        None => {
-            eprintln!("Next error: player not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Next error: player not initialized"),
     };
     drop(player_guard);
 
-    let result = RUNTIME.block_on(async {
-        parse_spotify_uri(&next_track.uri)
-    });
-
-    match result {
-        Ok(uri) => {
-            player.load(uri, true, 0);
-            IS_PLAYING.store(true, Ordering::SeqCst);
-            0
-        }
-        Err(e) => {
-            eprintln!("Next error: {}", e);
-            -1
-        }
+    match queue_controller::request_next(&player) {
+        Ok(()) => 0,
+        Err(failure) => error::report("Next", failure),
     }
 }
 
-/// Skips to the previous track in the queue.
-/// Returns 0 on success, -1 on error or if at start of queue.
+/// Skips to the previous track in the queue (wrapping to the end when repeating), or
+/// restarts the current track if playback is further into it than the jump-back window
+/// (see spotifly_set_previous_restart_ms()).
+/// Returns 0 on success, a negative error code on error or if at start of queue.
 #[no_mangle]
 pub extern "C" fn spotifly_previous() -> i32 {
-    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
-
-    if current_idx == 0 {
-        eprintln!("Previous error: already at first track");
-        return -1;
-    }
-
-    let queue_guard = QUEUE.lock().unwrap();
-    let prev_track = queue_guard[current_idx - 1].clone();
-    drop(queue_guard);
-
-    CURRENT_INDEX.store(current_idx - 1, Ordering::SeqCst);
-
+    power::note_activity();
     let player_guard = PLAYER.lock().unwrap();
     let player = match player_guard.as_ref() {
         Some(p) => Arc::clone(p),
-        None => {
-            eprintln!("Previous error: player not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Previous error: player not initialized"),
     };
     drop(player_guard);
 
-    let result = RUNTIME.block_on(async {
-        parse_spotify_uri(&prev_track.uri)
-    });
-
-    match result {
-        Ok(uri) => {
-            player.load(uri, true, 0);
-            IS_PLAYING.store(true, Ordering::SeqCst);
-            0
-        }
-        Err(e) => {
-            eprintln!("Previous error: {}", e);
-            -1
-        }
+    match queue_controller::request_previous(&player) {
+        Ok(()) => 0,
+        Err(failure) => error::report("Previous", failure),
     }
 }
 
 /// Seeks to the given position in milliseconds.
-/// Returns 0 on success, -1 on error.
+/// Positions past the end of the track are clamped to the track duration.
+/// Returns 0 on success, a negative error code on error.
 #[no_mangle]
 pub extern "C" fn spotifly_seek(position_ms: u32) -> i32 {
+    power::note_activity();
     let player_guard = PLAYER.lock().unwrap();
     let player = match player_guard.as_ref() {
         Some(p) => Arc::clone(p),
-        None => {
-            eprintln!("Seek error: player not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Seek error: player not initialized"),
     };
     drop(player_guard);
 
+    let duration_ms = DURATION_MS.load(Ordering::SeqCst);
+    let position_ms = if duration_ms > 0 { position_ms.min(duration_ms) } else { position_ms };
+
     player.seek(position_ms);
+    // Report the new position right away; the Seeked event confirms it shortly after
+    update_position(position_ms);
     0
 }
 
+/// Seeks to the given position in milliseconds.
+/// Same as spotifly_seek().
+/// Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_seek_ms(position_ms: u32) -> i32 {
+    spotifly_seek(position_ms)
+}
+
+/// Returns the duration of the current track in milliseconds.
+/// Returns 0 if no track is loaded.
+#[no_mangle]
+pub extern "C" fn spotifly_get_duration_ms() -> u32 {
+    DURATION_MS.load(Ordering::SeqCst)
+}
+
 /// Jumps to a specific track in the queue by index and starts playing.
-/// Returns 0 on success, -1 on error.
+/// Returns 0 on success, a negative error code on error.
 #[no_mangle]
 pub extern "C" fn spotifly_jump_to_index(index: usize) -> i32 {
-    let queue_guard = QUEUE.lock().unwrap();
-
-    if index >= queue_guard.len() {
-        eprintln!("Jump error: index {} out of bounds (queue length: {})", index, queue_guard.len());
-        drop(queue_guard);
-        return -1;
-    }
+    play_queue_index(index, false)
+}
 
-    let target_track = queue_guard[index].clone();
-    drop(queue_guard);
+/// Plays the queue item at the given index (e.g. a clicked row in a queue view).
+/// Same as spotifly_jump_to_index(). Returns 0 on success, a negative error code if the index is out
+/// of bounds or the player is not initialized; the queue is left unchanged on error.
+#[no_mangle]
+pub extern "C" fn spotifly_play_queue_index(index: usize) -> i32 {
+    play_queue_index(index, false)
+}
 
-    CURRENT_INDEX.store(index, Ordering::SeqCst);
+/// Jumps to a specific track in the queue by index and starts playing where it
+/// was last left off (from the start if it was never played or completed).
+/// Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_resume_queue_item(index: usize) -> i32 {
+    play_queue_index(index, true)
+}
 
+fn play_queue_index(index: usize, resume: bool) -> i32 {
+    power::note_activity();
     let player_guard = PLAYER.lock().unwrap();
     let player = match player_guard.as_ref() {
         Some(p) => Arc::clone(p),
-        None => {
-            eprintln!("Jump error: player not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Jump error: player not initialized"),
     };
     drop(player_guard);
 
-    let result = RUNTIME.block_on(async {
-        parse_spotify_uri(&target_track.uri)
-    });
-
-    match result {
-        Ok(uri) => {
-            player.load(uri, true, 0);
-            IS_PLAYING.store(true, Ordering::SeqCst);
-            0
-        }
-        Err(e) => {
-            eprintln!("Jump error: {}", e);
-            -1
-        }
+    match queue_controller::request_jump(index, resume, &player) {
+        Ok(()) => 0,
+        Err(failure) => error::report("Jump", failure),
     }
 }
 
@@ -1050,10 +1597,7 @@ pub extern "C" fn spotifly_get_queue_track_name(index: usize) -> *mut c_char {
         return ptr::null_mut();
     }
 
-    match CString::new(queue_guard[index].track_name.clone()) {
-        Ok(cstr) => cstr.into_raw(),
-        Err(_) => ptr::null_mut(),
-    }
+    to_c_string(&queue_guard[index].track_name)
 }
 
 /// Returns the artist name at the given index.
@@ -1066,10 +1610,7 @@ pub extern "C" fn spotifly_get_queue_artist_name(index: usize) -> *mut c_char {
         return ptr::null_mut();
     }
 
-    match CString::new(queue_guard[index].artist_name.clone()) {
-        Ok(cstr) => cstr.into_raw(),
-        Err(_) => ptr::null_mut(),
-    }
+    to_c_string(&queue_guard[index].artist_name)
 }
 
 /// Returns the album art URL at the given index.
@@ -1082,9 +1623,30 @@ pub extern "C" fn spotifly_get_queue_album_art_url(index: usize) -> *mut c_char
         return ptr::null_mut();
     }
 
-    match CString::new(queue_guard[index].album_art_url.clone()) {
-        Ok(cstr) => cstr.into_raw(),
-        Err(_) => ptr::null_mut(),
+    to_c_string(&queue_guard[index].album_art_url)
+}
+
+/// Returns the album art URL at the given index in the requested size.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL if index is out of bounds or the size is unknown.
+/// Returns an empty string if the track has no album art.
+///
+/// # Parameters
+/// - index: Queue index
+/// - size: 0 = small (about 64 px), 1 = medium (about 300 px), 2 = large (largest available)
+#[no_mangle]
+pub extern "C" fn spotifly_get_queue_album_art_url_sized(index: usize, size: u8) -> *mut c_char {
+    let queue_guard = QUEUE.lock().unwrap();
+    let urls = match queue_guard.get(index) {
+        Some(item) => &item.album_art_urls,
+        None => return ptr::null_mut(),
+    };
+
+    match size {
+        0 => to_c_string(&urls.small),
+        1 => to_c_string(&urls.medium),
+        2 => to_c_string(&urls.large),
+        _ => ptr::null_mut(),
     }
 }
 
@@ -1098,10 +1660,7 @@ pub extern "C" fn spotifly_get_queue_uri(index: usize) -> *mut c_char {
         return ptr::null_mut();
     }
 
-    match CString::new(queue_guard[index].uri.clone()) {
-        Ok(cstr) => cstr.into_raw(),
-        Err(_) => ptr::null_mut(),
-    }
+    to_c_string(&queue_guard[index].uri)
 }
 
 /// Returns the track duration in milliseconds at the given index.
@@ -1126,12 +1685,7 @@ pub extern "C" fn spotifly_get_queue_album_id(index: usize) -> *mut c_char {
     }
 
     match &queue_guard[index].album_id {
-        Some(album_id) => {
-            match CString::new(album_id.clone()) {
-                Ok(cstr) => cstr.into_raw(),
-                Err(_) => ptr::null_mut(),
-            }
-        }
+        Some(album_id) => to_c_string(album_id),
         None => ptr::null_mut(),
     }
 }
@@ -1147,16 +1701,45 @@ pub extern "C" fn spotifly_get_queue_artist_id(index: usize) -> *mut c_char {
     }
 
     match &queue_guard[index].artist_id {
-        Some(artist_id) => {
-            match CString::new(artist_id.clone()) {
-                Ok(cstr) => cstr.into_raw(),
-                Err(_) => ptr::null_mut(),
-            }
-        }
+        Some(artist_id) => to_c_string(artist_id),
         None => ptr::null_mut(),
     }
 }
 
+/// Returns the listened state of a queue item:
+/// 0 = unplayed, 1 = partially played, 2 = completed.
+/// Returns 0 if index is out of bounds.
+#[no_mangle]
+pub extern "C" fn spotifly_get_queue_play_state(index: usize) -> u8 {
+    let queue_guard = QUEUE.lock().unwrap();
+    if index >= queue_guard.len() {
+        return PLAY_STATE_UNPLAYED;
+    }
+    queue_guard[index].play_state
+}
+
+/// Returns the position in milliseconds where a partially played queue item was left off.
+/// Returns 0 if index is out of bounds or the item isn't partially played.
+#[no_mangle]
+pub extern "C" fn spotifly_get_queue_last_position_ms(index: usize) -> u32 {
+    let queue_guard = QUEUE.lock().unwrap();
+    if index >= queue_guard.len() {
+        return 0;
+    }
+    queue_guard[index].last_position_ms
+}
+
+/// Returns the kind of content a queue item plays: 0 = track, 1 = podcast episode.
+/// Returns 0 if index is out of bounds.
+#[no_mangle]
+pub extern "C" fn spotifly_get_queue_item_type(index: usize) -> u8 {
+    let queue_guard = QUEUE.lock().unwrap();
+    if index >= queue_guard.len() {
+        return ITEM_TYPE_TRACK;
+    }
+    queue_guard[index].item_type
+}
+
 /// Gets the external URL for a queue item by index.
 /// Caller must free the string with spotifly_free_string().
 /// Returns NULL if index is out of bounds or external URL is not available.
@@ -1168,12 +1751,7 @@ pub extern "C" fn spotifly_get_queue_external_url(index: usize) -> *mut c_char {
     }
 
     match &queue_guard[index].external_url {
-        Some(external_url) => {
-            match CString::new(external_url.clone()) {
-                Ok(cstr) => cstr.into_raw(),
-                Err(_) => ptr::null_mut(),
-            }
-        }
+        Some(external_url) => to_c_string(external_url),
         None => ptr::null_mut(),
     }
 }
@@ -1187,255 +1765,346 @@ pub extern "C" fn spotifly_get_all_queue_items() -> *mut c_char {
 
     // Serialize the entire queue to JSON
     match serde_json::to_string(&*queue_guard) {
-        Ok(json_string) => {
-            match CString::new(json_string) {
-                Ok(cstr) => cstr.into_raw(),
-                Err(_) => ptr::null_mut(),
-            }
-        }
+        Ok(json_string) => to_c_string(&json_string),
         Err(_) => ptr::null_mut(),
     }
 }
 
-/// Adds a track to the end of the current queue without clearing it.
-/// Returns 0 on success, -1 on error.
+/// Returns a page of the queue as one JSON document:
+/// {"items": [queue item], "total": n, "offset": n, "current_index": n}
+/// Items have every field of the per-index accessors, so a queue view needs one call per page,
+/// plus album_name, track_number and disc_number (0 if unknown), explicit and release_date
+/// ("YYYY-MM-DD", null if unknown).
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - offset: Index of the first item
+/// - limit: Maximum number of items, 0 for all items from offset on
+#[no_mangle]
+pub extern "C" fn spotifly_get_queue_json(offset: usize, limit: usize) -> *mut c_char {
+    let queue_guard = QUEUE.lock().unwrap();
+    let start = offset.min(queue_guard.len());
+    let end = if limit == 0 { queue_guard.len() } else { start.saturating_add(limit).min(queue_guard.len()) };
+
+    let page = json!({
+        "items": &queue_guard[start..end],
+        "total": queue_guard.len(),
+        "offset": start,
+        "current_index": CURRENT_INDEX.load(Ordering::SeqCst),
+    });
+    match serde_json::to_string(&page) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Adds a track (or podcast episode) to the end of the current queue without clearing it.
+/// Returns 0 on success, a negative error code on error.
 #[no_mangle]
 pub extern "C" fn spotifly_add_to_queue(track_uri: *const c_char) -> i32 {
+    power::note_activity();
     if track_uri.is_null() {
-        eprintln!("Add to queue error: track_uri is null");
-        return -1;
+        return error::fail(SpotiflyError::InvalidArgument, "Add to queue error: track_uri is null");
     }
 
     let uri_str = unsafe {
         match CStr::from_ptr(track_uri).to_str() {
             Ok(s) => s.to_string(),
-            Err(_) => {
-                eprintln!("Add to queue error: invalid track_uri string");
-                return -1;
-            }
+            Err(_) => return error::fail(
+                SpotiflyError::InvalidArgument,
+                "Add to queue error: invalid track_uri string",
+            ),
         }
     };
 
     let session_guard = SESSION.lock().unwrap();
     let session = match session_guard.as_ref() {
         Some(s) => s.clone(),
-        None => {
-            eprintln!("Add to queue error: session not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Add to queue error: session not initialized"),
     };
     drop(session_guard);
 
-    let result: Result<(), String> = RUNTIME.block_on(async {
-        // Parse the URI
-        let spotify_uri = parse_spotify_uri(&uri_str)?;
-
-        // Only support tracks for add to queue
-        match spotify_uri {
-            SpotifyUri::Track { .. } => {
-                let track = Track::get(&session, &spotify_uri).await
-                    .map_err(|e| format!("Failed to load track: {:?}", e))?;
-
-                let track_name = track.name.clone();
-                let artist_name = track.artists.iter()
-                    .map(|a| a.name.clone())
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                let album_art_url = get_album_art_url(&track);
-                let duration_ms = track.duration as u32;
-
-                let queue_item = QueueItem {
-                    uri: uri_str.clone(),
-                    track_name,
-                    artist_name,
-                    album_art_url,
-                    duration_ms,
-                    album_id: get_album_id(&track),
-                    artist_id: get_artist_id(&track),
-                    external_url: get_external_url(&uri_str),
-                };
-
-                // Add to queue instead of replacing
-                let mut queue_guard = QUEUE.lock().unwrap();
-                queue_guard.push(queue_item);
-                drop(queue_guard);
+    let result: Result<(), Failure> = RUNTIME.block_on(async {
+        let queue_item = load_queue_item(&session, &uri_str).await?;
 
-                Ok(())
-            }
-            _ => {
-                Err(format!("Only track URIs are supported for add to queue: {}", uri_str))
-            }
-        }
+        // Add to queue instead of replacing
+        queue_controller::edit_async(|queue, _| queue.push(queue_item)).await
+            .ok_or_else(queue_controller::stopped)
     });
 
     match result {
-        Ok(_) => 0,
+        Ok(_) => {
+            loading::emit_queue_updated(false);
+            0
+        }
         Err(e) => {
-            eprintln!("Add to queue error: {}", e);
-            -1
+            error::report("Add to queue", e)
         }
     }
 }
 
-/// Adds a track to play next (after the currently playing track).
+/// Adds a track (or podcast episode) to play next (after the currently playing track).
 /// If nothing is playing, adds it to the queue.
-/// Returns 0 on success, -1 on error.
+/// Returns 0 on success, a negative error code on error.
 #[no_mangle]
 pub extern "C" fn spotifly_add_next_to_queue(track_uri: *const c_char) -> i32 {
+    power::note_activity();
     if track_uri.is_null() {
-        eprintln!("Add next to queue error: track_uri is null");
-        return -1;
+        return error::fail(SpotiflyError::InvalidArgument, "Add next to queue error: track_uri is null");
     }
 
     let uri_str = unsafe {
         match CStr::from_ptr(track_uri).to_str() {
             Ok(s) => s.to_string(),
-            Err(_) => {
-                eprintln!("Add next to queue error: invalid track_uri string");
-                return -1;
-            }
+            Err(_) => return error::fail(
+                SpotiflyError::InvalidArgument,
+                "Add next to queue error: invalid track_uri string",
+            ),
         }
     };
 
     let session_guard = SESSION.lock().unwrap();
     let session = match session_guard.as_ref() {
         Some(s) => s.clone(),
-        None => {
-            eprintln!("Add next to queue error: session not initialized");
-            return -1;
-        }
+        None => return error::fail(SpotiflyError::NotInitialized, "Add next to queue error: session not initialized"),
     };
     drop(session_guard);
 
-    let result: Result<(), String> = RUNTIME.block_on(async {
-        // Parse the URI
-        let spotify_uri = parse_spotify_uri(&uri_str)?;
+    let result: Result<(), Failure> = RUNTIME.block_on(async {
+        let queue_item = load_queue_item(&session, &uri_str).await?;
+
+        // Insert after current index
+        queue_controller::edit_async(|queue, current_idx| {
+            // Insert at current_index + 1, or at the end if queue is empty
+            let insert_position = if queue.is_empty() {
+                0
+            } else {
+                (*current_idx + 1).min(queue.len())
+            };
+            queue.insert(insert_position, queue_item);
+        })
+        .await
+        .ok_or_else(queue_controller::stopped)
+    });
 
-        // Only support tracks for add to queue
-        match spotify_uri {
-            SpotifyUri::Track { .. } => {
-                let track = Track::get(&session, &spotify_uri).await
-                    .map_err(|e| format!("Failed to load track: {:?}", e))?;
-
-                let track_name = track.name.clone();
-                let artist_name = track.artists.iter()
-                    .map(|a| a.name.clone())
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                let album_art_url = get_album_art_url(&track);
-                let duration_ms = track.duration as u32;
-
-                let queue_item = QueueItem {
-                    uri: uri_str.clone(),
-                    track_name,
-                    artist_name,
-                    album_art_url,
-                    duration_ms,
-                    album_id: get_album_id(&track),
-                    artist_id: get_artist_id(&track),
-                    external_url: get_external_url(&uri_str),
-                };
+    match result {
+        Ok(_) => {
+            loading::emit_queue_updated(false);
+            0
+        }
+        Err(e) => {
+            error::report("Add next to queue", e)
+        }
+    }
+}
 
-                // Insert after current index
-                let mut queue_guard = QUEUE.lock().unwrap();
-                let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+// Track and episode URIs of any playable URI, in play order
+async fn context_item_uris(session: &Session, spotify_uri: &SpotifyUri) -> Result<Vec<String>, Failure> {
+    match spotify_uri {
+        SpotifyUri::Track { .. } | SpotifyUri::Episode { .. } => Ok(vec![spotify_uri.to_string()]),
+        SpotifyUri::Album { .. } => album_track_uris(session, spotify_uri).await,
+        SpotifyUri::Playlist { .. } => playlist_item_uris(session, spotify_uri).await,
+        SpotifyUri::Artist { .. } => artist_track_uris(session, spotify_uri).await,
+        SpotifyUri::Show { .. } => podcasts::show_episode_uris(session, spotify_uri).await,
+        _ => Err(Failure::new(SpotiflyError::InvalidUri, format!("Unsupported URI type: {}", spotify_uri))),
+    }
+}
 
-                // Insert at current_index + 1, or at the end if queue is empty
-                let insert_position = if queue_guard.is_empty() {
-                    0
-                } else {
-                    (current_idx + 1).min(queue_guard.len())
-                };
+/// Resolves a track, album, playlist, artist, episode or show exactly like
+/// spotifly_play_track() does and appends its items to the end of the queue.
+/// Current playback is left alone (nothing starts playing). Items that can't be loaded
+/// are left out. Sends QueueUpdated.
+/// Returns 0 on success, a negative error code on error or if nothing could be loaded.
+///
+/// # Parameters
+/// - uri_or_url: Spotify URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_enqueue(uri_or_url: *const c_char) -> i32 {
+    power::note_activity();
+    if uri_or_url.is_null() {
+        return error::fail(SpotiflyError::InvalidArgument, "Enqueue error: uri_or_url is null");
+    }
+    let input_str = match unsafe { CStr::from_ptr(uri_or_url) }.to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return error::fail(SpotiflyError::InvalidArgument, "Enqueue error: invalid uri_or_url string"),
+    };
 
-                queue_guard.insert(insert_position, queue_item);
-                drop(queue_guard);
+    let session_guard = SESSION.lock().unwrap();
+    let session = match session_guard.as_ref() {
+        Some(s) => s.clone(),
+        None => return error::fail(SpotiflyError::NotInitialized, "Enqueue error: session not initialized"),
+    };
+    drop(session_guard);
 
-                Ok(())
-            }
-            _ => {
-                Err(format!("Only track URIs are supported for add next to queue: {}", uri_str))
-            }
+    let result: Result<(), Failure> = RUNTIME.block_on(async {
+        let link = links::resolve_link(&session, &input_str).await?;
+        let spotify_uri = parse_spotify_uri(&link.uri)?;
+        let item_uris = context_item_uris(&session, &spotify_uri).await?;
+
+        let queue_items: Vec<QueueItem> = load_queue_items(&session, &item_uris).await
+            .into_iter()
+            .filter_map(|result| result.map_err(|e| log::warn!("Leaving out queue item: {}", e)).ok())
+            .collect();
+        if queue_items.is_empty() {
+            return Err(Failure::new(
+                SpotiflyError::Unavailable,
+                format!("Nothing playable to enqueue in {}", link.uri),
+            ));
         }
+
+        queue_controller::edit_async(|queue, _| queue.extend(queue_items)).await
+            .ok_or_else(queue_controller::stopped)
     });
 
     match result {
-        Ok(_) => 0,
+        Ok(_) => {
+            loading::emit_queue_updated(false);
+            0
+        }
         Err(e) => {
-            eprintln!("Add next to queue error: {}", e);
-            -1
+            error::report("Enqueue", e)
         }
     }
 }
 
 /// Removes a track from the queue at the given index.
 /// Only allows removing tracks AFTER the current index (unplayed tracks).
-/// Returns 0 on success, -1 on error or if trying to remove a played/playing track.
+/// Returns 0 on success, a negative error code on error or if trying to remove a played/playing track.
 #[no_mangle]
 pub extern "C" fn spotifly_remove_from_queue(index: usize) -> i32 {
-    let mut queue_guard = QUEUE.lock().unwrap();
-    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
-
-    // Validate index: must be after current track and within bounds
-    if index <= current_idx || index >= queue_guard.len() {
-        eprintln!(
-            "Remove from queue error: invalid index {} (current: {}, len: {})",
-            index,
-            current_idx,
-            queue_guard.len()
-        );
-        return -1;
-    }
-
-    queue_guard.remove(index);
-    0
+    let result = queue_controller::edit(move |queue, current_idx| {
+        // Validate index: must be after current track and within bounds
+        if index <= *current_idx || index >= queue.len() {
+            return Err(format!("invalid index {} (current: {}, len: {})", index, current_idx, queue.len()));
+        }
+        queue.remove(index);
+        Ok(())
+    });
+    queue_edited("Remove from queue", result)
 }
 
 /// Moves a track from one position to another in the queue.
 /// Only allows reordering tracks AFTER the current index (unplayed tracks).
-/// Returns 0 on success, -1 on error or if trying to move played/playing tracks.
+/// Returns 0 on success, a negative error code on error or if trying to move played/playing tracks.
 #[no_mangle]
 pub extern "C" fn spotifly_move_queue_item(from_index: usize, to_index: usize) -> i32 {
-    let mut queue_guard = QUEUE.lock().unwrap();
-    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+    let result = queue_controller::edit(move |queue, current_idx| {
+        // Validate indices: both must be after current track and within bounds
+        if from_index <= *current_idx
+            || to_index <= *current_idx
+            || from_index >= queue.len()
+            || to_index >= queue.len()
+        {
+            return Err(format!(
+                "invalid indices from={} to={} (current: {}, len: {})",
+                from_index,
+                to_index,
+                current_idx,
+                queue.len()
+            ));
+        }
+        let item = queue.remove(from_index);
+        queue.insert(to_index, item);
+        Ok(())
+    });
+    queue_edited("Move queue item", result)
+}
 
-    // Validate indices: both must be after current track and within bounds
-    if from_index <= current_idx
-        || to_index <= current_idx
-        || from_index >= queue_guard.len()
-        || to_index >= queue_guard.len()
-    {
-        eprintln!(
-            "Move queue item error: invalid indices from={} to={} (current: {}, len: {})",
-            from_index,
-            to_index,
-            current_idx,
-            queue_guard.len()
-        );
-        return -1;
+/// Clears all tracks after the currently playing track from the queue.
+/// Keeps the currently playing track and all previously played tracks.
+/// Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_clear_upcoming_queue() -> i32 {
+    // Truncate queue to current_idx + 1 (keep current and played)
+    let cleared = queue_controller::edit(|queue, current_idx| {
+        let cleared = *current_idx + 1 < queue.len();
+        queue.truncate(*current_idx + 1);
+        cleared
+    });
+    match cleared {
+        Some(false) => 0,
+        cleared => queue_edited("Clear upcoming queue", cleared.map(|_| Ok(()))),
     }
+}
 
-    if from_index == to_index {
-        return 0; // No-op, success
-    }
+/// Appends a track or episode to the end of the queue.
+/// Same as spotifly_add_to_queue(). Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_queue_add(uri: *const c_char) -> i32 {
+    spotifly_add_to_queue(uri)
+}
 
-    let item = queue_guard.remove(from_index);
-    queue_guard.insert(to_index, item);
-    0
+/// Inserts a track or episode right after the current one.
+/// Same as spotifly_add_next_to_queue(). Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_queue_insert_next(uri: *const c_char) -> i32 {
+    spotifly_add_next_to_queue(uri)
 }
 
-/// Clears all tracks after the currently playing track from the queue.
-/// Keeps the currently playing track and all previously played tracks.
-/// Returns 0 on success, -1 on error.
+/// Removes the queue item at the given index, including already played items.
+/// The current track keeps playing; removing an item before it shifts the current index.
+/// Returns 0 on success, a negative error code if the index is out of bounds or is the current track.
 #[no_mangle]
-pub extern "C" fn spotifly_clear_upcoming_queue() -> i32 {
-    let mut queue_guard = QUEUE.lock().unwrap();
-    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+pub extern "C" fn spotifly_queue_remove(index: usize) -> i32 {
+    let result = queue_controller::edit(move |queue, current_idx| remove_item(queue, current_idx, index));
+    queue_edited("Queue remove", result)
+}
 
-    // Truncate queue to current_idx + 1 (keep current and played)
-    if current_idx + 1 < queue_guard.len() {
-        queue_guard.truncate(current_idx + 1);
+/// Moves a queue item to another position. Any item can be moved, including the
+/// current track; the current index follows the playing track.
+/// Returns 0 on success, a negative error code if an index is out of bounds.
+#[no_mangle]
+pub extern "C" fn spotifly_queue_move(from_index: usize, to_index: usize) -> i32 {
+    let result = queue_controller::edit(move |queue, current_idx| move_item(queue, current_idx, from_index, to_index));
+    queue_edited("Queue move", result)
+}
+
+// Removes the item at `index` (not the current one), keeping `current_idx` on the current item
+fn remove_item(queue: &mut Vec<QueueItem>, current_idx: &mut usize, index: usize) -> Result<(), String> {
+    if index >= queue.len() || index == *current_idx {
+        return Err(format!("invalid index {} (current: {}, len: {})", index, current_idx, queue.len()));
+    }
+
+    queue.remove(index);
+    if index < *current_idx {
+        *current_idx -= 1;
+    }
+    Ok(())
+}
+
+// Moves an item to another position, keeping `current_idx` on the current item
+fn move_item(queue: &mut Vec<QueueItem>, current_idx: &mut usize, from_index: usize, to_index: usize)
+    -> Result<(), String>
+{
+    if from_index >= queue.len() || to_index >= queue.len() {
+        return Err(format!("invalid indices from={} to={} (len: {})", from_index, to_index, queue.len()));
+    }
+
+    let item = queue.remove(from_index);
+    queue.insert(to_index, item);
+
+    if from_index == *current_idx {
+        *current_idx = to_index;
+    } else if from_index < *current_idx && to_index >= *current_idx {
+        *current_idx -= 1;
+    } else if from_index > *current_idx && to_index <= *current_idx {
+        *current_idx += 1;
+    }
+    Ok(())
+}
+
+// Reports the outcome of a queue edit made through the queue controller:
+// sends QueueUpdated on success, an invalid-argument error if the edit was rejected
+fn queue_edited(action: &str, result: Option<Result<(), String>>) -> i32 {
+    match result {
+        Some(Ok(())) => {
+            loading::emit_queue_updated(false);
+            0
+        }
+        Some(Err(e)) => error::fail(SpotiflyError::InvalidArgument, format!("{} error: {}", action, e)),
+        None => error::report(action, queue_controller::stopped()),
     }
-    0
 }
 
 /// Gets radio tracks for a seed track and returns them as JSON.
@@ -1443,8 +2112,9 @@ pub extern "C" fn spotifly_clear_upcoming_queue() -> i32 {
 /// Caller must free the string with spotifly_free_string().
 #[no_mangle]
 pub extern "C" fn spotifly_get_radio_tracks(track_uri: *const c_char) -> *mut c_char {
+    power::note_activity();
     if track_uri.is_null() {
-        eprintln!("Get radio error: track_uri is null");
+        error::fail(SpotiflyError::InvalidArgument, "Get radio error: track_uri is null");
         return ptr::null_mut();
     }
 
@@ -1452,7 +2122,7 @@ pub extern "C" fn spotifly_get_radio_tracks(track_uri: *const c_char) -> *mut c_
         match CStr::from_ptr(track_uri).to_str() {
             Ok(s) => s.to_string(),
             Err(_) => {
-                eprintln!("Get radio error: invalid track_uri string");
+                error::fail(SpotiflyError::InvalidArgument, "Get radio error: invalid track_uri string");
                 return ptr::null_mut();
             }
         }
@@ -1462,23 +2132,23 @@ pub extern "C" fn spotifly_get_radio_tracks(track_uri: *const c_char) -> *mut c_
     let session = match session_guard.as_ref() {
         Some(s) => s.clone(),
         None => {
-            eprintln!("Get radio error: session not initialized");
+            error::fail(SpotiflyError::NotInitialized, "Get radio error: session not initialized");
             return ptr::null_mut();
         }
     };
     drop(session_guard);
 
-    let result: Result<Vec<String>, String> = RUNTIME.block_on(async {
+    let result: Result<Vec<String>, Failure> = RUNTIME.block_on(async {
         // Parse the URI
         let spotify_uri = parse_spotify_uri(&uri_str)?;
 
         // Get radio tracks from Spotify
         let response = session.spclient().get_radio_for_track(&spotify_uri).await
-            .map_err(|e| format!("Failed to get radio: {:?}", e))?;
+            .map_err(|e| error::librespot("Failed to get radio", &e))?;
 
         // Parse the JSON response
         let json: serde_json::Value = serde_json::from_slice(&response)
-            .map_err(|e| format!("Failed to parse radio response: {:?}", e))?;
+            .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Failed to parse radio response: {:?}", e)))?;
 
         // The API returns a playlist URI in mediaItems, not individual tracks
         // Format: { "mediaItems": [{ "uri": "spotify:playlist:xxx" }] }
@@ -1488,7 +2158,7 @@ pub extern "C" fn spotifly_get_radio_tracks(track_uri: *const c_char) -> *mut c_
             .and_then(|item| item.get("uri"))
             .and_then(|u| u.as_str())
             .filter(|uri| uri.starts_with("spotify:playlist:"))
-            .ok_or_else(|| "No radio playlist found in response".to_string())?;
+            .ok_or_else(|| Failure::new(SpotiflyError::NotFound, "No radio playlist found in response"))?;
 
         // Parse the playlist URI
         let playlist_spotify_uri = parse_spotify_uri(playlist_uri)?;
@@ -1502,7 +2172,7 @@ pub extern "C" fn spotifly_get_radio_tracks(track_uri: *const c_char) -> *mut c_
             .collect();
 
         if track_uris.is_empty() {
-            return Err("Radio playlist is empty".to_string());
+            return Err(Failure::new(SpotiflyError::Unavailable, "Radio playlist is empty"));
         }
 
         Ok(track_uris)
@@ -1511,49 +2181,118 @@ pub extern "C" fn spotifly_get_radio_tracks(track_uri: *const c_char) -> *mut c_
     match result {
         Ok(track_uris) => {
             match serde_json::to_string(&track_uris) {
-                Ok(json_string) => {
-                    match CString::new(json_string) {
-                        Ok(cstr) => cstr.into_raw(),
-                        Err(_) => ptr::null_mut(),
-                    }
-                }
+                Ok(json_string) => to_c_string(&json_string),
                 Err(_) => ptr::null_mut(),
             }
         }
         Err(e) => {
-            eprintln!("Get radio error: {}", e);
+            error::report("Get radio", e);
             ptr::null_mut()
         }
     }
 }
 
+/// Sets the mixer volume and notifies player event listeners (VolumeChanged).
+fn apply_volume(volume: u16) -> Result<(), Failure> {
+    let mixer = MIXER.lock().unwrap().clone()
+        .ok_or_else(|| Failure::new(SpotiflyError::NotInitialized, "mixer not initialized"))?;
+    fades::cancel();
+    mixer.set_volume(volume);
+
+    if let Some(player) = PLAYER.lock().unwrap().as_ref() {
+        player.emit_volume_changed_event(volume);
+    }
+    Ok(())
+}
+
 /// Sets the playback volume (0-65535).
-/// Returns 0 on success, -1 on error.
+/// Setting a volume while muted unmutes.
+/// Returns 0 on success, a negative error code on error.
 #[no_mangle]
 pub extern "C" fn spotifly_set_volume(volume: u16) -> i32 {
-    let mixer_guard = MIXER.lock().unwrap();
-    match mixer_guard.as_ref() {
-        Some(mixer) => {
-            mixer.set_volume(volume);
+    match apply_volume(volume) {
+        Ok(_) => {
+            MUTED.store(false, Ordering::SeqCst);
             0
         }
-        None => {
-            eprintln!("Set volume error: mixer not initialized");
-            -1
+        Err(e) => {
+            error::report("Set volume", e)
         }
     }
 }
 
+/// Returns the current playback volume (0-65535).
+/// Returns 0 if the mixer is not initialized.
+#[no_mangle]
+pub extern "C" fn spotifly_get_volume() -> u16 {
+    // The mixer runs below the user's volume while fading on pause
+    if let Some(volume) = fades::user_volume() {
+        return volume;
+    }
+    MIXER.lock().unwrap()
+        .as_ref()
+        .map(|mixer| mixer.volume())
+        .unwrap_or(0)
+}
+
+/// Sets the playback volume as a fraction (0.0 - 1.0).
+/// Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_set_volume_level(level: f32) -> i32 {
+    let level = if level.is_nan() { 0.0 } else { level.clamp(0.0, 1.0) };
+    spotifly_set_volume((level * u16::MAX as f32).round() as u16)
+}
+
+/// Returns the current playback volume as a fraction (0.0 - 1.0).
+#[no_mangle]
+pub extern "C" fn spotifly_get_volume_level() -> f32 {
+    spotifly_get_volume() as f32 / u16::MAX as f32
+}
+
+/// Mutes or unmutes playback. Unmuting restores the volume from before muting.
+/// Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_set_muted(muted: bool) -> i32 {
+    if muted == MUTED.load(Ordering::SeqCst) {
+        return 0;
+    }
+
+    let result = if muted {
+        VOLUME_BEFORE_MUTE.store(spotifly_get_volume(), Ordering::SeqCst);
+        apply_volume(0)
+    } else {
+        apply_volume(VOLUME_BEFORE_MUTE.load(Ordering::SeqCst))
+    };
+
+    match result {
+        Ok(_) => {
+            MUTED.store(muted, Ordering::SeqCst);
+            0
+        }
+        Err(e) => {
+            error::report("Set muted", e)
+        }
+    }
+}
+
+/// Returns true if playback is muted.
+#[no_mangle]
+pub extern "C" fn spotifly_is_muted() -> bool {
+    MUTED.load(Ordering::SeqCst)
+}
+
 /// Sets the streaming bitrate.
 /// 0 = 96 kbps, 1 = 160 kbps (default), 2 = 320 kbps
-/// Note: Takes effect on next player initialization (restart playback to apply).
+/// Takes effect from the next track loaded: the player is then recreated with the new
+/// bitrate, which re-registers the Spotify Connect device on a new session (Connect clients
+/// see it drop out briefly; a ConnectLost event is sent if that fails).
 #[no_mangle]
 pub extern "C" fn spotifly_set_bitrate(bitrate: u8) {
     let value = bitrate.min(2); // Clamp to valid range
     let old_value = BITRATE_SETTING.swap(value, Ordering::SeqCst);
     if old_value != value {
         let kbps = match value { 0 => 96, 2 => 320, _ => 160 };
-        println!("[Spotifly] Bitrate changed to {}kbps (restart playback to apply)", kbps);
+        log::info!("Bitrate changed to {}kbps (applies from the next track)", kbps);
     }
 }
 
@@ -1564,13 +2303,42 @@ pub extern "C" fn spotifly_get_bitrate() -> u8 {
     BITRATE_SETTING.load(Ordering::SeqCst)
 }
 
+/// Sets the streaming bitrate in kbps (96, 160 or 320).
+/// Takes effect from the next track loaded (see spotifly_set_bitrate()).
+/// Returns 0 on success, a negative error code for an unsupported bitrate.
+#[no_mangle]
+pub extern "C" fn spotifly_set_bitrate_kbps(kbps: u16) -> i32 {
+    match kbps {
+        96 => spotifly_set_bitrate(0),
+        160 => spotifly_set_bitrate(1),
+        320 => spotifly_set_bitrate(2),
+        _ => {
+            return error::fail(
+                SpotiflyError::InvalidArgument,
+                format!("Set bitrate error: unsupported bitrate {}kbps", kbps),
+            );
+        }
+    }
+    0
+}
+
+/// Gets the current bitrate setting in kbps (96, 160 or 320).
+#[no_mangle]
+pub extern "C" fn spotifly_get_bitrate_kbps() -> u16 {
+    match BITRATE_SETTING.load(Ordering::SeqCst) {
+        0 => 96,
+        2 => 320,
+        _ => 160,
+    }
+}
+
 /// Sets gapless playback (true = enabled, false = disabled).
 /// Enabled by default. Takes effect on next player initialization (restart playback to apply).
 #[no_mangle]
 pub extern "C" fn spotifly_set_gapless(enabled: bool) {
     let old_value = GAPLESS_SETTING.swap(enabled, Ordering::SeqCst);
     if old_value != enabled {
-        println!("[Spotifly] Gapless playback changed to {} (restart playback to apply)", enabled);
+        log::info!("Gapless playback changed to {} (restart playback to apply)", enabled);
     }
 }
 
@@ -1579,3 +2347,110 @@ pub extern "C" fn spotifly_set_gapless(enabled: bool) {
 pub extern "C" fn spotifly_get_gapless() -> bool {
     GAPLESS_SETTING.load(Ordering::SeqCst)
 }
+
+/// Sets the directory where credentials, volume, audio files and artwork are cached, so they
+/// persist across launches. Audio files go into an "audio" subdirectory, images fetched with
+/// spotifly_fetch_artwork() into an "artwork" subdirectory.
+/// Takes effect on next player initialization (restart playback to apply), immediately for artwork.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - path: Cache directory, NULL = no caching (default)
+/// - max_audio_cache_bytes: Size limit for cached audio files, 0 = unlimited
+#[no_mangle]
+pub extern "C" fn spotifly_set_cache_dir(path: *const c_char, max_audio_cache_bytes: u64) -> i32 {
+    if path.is_null() {
+        CACHE_SETTINGS.lock().unwrap().take();
+        log::info!("Cache disabled (restart playback to apply)");
+        return 0;
+    }
+
+    let path_str = unsafe {
+        match CStr::from_ptr(path).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => return error::fail(SpotiflyError::InvalidArgument, "Set cache dir error: invalid path string"),
+        }
+    };
+
+    *CACHE_SETTINGS.lock().unwrap() = Some(CacheSettings {
+        dir: PathBuf::from(&path_str),
+        audio_size_limit: (max_audio_cache_bytes > 0).then_some(max_audio_cache_bytes),
+    });
+    log::info!("Cache directory set to {} (restart playback to apply)", path_str);
+    0
+}
+
+/// Configures volume normalisation.
+/// Disabled by default. Takes effect on next player initialization (restart playback to apply).
+/// Returns 0 on success, a negative error code for an unknown mode or invalid pre-gain.
+///
+/// # Parameters
+/// - enabled: Whether tracks are normalised to a common loudness
+/// - mode: 0 = auto (album gain while playing an album, track gain otherwise),
+///   1 = track, 2 = album
+/// - pregain_db: Gain applied on top of normalisation, in dB (-10.0 to 10.0)
+#[no_mangle]
+pub extern "C" fn spotifly_set_normalization(enabled: bool, mode: u8, pregain_db: f32) -> i32 {
+    if mode > 2 {
+        return error::fail(SpotiflyError::InvalidArgument, format!("Set normalization error: unknown mode {}", mode));
+    }
+    if !pregain_db.is_finite() || pregain_db.abs() > MAX_NORMALIZATION_PREGAIN_DB {
+        return error::fail(
+            SpotiflyError::InvalidArgument,
+            format!("Set normalization error: pre-gain out of range: {}", pregain_db),
+        );
+    }
+
+    let old_enabled = NORMALIZATION_SETTING.swap(enabled, Ordering::SeqCst);
+    let old_mode = NORMALIZATION_TYPE_SETTING.swap(mode, Ordering::SeqCst);
+    let old_pregain = NORMALIZATION_PREGAIN_SETTING.swap(pregain_db.to_bits(), Ordering::SeqCst);
+    if old_enabled != enabled || old_mode != mode || old_pregain != pregain_db.to_bits() {
+        log::info!(
+            "Normalization changed to enabled={}, mode={}, pregain={}dB (restart playback to apply)",
+            enabled, mode, pregain_db,
+        );
+    }
+    0
+}
+
+/// Gets whether volume normalisation is enabled.
+#[no_mangle]
+pub extern "C" fn spotifly_get_normalization_enabled() -> bool {
+    NORMALIZATION_SETTING.load(Ordering::SeqCst)
+}
+
+/// Gets the normalisation mode.
+/// 0 = auto, 1 = track, 2 = album
+#[no_mangle]
+pub extern "C" fn spotifly_get_normalization_mode() -> u8 {
+    NORMALIZATION_TYPE_SETTING.load(Ordering::SeqCst)
+}
+
+/// Gets the normalisation pre-gain in dB.
+#[no_mangle]
+pub extern "C" fn spotifly_get_normalization_pregain() -> f32 {
+    f32::from_bits(NORMALIZATION_PREGAIN_SETTING.load(Ordering::SeqCst))
+}
+
+/// Sets the repeat mode.
+/// 0 = off (stop at the end of the queue), 1 = repeat the queue, 2 = repeat the current track.
+/// Takes effect immediately. Returns 0 on success, a negative error code for an unknown mode.
+#[no_mangle]
+pub extern "C" fn spotifly_set_repeat_mode(mode: u8) -> i32 {
+    match mode {
+        REPEAT_OFF | REPEAT_CONTEXT | REPEAT_TRACK => {
+            REPEAT_MODE.store(mode, Ordering::SeqCst);
+            0
+        }
+        _ => {
+            error::fail(SpotiflyError::InvalidArgument, format!("Set repeat mode error: unknown mode {}", mode))
+        }
+    }
+}
+
+/// Gets the current repeat mode.
+/// 0 = off, 1 = repeat the queue, 2 = repeat the current track
+#[no_mangle]
+pub extern "C" fn spotifly_get_repeat_mode() -> u8 {
+    REPEAT_MODE.load(Ordering::SeqCst)
+}
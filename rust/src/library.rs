@@ -0,0 +1,355 @@
+// The logged-in user's library: playlists (rootlist), saved tracks and follows.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::webapi::{self, PlaylistSummary};
+use crate::{
+    links, load_track, parse_spotify_uri, power, queue_controller, station, to_c_string, QueueItem, IS_PLAYING,
+    PLAYER, RUNTIME,
+};
+use http::Method;
+use librespot_core::session::Session;
+use serde::Serialize;
+use serde_json::Value;
+use std::ffi::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// The Web API returns at most 50 items per page
+const PAGE_SIZE: usize = 50;
+
+/// A saved ("liked") track.
+#[derive(Serialize)]
+pub(crate) struct SavedTrack {
+    #[serde(flatten)]
+    pub item: QueueItem,
+    /// When the track was saved (ISO 8601)
+    pub added_at: String,
+}
+
+// Bumped whenever saved tracks start playing, so an older background load stops
+static SAVED_TRACKS_LOAD: AtomicU64 = AtomicU64::new(0);
+
+/// Fetches all playlists in the user's library (owned and followed), in rootlist order.
+pub(crate) async fn fetch_user_playlists(session: &Session) -> Result<Vec<PlaylistSummary>, Failure> {
+    let mut playlists = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let path = format!("/me/playlists?limit={}&offset={}", PAGE_SIZE, offset);
+        let page = webapi::get(session, &path).await?;
+        let items = page.get("items").and_then(Value::as_array).cloned().unwrap_or_default();
+        playlists.extend(items.iter().filter_map(webapi::playlist_summary_from_json));
+
+        offset += items.len();
+        if items.len() < PAGE_SIZE || page.get("next").is_none_or(Value::is_null) {
+            break;
+        }
+    }
+
+    Ok(playlists)
+}
+
+/// Fetches a page of the user's saved tracks, most recently saved first.
+/// Returns the tracks and the total number of saved tracks.
+pub(crate) async fn fetch_saved_tracks(
+    session: &Session,
+    offset: usize,
+    limit: usize,
+) -> Result<(Vec<SavedTrack>, u64), Failure> {
+    let path = format!("/me/tracks?limit={}&offset={}", limit.clamp(1, PAGE_SIZE), offset);
+    let page = webapi::get(session, &path).await?;
+
+    let tracks = page.get("items").and_then(Value::as_array).into_iter().flatten()
+        .filter_map(|entry| {
+            Some(SavedTrack {
+                item: webapi::queue_item_from_json(entry.get("track")?)?,
+                added_at: webapi::str_field(entry, "added_at"),
+            })
+        })
+        .collect();
+    let total = page.get("total").and_then(Value::as_u64).unwrap_or(0);
+    Ok((tracks, total))
+}
+
+// Appends the rest of the saved tracks to the queue in the background,
+// as long as the queue still holds them
+fn load_remaining_saved_tracks(generation: u64, first_uri: String, mut offset: usize, total: u64) {
+    RUNTIME.spawn(async move {
+        while (offset as u64) < total {
+            let page = async {
+                let session = webapi::current_session()?;
+                fetch_saved_tracks(&session, offset, PAGE_SIZE).await
+            }.await;
+            let tracks = match page {
+                Ok((tracks, _)) if !tracks.is_empty() => tracks,
+                Ok(_) => break,
+                Err(e) => {
+                    log::error!("Load saved tracks error: {}", e);
+                    break;
+                }
+            };
+            offset += PAGE_SIZE;
+
+            let first_uri = first_uri.clone();
+            let still_current = queue_controller::edit_async(move |queue, _| {
+                let still_current = SAVED_TRACKS_LOAD.load(Ordering::SeqCst) == generation
+                    && queue.first().is_some_and(|item| item.uri == first_uri);
+                if still_current {
+                    queue.extend(tracks.into_iter().map(|t| t.item));
+                }
+                still_current
+            });
+            if still_current.await != Some(true) {
+                break;
+            }
+        }
+    });
+}
+
+/// Returns a page of the user's saved ("liked") tracks, most recently saved first, as JSON:
+/// {"items": [queue item + "added_at"], "total": n, "offset": n}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - offset: Index of the first track
+/// - limit: Maximum number of tracks (1-50)
+#[no_mangle]
+pub extern "C" fn spotifly_get_saved_tracks(offset: u32, limit: u32) -> *mut c_char {
+    power::note_activity();
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        fetch_saved_tracks(&session, offset as usize, limit as usize).await
+    });
+
+    match result {
+        Ok((tracks, total)) => {
+            let page = serde_json::json!({
+                "items": tracks,
+                "total": total,
+                "offset": offset,
+            });
+            to_c_string(&page.to_string())
+        }
+        Err(e) => {
+            error::report("Get saved tracks", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Replaces the queue with the user's saved tracks (most recently saved first)
+/// and starts playing. Playback starts after the first page has loaded; the rest
+/// is appended to the queue in the background.
+/// Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_play_saved_tracks() -> i32 {
+    power::note_activity();
+    let player = match PLAYER.lock().unwrap().clone() {
+        Some(p) => p,
+        None => return error::fail(SpotiflyError::NotInitialized, "Play saved tracks error: player not initialized"),
+    };
+
+    // Explicitly chosen content replaces any running station
+    station::stop_station();
+    let generation = SAVED_TRACKS_LOAD.fetch_add(1, Ordering::SeqCst) + 1;
+
+    let result: Result<(String, u64), Failure> = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        let (tracks, total) = fetch_saved_tracks(&session, 0, PAGE_SIZE).await?;
+        let first = tracks.first().ok_or_else(|| Failure::new(SpotiflyError::NotFound, "No saved tracks"))?;
+        let first_uri_str = first.item.uri.clone();
+        let first_uri = parse_spotify_uri(&first_uri_str)?;
+
+        queue_controller::replace(tracks.into_iter().map(|t| t.item).collect()).await?;
+        load_track(&player, first_uri);
+        Ok((first_uri_str, total))
+    });
+
+    match result {
+        Ok((first_uri, total)) => {
+            IS_PLAYING.store(true, Ordering::SeqCst);
+            load_remaining_saved_tracks(generation, first_uri, PAGE_SIZE, total);
+            0
+        }
+        Err(e) => {
+            error::report("Play saved tracks", e)
+        }
+    }
+}
+
+/// Returns the logged-in user's playlists (owned and followed) as a JSON array of
+/// {uri, name, owner_name, owner_id, image_url, track_count, collaborative}.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+#[no_mangle]
+pub extern "C" fn spotifly_get_user_playlists() -> *mut c_char {
+    power::note_activity();
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        fetch_user_playlists(&session).await
+    });
+
+    match result {
+        Ok(playlists) => match serde_json::to_string(&playlists) {
+            Ok(json_string) => to_c_string(&json_string),
+            Err(_) => ptr::null_mut(),
+        },
+        Err(e) => {
+            error::report("Get user playlists", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+// Adds an item to or removes it from the user's library with a PUT or DELETE
+fn update_library(uri: *const c_char, kind: &str, method: Method, path: impl Fn(&str) -> String, action: &str) -> i32 {
+    power::note_activity();
+    let id = match links::id_arg(uri, kind, action) {
+        Ok(id) => id,
+        Err(code) => return code,
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        webapi::request(&session, method, &path(&id), None).await
+    });
+
+    match result {
+        Ok(_) => 0,
+        Err(e) => error::report(action, e),
+    }
+}
+
+// Asks a "contains" endpoint whether an item is in the user's library; returns 1, 0 or an error code
+fn library_contains(uri: *const c_char, kind: &str, path: impl Fn(&str) -> String, action: &str) -> i32 {
+    power::note_activity();
+    let id = match links::id_arg(uri, kind, action) {
+        Ok(id) => id,
+        Err(code) => return code,
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        webapi::get(&session, &path(&id)).await
+    });
+
+    match result.map(|contains| contains.get(0).and_then(Value::as_bool)) {
+        Ok(Some(contained)) => contained as i32,
+        Ok(None) => error::fail(SpotiflyError::Unknown, format!("{} error: unexpected response", action)),
+        Err(e) => error::report(action, e),
+    }
+}
+
+fn saved_tracks_path(id: &str) -> String {
+    format!("/me/tracks?ids={}", id)
+}
+
+fn followed_artists_path(id: &str) -> String {
+    format!("/me/following?type=artist&ids={}", id)
+}
+
+fn playlist_followers_path(id: &str) -> String {
+    format!("/playlists/{}/followers", id)
+}
+
+/// Adds a track to the user's saved ("liked") tracks.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify track URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_save_track(uri: *const c_char) -> i32 {
+    update_library(uri, "track", Method::PUT, saved_tracks_path, "Save track")
+}
+
+/// Removes a track from the user's saved ("liked") tracks.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify track URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_remove_saved_track(uri: *const c_char) -> i32 {
+    update_library(uri, "track", Method::DELETE, saved_tracks_path, "Remove saved track")
+}
+
+/// Checks whether a track is in the user's saved ("liked") tracks.
+/// Returns 1 if it is, 0 if it isn't, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify track URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_is_track_saved(uri: *const c_char) -> i32 {
+    library_contains(uri, "track", |id| format!("/me/tracks/contains?ids={}", id), "Is track saved")
+}
+
+/// Follows an artist.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify artist URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_follow_artist(uri: *const c_char) -> i32 {
+    update_library(uri, "artist", Method::PUT, followed_artists_path, "Follow artist")
+}
+
+/// Unfollows an artist.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify artist URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_unfollow_artist(uri: *const c_char) -> i32 {
+    update_library(uri, "artist", Method::DELETE, followed_artists_path, "Unfollow artist")
+}
+
+/// Checks whether the user follows an artist.
+/// Returns 1 if they do, 0 if they don't, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify artist URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_is_artist_followed(uri: *const c_char) -> i32 {
+    library_contains(
+        uri,
+        "artist",
+        |id| format!("/me/following/contains?type=artist&ids={}", id),
+        "Is artist followed",
+    )
+}
+
+/// Follows a playlist, adding it to the user's library.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify playlist URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_follow_playlist(uri: *const c_char) -> i32 {
+    update_library(uri, "playlist", Method::PUT, playlist_followers_path, "Follow playlist")
+}
+
+/// Unfollows a playlist, removing it from the user's library.
+/// Unfollowing a playlist the user owns deletes it from their profile.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify playlist URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_unfollow_playlist(uri: *const c_char) -> i32 {
+    update_library(uri, "playlist", Method::DELETE, playlist_followers_path, "Unfollow playlist")
+}
+
+/// Checks whether the user follows a playlist.
+/// Returns 1 if they do, 0 if they don't, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify playlist URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_is_playlist_followed(uri: *const c_char) -> i32 {
+    library_contains(
+        uri,
+        "playlist",
+        |id| format!("/playlists/{}/followers/contains", id),
+        "Is playlist followed",
+    )
+}
@@ -0,0 +1,362 @@
+// Spotify link parsing.
+//
+// Turns the many shapes of Spotify share links into canonical `spotify:` URIs:
+// - `spotify:track:xxx` URIs (including legacy `spotify:user:<name>:playlist:xxx`)
+// - `spotify://track/xxx` app deep links
+// - `https://open.spotify.com/[intl-xx/][embed/]<type>/<id>?si=...`, with or without the scheme
+// - legacy `https://open.spotify.com/user/<name>/playlist/<id>` links
+// - legacy `https://embed.spotify.com/?uri=spotify:track:xxx` embeds
+// - album/playlist links with a `highlight=spotify:track:xxx` anchor
+// - `https://spotify.link/...` and `spotify.app.link` short links (resolved by following
+//   the redirect)
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{power, to_c_string, webapi, RUNTIME};
+use bytes::Bytes;
+use http::header::LOCATION;
+use http::{Method, Request};
+use librespot_core::session::Session;
+use serde_json::json;
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+const SHORT_LINK_HOSTS: &[&str] = &["spotify.link/", "spotify.app.link/"];
+const MAX_REDIRECTS: usize = 5;
+
+// Content types that can appear in open.spotify.com links
+const CONTENT_TYPES: &[&str] = &["track", "album", "playlist", "artist", "episode", "show"];
+
+/// A parsed Spotify link.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SpotifyLink {
+    /// Canonical URI, e.g. "spotify:album:xxx"
+    pub uri: String,
+    /// Track URI highlighted inside an album/playlist link, if any
+    pub highlight: Option<String>,
+}
+
+/// Strips an http(s) scheme, if any.
+fn without_http_scheme(input: &str) -> &str {
+    input
+        .strip_prefix("https://")
+        .or_else(|| input.strip_prefix("http://"))
+        .unwrap_or(input)
+}
+
+/// Returns true for `spotify.link` short links, which need a network round-trip to resolve.
+pub(crate) fn is_short_link(input: &str) -> bool {
+    let without_scheme = without_http_scheme(input.trim());
+    SHORT_LINK_HOSTS.iter().any(|host| without_scheme.starts_with(host))
+}
+
+/// Parses a Spotify URI, app deep link or open.spotify.com URL.
+/// Returns None if the input isn't a recognizable Spotify link.
+pub(crate) fn parse_link(input: &str) -> Option<SpotifyLink> {
+    let input = input.trim();
+
+    // App deep links: spotify://track/<id>
+    if let Some(path) = input.strip_prefix("spotify://") {
+        return parse_path(path);
+    }
+    if input.starts_with("spotify:") {
+        return Some(SpotifyLink {
+            uri: normalize_uri(input),
+            highlight: None,
+        });
+    }
+
+    let without_scheme = without_http_scheme(input);
+    if let Some(after_host) = without_scheme.strip_prefix("embed.spotify.com/") {
+        // Legacy embeds carry the URI in the query: /?uri=spotify:track:xxx
+        let query = after_host.split_once('?').map(|(_, query)| query)?;
+        let uri = query_value(query, "uri").filter(|uri| uri.starts_with("spotify:"))?;
+        return parse_link(&uri);
+    }
+    let after_host = without_scheme
+        .strip_prefix("open.spotify.com/")
+        .or_else(|| without_scheme.strip_prefix("play.spotify.com/"))?;
+    parse_path(after_host)
+}
+
+/// Parses the path (and query) of an open.spotify.com URL or app deep link.
+fn parse_path(after_host: &str) -> Option<SpotifyLink> {
+    // Split off fragment and query string
+    let after_host = after_host.split('#').next().unwrap_or_default();
+    let (path, query) = match after_host.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (after_host, None),
+    };
+
+    // Drop locale prefixes like "intl-de" and embed markers
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .filter(|s| !s.starts_with("intl-"))
+        .filter(|s| *s != "embed" && *s != "embed-legacy")
+        .collect();
+
+    let uri = match segments.as_slice() {
+        // Legacy user playlist form: /user/<name>/playlist/<id>
+        ["user", _, "playlist", id, ..] => format!("spotify:playlist:{}", id),
+        ["user", name] => format!("spotify:user:{}", name),
+        [content_type, id, ..] if CONTENT_TYPES.contains(content_type) => {
+            format!("spotify:{}:{}", content_type, id)
+        }
+        _ => return None,
+    };
+
+    let highlight = query
+        .and_then(|query| query_value(query, "highlight"))
+        .filter(|value| value.starts_with("spotify:track:"));
+
+    Some(SpotifyLink { uri, highlight })
+}
+
+/// Rewrites legacy `spotify:user:<name>:playlist:<id>` URIs to `spotify:playlist:<id>`.
+fn normalize_uri(uri: &str) -> String {
+    let parts: Vec<&str> = uri.split(':').collect();
+    match parts.as_slice() {
+        ["spotify", "user", _, "playlist", id] => format!("spotify:playlist:{}", id),
+        _ => uri.to_string(),
+    }
+}
+
+/// Extracts a (percent-decoded) parameter from a query string.
+fn query_value(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+/// Minimal percent-decoding for query values (enough for `spotify%3Atrack%3Axxx`).
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Reads a URI or URL argument of the given content type ("track", "artist", ...) and
+/// returns its base62 ID. On failure, records the error and returns its code.
+pub(crate) fn id_arg(input: *const c_char, kind: &str, action: &str) -> Result<String, i32> {
+    if input.is_null() {
+        return Err(error::fail(SpotiflyError::InvalidArgument, format!("{} error: uri is null", action)));
+    }
+
+    let input = unsafe {
+        match CStr::from_ptr(input).to_str() {
+            Ok(s) => s,
+            Err(_) => {
+                return Err(error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid uri string", action)));
+            }
+        }
+    };
+
+    let prefix = format!("spotify:{}:", kind);
+    match parse_link(input).and_then(|link| link.uri.strip_prefix(&prefix).map(str::to_string)) {
+        Some(id) => Ok(id),
+        None => Err(error::fail(
+            SpotiflyError::InvalidUri,
+            format!("{} error: not a {} URI: {}", action, kind, input),
+        )),
+    }
+}
+
+/// Follows a `spotify.link` short link's redirects until it lands on an open.spotify.com URL.
+pub(crate) async fn resolve_short_link(session: &Session, url: &str) -> Result<String, Failure> {
+    let mut current = url.trim().to_string();
+    if !current.starts_with("http") {
+        current = format!("https://{}", current);
+    }
+
+    for _ in 0..MAX_REDIRECTS {
+        if parse_link(&current).is_some() {
+            return Ok(current);
+        }
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(current.as_str())
+            .body(Bytes::new())
+            .map_err(|e| Failure::new(SpotiflyError::InvalidUri, format!("Invalid short link: {}", e)))?;
+
+        let response = session.http_client().request_fut(request)
+            .map_err(|e| error::librespot("Failed to resolve short link", &e))?
+            .await
+            .map_err(|e| Failure::new(SpotiflyError::Network, format!("Failed to resolve short link: {}", e)))?;
+
+        if !response.status().is_redirection() {
+            return Err(Failure::new(
+                SpotiflyError::InvalidUri,
+                format!("Short link did not redirect (status {})", response.status()),
+            ));
+        }
+
+        current = response.headers().get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| Failure::new(SpotiflyError::InvalidUri, "Short link redirect has no location"))?
+            .to_string();
+    }
+
+    Err(Failure::new(SpotiflyError::InvalidUri, "Too many redirects resolving short link"))
+}
+
+/// Resolves any supported link form (including short links) to a canonical URI.
+/// Input that isn't a recognizable link is passed through unchanged.
+pub(crate) async fn resolve_link(session: &Session, input: &str) -> Result<SpotifyLink, Failure> {
+    let input = if is_short_link(input) {
+        resolve_short_link(session, input).await?
+    } else {
+        input.trim().to_string()
+    };
+
+    Ok(parse_link(&input).unwrap_or(SpotifyLink {
+        uri: input,
+        highlight: None,
+    }))
+}
+
+/// Resolves a Spotify link in any supported form (URI, `spotify://` deep link,
+/// open.spotify.com or embed URL, legacy user-playlist link, or short link, which needs
+/// the player to be initialized) to its canonical form, as JSON
+/// {uri, content_type, highlight}. content_type is "track", "album", "playlist",
+/// "artist", "episode", "show" or "user"; highlight is the track URI highlighted in an
+/// album or playlist link, or null.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error (InvalidUri if the input isn't a Spotify link).
+///
+/// # Parameters
+/// - input: Spotify URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_resolve_link(input: *const c_char) -> *mut c_char {
+    let action = "Resolve link";
+    let input = match (!input.is_null()).then(|| unsafe { CStr::from_ptr(input) }.to_str()) {
+        Some(Ok(input)) => input,
+        _ => {
+            error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid input", action));
+            return ptr::null_mut();
+        }
+    };
+
+    let link = if is_short_link(input) {
+        power::note_activity();
+        let resolved = webapi::current_session()
+            .and_then(|session| RUNTIME.block_on(resolve_short_link(&session, input)));
+        match resolved {
+            Ok(url) => parse_link(&url),
+            Err(e) => {
+                error::report(action, e);
+                return ptr::null_mut();
+            }
+        }
+    } else {
+        parse_link(input)
+    };
+
+    let Some(link) = link else {
+        error::fail(SpotiflyError::InvalidUri, format!("{} error: not a Spotify link: {}", action, input));
+        return ptr::null_mut();
+    };
+    let content_type = link.uri.split(':').nth(1).unwrap_or_default();
+    let resolved = json!({ "uri": link.uri, "content_type": content_type, "highlight": link.highlight });
+    to_c_string(&resolved.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACK: &str = "spotify:track:4uLU6hMCjMI75M1A2tKUQC";
+    const PLAYLIST: &str = "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M";
+
+    fn link(uri: &str, highlight: Option<&str>) -> Option<SpotifyLink> {
+        Some(SpotifyLink { uri: uri.to_string(), highlight: highlight.map(str::to_string) })
+    }
+
+    #[test]
+    fn parses_uris_and_open_links() {
+        assert_eq!(parse_link(TRACK), link(TRACK, None));
+        assert_eq!(parse_link("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc"), link(TRACK, None));
+        assert_eq!(parse_link("open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC"), link(TRACK, None));
+        assert_eq!(parse_link("https://example.com/track/4uLU6hMCjMI75M1A2tKUQC"), None);
+    }
+
+    #[test]
+    fn drops_intl_prefixes() {
+        assert_eq!(parse_link("https://open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC"), link(TRACK, None));
+        assert_eq!(
+            parse_link("https://open.spotify.com/intl-pt-BR/playlist/37i9dQZF1DXcBWIGoYBM5M?si=x"),
+            link(PLAYLIST, None),
+        );
+    }
+
+    #[test]
+    fn drops_embed_markers() {
+        assert_eq!(parse_link("https://open.spotify.com/embed/track/4uLU6hMCjMI75M1A2tKUQC"), link(TRACK, None));
+        assert_eq!(
+            parse_link("https://open.spotify.com/embed-legacy/playlist/37i9dQZF1DXcBWIGoYBM5M"),
+            link(PLAYLIST, None),
+        );
+    }
+
+    #[test]
+    fn rewrites_legacy_user_playlists() {
+        assert_eq!(
+            parse_link("https://open.spotify.com/user/spotify/playlist/37i9dQZF1DXcBWIGoYBM5M"),
+            link(PLAYLIST, None),
+        );
+        assert_eq!(parse_link("spotify:user:spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"), link(PLAYLIST, None));
+        assert_eq!(parse_link("https://open.spotify.com/user/spotify"), link("spotify:user:spotify", None));
+    }
+
+    #[test]
+    fn parses_app_deep_links() {
+        assert_eq!(parse_link("spotify://track/4uLU6hMCjMI75M1A2tKUQC"), link(TRACK, None));
+        assert_eq!(parse_link("spotify://playlist/37i9dQZF1DXcBWIGoYBM5M"), link(PLAYLIST, None));
+        assert_eq!(parse_link("spotify://nowhere"), None);
+    }
+
+    #[test]
+    fn decodes_highlighted_tracks() {
+        assert_eq!(
+            parse_link(
+                "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M\
+                 ?si=x&highlight=spotify%3Atrack%3A4uLU6hMCjMI75M1A2tKUQC"
+            ),
+            link(PLAYLIST, Some(TRACK)),
+        );
+        // Only tracks can be highlighted
+        assert_eq!(
+            parse_link("https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M?highlight=spotify%3Aalbum%3Axyz"),
+            link(PLAYLIST, None),
+        );
+    }
+
+    #[test]
+    fn parses_legacy_embeds() {
+        assert_eq!(
+            parse_link("https://embed.spotify.com/?uri=spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+            link(TRACK, None),
+        );
+        assert_eq!(
+            parse_link("https://embed.spotify.com/?theme=white&uri=spotify%3Aplaylist%3A37i9dQZF1DXcBWIGoYBM5M"),
+            link(PLAYLIST, None),
+        );
+        assert_eq!(parse_link("https://embed.spotify.com/?uri=https://example.com"), None);
+        assert_eq!(parse_link("https://embed.spotify.com/"), None);
+    }
+}
@@ -0,0 +1,232 @@
+// Non-blocking and cancellable content loads.
+//
+// The blocking play functions fetch metadata for everything they queue, which can take
+// seconds for large playlists. The async variants run them on a separate thread and
+// return a request ID right away; the outcome arrives as a LoadCompleted event carrying
+// that ID.
+//
+// Albums, playlists, artists and shows start playing as soon as their first item has
+// loaded. The rest of the queue fills in behind it in the background, in order, with a
+// QueueUpdated event per batch; items the host adds meanwhile stay after the collection.
+//
+// Each load that replaces the queue gets a load ID. Starting another load (or calling
+// spotifly_cancel_pending_load()) supersedes it: the superseded load stops at its next
+// metadata request and fails with SpotiflyError::Cancelled, leaving the queue alone.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    events, load_queue_item, load_track_from, parse_spotify_uri, queue_controller, spotifly_play_track,
+    spotifly_play_tracks, QueueItem, METADATA_CONCURRENCY, QUEUE, RUNTIME,
+};
+use futures_util::stream::{self, StreamExt};
+use librespot_core::session::Session;
+use librespot_playback::player::Player;
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::ffi::{c_char, CStr, CString};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use tokio::sync::watch;
+
+/// Error message of superseded loads.
+const CANCELLED: &str = "load cancelled";
+
+// Items added to the queue per QueueUpdated event while it fills
+const QUEUE_FILL_BATCH: usize = 50;
+
+// Request IDs start at 1; 0 means the request was rejected
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+// ID of the most recent load; older loads are cancelled
+static LATEST_LOAD: Lazy<watch::Sender<u64>> = Lazy::new(|| watch::channel(0).0);
+
+/// Starts a new load, cancelling any load still in progress. Returns its load ID.
+pub(crate) fn begin_load() -> u64 {
+    let mut load_id = 0;
+    LATEST_LOAD.send_modify(|latest| {
+        *latest += 1;
+        load_id = *latest;
+    });
+    load_id
+}
+
+/// Returns true if no newer load has started since `load_id`.
+pub(crate) fn is_latest(load_id: u64) -> bool {
+    *LATEST_LOAD.borrow() == load_id
+}
+
+/// Runs a load until it completes or a newer load supersedes it.
+pub(crate) async fn unless_cancelled<T>(
+    load_id: u64,
+    load: impl std::future::Future<Output = Result<T, Failure>>,
+) -> Result<T, Failure> {
+    let mut latest = LATEST_LOAD.subscribe();
+    tokio::select! {
+        result = load => result,
+        _ = latest.wait_for(|latest| *latest != load_id) => Err(Failure::new(SpotiflyError::Cancelled, CANCELLED)),
+    }
+}
+
+/// Replaces the queue with a collection and starts playing it once the item at `start`
+/// has loaded, at `position_ms` if given. Unplayable items are skipped (the next playable
+/// item starts from its usual start). The remaining items are loaded in the background
+/// until a newer load supersedes this one.
+pub(crate) async fn play_progressively(
+    session: &Session,
+    player: &Player,
+    uris: Vec<String>,
+    start: usize,
+    position_ms: Option<u32>,
+    load_id: u64,
+) -> Result<(), Failure> {
+    // Start with the first item at or after `start` that loads
+    let mut first = None;
+    for (index, uri) in uris.iter().enumerate().skip(start) {
+        if let Ok(item) = load_queue_item(session, uri).await {
+            first = Some((index, item));
+            break;
+        }
+    }
+    let (first_index, first_item) = first
+        .ok_or_else(|| Failure::new(SpotiflyError::Unavailable, "Nothing playable to load"))?;
+    let first_uri = parse_spotify_uri(&first_item.uri)?;
+    let anchor_uri = first_item.uri.clone();
+
+    queue_controller::replace(vec![first_item]).await?;
+    load_track_from(player, first_uri, position_ms.filter(|_| first_index == start));
+
+    let before = uris[..start].to_vec();
+    let after = uris[first_index + 1..].to_vec();
+    if !before.is_empty() || !after.is_empty() {
+        let session = session.clone();
+        RUNTIME.spawn(unless_cancelled(load_id, fill_queue(session, before, after, anchor_uri)));
+    }
+    Ok(())
+}
+
+// Loads items in order, yielding them in batches; items that fail to load are left out
+// (with a warning)
+fn load_batches(session: Session, uris: Vec<String>) -> impl futures_util::Stream<Item = Vec<QueueItem>> {
+    stream::iter(uris)
+        .map(move |uri| {
+            let session = session.clone();
+            async move { load_queue_item(&session, &uri).await }
+        })
+        .buffered(METADATA_CONCURRENCY)
+        .filter_map(|result| async move {
+            result.map_err(|e| log::warn!("Leaving out queue item: {}", e)).ok()
+        })
+        .chunks(QUEUE_FILL_BATCH)
+}
+
+/// Tells the host the queue changed.
+pub(crate) fn emit_queue_updated(loading: bool) {
+    let length = QUEUE.lock().unwrap().len();
+    events::emit(events::EVENT_QUEUE_UPDATED, json!({ "length": length, "loading": loading }));
+}
+
+// Fills in the rest of a progressively loaded collection: the items after the first one
+// go after the last collection item queued so far, the ones before it go in front
+async fn fill_queue(
+    session: Session,
+    before: Vec<String>,
+    after: Vec<String>,
+    mut anchor_uri: String,
+) -> Result<(), Failure> {
+    let mut batches = Box::pin(load_batches(session.clone(), after));
+    while let Some(batch) = batches.next().await {
+        let after_uri = anchor_uri.clone();
+        if let Some(last) = batch.last() {
+            anchor_uri = last.uri.clone();
+        }
+        queue_controller::edit_async(move |queue, _| {
+            let position = queue.iter()
+                .rposition(|item| item.uri == after_uri)
+                .map_or(queue.len(), |index| index + 1);
+            queue.splice(position..position, batch);
+        })
+        .await
+        .ok_or_else(queue_controller::stopped)?;
+        emit_queue_updated(true);
+    }
+
+    let mut front = Vec::new();
+    let mut batches = Box::pin(load_batches(session, before));
+    while let Some(batch) = batches.next().await {
+        front.extend(batch);
+    }
+    if !front.is_empty() {
+        queue_controller::edit_async(|queue, current_idx| {
+            *current_idx += front.len();
+            queue.splice(0..0, front);
+        })
+        .await
+        .ok_or_else(queue_controller::stopped)?;
+    }
+
+    emit_queue_updated(false);
+    Ok(())
+}
+
+// Runs a blocking play function on its own thread and reports the result as an event
+fn spawn_load(action: &str, arg: *const c_char, play: extern "C" fn(*const c_char) -> i32) -> u64 {
+    if arg.is_null() {
+        error::fail(SpotiflyError::InvalidArgument, format!("{} error: argument is null", action));
+        return 0;
+    }
+    let arg = unsafe { CStr::from_ptr(arg) }.to_owned();
+
+    let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::SeqCst);
+    let spawned = thread::Builder::new()
+        .name(format!("spotifly-load-{}", request_id))
+        .spawn(move || finish_load(request_id, &arg, play(arg.as_ptr())));
+
+    match spawned {
+        Ok(_) => request_id,
+        Err(e) => {
+            error::fail(SpotiflyError::Unknown, format!("{} error: failed to start load: {}", action, e));
+            0
+        }
+    }
+}
+
+fn finish_load(request_id: u64, arg: &CString, result: i32) {
+    let message = if result == 0 { None } else { error::last_message() };
+
+    events::emit(events::EVENT_LOAD_COMPLETED, json!({
+        "request_id": request_id,
+        "request": arg.to_string_lossy(),
+        "result": result,
+        "error": message,
+    }));
+}
+
+/// Non-blocking spotifly_play_track(): returns immediately and sends a LoadCompleted
+/// event {request_id, request, result, error} once playback started or failed.
+/// result is 0 on success or a negative error code.
+/// Returns the request ID, or 0 if the request was rejected.
+///
+/// # Parameters
+/// - uri_or_url: Spotify URI or URL of a track, album, playlist, artist, episode or show
+#[no_mangle]
+pub extern "C" fn spotifly_play_track_async(uri_or_url: *const c_char) -> u64 {
+    spawn_load("Play", uri_or_url, spotifly_play_track)
+}
+
+/// Non-blocking spotifly_play_tracks(): returns immediately and sends a LoadCompleted
+/// event {request_id, request, result, error} once playback started or failed.
+/// Returns the request ID, or 0 if the request was rejected.
+///
+/// # Parameters
+/// - track_uris_json: JSON array of track URIs as a C string
+#[no_mangle]
+pub extern "C" fn spotifly_play_tracks_async(track_uris_json: *const c_char) -> u64 {
+    spawn_load("Play tracks", track_uris_json, spotifly_play_tracks)
+}
+
+/// Cancels the load in progress (from spotifly_play_track(), spotifly_play_tracks(),
+/// spotifly_start_station(), spotifly_load_playlist_page() or their async variants), if any.
+/// The cancelled call fails with SPOTIFLY_ERROR_CANCELLED and the queue is left as it was.
+#[no_mangle]
+pub extern "C" fn spotifly_cancel_pending_load() {
+    begin_load();
+}
@@ -0,0 +1,104 @@
+// Diagnostics logging.
+//
+// The crate and librespot log through the `log` facade. Records go to stderr by default,
+// which GUI apps never see, so the host can register a callback to surface or persist
+// them instead and choose how much is logged with spotifly_set_log_level(). Records
+// arrive on whichever thread logged them.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use std::ffi::{c_char, c_void, CString};
+use std::sync::{Mutex, Once};
+
+/// Log callback: (level, target, message, user data). Levels are 1 = error, 2 = warn,
+/// 3 = info, 4 = debug, 5 = trace. The strings are only valid for the duration of the call.
+pub type LogCallback = extern "C" fn(u8, *const c_char, *const c_char, *mut c_void);
+
+struct Registration {
+    callback: LogCallback,
+    // Opaque host pointer, passed back untouched
+    user_data: usize,
+}
+
+static CALLBACK: Lazy<Mutex<Option<Registration>>> = Lazy::new(|| Mutex::new(None));
+static INSTALL: Once = Once::new();
+
+struct Logger;
+
+static LOGGER: Logger = Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let registration = CALLBACK.lock().unwrap().as_ref().map(|r| (r.callback, r.user_data));
+        let Some((callback, user_data)) = registration else {
+            // The crate's own records keep the prefix they always had on stderr
+            let source = if record.target().starts_with("spotifly_rust") { "Spotifly" } else { record.target() };
+            eprintln!("[{}] {}", source, record.args());
+            return;
+        };
+
+        // Interior NULs can't cross into C; the rest of the message still can
+        let to_c = |s: String| CString::new(s.replace('\0', "\u{FFFD}")).unwrap_or_default();
+        let target = to_c(record.target().to_string());
+        let message = to_c(record.args().to_string());
+        callback(record.level() as u8, target.as_ptr(), message.as_ptr(), user_data as *mut c_void);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger, once. Called on first use of the player or the logging functions.
+pub(crate) fn install() {
+    INSTALL.call_once(|| {
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(LevelFilter::Info);
+        }
+    });
+}
+
+/// Sets how much is logged. Applies to the crate and librespot alike.
+///
+/// # Parameters
+/// - level: 0 = off, 1 = errors, 2 = warnings, 3 = info (default), 4 = debug, 5 = trace
+#[no_mangle]
+pub extern "C" fn spotifly_set_log_level(level: u8) {
+    install();
+    let filter = match level {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    log::set_max_level(filter);
+}
+
+/// Returns the current log level (see spotifly_set_log_level()).
+#[no_mangle]
+pub extern "C" fn spotifly_get_log_level() -> u8 {
+    log::max_level().to_level().map_or(0, |level: Level| level as u8)
+}
+
+/// Registers a callback that receives log records instead of stderr, replacing any
+/// previous one. Pass NULL to log to stderr again.
+/// The callback is invoked from background threads.
+///
+/// # Parameters
+/// - callback: Log callback, or NULL
+/// - user_data: Passed back to the callback untouched
+#[no_mangle]
+pub extern "C" fn spotifly_register_log_callback(callback: Option<LogCallback>, user_data: *mut c_void) {
+    install();
+    *CALLBACK.lock().unwrap() = callback.map(|callback| Registration {
+        callback,
+        user_data: user_data as usize,
+    });
+}
@@ -0,0 +1,95 @@
+// Track lyrics, line-synced where Spotify provides timings.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{links, parse_spotify_uri, power, to_c_string, webapi, with_metadata_timeout, RUNTIME};
+use librespot_core::SpotifyUri;
+use librespot_metadata::lyrics::{Lyrics, SyncType};
+use serde::Serialize;
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+#[derive(Serialize)]
+struct LyricsLine {
+    /// Start of the line (0 for unsynced lyrics)
+    start_ms: u64,
+    /// End of the line, if Spotify provides it
+    end_ms: Option<u64>,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct TrackLyrics {
+    /// True if lines carry timestamps
+    synced: bool,
+    language: String,
+    provider: String,
+    lines: Vec<LyricsLine>,
+}
+
+fn convert(lyrics: Lyrics) -> TrackLyrics {
+    let inner = lyrics.lyrics;
+    let lines = inner.lines.into_iter()
+        .map(|line| LyricsLine {
+            start_ms: line.start_time_ms.parse().unwrap_or(0),
+            // "0" stands for a missing end time
+            end_ms: line.end_time_ms.parse().ok().filter(|&ms| ms > 0),
+            text: line.words,
+        })
+        .collect();
+
+    TrackLyrics {
+        synced: inner.sync_type == SyncType::LineSynced,
+        language: inner.language,
+        provider: inner.provider_display_name,
+        lines,
+    }
+}
+
+/// Returns the lyrics of a track as JSON:
+/// {"synced": bool, "language", "provider", "lines": [{"start_ms", "end_ms", "text"}]}
+/// For synced lyrics, match start_ms against spotifly_get_position_ms() to highlight
+/// the current line. Returns NULL on error or if the track has no lyrics.
+/// Caller must free the string with spotifly_free_string().
+///
+/// # Parameters
+/// - track_uri: Spotify track URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_get_lyrics(track_uri: *const c_char) -> *mut c_char {
+    power::note_activity();
+    if track_uri.is_null() {
+        error::fail(SpotiflyError::InvalidArgument, "Get lyrics error: track_uri is null");
+        return ptr::null_mut();
+    }
+
+    let uri_str = unsafe {
+        match CStr::from_ptr(track_uri).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => {
+                error::fail(SpotiflyError::InvalidArgument, "Get lyrics error: invalid track_uri string");
+                return ptr::null_mut();
+            }
+        }
+    };
+
+    let result: Result<TrackLyrics, Failure> = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        let link = links::resolve_link(&session, &uri_str).await?;
+        let track_id = match parse_spotify_uri(&link.uri)? {
+            SpotifyUri::Track { id } => id,
+            _ => return Err(Failure::new(SpotiflyError::InvalidUri, format!("Not a track: {}", link.uri))),
+        };
+        let lyrics = with_metadata_timeout("lyrics", Lyrics::get(&session, &track_id)).await?;
+        Ok(convert(lyrics))
+    });
+
+    match result {
+        Ok(lyrics) => match serde_json::to_string(&lyrics) {
+            Ok(json_string) => to_c_string(&json_string),
+            Err(_) => ptr::null_mut(),
+        },
+        Err(e) => {
+            error::report("Get lyrics", e);
+            ptr::null_mut()
+        }
+    }
+}
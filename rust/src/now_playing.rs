@@ -0,0 +1,127 @@
+// Everything about the current track in one call.
+//
+// The queue item at CURRENT_INDEX provides the basics; the player's metadata for the
+// loaded track adds the full artist list and album name once the track has loaded.
+
+use crate::artwork::ArtworkUrls;
+use crate::{spotifly_get_position_ms, to_c_string, CURRENT_INDEX, IS_PLAYING, QUEUE};
+use librespot_metadata::audio::{AudioItem, UniqueFields};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::ffi::c_char;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+#[derive(Clone, Serialize)]
+pub(crate) struct ArtistRef {
+    pub(crate) name: String,
+    pub(crate) id: Option<String>,
+}
+
+// Metadata of the track the player loaded last
+struct LoadedItem {
+    uri: String,
+    artists: Vec<ArtistRef>,
+    album_name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct AlbumRef {
+    pub(crate) name: Option<String>,
+    pub(crate) id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct NowPlaying {
+    pub(crate) uri: String,
+    pub(crate) title: String,
+    pub(crate) artists: Vec<ArtistRef>,
+    pub(crate) album: AlbumRef,
+    pub(crate) artwork: ArtworkUrls,
+    pub(crate) duration_ms: u32,
+    pub(crate) position_ms: u32,
+    pub(crate) is_playing: bool,
+    pub(crate) queue_index: usize,
+    pub(crate) queue_length: usize,
+}
+
+static LOADED: Lazy<Mutex<Option<LoadedItem>>> = Lazy::new(|| Mutex::new(None));
+
+/// Remembers the metadata of a newly loaded track.
+pub(crate) fn on_track_changed(audio_item: &AudioItem) {
+    let (artists, album_name) = match &audio_item.unique_fields {
+        UniqueFields::Track { artists, album, .. } => (
+            artists.iter()
+                .map(|artist| ArtistRef {
+                    name: artist.name.clone(),
+                    id: artist.id.to_id().ok(),
+                })
+                .collect(),
+            Some(album.clone()),
+        ),
+        UniqueFields::Episode { show_name, .. } => (vec![ArtistRef { name: show_name.clone(), id: None }], None),
+        UniqueFields::Local { artists, album, .. } => (
+            artists.iter().map(|name| ArtistRef { name: name.clone(), id: None }).collect(),
+            album.clone(),
+        ),
+    };
+
+    *LOADED.lock().unwrap() = Some(LoadedItem {
+        uri: audio_item.uri.clone(),
+        artists,
+        album_name,
+    });
+}
+
+/// The current track and playback state, None if the queue is empty.
+pub(crate) fn current() -> Option<NowPlaying> {
+    let queue_guard = QUEUE.lock().unwrap();
+    let queue_index = CURRENT_INDEX.load(Ordering::SeqCst);
+    let item = queue_guard.get(queue_index)?;
+
+    let loaded_guard = LOADED.lock().unwrap();
+    let loaded = loaded_guard.as_ref().filter(|loaded| loaded.uri == item.uri);
+    let artists = match loaded {
+        Some(loaded) => loaded.artists.clone(),
+        None => vec![ArtistRef {
+            name: item.artist_name.clone(),
+            id: item.artist_id.clone(),
+        }],
+    };
+
+    Some(NowPlaying {
+        uri: item.uri.clone(),
+        title: item.track_name.clone(),
+        artists,
+        album: AlbumRef {
+            name: loaded.and_then(|loaded| loaded.album_name.clone()),
+            id: item.album_id.clone(),
+        },
+        artwork: item.album_art_urls.clone(),
+        duration_ms: item.duration_ms,
+        position_ms: spotifly_get_position_ms(),
+        is_playing: IS_PLAYING.load(Ordering::SeqCst),
+        queue_index,
+        queue_length: queue_guard.len(),
+    })
+}
+
+/// The now-playing JSON document, None if the queue is empty.
+pub(crate) fn json() -> Option<String> {
+    serde_json::to_string(&current()?).ok()
+}
+
+/// Returns the current track and playback state as a single JSON document:
+/// {uri, title, artists: [{name, id}], album: {name, id}, artwork: {small, medium, large},
+/// duration_ms, position_ms, is_playing, queue_index, queue_length}
+/// album.name and the full artist list are filled in once the track has loaded.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL if the queue is empty.
+#[no_mangle]
+pub extern "C" fn spotifly_get_now_playing_json() -> *mut c_char {
+    match json() {
+        Some(json_string) => to_c_string(&json_string),
+        None => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,367 @@
+// Browser OAuth flow (authorization code with PKCE).
+//
+// Opens the authorization page in the browser and waits for the redirect on a local
+// listener, then exchanges the code for a token set (see auth.rs). The flow runs on its
+// own thread and reports through a completion callback, so the host never blocks on the
+// browser: it can give up after a timeout or be cancelled, e.g. when the user closes the
+// browser window instead of signing in. Hosts choose the scopes they need and, if the
+// redirect URI isn't where the listener should bind, the address and port to listen on.
+
+use crate::auth::{self, OAuthResult};
+use crate::error::{self, Failure, SpotiflyError};
+use crate::RUNTIME;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::distr::{Alphanumeric, SampleString};
+use sha2::{Digest, Sha256};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const AUTHORIZE_URL: &str = "https://accounts.spotify.com/authorize";
+const DEFAULT_TIMEOUT_SECS: u32 = 300;
+// How often the listener checks for the redirect, a cancel or the deadline
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Scopes requested when the host doesn't pass its own: everything the crate uses
+const DEFAULT_SCOPES: &[&str] = &[
+    "user-read-private",
+    "user-read-email",
+    "streaming",
+    "user-read-playback-state",
+    "user-modify-playback-state",
+    "user-read-currently-playing",
+    "playlist-read-private",
+    "playlist-read-collaborative",
+    "playlist-modify-public",
+    "playlist-modify-private",
+    "user-library-read",
+    "user-library-modify",
+    "user-follow-read",
+    "user-read-recently-played",
+    "user-top-read",
+];
+
+/// OAuth completion callback: (0 or a negative error code, OAuth result JSON or NULL,
+/// user data). The JSON is only valid for the duration of the call.
+pub type OAuthCallback = extern "C" fn(i32, *const c_char, *mut c_void);
+
+// Id of the flow in progress; a flow gives up as soon as this changes
+static CURRENT_FLOW: AtomicU64 = AtomicU64::new(0);
+
+struct Flow {
+    id: u64,
+    client_id: String,
+    redirect_uri: String,
+    scopes: Vec<String>,
+    // host:port the redirect listener binds
+    listen_address: String,
+    deadline: Instant,
+    verifier: String,
+    state: String,
+}
+
+fn is_current(id: u64) -> bool {
+    CURRENT_FLOW.load(Ordering::SeqCst) == id
+}
+
+// Host and port of a loopback redirect URI such as http://127.0.0.1:8888/callback
+fn redirect_host_port(redirect_uri: &str) -> Option<(String, u16)> {
+    let rest = redirect_uri.strip_prefix("http://")?;
+    let authority = rest.split('/').next().filter(|a| !a.is_empty())?;
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+        None => Some((authority.to_string(), 80)),
+    }
+}
+
+fn authorize_url(flow: &Flow) -> String {
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(flow.verifier.as_bytes()));
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("client_id", &flow.client_id)
+        .append_pair("response_type", "code")
+        .append_pair("redirect_uri", &flow.redirect_uri)
+        .append_pair("scope", &flow.scopes.join(" "))
+        .append_pair("state", &flow.state)
+        .append_pair("code_challenge_method", "S256")
+        .append_pair("code_challenge", &challenge)
+        .finish();
+    format!("{}?{}", AUTHORIZE_URL, query)
+}
+
+fn respond(stream: &mut TcpStream, message: &str) {
+    let body = format!(
+        "<html><body><p>{}</p><p>You can close this window.</p></body></html>",
+        message
+    );
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+// Reads the redirect request and returns its query parameters, or None for anything
+// else the browser asks for (e.g. the favicon)
+fn read_redirect(stream: &mut TcpStream) -> Option<Vec<(String, String)>> {
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let mut buffer = [0u8; 4096];
+    let read = stream.read(&mut buffer).ok()?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+
+    let target = request.lines().next()?.split_whitespace().nth(1)?;
+    let query = target.split_once('?')?.1;
+    let params: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+    params.iter().any(|(key, _)| key == "code" || key == "error").then_some(params)
+}
+
+// Waits for the browser to come back with an authorization code
+fn wait_for_code(flow: &Flow, listener: &TcpListener) -> Result<String, Failure> {
+    loop {
+        if !is_current(flow.id) {
+            return Err(Failure::new(SpotiflyError::Cancelled, "OAuth flow cancelled"));
+        }
+        if Instant::now() >= flow.deadline {
+            return Err(Failure::new(SpotiflyError::Timeout, "Timed out waiting for the browser"));
+        }
+
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => return Err(Failure::new(SpotiflyError::Network, format!("Redirect listener failed: {}", e))),
+        };
+        let params = match read_redirect(&mut stream) {
+            Some(params) => params,
+            None => continue,
+        };
+        let param = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone());
+
+        if param("state").as_deref() != Some(flow.state.as_str()) {
+            respond(&mut stream, "Sign-in failed: the response did not match the request.");
+            return Err(Failure::new(SpotiflyError::NotAuthenticated, "OAuth state mismatch"));
+        }
+        if let Some(error) = param("error") {
+            respond(&mut stream, "Sign-in was not completed.");
+            return Err(Failure::new(SpotiflyError::NotAuthenticated, format!("Authorization denied: {}", error)));
+        }
+        match param("code") {
+            Some(code) => {
+                respond(&mut stream, "Signed in to Spotify.");
+                return Ok(code);
+            }
+            None => {
+                respond(&mut stream, "Sign-in failed: no authorization code was returned.");
+                return Err(Failure::new(SpotiflyError::NotAuthenticated, "No authorization code in redirect"));
+            }
+        }
+    }
+}
+
+// Binds the redirect listener. A flow that was just cancelled may still hold the port
+// for a moment, so a busy port is retried until the deadline.
+fn bind(flow: &Flow) -> Result<TcpListener, Failure> {
+    loop {
+        match TcpListener::bind(&flow.listen_address) {
+            Ok(listener) => {
+                return listener.set_nonblocking(true).map(|_| listener).map_err(|e| {
+                    Failure::new(SpotiflyError::Network, format!("Redirect listener failed: {}", e))
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::AddrInUse && is_current(flow.id) && Instant::now() < flow.deadline => {
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => {
+                return Err(Failure::new(
+                    SpotiflyError::Network,
+                    format!("Could not listen on {}: {}", flow.listen_address, e),
+                ));
+            }
+        }
+    }
+}
+
+fn run(flow: &Flow) -> Result<OAuthResult, Failure> {
+    let listener = bind(flow)?;
+
+    let url = authorize_url(flow);
+    if let Err(e) = open::that_detached(&url) {
+        // The host can still show the URL itself; keep waiting for the redirect
+        log::warn!("Could not open the browser ({}); sign in at {}", e, url);
+    }
+
+    let code = wait_for_code(flow, &listener)?;
+    drop(listener);
+    if !is_current(flow.id) {
+        return Err(Failure::new(SpotiflyError::Cancelled, "OAuth flow cancelled"));
+    }
+    RUNTIME
+        .block_on(auth::exchange_code(&flow.client_id, &code, &flow.redirect_uri, &flow.verifier))
+}
+
+fn c_str_arg(arg: *const c_char) -> Option<String> {
+    if arg.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(arg) }.to_str().ok().map(str::to_string)
+}
+
+// Reads the host's scope array; NULL or empty means the default scopes
+fn scopes_arg(scopes: *const *const c_char, scope_count: usize) -> Option<Vec<String>> {
+    if scopes.is_null() || scope_count == 0 {
+        return Some(DEFAULT_SCOPES.iter().map(|scope| scope.to_string()).collect());
+    }
+    let pointers = unsafe { std::slice::from_raw_parts(scopes, scope_count) };
+    pointers.iter().map(|&scope| c_str_arg(scope).filter(|s| !s.is_empty())).collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start(
+    client_id: *const c_char,
+    redirect_uri: *const c_char,
+    scopes: Vec<String>,
+    bind_address: Option<String>,
+    port: u16,
+    timeout_secs: u32,
+    callback: Option<OAuthCallback>,
+    user_data: *mut c_void,
+) -> i32 {
+    let (client_id, redirect_uri, callback) = match (c_str_arg(client_id), c_str_arg(redirect_uri), callback) {
+        (Some(c), Some(r), Some(callback)) => (c, r, callback),
+        _ => {
+            return error::fail(
+                SpotiflyError::InvalidArgument,
+                "Start OAuth error: client_id, redirect_uri and callback are required",
+            );
+        }
+    };
+    let (redirect_host, redirect_port) = match redirect_host_port(&redirect_uri) {
+        Some(host_port) => host_port,
+        None => {
+            return error::fail(
+                SpotiflyError::InvalidArgument,
+                format!("Start OAuth error: redirect URI must be an http:// loopback address: {}", redirect_uri),
+            );
+        }
+    };
+    let listen_address = format!(
+        "{}:{}",
+        bind_address.unwrap_or(redirect_host),
+        if port == 0 { redirect_port } else { port }
+    );
+
+    let timeout_secs = if timeout_secs == 0 { DEFAULT_TIMEOUT_SECS } else { timeout_secs };
+    let flow = Flow {
+        id: CURRENT_FLOW.fetch_add(1, Ordering::SeqCst) + 1,
+        client_id,
+        redirect_uri,
+        scopes,
+        listen_address,
+        deadline: Instant::now() + Duration::from_secs(timeout_secs.into()),
+        verifier: Alphanumeric.sample_string(&mut rand::rng(), 64),
+        state: Alphanumeric.sample_string(&mut rand::rng(), 16),
+    };
+    let user_data = user_data as usize;
+
+    let spawned = thread::Builder::new().name("spotifly-oauth".to_string()).spawn(move || {
+        let (code, json) = match run(&flow) {
+            Ok(result) => {
+                log::info!("OAuth flow completed");
+                (0, serde_json::to_string(&result).ok().and_then(|json| CString::new(json).ok()))
+            }
+            Err(e) => {
+                log::warn!("OAuth error: {}", e);
+                (error::report("OAuth", e), None)
+            }
+        };
+        callback(code, json.as_ref().map_or(ptr::null(), |json| json.as_ptr()), user_data as *mut c_void);
+    });
+
+    match spawned {
+        Ok(_) => 0,
+        Err(e) => error::fail(SpotiflyError::Unknown, format!("Start OAuth error: {}", e)),
+    }
+}
+
+/// Starts the browser OAuth flow (authorization code with PKCE) without blocking.
+/// The authorization page opens in the default browser and a listener on the redirect
+/// URI's port waits for it to come back. The token set is stored (see
+/// spotifly_get_oauth_result_json()) and passed to the callback, from a background thread.
+/// On failure the callback gets a negative error code and NULL: Timeout if the browser
+/// didn't come back in time, Cancelled after spotifly_cancel_oauth() or a newer flow,
+/// NotAuthenticated if the user declined. Starting a flow cancels any flow in progress.
+/// Returns 0 if the flow started, a negative error code otherwise.
+///
+/// # Parameters
+/// - client_id: The Spotify app's client ID
+/// - redirect_uri: Loopback redirect URI registered for the app (e.g. "http://127.0.0.1:8888/callback")
+/// - timeout_secs: How long to wait for the browser, 0 = 300 seconds
+/// - callback: Called once when the flow finishes
+/// - user_data: Passed back to the callback untouched
+#[no_mangle]
+pub extern "C" fn spotifly_start_oauth_async(
+    client_id: *const c_char,
+    redirect_uri: *const c_char,
+    timeout_secs: u32,
+    callback: Option<OAuthCallback>,
+    user_data: *mut c_void,
+) -> i32 {
+    let scopes = DEFAULT_SCOPES.iter().map(|scope| scope.to_string()).collect();
+    start(client_id, redirect_uri, scopes, None, 0, timeout_secs, callback, user_data)
+}
+
+/// Like spotifly_start_oauth_async(), but with the host's own scopes and redirect
+/// listener address. The listener binds the redirect URI's host and port unless
+/// overridden, e.g. to listen on all interfaces or on a forwarded port.
+/// Returns 0 if the flow started, a negative error code otherwise.
+///
+/// # Parameters
+/// - client_id: The Spotify app's client ID
+/// - redirect_uri: Redirect URI registered for the app
+/// - scopes: Array of scope names (e.g. "playlist-modify-private"), NULL for the default scopes
+/// - scope_count: Number of entries in scopes
+/// - bind_address: Address for the listener to bind (e.g. "0.0.0.0"), NULL for the redirect URI's host
+/// - port: Port for the listener to bind, 0 for the redirect URI's port
+/// - timeout_secs: How long to wait for the browser, 0 = 300 seconds
+/// - callback: Called once when the flow finishes
+/// - user_data: Passed back to the callback untouched
+#[no_mangle]
+pub extern "C" fn spotifly_start_oauth_ex(
+    client_id: *const c_char,
+    redirect_uri: *const c_char,
+    scopes: *const *const c_char,
+    scope_count: usize,
+    bind_address: *const c_char,
+    port: u16,
+    timeout_secs: u32,
+    callback: Option<OAuthCallback>,
+    user_data: *mut c_void,
+) -> i32 {
+    let scopes = match scopes_arg(scopes, scope_count) {
+        Some(scopes) => scopes,
+        None => return error::fail(SpotiflyError::InvalidArgument, "Start OAuth error: invalid scope"),
+    };
+    let bind_address = if bind_address.is_null() {
+        None
+    } else {
+        match c_str_arg(bind_address).filter(|address| !address.is_empty()) {
+            Some(address) => Some(address),
+            None => return error::fail(SpotiflyError::InvalidArgument, "Start OAuth error: invalid bind address"),
+        }
+    };
+    start(client_id, redirect_uri, scopes, bind_address, port, timeout_secs, callback, user_data)
+}
+
+/// Cancels the OAuth flow in progress, if any. Its callback is called with Cancelled.
+#[no_mangle]
+pub extern "C" fn spotifly_cancel_oauth() {
+    CURRENT_FLOW.fetch_add(1, Ordering::SeqCst);
+}
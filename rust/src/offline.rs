@@ -0,0 +1,226 @@
+// Offline downloads.
+//
+// spotifly_download_for_offline() fetches the encrypted audio files of a track, album,
+// playlist, artist, episode or show in full into the audio cache (see
+// spotifly_set_cache_dir()), where the player picks them up, so they play instantly and
+// without a network connection for the audio. Downloads run in the background, one file at
+// a time, sending DownloadProgress events {uri, downloaded, failed, total, error}. Each
+// download is recorded with its items' file IDs in the data directory, so
+// spotifly_get_offline_status() can tell what is on disk without going online. Files are
+// fetched at the current bitrate setting, and the audio cache size limit applies to them
+// like to any other cached audio, so offline use needs a generous (or no) limit.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    build_cache, context_item_uris, events, links, parse_spotify_uri, power, spotifly_get_bitrate_kbps, storage,
+    to_c_string, webapi, with_metadata_timeout, CACHE_SETTINGS, RUNTIME,
+};
+use librespot_audio::AudioFile;
+use librespot_core::session::Session;
+use librespot_core::FileId;
+use librespot_metadata::audio::{AudioFileFormat, AudioItem};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::ffi::{c_char, CStr};
+use std::io;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const DOWNLOADS_FILE: &str = "offline.json";
+// How long to wait for a fully fetched file to be written to the cache
+const CACHE_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+const CACHE_WRITE_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Serialize, Deserialize)]
+struct DownloadedFile {
+    uri: String,
+    /// Audio file ID, base16
+    file_id: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Download {
+    uri: String,
+    files: Vec<DownloadedFile>,
+}
+
+static DOWNLOADS: Lazy<Mutex<Vec<Download>>> = Lazy::new(|| Mutex::new(Vec::new()));
+// URIs being downloaded now
+static IN_PROGRESS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Loads the download records from the data directory.
+pub(crate) fn load() {
+    if let Some(downloads) = storage::load_json::<Vec<Download>>(DOWNLOADS_FILE) {
+        *DOWNLOADS.lock().unwrap() = downloads;
+    }
+}
+
+// Where the audio cache keeps a file, if audio caching is on
+fn cached_path(file_id: &str) -> Option<PathBuf> {
+    let cache = build_cache().ok()?;
+    let bytes = (0..file_id.len()).step_by(2)
+        .map(|i| file_id.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+    cache.file_path(FileId::from_raw(&bytes))
+}
+
+// Audio formats to download, best match for the bitrate setting first (as the player picks them)
+fn preferred_formats() -> [AudioFileFormat; 4] {
+    use AudioFileFormat::*;
+    match spotifly_get_bitrate_kbps() {
+        96 => [OGG_VORBIS_96, MP3_96, OGG_VORBIS_160, OGG_VORBIS_320],
+        320 => [OGG_VORBIS_320, MP3_320, OGG_VORBIS_160, OGG_VORBIS_96],
+        _ => [OGG_VORBIS_160, MP3_160, OGG_VORBIS_96, OGG_VORBIS_320],
+    }
+}
+
+// Fetches one item's audio file into the cache, unless it is there already
+async fn download_item(session: &Session, uri: &str) -> Result<DownloadedFile, Failure> {
+    let item = with_metadata_timeout("audio item", AudioItem::get_file(session, parse_spotify_uri(uri)?)).await?;
+    let file_id = preferred_formats().iter()
+        .find_map(|format| item.files.get(format).copied())
+        .ok_or_else(|| {
+            Failure::new(SpotiflyError::Unavailable, format!("{} is not available in a supported format", uri))
+        })?;
+    let file_id_str = file_id.to_base16().map_err(|e| error::librespot("Invalid file ID", &e))?;
+    let path = session.cache().and_then(|cache| cache.file_path(file_id))
+        .ok_or_else(|| Failure::new(SpotiflyError::NotInitialized, "Audio cache is disabled"))?;
+
+    if !path.exists() {
+        let bytes_per_second = usize::from(spotifly_get_bitrate_kbps()) * 1024 / 8;
+        let mut file = AudioFile::open(session, file_id, bytes_per_second).await
+            .map_err(|e| error::librespot(format_args!("Failed to open {}", uri), &e))?;
+        // Reading the file to its end completes the download, which librespot then caches
+        RUNTIME.spawn_blocking(move || io::copy(&mut file, &mut io::sink()))
+            .await
+            .map_err(|e| Failure::new(SpotiflyError::Unknown, e.to_string()))?
+            .map_err(|e| Failure::new(SpotiflyError::Network, format!("Failed to download {}: {}", uri, e)))?;
+
+        let mut waited = Duration::ZERO;
+        while !path.exists() {
+            if waited >= CACHE_WRITE_TIMEOUT {
+                return Err(Failure::new(SpotiflyError::Unknown, format!("{} was downloaded but not cached", uri)));
+            }
+            tokio::time::sleep(CACHE_WRITE_POLL).await;
+            waited += CACHE_WRITE_POLL;
+        }
+    }
+    Ok(DownloadedFile { uri: uri.to_string(), file_id: file_id_str })
+}
+
+fn emit_progress(uri: &str, downloaded: usize, failed: usize, total: usize, error: Option<&str>) {
+    events::emit(
+        events::EVENT_DOWNLOAD_PROGRESS,
+        json!({ "uri": uri, "downloaded": downloaded, "failed": failed, "total": total, "error": error }),
+    );
+}
+
+async fn download(session: Session, uri: String) {
+    let result = async {
+        let item_uris = context_item_uris(&session, &parse_spotify_uri(&uri)?).await?;
+        let total = item_uris.len();
+        emit_progress(&uri, 0, 0, total, None);
+
+        let mut files = Vec::new();
+        let mut failed = 0;
+        for item_uri in &item_uris {
+            match download_item(&session, item_uri).await {
+                Ok(file) => files.push(file),
+                Err(e) => {
+                    log::warn!("Offline download of {} failed: {}", item_uri, e);
+                    failed += 1;
+                }
+            }
+            emit_progress(&uri, files.len(), failed, total, None);
+        }
+
+        let mut downloads = DOWNLOADS.lock().unwrap();
+        downloads.retain(|download| download.uri != uri);
+        downloads.push(Download { uri: uri.clone(), files });
+        storage::save_json(DOWNLOADS_FILE, &*downloads);
+        Ok::<(), Failure>(())
+    }.await;
+
+    if let Err(e) = result {
+        log::error!("Offline download of {} failed: {}", uri, e);
+        emit_progress(&uri, 0, 0, 0, Some(&e.message));
+    }
+    IN_PROGRESS.lock().unwrap().remove(&uri);
+}
+
+// Reads a URI or URL argument and returns its canonical URI
+fn uri_arg(input: *const c_char, action: &str) -> Result<String, i32> {
+    let input = match (!input.is_null()).then(|| unsafe { CStr::from_ptr(input) }.to_str()) {
+        Some(Ok(input)) => input,
+        _ => return Err(error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid uri", action))),
+    };
+    match links::parse_link(input) {
+        Some(link) => Ok(link.uri),
+        None => Err(error::fail(SpotiflyError::InvalidUri, format!("{} error: not a Spotify URI: {}", action, input))),
+    }
+}
+
+/// Downloads the audio of a track, album, playlist, artist, episode or show in full into
+/// the audio cache, so it plays instantly and without a network connection for the audio.
+/// Returns right away; the download runs in the background and sends DownloadProgress
+/// events {uri, downloaded, failed, total, error} (error is set if the download couldn't
+/// start). Downloading an item that is being downloaded does nothing. Needs the player
+/// to be initialized and an audio cache (spotifly_set_cache_dir()); downloads are
+/// recorded in the data directory.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_download_for_offline(uri: *const c_char) -> i32 {
+    power::note_activity();
+    let action = "Download for offline";
+    let uri = match uri_arg(uri, action) {
+        Ok(uri) => uri,
+        Err(code) => return code,
+    };
+    let session = match webapi::current_session() {
+        Ok(session) => session,
+        Err(e) => return error::report(action, e),
+    };
+    if CACHE_SETTINGS.lock().unwrap().is_none() {
+        return error::fail(SpotiflyError::InvalidArgument, format!("{} error: audio cache is disabled", action));
+    }
+
+    if IN_PROGRESS.lock().unwrap().insert(uri.clone()) {
+        RUNTIME.spawn(download(session, uri));
+    }
+    0
+}
+
+/// Returns what of a track, album, playlist, artist, episode or show is downloaded, as
+/// JSON {uri, total, downloaded, downloading}: total is the number of items with audio
+/// recorded by the last download (0 if it was never downloaded), downloaded how many of
+/// them are still in the audio cache, and downloading is true while a download runs.
+/// Works offline. Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - uri: Spotify URI or URL
+#[no_mangle]
+pub extern "C" fn spotifly_get_offline_status(uri: *const c_char) -> *mut c_char {
+    let Ok(uri) = uri_arg(uri, "Get offline status") else { return ptr::null_mut() };
+
+    let (total, downloaded) = DOWNLOADS.lock().unwrap().iter()
+        .find(|download| download.uri == uri)
+        .map(|download| {
+            let downloaded = download.files.iter()
+                .filter(|file| cached_path(&file.file_id).is_some_and(|path| path.exists()))
+                .count();
+            (download.files.len(), downloaded)
+        })
+        .unwrap_or((0, 0));
+    let downloading = IN_PROGRESS.lock().unwrap().contains(&uri);
+
+    let status = json!({ "uri": uri, "total": total, "downloaded": downloaded, "downloading": downloading });
+    to_c_string(&status.to_string())
+}
@@ -0,0 +1,366 @@
+// Audio output selection.
+//
+// librespot can play through several backends (rodio, which uses the OS audio system,
+// plus "pipe" and "subprocess" for raw PCM). The backend and its device are chosen when
+// the player is initialized; the device string means whatever the backend makes of it
+// (an output device name for rodio, a file path for pipe, a shell command for subprocess).
+//
+// The player writes to a SwitchableSink wrapping the backend's sink. Changing the device
+// bumps a generation counter; the wrapper notices on its next write and reopens itself on
+// the new device, so the queue and position are untouched. With the rodio backend a
+// watcher polls the OS device list to notice unplugged devices and default device changes.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    analysis, buffering, crossfade, eq, events, speed, spotifly_init_player, spotifly_pause, tap, to_c_string, IS_PLAYING, RUNTIME,
+};
+use cpal::traits::{DeviceTrait, HostTrait};
+use librespot_playback::audio_backend::{self, Sink, SinkBuilder, SinkResult};
+use librespot_playback::config::AudioFormat;
+use librespot_playback::convert::Converter;
+use librespot_playback::decoder::AudioPacket;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::json;
+use std::ffi::{c_char, CStr};
+use std::panic;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// The backend whose devices are OS output devices
+const RODIO_BACKEND: &str = "rodio";
+// How often the device watcher checks the OS device list
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Default)]
+struct OutputSelection {
+    // None = the default (first) backend
+    backend: Option<String>,
+    // None = the backend's default device
+    device: Option<String>,
+}
+
+static SELECTED: Lazy<Mutex<OutputSelection>> = Lazy::new(|| Mutex::new(OutputSelection::default()));
+// Bumped whenever the sink should reopen on another device
+static OUTPUT_GENERATION: AtomicU64 = AtomicU64::new(0);
+// Pause (rather than carry on through the default device) when the output device goes away
+static PAUSE_ON_DEVICE_LOSS: AtomicBool = AtomicBool::new(true);
+static WATCHER_STARTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize)]
+struct OutputDevice {
+    name: String,
+    is_default: bool,
+}
+
+/// The player's sink: the backend's sink, reopened whenever the output device changes.
+/// Also mixes in crossfades, applies the playback speed and equalizer, and feeds the audio tap and level analysis.
+pub(crate) struct SwitchableSink {
+    builder: SinkBuilder,
+    format: AudioFormat,
+    generation: u64,
+    inner: Box<dyn Sink>,
+    running: bool,
+    stretcher: speed::Stretcher,
+}
+
+impl SwitchableSink {
+    pub(crate) fn open(builder: SinkBuilder, format: AudioFormat) -> Self {
+        let generation = OUTPUT_GENERATION.load(Ordering::SeqCst);
+        let device = SELECTED.lock().unwrap().device.clone();
+        SwitchableSink {
+            builder,
+            format,
+            generation,
+            inner: open_backend(builder, device, format),
+            running: false,
+            stretcher: speed::Stretcher::new(),
+        }
+    }
+
+    // Reopens the backend's sink if the device changed since it was opened
+    fn follow_device_change(&mut self) -> SinkResult<()> {
+        let generation = OUTPUT_GENERATION.load(Ordering::SeqCst);
+        if generation == self.generation {
+            return Ok(());
+        }
+        self.generation = generation;
+
+        let device = SELECTED.lock().unwrap().device.clone();
+        log::info!("Switching audio output to {}", device.as_deref().unwrap_or("the default device"));
+        // Drop the old sink first: some devices can only be opened once
+        self.inner = Box::new(NullSink);
+        self.inner = open_backend(self.builder, device, self.format);
+        if self.running {
+            self.inner.start()?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for SwitchableSink {
+    fn start(&mut self) -> SinkResult<()> {
+        self.follow_device_change()?;
+        self.running = true;
+        self.inner.start()
+    }
+
+    fn stop(&mut self) -> SinkResult<()> {
+        self.running = false;
+        self.stretcher.reset();
+        self.inner.stop()
+    }
+
+    fn write(&mut self, mut packet: AudioPacket, converter: &mut Converter) -> SinkResult<()> {
+        self.follow_device_change()?;
+        buffering::on_write(&packet, self.format);
+        crossfade::process(&mut packet);
+        let mut packet = self.stretcher.process(packet);
+        if packet.is_empty() {
+            // Held back until the stretcher has a whole window
+            return Ok(());
+        }
+        eq::process(&mut packet);
+        tap::deliver(&packet);
+        analysis::feed(&packet);
+        self.inner.write(packet, converter)
+    }
+}
+
+// Stand-in while the real sink is being replaced
+struct NullSink;
+
+impl Sink for NullSink {
+    fn write(&mut self, _: AudioPacket, _: &mut Converter) -> SinkResult<()> {
+        Ok(())
+    }
+}
+
+// Opens the backend on a device, falling back to the default device if that fails
+// (backends panic on devices they can't open)
+fn open_backend(builder: SinkBuilder, device: Option<String>, format: AudioFormat) -> Box<dyn Sink> {
+    let Some(name) = device else {
+        return builder(None, format);
+    };
+    match panic::catch_unwind(|| builder(Some(name.clone()), format)) {
+        Ok(sink) => sink,
+        Err(_) => {
+            log::warn!("Audio device {} unavailable, using the default device", name);
+            builder(None, format)
+        }
+    }
+}
+
+/// Opens a plain sink on the selected device, for players that don't need the
+/// SwitchableSink processing (e.g. previews).
+pub(crate) fn open_sink(builder: SinkBuilder, format: AudioFormat) -> Box<dyn Sink> {
+    let device = SELECTED.lock().unwrap().device.clone();
+    open_backend(builder, device, format)
+}
+
+fn is_rodio_backend() -> bool {
+    let backend = SELECTED.lock().unwrap().backend.clone();
+    let default_backend = audio_backend::BACKENDS.first().map(|(name, _)| *name);
+    backend.as_deref().or(default_backend) == Some(RODIO_BACKEND)
+}
+
+// Names of the OS output devices and of the default one
+fn os_output_devices() -> (Vec<String>, Option<String>) {
+    let host = cpal::default_host();
+    let default_name = host.default_output_device().and_then(|device| device.name().ok());
+    let names = host.output_devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default();
+    (names, default_name)
+}
+
+// Switches the output to `device` (None = default) and tells the host why
+fn switch_device(device: Option<String>, reason: &str) {
+    let previous = std::mem::replace(&mut SELECTED.lock().unwrap().device, device.clone());
+    OUTPUT_GENERATION.fetch_add(1, Ordering::SeqCst);
+    events::emit(events::EVENT_OUTPUT_DEVICE_CHANGED, json!({
+        "device": device,
+        "previous": previous,
+        "reason": reason,
+    }));
+}
+
+/// Starts watching the OS device list (once, for the rodio backend).
+pub(crate) fn start_device_watcher() {
+    if !is_rodio_backend() || WATCHER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    RUNTIME.spawn(async {
+        let mut last_default: Option<String> = None;
+        loop {
+            tokio::time::sleep(DEVICE_POLL_INTERVAL).await;
+            let (names, default_name) = match tokio::task::spawn_blocking(os_output_devices).await {
+                Ok(devices) => devices,
+                Err(_) => continue,
+            };
+            let selected = SELECTED.lock().unwrap().device.clone();
+
+            // A selected device can only go away; following the default, the output moves
+            // along when the default changes, and the old device may or may not be gone
+            let (changed, gone) = match &selected {
+                Some(name) => {
+                    let gone = !names.contains(name);
+                    (gone, gone)
+                }
+                None => {
+                    let changed = last_default.is_some() && default_name.is_some() && last_default != default_name;
+                    (changed, changed && last_default.as_ref().is_some_and(|name| !names.contains(name)))
+                }
+            };
+            if default_name.is_some() {
+                last_default = default_name;
+            }
+            if !changed {
+                continue;
+            }
+
+            log::info!("Audio output device changed");
+            if gone && PAUSE_ON_DEVICE_LOSS.load(Ordering::SeqCst) && IS_PLAYING.load(Ordering::SeqCst) {
+                spotifly_pause();
+            }
+            let reason = if gone { "disconnected" } else { "default_changed" };
+            switch_device(None, reason);
+        }
+    });
+}
+
+/// The sink builder of the selected backend.
+pub(crate) fn sink_builder() -> Result<SinkBuilder, Failure> {
+    match SELECTED.lock().unwrap().backend.clone() {
+        Some(name) => audio_backend::find(Some(name.clone()))
+            .ok_or_else(|| Failure::new(SpotiflyError::Unavailable, format!("Unknown audio backend: {}", name))),
+        None => audio_backend::find(None)
+            .ok_or_else(|| Failure::new(SpotiflyError::Unavailable, "No audio backend found")),
+    }
+}
+
+// Reads an optional C string argument (NULL = None)
+fn optional_string_arg(s: *const c_char, name: &str, action: &str) -> Result<Option<String>, i32> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    unsafe {
+        match CStr::from_ptr(s).to_str() {
+            Ok(s) => Ok(Some(s.to_string())),
+            Err(_) => Err(error::fail(
+                SpotiflyError::InvalidArgument,
+                format!("{} error: invalid {} string", action, name),
+            )),
+        }
+    }
+}
+
+/// Returns the names of the available audio backends as a JSON array, default first
+/// (e.g. ["rodio", "pipe", "subprocess"]).
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+#[no_mangle]
+pub extern "C" fn spotifly_list_audio_backends() -> *mut c_char {
+    let names: Vec<&str> = audio_backend::BACKENDS.iter().map(|(name, _)| *name).collect();
+    match serde_json::to_string(&names) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Initializes the player like spotifly_init_player(), playing through the given audio
+/// backend and device. Has no effect on a player that is already initialized.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - access_token: OAuth access token
+/// - backend_name: Name from spotifly_list_audio_backends(), NULL = default
+/// - device_name: Backend-specific device (output device name, file path for "pipe",
+///   shell command for "subprocess"), NULL = the backend's default
+#[no_mangle]
+pub extern "C" fn spotifly_init_player_with_backend(
+    access_token: *const c_char,
+    backend_name: *const c_char,
+    device_name: *const c_char,
+) -> i32 {
+    let action = "Player init";
+    let backend = match optional_string_arg(backend_name, "backend_name", action) {
+        Ok(backend) => backend,
+        Err(code) => return code,
+    };
+    let device = match optional_string_arg(device_name, "device_name", action) {
+        Ok(device) => device,
+        Err(code) => return code,
+    };
+
+    if let Some(name) = &backend {
+        if audio_backend::find(Some(name.clone())).is_none() {
+            return error::fail(
+                SpotiflyError::InvalidArgument,
+                format!("{} error: unknown audio backend: {}", action, name),
+            );
+        }
+    }
+
+    *SELECTED.lock().unwrap() = OutputSelection { backend, device };
+    spotifly_init_player(access_token)
+}
+
+/// Returns the OS audio output devices as a JSON array of {name, is_default}.
+/// Pass a name to spotifly_set_output_device() to play through that device.
+/// Only applies to the rodio backend (the default).
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+#[no_mangle]
+pub extern "C" fn spotifly_list_output_devices() -> *mut c_char {
+    let (names, default_name) = os_output_devices();
+    let devices: Vec<OutputDevice> = names.into_iter()
+        .map(|name| OutputDevice {
+            is_default: Some(&name) == default_name.as_ref(),
+            name,
+        })
+        .collect();
+    match serde_json::to_string(&devices) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Switches audio output to another device. Playback continues on the new device
+/// without losing the queue or position. Sends an OutputDeviceChanged event.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - device_name: Name from spotifly_list_output_devices() (or a backend-specific
+///   device, see spotifly_init_player_with_backend()), NULL = the default device
+#[no_mangle]
+pub extern "C" fn spotifly_set_output_device(device_name: *const c_char) -> i32 {
+    let action = "Set output device";
+    let device = match optional_string_arg(device_name, "device_name", action) {
+        Ok(device) => device,
+        Err(code) => return code,
+    };
+
+    if let Some(name) = &device {
+        if is_rodio_backend() && !os_output_devices().0.contains(name) {
+            return error::fail(SpotiflyError::NotFound, format!("{} error: no output device named {}", action, name));
+        }
+    }
+
+    switch_device(device, "requested");
+    0
+}
+
+/// Sets what happens when the output device goes away (e.g. headphones are unplugged):
+/// playback pauses (default), or carries on through the default device. Either way
+/// output moves to the default device and an OutputDeviceChanged event is sent.
+///
+/// # Parameters
+/// - enabled: true to pause, false to carry on
+#[no_mangle]
+pub extern "C" fn spotifly_set_pause_on_device_loss(enabled: bool) {
+    PAUSE_ON_DEVICE_LOSS.store(enabled, Ordering::SeqCst);
+}
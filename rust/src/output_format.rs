@@ -0,0 +1,109 @@
+// Output sample format and dither.
+//
+// librespot decodes to 64-bit float and converts to the output format as the last step,
+// dithering when it reduces the bit depth. Both are set through SpotiflyOutputConfig and
+// take effect on the next player initialization, like the other playback settings. The
+// default is what librespot uses: 16-bit output with triangular (TPDF) dither. Hosts
+// feeding an external DAC through the pipe or subprocess backend get exactly the chosen
+// format; the rodio backend asks the OS device for it and falls back to a format the
+// device supports.
+
+use crate::error::{self, SpotiflyError};
+use librespot_playback::config::AudioFormat;
+use librespot_playback::dither::{self, DithererBuilder, GaussianDitherer, HighPassDitherer, TriangularDitherer};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+// Sample format codes, in SpotiflyOutputConfig.format
+const FORMATS: [AudioFormat; 6] = [
+    AudioFormat::S16,
+    AudioFormat::S24,
+    AudioFormat::S24_3,
+    AudioFormat::S32,
+    AudioFormat::F32,
+    AudioFormat::F64,
+];
+// Dither codes, in SpotiflyOutputConfig.dither
+const DITHER_NONE: u8 = 0;
+const DITHER_TPDF: u8 = 1;
+const DITHER_GPDF: u8 = 2;
+const DITHER_TPDF_HP: u8 = 3;
+
+/// Output format settings.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SpotiflyOutputConfig {
+    /// 0 = S16 (default), 1 = S24 (in 32-bit words), 2 = S24_3 (packed), 3 = S32,
+    /// 4 = F32, 5 = F64
+    pub format: u8,
+    /// 0 = none, 1 = triangular (TPDF, default), 2 = Gaussian, 3 = high-passed triangular
+    pub dither: u8,
+}
+
+static CONFIG: Lazy<Mutex<SpotiflyOutputConfig>> =
+    Lazy::new(|| Mutex::new(SpotiflyOutputConfig { format: 0, dither: DITHER_TPDF }));
+
+/// The sample format for the player's output.
+pub(crate) fn audio_format() -> AudioFormat {
+    FORMATS[usize::from(CONFIG.lock().unwrap().format)]
+}
+
+/// The ditherer for the player's output (None = no dither).
+pub(crate) fn ditherer() -> Option<DithererBuilder> {
+    match CONFIG.lock().unwrap().dither {
+        DITHER_TPDF => Some(dither::mk_ditherer::<TriangularDitherer>),
+        DITHER_GPDF => Some(dither::mk_ditherer::<GaussianDitherer>),
+        DITHER_TPDF_HP => Some(dither::mk_ditherer::<HighPassDitherer>),
+        _ => None,
+    }
+}
+
+/// Sets the output sample format and dither.
+/// Dither only applies to the integer formats; float output is never dithered.
+/// Takes effect on next player initialization (restart playback to apply).
+/// Returns 0 on success, a negative error code for a NULL config or unknown format or dither.
+///
+/// # Parameters
+/// - config: Output format settings
+#[no_mangle]
+pub extern "C" fn spotifly_set_output_config(config: *const SpotiflyOutputConfig) -> i32 {
+    let Some(config) = (unsafe { config.as_ref() }).copied() else {
+        return error::fail(SpotiflyError::InvalidArgument, "Set output config error: config is NULL");
+    };
+    if usize::from(config.format) >= FORMATS.len() {
+        return error::fail(
+            SpotiflyError::InvalidArgument,
+            format!("Set output config error: unknown format {}", config.format),
+        );
+    }
+    if !matches!(config.dither, DITHER_NONE | DITHER_TPDF | DITHER_GPDF | DITHER_TPDF_HP) {
+        return error::fail(
+            SpotiflyError::InvalidArgument,
+            format!("Set output config error: unknown dither {}", config.dither),
+        );
+    }
+
+    *CONFIG.lock().unwrap() = config;
+    log::info!(
+        "Output format changed to {:?}, dither {} (restart playback to apply)",
+        FORMATS[usize::from(config.format)],
+        config.dither,
+    );
+    0
+}
+
+/// Gets the output sample format and dither settings.
+/// Returns 0 on success, a negative error code if config is NULL.
+///
+/// # Parameters
+/// - config: Filled in with the current settings
+#[no_mangle]
+pub extern "C" fn spotifly_get_output_config(config: *mut SpotiflyOutputConfig) -> i32 {
+    match unsafe { config.as_mut() } {
+        Some(config) => {
+            *config = *CONFIG.lock().unwrap();
+            0
+        }
+        None => error::fail(SpotiflyError::InvalidArgument, "Get output config error: config is NULL"),
+    }
+}
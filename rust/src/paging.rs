@@ -0,0 +1,191 @@
+// Windowed loading of very large playlists.
+//
+// Playing a playlist loads metadata for every item into the queue, which for playlists
+// with thousands of tracks takes long and holds a lot of memory. spotifly_load_playlist_page()
+// instead queues one window of the playlist and keeps only the remaining URIs. As playback
+// approaches the end of what is loaded, the next window is fetched and appended, so the
+// queue extends itself until the playlist is exhausted or other content is played.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    load_queue_item, load_track, loading, parse_spotify_uri, playlist_item_uris, power, queue_controller, station,
+    webapi,
+    QueueItem, CURRENT_INDEX, IS_PLAYING, METADATA_CONCURRENCY, PLAYER, QUEUE, RUNTIME,
+};
+use futures_util::stream::{self, StreamExt};
+use librespot_core::session::Session;
+use librespot_core::SpotifyUri;
+use once_cell::sync::Lazy;
+use std::ffi::{c_char, CStr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// Fetch the next window once this many (or fewer) upcoming items remain
+const EXTEND_THRESHOLD: usize = 5;
+
+struct PlaylistWindow {
+    // Playable item URIs of the whole playlist
+    item_uris: Vec<String>,
+    // Index in item_uris of the first item not loaded yet
+    next_offset: usize,
+    page_size: usize,
+    // Last queued item of the window; later pages go after it
+    anchor_uri: String,
+    // Load that queued the window; a newer load ends it
+    load_id: u64,
+}
+
+static WINDOW: Lazy<Mutex<Option<PlaylistWindow>>> = Lazy::new(|| Mutex::new(None));
+static EXTENDING: AtomicBool = AtomicBool::new(false);
+
+// Loads queue items for a range of the playlist; items that fail to load are left out
+async fn load_page(session: Session, uris: Vec<String>) -> Vec<QueueItem> {
+    stream::iter(uris)
+        .map(move |uri| {
+            let session = session.clone();
+            async move { load_queue_item(&session, &uri).await }
+        })
+        .buffered(METADATA_CONCURRENCY)
+        .filter_map(|result| async move {
+            result.map_err(|e| log::warn!("Leaving out playlist item: {}", e)).ok()
+        })
+        .collect()
+        .await
+}
+
+/// Appends the next window of the playlist if the queue is about to run out of loaded items.
+/// Called after the queue advances; does nothing without a windowed playlist.
+pub(crate) fn maybe_extend() {
+    let (uris, anchor_uri, load_id) = {
+        let mut window_guard = WINDOW.lock().unwrap();
+        let Some(window) = window_guard.as_ref() else { return };
+        if !loading::is_latest(window.load_id) || window.next_offset >= window.item_uris.len() {
+            // Other content was played, or the whole playlist is queued
+            window_guard.take();
+            return;
+        }
+        let remaining = {
+            let queue_guard = QUEUE.lock().unwrap();
+            let end = queue_guard.iter().rposition(|item| item.uri == window.anchor_uri)
+                .unwrap_or(queue_guard.len().saturating_sub(1));
+            end.saturating_sub(CURRENT_INDEX.load(Ordering::SeqCst))
+        };
+        if remaining > EXTEND_THRESHOLD || EXTENDING.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let end = (window.next_offset + window.page_size).min(window.item_uris.len());
+        (window.item_uris[window.next_offset..end].to_vec(), window.anchor_uri.clone(), window.load_id)
+    };
+
+    RUNTIME.spawn(async move {
+        let result = loading::unless_cancelled(load_id, async {
+            let session = webapi::current_session()?;
+            let page_len = uris.len();
+            let items = load_page(session, uris).await;
+
+            {
+                let mut window_guard = WINDOW.lock().unwrap();
+                let window = window_guard.as_mut().filter(|w| w.load_id == load_id)
+                    .ok_or_else(|| Failure::new(SpotiflyError::Cancelled, "Playlist window ended"))?;
+                window.next_offset += page_len;
+                if let Some(last) = items.last() {
+                    window.anchor_uri = last.uri.clone();
+                }
+            }
+
+            queue_controller::edit_async(move |queue, _| {
+                let position = queue.iter()
+                    .rposition(|item| item.uri == anchor_uri)
+                    .map_or(queue.len(), |index| index + 1);
+                queue.splice(position..position, items);
+            })
+            .await
+            .ok_or_else(queue_controller::stopped)
+        }).await;
+
+        match result {
+            Ok(()) => loading::emit_queue_updated(false),
+            Err(e) => log::error!("Playlist window error: {}", e),
+        }
+        EXTENDING.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Plays one page of a playlist: replaces the queue with `limit` items starting at
+/// `offset` and starts playing the first. Later pages of the same size are appended
+/// automatically as playback nears the end of the loaded items (announced by QueueUpdated
+/// events), until the playlist ends or other content is played.
+/// Use this instead of spotifly_play_track() for playlists with thousands of items.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify URI or URL of a playlist
+/// - offset: Index of the first item to play (local files, which can't be played, don't count)
+/// - limit: Number of items to load per page
+#[no_mangle]
+pub extern "C" fn spotifly_load_playlist_page(uri: *const c_char, offset: usize, limit: usize) -> i32 {
+    power::note_activity();
+    if uri.is_null() {
+        return error::fail(SpotiflyError::InvalidArgument, "Load playlist page error: uri is null");
+    }
+    let input_str = match unsafe { CStr::from_ptr(uri) }.to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return error::fail(SpotiflyError::InvalidArgument, "Load playlist page error: invalid uri string"),
+    };
+    if limit == 0 {
+        return error::fail(SpotiflyError::InvalidArgument, "Load playlist page error: limit must be at least 1");
+    }
+    let player = match PLAYER.lock().unwrap().clone() {
+        Some(player) => player,
+        None => return error::fail(SpotiflyError::NotInitialized, "Load playlist page error: player not initialized"),
+    };
+
+    // Explicitly chosen content replaces any running station
+    station::stop_station();
+
+    let load_id = loading::begin_load();
+    let result: Result<(), Failure> = RUNTIME.block_on(loading::unless_cancelled(load_id, async {
+        let session = webapi::current_session()?;
+        let playlist_uri = parse_spotify_uri(&crate::links::resolve_link(&session, &input_str).await?.uri)?;
+        if !matches!(playlist_uri, SpotifyUri::Playlist { .. }) {
+            return Err(Failure::new(SpotiflyError::InvalidUri, format!("Not a playlist: {}", input_str)));
+        }
+
+        let item_uris = playlist_item_uris(&session, &playlist_uri).await?;
+        if offset >= item_uris.len() {
+            return Err(Failure::new(
+                SpotiflyError::InvalidArgument,
+                format!("offset {} is past the end of the playlist ({} items)", offset, item_uris.len()),
+            ));
+        }
+        let end = (offset + limit).min(item_uris.len());
+        let items = load_page(session.clone(), item_uris[offset..end].to_vec()).await;
+        let (Some(first), Some(last)) = (items.first(), items.last()) else {
+            return Err(Failure::new(SpotiflyError::Unavailable, "Nothing playable on this page"));
+        };
+        let first_uri = parse_spotify_uri(&first.uri)?;
+        let anchor_uri = last.uri.clone();
+
+        player.set_auto_normalise_as_album(false);
+        queue_controller::replace(items).await?;
+        *WINDOW.lock().unwrap() = Some(PlaylistWindow {
+            item_uris,
+            next_offset: end,
+            page_size: limit,
+            anchor_uri,
+            load_id,
+        });
+        load_track(&player, first_uri);
+        Ok(())
+    }));
+
+    match result {
+        Ok(()) => {
+            IS_PLAYING.store(true, Ordering::SeqCst);
+            loading::emit_queue_updated(false);
+            maybe_extend();
+            0
+        }
+        Err(e) => error::report("Load playlist page", e),
+    }
+}
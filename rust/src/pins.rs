@@ -0,0 +1,142 @@
+// Pinned items.
+//
+// Spotify has no pin API, so the crate keeps the user's pinned playlists, albums,
+// artists and shows itself, for the host to surface at the top of a Home view. A pin
+// records the item's name and image when the Web API can provide them, so the host can
+// show pins without a round-trip. Pins are stored in the data directory.
+
+use crate::error::{self, SpotiflyError};
+use crate::webapi::{self, first_image_url, str_field};
+use crate::{current_timestamp_ms, links, power, storage, to_c_string, RUNTIME};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, CStr};
+use std::ptr;
+use std::sync::Mutex;
+
+const PINS_FILE: &str = "pins.json";
+// Content types that can be pinned
+const PINNABLE_TYPES: &[&str] = &["playlist", "album", "artist", "show", "track", "episode"];
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Pin {
+    uri: String,
+    /// "playlist", "album", "artist", "show", "track" or "episode"
+    item_type: String,
+    name: Option<String>,
+    image_url: Option<String>,
+    pinned_at_ms: u64,
+}
+
+// Most recently pinned first
+static PINS: Lazy<Mutex<Vec<Pin>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Loads the stored pins from the data directory.
+pub(crate) fn load() {
+    if let Some(pins) = storage::load_json::<Vec<Pin>>(PINS_FILE) {
+        *PINS.lock().unwrap() = pins;
+    }
+}
+
+// Reads a URI or URL argument and returns its canonical URI and content type
+fn item_arg(input: *const c_char, action: &str) -> Result<(String, String), i32> {
+    if input.is_null() {
+        return Err(error::fail(SpotiflyError::InvalidArgument, format!("{} error: uri is null", action)));
+    }
+    let Ok(input) = unsafe { CStr::from_ptr(input) }.to_str() else {
+        return Err(error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid uri string", action)));
+    };
+
+    let link = links::parse_link(input);
+    let item_type = link.as_ref()
+        .and_then(|link| link.uri.split(':').nth(1))
+        .filter(|item_type| PINNABLE_TYPES.contains(item_type));
+    match (link.as_ref(), item_type) {
+        (Some(link), Some(item_type)) => Ok((link.uri.clone(), item_type.to_string())),
+        _ => Err(error::fail(SpotiflyError::InvalidUri, format!("{} error: not a pinnable URI: {}", action, input))),
+    }
+}
+
+// The item's name and image, if a session is up and the Web API has them
+fn fetch_details(uri: &str, item_type: &str) -> (Option<String>, Option<String>) {
+    let Ok(session) = webapi::current_session() else { return (None, None) };
+    let id = uri.rsplit(':').next().unwrap_or_default();
+    let path = match item_type {
+        "playlist" => format!("/playlists/{}?fields=name,images", id),
+        _ => format!("/{}s/{}", item_type, id),
+    };
+
+    match RUNTIME.block_on(webapi::get(&session, &path)) {
+        Ok(item) => {
+            // Tracks have no images of their own; use the album's
+            let image_url = Some(first_image_url(&item))
+                .filter(|url| !url.is_empty())
+                .or_else(|| item.get("album").map(first_image_url).filter(|url| !url.is_empty()));
+            (Some(str_field(&item, "name")).filter(|name| !name.is_empty()), image_url)
+        }
+        Err(e) => {
+            log::warn!("Pin details for {} unavailable: {}", uri, e);
+            (None, None)
+        }
+    }
+}
+
+/// Pins a playlist, album, artist, show, track or episode, putting it first in
+/// spotifly_get_pins(). Pinning an item that is already pinned moves it to the top.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify URI or URL of the item
+#[no_mangle]
+pub extern "C" fn spotifly_pin_item(uri: *const c_char) -> i32 {
+    power::note_activity();
+    let (uri, item_type) = match item_arg(uri, "Pin item") {
+        Ok(item) => item,
+        Err(code) => return code,
+    };
+
+    let (name, image_url) = fetch_details(&uri, &item_type);
+    let mut pins = PINS.lock().unwrap();
+    let previous = pins.iter().position(|pin| pin.uri == uri).map(|index| pins.remove(index));
+    pins.insert(0, Pin {
+        // Keep what an earlier pin learned if the Web API can't be reached now
+        name: name.or_else(|| previous.as_ref().and_then(|pin| pin.name.clone())),
+        image_url: image_url.or_else(|| previous.and_then(|pin| pin.image_url)),
+        uri,
+        item_type,
+        pinned_at_ms: current_timestamp_ms(),
+    });
+    storage::save_json(PINS_FILE, &*pins);
+    0
+}
+
+/// Unpins an item. Unpinning an item that isn't pinned does nothing.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - uri: Spotify URI or URL of the item
+#[no_mangle]
+pub extern "C" fn spotifly_unpin_item(uri: *const c_char) -> i32 {
+    let (uri, _) = match item_arg(uri, "Unpin item") {
+        Ok(item) => item,
+        Err(code) => return code,
+    };
+
+    let mut pins = PINS.lock().unwrap();
+    pins.retain(|pin| pin.uri != uri);
+    storage::save_json(PINS_FILE, &*pins);
+    0
+}
+
+/// Returns the pinned items, most recently pinned first, as a JSON array of
+/// {uri, item_type, name, image_url, pinned_at_ms}. name and image_url are null if they
+/// couldn't be looked up when the item was pinned. pinned_at_ms is milliseconds since the Unix epoch.
+/// Caller must free the string with spotifly_free_string().
+#[no_mangle]
+pub extern "C" fn spotifly_get_pins() -> *mut c_char {
+    let pins = PINS.lock().unwrap();
+    match serde_json::to_string(&*pins) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
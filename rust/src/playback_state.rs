@@ -0,0 +1,163 @@
+// Queue and playback state persistence.
+//
+// spotifly_save_state() snapshots the queue, the current item and position, the repeat
+// and shuffle modes and the volume to a JSON file, and spotifly_restore_state() puts them
+// back after a relaunch, loading the current item paused at the saved position so the
+// listener picks up exactly where they left off. The played part of the queue is already
+// in play order; shuffle only decides what comes next.
+
+use crate::error::{self, SpotiflyError};
+use crate::queue_controller::{self, spotifly_get_shuffle, spotifly_set_shuffle};
+use crate::{
+    apply_volume, load_item, loading, parse_spotify_uri, spotifly_get_position_ms, spotifly_get_volume, update_position,
+    QueueItem, CURRENT_INDEX, IS_PLAYING, MUTED, PLAYER, QUEUE, REPEAT_MODE, REPEAT_TRACK, VOLUME_BEFORE_MUTE,
+};
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, CStr};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+#[derive(Serialize, Deserialize)]
+struct PlaybackState {
+    queue: Vec<QueueItem>,
+    current_index: usize,
+    position_ms: u32,
+    repeat_mode: u8,
+    // Missing from state saved before shuffle was added
+    #[serde(default)]
+    shuffle: bool,
+    // The volume to restore; while muted, the volume from before muting
+    volume: u16,
+    muted: bool,
+}
+
+fn path_arg(path: *const c_char, action: &str) -> Result<PathBuf, i32> {
+    if path.is_null() {
+        return Err(error::fail(SpotiflyError::InvalidArgument, format!("{} error: path is null", action)));
+    }
+    match unsafe { CStr::from_ptr(path) }.to_str() {
+        Ok(s) => Ok(PathBuf::from(s)),
+        Err(_) => Err(error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid path string", action))),
+    }
+}
+
+fn snapshot() -> PlaybackState {
+    let muted = MUTED.load(Ordering::SeqCst);
+    PlaybackState {
+        queue: QUEUE.lock().unwrap().clone(),
+        current_index: CURRENT_INDEX.load(Ordering::SeqCst),
+        position_ms: spotifly_get_position_ms(),
+        repeat_mode: REPEAT_MODE.load(Ordering::SeqCst),
+        shuffle: spotifly_get_shuffle(),
+        volume: if muted { VOLUME_BEFORE_MUTE.load(Ordering::SeqCst) } else { spotifly_get_volume() },
+        muted,
+    }
+}
+
+/// Saves the queue, current item and position, repeat and shuffle modes and volume to a file, so
+/// spotifly_restore_state() can resume from there after a relaunch.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - path: File to write (replaced if it exists)
+#[no_mangle]
+pub extern "C" fn spotifly_save_state(path: *const c_char) -> i32 {
+    let path = match path_arg(path, "Save state") {
+        Ok(path) => path,
+        Err(code) => return code,
+    };
+
+    // Write to a temporary file first so a crash can't leave a truncated state behind
+    let result = serde_json::to_vec(&snapshot())
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, data).map_err(|e| e.to_string())?;
+            fs::rename(&tmp_path, &path).map_err(|e| e.to_string())
+        });
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => error::fail(SpotiflyError::Unknown, format!("Save state error: {}", e)),
+    }
+}
+
+/// Restores state saved with spotifly_save_state(): the queue, repeat and shuffle modes and volume are
+/// put back and the current item is loaded, paused at the saved position (call
+/// spotifly_resume() to continue). Cancels any collection still loading.
+/// The player must be initialized first. Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - path: File written by spotifly_save_state()
+#[no_mangle]
+pub extern "C" fn spotifly_restore_state(path: *const c_char) -> i32 {
+    let path = match path_arg(path, "Restore state") {
+        Ok(path) => path,
+        Err(code) => return code,
+    };
+    let player = match PLAYER.lock().unwrap().clone() {
+        Some(player) => player,
+        None => return error::fail(SpotiflyError::NotInitialized, "Restore state error: player not initialized"),
+    };
+
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            let code = match e.kind() {
+                io::ErrorKind::NotFound => SpotiflyError::NotFound,
+                _ => SpotiflyError::Unknown,
+            };
+            return error::fail(code, format!("Restore state error: {}: {}", path.display(), e));
+        }
+    };
+    let state: PlaybackState = match serde_json::from_slice(&data) {
+        Ok(state) => state,
+        Err(e) => {
+            return error::fail(
+                SpotiflyError::InvalidArgument,
+                format!("Restore state error: {}: {}", path.display(), e),
+            );
+        }
+    };
+    if state.repeat_mode > REPEAT_TRACK || (!state.queue.is_empty() && state.current_index >= state.queue.len()) {
+        return error::fail(SpotiflyError::InvalidArgument, "Restore state error: inconsistent saved state");
+    }
+    let current_uri = match state.queue.get(state.current_index).map(|item| parse_spotify_uri(&item.uri)) {
+        Some(Ok(uri)) => Some(uri),
+        Some(Err(e)) => return error::report("Restore state", e),
+        None => None,
+    };
+
+    // A collection still filling the queue would overwrite it
+    loading::begin_load();
+    player.stop();
+    IS_PLAYING.store(false, Ordering::SeqCst);
+
+    let (queue, current_index) = (state.queue, state.current_index);
+    queue_controller::edit(move |queued, current_idx| {
+        *queued = queue;
+        *current_idx = current_index;
+    });
+    REPEAT_MODE.store(state.repeat_mode, Ordering::SeqCst);
+    spotifly_set_shuffle(state.shuffle);
+
+    let volume_result = if state.muted {
+        VOLUME_BEFORE_MUTE.store(state.volume, Ordering::SeqCst);
+        apply_volume(0)
+    } else {
+        apply_volume(state.volume)
+    };
+    match volume_result {
+        Ok(()) => MUTED.store(state.muted, Ordering::SeqCst),
+        Err(e) => log::warn!("Could not restore volume: {}", e),
+    }
+
+    if let Some(uri) = current_uri {
+        load_item(&player, uri, false, state.position_ms);
+        update_position(state.position_ms);
+    }
+    loading::emit_queue_updated(false);
+    0
+}
@@ -0,0 +1,126 @@
+// Live updates of the playlist being played.
+//
+// Playing a playlist subscribes to its change notifications (hm://playlist/v2/playlist/<id>)
+// on the session. When another client changes it, e.g. a collaborator adds tracks, a
+// ContextUpdated event {uri, added, merged} is sent with the number of items added since
+// the queue was built. With merging on, those items are also appended to the end of the queue.
+// Removed and reordered items are left as they are in the queue; replaying the playlist
+// picks them up. Playing other content ends the subscription.
+
+use crate::error::Failure;
+use crate::{events, load_queue_item, loading, parse_spotify_uri, playlist_item_uris, queue_controller, RUNTIME};
+use librespot_core::session::Session;
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tokio::task::JoinHandle;
+
+struct Watched {
+    playlist_uri: String,
+    // Load that queued the playlist; a newer load replaced the queue
+    load_id: u64,
+    // Playlist items seen so far
+    known: HashSet<String>,
+    task: JoinHandle<()>,
+}
+
+static WATCHED: Lazy<Mutex<Option<Watched>>> = Lazy::new(|| Mutex::new(None));
+static MERGE: AtomicBool = AtomicBool::new(false);
+
+/// Follows changes to a playlist queued by load `load_id`, whose items are `item_uris`,
+/// replacing any playlist followed so far.
+pub(crate) fn watch(session: &Session, playlist_uri: &str, item_uris: &[String], load_id: u64) {
+    let id = playlist_uri.rsplit(':').next().unwrap_or_default();
+    let subscription = session.mercury().subscribe(format!("hm://playlist/v2/playlist/{}", id));
+    let session = session.clone();
+    let uri = playlist_uri.to_string();
+    let task = RUNTIME.spawn(async move {
+        let mut notifications = match subscription.await {
+            Ok(notifications) => notifications,
+            Err(e) => {
+                log::warn!("Couldn't subscribe to changes of {}: {}", uri, e);
+                return;
+            }
+        };
+        // Each notification carries the changes, but refetching the playlist is simpler
+        while notifications.recv().await.is_some() {
+            if let Err(e) = on_changed(&session, &uri).await {
+                log::warn!("Failed to update {}: {}", uri, e);
+            }
+        }
+    });
+
+    let previous = WATCHED.lock().unwrap().replace(Watched {
+        playlist_uri: playlist_uri.to_string(),
+        load_id,
+        known: item_uris.iter().cloned().collect(),
+        task,
+    });
+    if let Some(previous) = previous {
+        previous.task.abort();
+    }
+}
+
+// Picks up the items added to the followed playlist `uri`, merging them into the queue if enabled
+async fn on_changed(session: &Session, uri: &str) -> Result<(), Failure> {
+    let item_uris = playlist_item_uris(session, &parse_spotify_uri(uri)?).await?;
+
+    let added: Vec<String> = {
+        let mut watched_guard = WATCHED.lock().unwrap();
+        let Some(watched) = watched_guard.as_mut().filter(|watched| watched.playlist_uri == uri) else {
+            return Ok(());
+        };
+        if !loading::is_latest(watched.load_id) {
+            // Other content replaced the playlist in the queue
+            if let Some(watched) = watched_guard.take() {
+                watched.task.abort();
+            }
+            return Ok(());
+        }
+        let added = item_uris.iter().filter(|item_uri| !watched.known.contains(*item_uri)).cloned().collect();
+        watched.known.extend(item_uris);
+        added
+    };
+
+    let merged = !added.is_empty() && MERGE.load(Ordering::SeqCst);
+    if merged {
+        let mut items = Vec::new();
+        for item_uri in &added {
+            match load_queue_item(session, item_uri).await {
+                Ok(item) => items.push(item),
+                Err(e) => log::warn!("Leaving out queue item: {}", e),
+            }
+        }
+        queue_controller::edit_async(|queue, _| queue.extend(items)).await
+            .ok_or_else(queue_controller::stopped)?;
+        loading::emit_queue_updated(false);
+    }
+    events::emit(events::EVENT_CONTEXT_UPDATED, json!({ "uri": uri, "added": added.len(), "merged": merged }));
+    Ok(())
+}
+
+/// Stops following the playlist (e.g. because the player was cleaned up).
+pub(crate) fn reset() {
+    if let Some(watched) = WATCHED.lock().unwrap().take() {
+        watched.task.abort();
+    }
+}
+
+/// Sets whether items that other clients add to the playlist being played are appended
+/// to the end of the queue (off by default). Either way, a ContextUpdated event is sent
+/// when the playlist changes.
+///
+/// # Parameters
+/// - enabled: true to merge added items into the queue
+#[no_mangle]
+pub extern "C" fn spotifly_set_merge_playlist_updates(enabled: bool) {
+    MERGE.store(enabled, Ordering::SeqCst);
+}
+
+/// Returns true if items added to the playlist being played are merged into the queue.
+#[no_mangle]
+pub extern "C" fn spotifly_get_merge_playlist_updates() -> bool {
+    MERGE.load(Ordering::SeqCst)
+}
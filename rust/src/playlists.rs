@@ -0,0 +1,204 @@
+// Editing the user's playlists via the Web API.
+//
+// Needs the playlist-modify-public / playlist-modify-private scopes on the host's token.
+// Track lists are sent in chunks of 100, the most the Web API accepts per request.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::webapi::{self, playlist_summary_from_json};
+use crate::{links, power, to_c_string, RUNTIME};
+use http::Method;
+use serde_json::{json, Value};
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+const MAX_URIS_PER_REQUEST: usize = 100;
+
+// Reads a C string argument
+fn string_arg(s: *const c_char, name: &str, action: &str) -> Result<String, i32> {
+    if s.is_null() {
+        return Err(error::fail(SpotiflyError::InvalidArgument, format!("{} error: {} is null", action, name)));
+    }
+    unsafe {
+        CStr::from_ptr(s).to_str()
+            .map(|s| s.to_string())
+            .map_err(|_| {
+                error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid {} string", action, name))
+            })
+    }
+}
+
+// Reads a JSON array of track/episode URIs (or URLs)
+fn uris_arg(uris_json: *const c_char, action: &str) -> Result<Vec<String>, i32> {
+    let json_str = string_arg(uris_json, "uris_json", action)?;
+    let inputs: Vec<String> = serde_json::from_str(&json_str).map_err(|e| {
+        error::fail(SpotiflyError::InvalidArgument, format!("{} error: failed to parse JSON: {:?}", action, e))
+    })?;
+
+    inputs.iter()
+        .map(|input| match links::parse_link(input) {
+            Some(link) if link.uri.starts_with("spotify:track:") || link.uri.starts_with("spotify:episode:") => {
+                Ok(link.uri)
+            }
+            _ => Err(error::fail(SpotiflyError::InvalidUri, format!("{} error: not a track URI: {}", action, input))),
+        })
+        .collect()
+}
+
+/// Creates a playlist in the user's library and returns it as JSON:
+/// {uri, name, owner_name, owner_id, image_url, track_count, collaborative}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - name: Playlist name
+/// - public: true to list the playlist on the user's profile
+#[no_mangle]
+pub extern "C" fn spotifly_create_playlist(name: *const c_char, public: bool) -> *mut c_char {
+    power::note_activity();
+    let name = match string_arg(name, "name", "Create playlist") {
+        Ok(name) => name,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        let body = json!({ "name": name, "public": public });
+        webapi::request(&session, Method::POST, "/me/playlists", Some(body)).await
+    });
+
+    match result.map(|playlist| playlist_summary_from_json(&playlist)) {
+        Ok(Some(summary)) => match serde_json::to_string(&summary) {
+            Ok(json_string) => to_c_string(&json_string),
+            Err(_) => ptr::null_mut(),
+        },
+        Ok(None) => {
+            error::fail(SpotiflyError::Unknown, "Create playlist error: unexpected response");
+            ptr::null_mut()
+        }
+        Err(e) => {
+            error::report("Create playlist", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Adds tracks or episodes to a playlist.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - playlist_uri: Spotify playlist URI or URL
+/// - uris_json: JSON array of track/episode URIs or URLs
+/// - position: Index to insert at, or -1 to append
+#[no_mangle]
+pub extern "C" fn spotifly_playlist_add_tracks(
+    playlist_uri: *const c_char,
+    uris_json: *const c_char,
+    position: i32,
+) -> i32 {
+    power::note_activity();
+    let action = "Playlist add tracks";
+    let playlist_id = match links::id_arg(playlist_uri, "playlist", action) {
+        Ok(id) => id,
+        Err(code) => return code,
+    };
+    let uris = match uris_arg(uris_json, action) {
+        Ok(uris) => uris,
+        Err(code) => return code,
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        let path = format!("/playlists/{}/tracks", playlist_id);
+        for (chunk_index, chunk) in uris.chunks(MAX_URIS_PER_REQUEST).enumerate() {
+            let mut body = json!({ "uris": chunk });
+            if position >= 0 {
+                // Keep chunks in order by inserting each after the previous one
+                body["position"] = Value::from(position as usize + chunk_index * MAX_URIS_PER_REQUEST);
+            }
+            webapi::request(&session, Method::POST, &path, Some(body)).await?;
+        }
+        Ok::<(), Failure>(())
+    });
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => error::report(action, e),
+    }
+}
+
+/// Removes all occurrences of the given tracks or episodes from a playlist.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - playlist_uri: Spotify playlist URI or URL
+/// - uris_json: JSON array of track/episode URIs or URLs
+#[no_mangle]
+pub extern "C" fn spotifly_playlist_remove_tracks(playlist_uri: *const c_char, uris_json: *const c_char) -> i32 {
+    power::note_activity();
+    let action = "Playlist remove tracks";
+    let playlist_id = match links::id_arg(playlist_uri, "playlist", action) {
+        Ok(id) => id,
+        Err(code) => return code,
+    };
+    let uris = match uris_arg(uris_json, action) {
+        Ok(uris) => uris,
+        Err(code) => return code,
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        let path = format!("/playlists/{}/tracks", playlist_id);
+        for chunk in uris.chunks(MAX_URIS_PER_REQUEST) {
+            let tracks: Vec<Value> = chunk.iter().map(|uri| json!({ "uri": uri })).collect();
+            webapi::request(&session, Method::DELETE, &path, Some(json!({ "tracks": tracks }))).await?;
+        }
+        Ok::<(), Failure>(())
+    });
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => error::report(action, e),
+    }
+}
+
+/// Moves a range of items within a playlist.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - playlist_uri: Spotify playlist URI or URL
+/// - range_start: Index of the first item to move
+/// - range_length: Number of items to move (at least 1)
+/// - insert_before: Index (in the playlist before the move) to move the items in front of;
+///   the playlist length moves them to the end
+#[no_mangle]
+pub extern "C" fn spotifly_playlist_reorder(
+    playlist_uri: *const c_char,
+    range_start: u32,
+    range_length: u32,
+    insert_before: u32,
+) -> i32 {
+    power::note_activity();
+    let action = "Playlist reorder";
+    let playlist_id = match links::id_arg(playlist_uri, "playlist", action) {
+        Ok(id) => id,
+        Err(code) => return code,
+    };
+    if range_length == 0 {
+        return error::fail(SpotiflyError::InvalidArgument, format!("{} error: empty range", action));
+    }
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        let body = json!({
+            "range_start": range_start,
+            "range_length": range_length,
+            "insert_before": insert_before,
+        });
+        webapi::request(&session, Method::PUT, &format!("/playlists/{}/tracks", playlist_id), Some(body)).await
+    });
+
+    match result {
+        Ok(_) => 0,
+        Err(e) => error::report(action, e),
+    }
+}
@@ -0,0 +1,228 @@
+// Podcast shows and episodes.
+//
+// Episodes play through the same queue as tracks. Queue items are built from episode
+// metadata; browsing a show's episode list goes through the Web API, which has
+// descriptions, release dates and resume points without a metadata request per episode.
+
+use crate::artwork::ArtworkUrls;
+use crate::error::{self, Failure, SpotiflyError};
+use crate::webapi::{self, first_image_url, str_field};
+use crate::{
+    date_string, power, to_c_string, with_metadata_timeout, QueueItem, ITEM_TYPE_EPISODE, PLAY_STATE_UNPLAYED, RUNTIME,
+};
+use librespot_core::session::Session;
+use librespot_core::SpotifyUri;
+use librespot_metadata::{Episode, Metadata, Show};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+// Shows can have hundreds of episodes; only the first ones are queued
+const MAX_QUEUED_EPISODES: usize = 50;
+// The Web API returns at most 50 episodes per page
+const MAX_PAGE_SIZE: u32 = 50;
+
+#[derive(Serialize)]
+struct EpisodeSummary {
+    uri: String,
+    name: String,
+    description: String,
+    duration_ms: u64,
+    release_date: String,
+    image_url: String,
+    explicit: bool,
+    /// Where the user stopped listening, from their Spotify account
+    resume_position_ms: u64,
+    fully_played: bool,
+}
+
+#[derive(Serialize)]
+struct ShowSummary {
+    uri: String,
+    name: String,
+    publisher: String,
+    description: String,
+    image_url: String,
+    total_episodes: u64,
+}
+
+/// Builds a queue item from episode metadata. The show name takes the artist's place.
+pub(crate) fn queue_item_from_episode(uri_str: &str, episode: &Episode) -> QueueItem {
+    let album_art_urls = ArtworkUrls::from_images(episode.covers.iter());
+    QueueItem {
+        uri: uri_str.to_string(),
+        item_type: ITEM_TYPE_EPISODE,
+        track_name: episode.name.clone(),
+        artist_name: episode.show_name.clone(),
+        album_art_url: album_art_urls.large.clone(),
+        album_art_urls,
+        duration_ms: episode.duration as u32,
+        album_id: None,
+        artist_id: None,
+        external_url: episode.id.to_id().ok()
+            .map(|id| format!("https://open.spotify.com/episode/{}", id)),
+        play_state: PLAY_STATE_UNPLAYED,
+        last_position_ms: 0,
+        album_name: None,
+        track_number: 0,
+        disc_number: 0,
+        explicit: episode.is_explicit,
+        release_date: date_string(&episode.publish_time),
+    }
+}
+
+/// Loads a single episode as a queue item.
+pub(crate) async fn load_episode(session: &Session, episode_uri: &SpotifyUri) -> Result<QueueItem, Failure> {
+    let episode = with_metadata_timeout("episode", Episode::get(session, episode_uri)).await?;
+    Ok(queue_item_from_episode(&episode_uri.to_string(), &episode))
+}
+
+/// Episode URIs of a show, in the show's order.
+pub(crate) async fn show_episode_uris(session: &Session, show_uri: &SpotifyUri) -> Result<Vec<String>, Failure> {
+    let show = with_metadata_timeout("show", Show::get(session, show_uri)).await?;
+    Ok(show.episodes.iter()
+        .take(MAX_QUEUED_EPISODES)
+        .map(|uri| uri.to_string())
+        .collect())
+}
+
+fn episode_summary(episode: &Value) -> Option<EpisodeSummary> {
+    let resume_point = episode.get("resume_point");
+    Some(EpisodeSummary {
+        uri: episode.get("uri")?.as_str()?.to_string(),
+        name: str_field(episode, "name"),
+        description: str_field(episode, "description"),
+        duration_ms: episode.get("duration_ms").and_then(Value::as_u64).unwrap_or(0),
+        release_date: str_field(episode, "release_date"),
+        image_url: first_image_url(episode),
+        explicit: episode.get("explicit").and_then(Value::as_bool).unwrap_or(false),
+        resume_position_ms: resume_point
+            .and_then(|r| r.get("resume_position_ms"))
+            .and_then(Value::as_u64)
+            .unwrap_or(0),
+        fully_played: resume_point
+            .and_then(|r| r.get("fully_played"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+    })
+}
+
+// Reads a show URI (or URL) argument and returns the show's base62 ID
+fn show_id_arg(show_uri: *const c_char, action: &str) -> Option<String> {
+    if show_uri.is_null() {
+        error::fail(SpotiflyError::InvalidArgument, format!("{} error: show_uri is null", action));
+        return None;
+    }
+
+    let uri_str = unsafe {
+        match CStr::from_ptr(show_uri).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => {
+                error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid show_uri string", action));
+                return None;
+            }
+        }
+    };
+
+    match SpotifyUri::from_uri(&uri_str) {
+        Ok(uri @ SpotifyUri::Show { .. }) => uri.to_id().ok(),
+        _ => {
+            error::fail(SpotiflyError::InvalidUri, format!("{} error: not a show URI: {}", action, uri_str));
+            None
+        }
+    }
+}
+
+/// Returns information about a podcast show as JSON:
+/// {uri, name, publisher, description, image_url, total_episodes}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - show_uri: Spotify show URI (spotify:show:...)
+#[no_mangle]
+pub extern "C" fn spotifly_get_show(show_uri: *const c_char) -> *mut c_char {
+    power::note_activity();
+    let show_id = match show_id_arg(show_uri, "Get show") {
+        Some(id) => id,
+        None => return ptr::null_mut(),
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        webapi::get(&session, &format!("/shows/{}", show_id)).await
+    });
+
+    match result {
+        Ok(show) => {
+            let summary = ShowSummary {
+                uri: str_field(&show, "uri"),
+                name: str_field(&show, "name"),
+                publisher: str_field(&show, "publisher"),
+                description: str_field(&show, "description"),
+                image_url: first_image_url(&show),
+                total_episodes: show.get("total_episodes").and_then(Value::as_u64).unwrap_or(0),
+            };
+            match serde_json::to_string(&summary) {
+                Ok(json_string) => to_c_string(&json_string),
+                Err(_) => ptr::null_mut(),
+            }
+        }
+        Err(e) => {
+            error::report("Get show", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns a page of a show's episodes (newest first) as JSON:
+/// {"items": [{uri, name, description, duration_ms, release_date, image_url, explicit,
+/// resume_position_ms, fully_played}], "total": n, "offset": n}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - show_uri: Spotify show URI (spotify:show:...)
+/// - offset: Index of the first episode
+/// - limit: Maximum number of episodes (1-50)
+#[no_mangle]
+pub extern "C" fn spotifly_get_show_episodes(show_uri: *const c_char, offset: u32, limit: u32) -> *mut c_char {
+    power::note_activity();
+    let show_id = match show_id_arg(show_uri, "Get show episodes") {
+        Some(id) => id,
+        None => return ptr::null_mut(),
+    };
+
+    let result = RUNTIME.block_on(async {
+        let session = webapi::current_session()?;
+        let path = format!(
+            "/shows/{}/episodes?limit={}&offset={}",
+            show_id,
+            limit.clamp(1, MAX_PAGE_SIZE),
+            offset,
+        );
+        webapi::get(&session, &path).await
+    });
+
+    match result {
+        Ok(page) => {
+            // Items can be null for episodes that are no longer available
+            let episodes: Vec<EpisodeSummary> = page.get("items").and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(episode_summary)
+                .collect();
+            let response = json!({
+                "items": episodes,
+                "total": page.get("total").and_then(Value::as_u64).unwrap_or(0),
+                "offset": offset,
+            });
+            to_c_string(&response.to_string())
+        }
+        Err(e) => {
+            error::report("Get show episodes", e);
+            ptr::null_mut()
+        }
+    }
+}
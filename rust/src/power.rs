@@ -0,0 +1,280 @@
+// System sleep/wake and network change handling.
+//
+// The host app forwards OS notifications (NSWorkspace will-sleep/did-wake, network path
+// changes) through the FFI hooks below. As a fallback, a monitor thread detects wake-ups
+// by watching for wall-clock jumps that the monotonic clock didn't see (the monotonic
+// clock doesn't advance while the system is asleep).
+//
+// The same thread enforces the idle policy: after a configurable time without playback or
+// commands the session is shut down to save battery and network, and the next command that
+// needs it reconnects transparently.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    current_timestamp_ms, load_item, parse_spotify_uri, reconnect_session, set_connection_state,
+    spotifly_get_position_ms, CONNECTION_CONNECTED, CONNECTION_DISCONNECTED, CONNECTION_IDLE,
+    CONNECTION_RECONNECTING, CONNECTION_STATE, CONNECTION_SUSPENDED, CURRENT_INDEX, IS_PLAYING,
+    PLAYER, POSITION_MS, QUEUE, RUNTIME, SESSION, SPIRC,
+};
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+// Playback snapshot taken before sleep (or right after an unannounced wake)
+struct SuspendedPlayback {
+    index: usize,
+    uri: String,
+    position_ms: u32,
+    was_playing: bool,
+}
+
+static SUSPENDED: Lazy<Mutex<Option<SuspendedPlayback>>> = Lazy::new(|| Mutex::new(None));
+// Serializes resume attempts from the host hooks and the wake monitor
+static RESUME_LOCK: Mutex<()> = Mutex::new(());
+static WAKE_MONITOR_RUNNING: AtomicBool = AtomicBool::new(false);
+// Idle time after which the session is shut down (0 = keep alive)
+static IDLE_DISCONNECT_MINUTES: AtomicU32 = AtomicU32::new(0);
+// Last playback or command activity (ms since epoch)
+static LAST_ACTIVITY_MS: AtomicU64 = AtomicU64::new(0);
+
+const WAKE_MONITOR_INTERVAL: Duration = Duration::from_secs(5);
+// Wall clock running ahead of the monotonic clock by more than this means we slept
+const WAKE_DETECTION_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// Pauses playback and remembers the current track and position.
+/// Does nothing if a snapshot already exists.
+pub(crate) fn suspend(position_ms: u32) {
+    let player = match PLAYER.lock().unwrap().clone() {
+        Some(p) => p,
+        None => return,
+    };
+
+    let mut suspended_guard = SUSPENDED.lock().unwrap();
+    if suspended_guard.is_some() {
+        return;
+    }
+
+    let queue_guard = QUEUE.lock().unwrap();
+    let index = CURRENT_INDEX.load(Ordering::SeqCst);
+    let uri = match queue_guard.get(index) {
+        Some(item) => item.uri.clone(),
+        None => return,
+    };
+    drop(queue_guard);
+
+    let was_playing = IS_PLAYING.load(Ordering::SeqCst);
+    if was_playing {
+        player.pause();
+        IS_PLAYING.store(false, Ordering::SeqCst);
+    }
+
+    *suspended_guard = Some(SuspendedPlayback {
+        index,
+        uri,
+        position_ms,
+        was_playing,
+    });
+    set_connection_state(CONNECTION_SUSPENDED);
+}
+
+/// Re-validates the session (reconnecting if it died) and restores the suspended track
+/// at its saved position.
+async fn resume() -> Result<(), Failure> {
+    set_connection_state(CONNECTION_RECONNECTING);
+
+    let session_valid = SESSION.lock().unwrap()
+        .as_ref()
+        .is_some_and(|s| !s.is_invalid());
+    if !session_valid {
+        log::info!("Session lost, reconnecting");
+        reconnect_session().await?;
+    }
+
+    let snapshot = SUSPENDED.lock().unwrap().take();
+    if let Some(snapshot) = snapshot {
+        // Only restore if the queue hasn't moved on in the meantime
+        let queue_guard = QUEUE.lock().unwrap();
+        let unchanged = CURRENT_INDEX.load(Ordering::SeqCst) == snapshot.index
+            && queue_guard.get(snapshot.index).is_some_and(|item| item.uri == snapshot.uri);
+        drop(queue_guard);
+
+        if unchanged {
+            let player = PLAYER.lock().unwrap().clone()
+                .ok_or_else(|| Failure::new(SpotiflyError::NotInitialized, "Player not initialized"))?;
+            let uri = parse_spotify_uri(&snapshot.uri)?;
+            load_item(&player, uri, snapshot.was_playing, snapshot.position_ms);
+            IS_PLAYING.store(snapshot.was_playing, Ordering::SeqCst);
+        }
+    }
+
+    set_connection_state(CONNECTION_CONNECTED);
+    Ok(())
+}
+
+/// Reconnects if needed and restores the suspended track, blocking until done.
+/// On failure the connection state stays at reconnecting, for callers that retry.
+pub(crate) fn try_resume() -> Result<(), Failure> {
+    let _resume_guard = RESUME_LOCK.lock().unwrap();
+    RUNTIME.block_on(resume())
+}
+
+fn resume_blocking() -> Result<(), Failure> {
+    let result = try_resume();
+    if result.is_err() {
+        set_connection_state(CONNECTION_DISCONNECTED);
+    }
+    result
+}
+
+/// Records user activity. If the session was shut down for being idle, it is
+/// reconnected first, so the caller finds a working session.
+pub(crate) fn note_activity() {
+    LAST_ACTIVITY_MS.store(current_timestamp_ms(), Ordering::SeqCst);
+    if CONNECTION_STATE.load(Ordering::SeqCst) != CONNECTION_IDLE {
+        return;
+    }
+
+    log::info!("Reconnecting idle session");
+    if let Err(e) = resume_blocking() {
+        log::error!("Idle reconnect error: {}", e);
+    }
+}
+
+// Shuts down the session once nothing has played and no command arrived for the
+// configured idle time
+fn check_idle() {
+    let now_ms = current_timestamp_ms();
+    if IS_PLAYING.load(Ordering::SeqCst) {
+        LAST_ACTIVITY_MS.store(now_ms, Ordering::SeqCst);
+        return;
+    }
+
+    let idle_minutes = IDLE_DISCONNECT_MINUTES.load(Ordering::SeqCst) as u64;
+    let idle_ms = now_ms.saturating_sub(LAST_ACTIVITY_MS.load(Ordering::SeqCst));
+    if idle_minutes == 0
+        || idle_ms < idle_minutes * 60 * 1000
+        || CONNECTION_STATE.load(Ordering::SeqCst) != CONNECTION_CONNECTED
+    {
+        return;
+    }
+
+    let _resume_guard = RESUME_LOCK.lock().unwrap();
+    log::info!("Idle for {} minutes, disconnecting session", idle_minutes);
+    suspend(POSITION_MS.load(Ordering::SeqCst));
+    if let Some(spirc) = SPIRC.lock().unwrap().take() {
+        let _ = spirc.shutdown();
+    }
+    if let Some(session) = SESSION.lock().unwrap().as_ref() {
+        session.shutdown();
+    }
+    set_connection_state(CONNECTION_IDLE);
+}
+
+/// Starts the background wake detector if it isn't running yet.
+/// The thread exits once the player has been cleaned up.
+pub(crate) fn start_wake_monitor() {
+    if WAKE_MONITOR_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    std::thread::spawn(|| {
+        loop {
+            let wall_before = SystemTime::now();
+            let mono_before = Instant::now();
+            std::thread::sleep(WAKE_MONITOR_INTERVAL);
+
+            if PLAYER.lock().unwrap().is_none() {
+                break;
+            }
+
+            let mono_elapsed = mono_before.elapsed();
+            let wall_elapsed = wall_before.elapsed().unwrap_or(mono_elapsed);
+            if wall_elapsed.saturating_sub(mono_elapsed) > WAKE_DETECTION_THRESHOLD {
+                log::info!("System wake detected");
+                // Audio output was interrupted by sleep - resume from the last reported position
+                suspend(POSITION_MS.load(Ordering::SeqCst));
+                if let Err(e) = resume_blocking() {
+                    log::error!("Wake resume error: {}", e);
+                }
+            }
+            check_idle();
+        }
+        WAKE_MONITOR_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Tells the player the system is about to sleep.
+/// Pauses playback and remembers the current track and position.
+/// Returns 0 on success, a negative error code if the player is not initialized.
+#[no_mangle]
+pub extern "C" fn spotifly_notify_system_will_sleep() -> i32 {
+    if PLAYER.lock().unwrap().is_none() {
+        return error::fail(SpotiflyError::NotInitialized, "Will sleep error: player not initialized");
+    }
+
+    suspend(spotifly_get_position_ms());
+    0
+}
+
+/// Tells the player the system has woken up.
+/// Re-validates the session (reconnecting if needed) and resumes the suspended track
+/// at its saved position. Connection progress is visible via spotifly_get_connection_state().
+/// Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_notify_system_did_wake() -> i32 {
+    if PLAYER.lock().unwrap().is_none() {
+        return error::fail(SpotiflyError::NotInitialized, "Did wake error: player not initialized");
+    }
+
+    match resume_blocking() {
+        Ok(_) => 0,
+        Err(e) => {
+            error::report("Did wake", e)
+        }
+    }
+}
+
+/// Tells the player the network configuration changed (interface switch, VPN, etc).
+/// If the session was lost, playback is paused, the session is re-established and the
+/// current track resumes at its last position.
+/// Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_notify_network_changed() -> i32 {
+    if PLAYER.lock().unwrap().is_none() {
+        return error::fail(SpotiflyError::NotInitialized, "Network change error: player not initialized");
+    }
+
+    let session_valid = SESSION.lock().unwrap()
+        .as_ref()
+        .is_some_and(|s| !s.is_invalid());
+    if session_valid {
+        return 0;
+    }
+
+    suspend(spotifly_get_position_ms());
+    match resume_blocking() {
+        Ok(_) => 0,
+        Err(e) => {
+            error::report("Network change", e)
+        }
+    }
+}
+
+/// Sets how long the session stays connected without playback or commands.
+/// After that it is shut down to save battery and network (connection state 4 = idle)
+/// and reconnected transparently on the next command that needs it.
+///
+/// # Parameters
+/// - minutes: Idle time before disconnecting, 0 = keep the session alive (default)
+#[no_mangle]
+pub extern "C" fn spotifly_set_idle_disconnect_minutes(minutes: u32) {
+    LAST_ACTIVITY_MS.store(current_timestamp_ms(), Ordering::SeqCst);
+    IDLE_DISCONNECT_MINUTES.store(minutes, Ordering::SeqCst);
+}
+
+/// Returns the idle time in minutes after which the session is disconnected (0 = never).
+#[no_mangle]
+pub extern "C" fn spotifly_get_idle_disconnect_minutes() -> u32 {
+    IDLE_DISCONNECT_MINUTES.load(Ordering::SeqCst)
+}
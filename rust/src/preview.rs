@@ -0,0 +1,197 @@
+// Preview playback.
+//
+// A second, lightweight player for press-and-hold previews (e.g. in search results). It
+// plays a 30-second clip from the middle of a track at a reduced volume, on the main
+// player's session but through its own output stream, so the queue is left alone. Main
+// playback is paused while a clip plays and resumed when it ends; starting main playback
+// ends the clip instead. Clips aren't counted in statistics, history or scrobbles.
+// Sends a PreviewEnded event {uri, completed} when a clip ends.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    events, links, output, output_format, parse_spotify_uri, power, spotifly_get_volume, spotifly_pause,
+    spotifly_resume, with_metadata_timeout, IS_PLAYING, RUNTIME, SESSION,
+};
+use librespot_core::session::Session;
+use librespot_metadata::{Metadata, Track};
+use librespot_playback::config::PlayerConfig;
+use librespot_playback::mixer::softmixer::SoftMixer;
+use librespot_playback::mixer::{Mixer, MixerConfig};
+use librespot_playback::player::{Player, PlayerEvent};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::ffi::{c_char, CStr};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const CLIP_LENGTH_MS: u32 = 30_000;
+// Clip volume as a share of the main volume
+const CLIP_VOLUME_SHARE: f32 = 0.6;
+
+struct PreviewPlayer {
+    session: Session,
+    player: Arc<Player>,
+    mixer: Arc<SoftMixer>,
+}
+
+// The clip playing now
+struct Clip {
+    uri: String,
+    end_ms: u32,
+    // Main playback was paused for the clip
+    resume_main: bool,
+}
+
+static PREVIEW_PLAYER: Lazy<Mutex<Option<PreviewPlayer>>> = Lazy::new(|| Mutex::new(None));
+static CLIP: Lazy<Mutex<Option<Clip>>> = Lazy::new(|| Mutex::new(None));
+
+// The preview player for `session`, created on first use and again after a reconnect
+fn preview_player(session: &Session) -> Result<(Arc<Player>, Arc<SoftMixer>), Failure> {
+    let mut guard = PREVIEW_PLAYER.lock().unwrap();
+    if let Some(preview) = guard.as_ref() {
+        if !preview.session.is_invalid() && preview.session.session_id() == session.session_id() {
+            return Ok((Arc::clone(&preview.player), Arc::clone(&preview.mixer)));
+        }
+    }
+
+    let mixer = SoftMixer::open(MixerConfig::default())
+        .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Mixer error: {}", e)))?;
+    let mixer = Arc::new(mixer);
+    let backend = output::sink_builder()?;
+    let config = PlayerConfig {
+        position_update_interval: Some(Duration::from_millis(200)),
+        ditherer: output_format::ditherer(),
+        ..PlayerConfig::default()
+    };
+    let player = {
+        let _runtime = RUNTIME.enter();
+        Player::new(config, session.clone(), mixer.get_soft_volume(), move || {
+            output::open_sink(backend, output_format::audio_format())
+        })
+    };
+    watch(&player);
+
+    *guard = Some(PreviewPlayer { session: session.clone(), player: Arc::clone(&player), mixer: Arc::clone(&mixer) });
+    Ok((player, mixer))
+}
+
+// Ends clips that reach their end or can't be played. Stops when the player is dropped.
+fn watch(player: &Player) {
+    let mut event_channel = player.get_player_event_channel();
+    RUNTIME.spawn(async move {
+        while let Some(event) = event_channel.recv().await {
+            match event {
+                PlayerEvent::PositionChanged { track_id, position_ms, .. } => {
+                    let uri = track_id.to_string();
+                    let reached_end = CLIP.lock().unwrap().as_ref()
+                        .is_some_and(|clip| clip.uri == uri && position_ms >= clip.end_ms);
+                    if reached_end {
+                        finish(Some(&uri), true, true);
+                    }
+                }
+                PlayerEvent::EndOfTrack { track_id, .. } => finish(Some(&track_id.to_string()), true, true),
+                PlayerEvent::Unavailable { track_id, .. } => {
+                    log::warn!("Preview unavailable: {}", track_id);
+                    finish(Some(&track_id.to_string()), false, true);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+// Ends the current clip (only if it is of `uri`, when given), resuming main playback
+// if it was paused for the clip and `resume_main` is set
+fn finish(uri: Option<&str>, completed: bool, resume_main: bool) {
+    let clip = {
+        let mut clip_guard = CLIP.lock().unwrap();
+        if clip_guard.as_ref().is_none_or(|clip| uri.is_some_and(|uri| uri != clip.uri)) {
+            return;
+        }
+        clip_guard.take()
+    };
+    let Some(clip) = clip else { return };
+
+    if let Some(preview) = PREVIEW_PLAYER.lock().unwrap().as_ref() {
+        preview.player.stop();
+    }
+    if clip.resume_main && resume_main {
+        spotifly_resume();
+    }
+    events::emit(events::EVENT_PREVIEW_ENDED, json!({ "uri": clip.uri, "completed": completed }));
+}
+
+/// Ends a playing clip because main playback started. Called from the main player's events.
+pub(crate) fn on_main_playing() {
+    finish(None, false, false);
+}
+
+/// Drops the preview player. Called when the main player is cleaned up.
+pub(crate) fn reset() {
+    CLIP.lock().unwrap().take();
+    PREVIEW_PLAYER.lock().unwrap().take();
+}
+
+// Where a clip of `duration_ms` starts: a third of the way in, keeping a full clip where possible
+fn clip_start_ms(duration_ms: u32) -> u32 {
+    (duration_ms / 3).min(duration_ms.saturating_sub(CLIP_LENGTH_MS))
+}
+
+/// Plays a 30-second preview of a track at a reduced volume, replacing any preview
+/// playing now. Main playback is paused meanwhile and resumed when the preview ends;
+/// the queue is left alone. Sends PreviewEnded {uri, completed} when the preview ends.
+/// The player must be initialized. Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - track_uri: Spotify URI or URL of the track
+#[no_mangle]
+pub extern "C" fn spotifly_play_preview(track_uri: *const c_char) -> i32 {
+    power::note_activity();
+    let action = "Play preview";
+    let uri = match (!track_uri.is_null()).then(|| unsafe { CStr::from_ptr(track_uri) }.to_str()) {
+        Some(Ok(input)) => match links::parse_link(input).filter(|link| link.uri.starts_with("spotify:track:")) {
+            Some(link) => link.uri,
+            None => return error::fail(SpotiflyError::InvalidUri, format!("{} error: not a track: {}", action, input)),
+        },
+        _ => return error::fail(SpotiflyError::InvalidArgument, format!("{} error: invalid track_uri", action)),
+    };
+    let Some(session) = SESSION.lock().unwrap().clone() else {
+        return error::fail(SpotiflyError::NotInitialized, format!("{} error: player not initialized", action));
+    };
+
+    let result = parse_spotify_uri(&uri).and_then(|spotify_uri| {
+        let track = RUNTIME.block_on(with_metadata_timeout("track", Track::get(&session, &spotify_uri)))?;
+        let (player, mixer) = preview_player(&session)?;
+        Ok((spotify_uri, clip_start_ms(track.duration.max(0) as u32), player, mixer))
+    });
+    let (spotify_uri, start_ms, player, mixer) = match result {
+        Ok(loaded) => loaded,
+        Err(e) => return error::report(action, e),
+    };
+
+    // A preview replacing another keeps the main player waiting
+    let resume_main = match CLIP.lock().unwrap().take() {
+        Some(previous) => previous.resume_main,
+        None => IS_PLAYING.load(Ordering::SeqCst) && spotifly_pause() == 0,
+    };
+    mixer.set_volume((spotifly_get_volume() as f32 * CLIP_VOLUME_SHARE) as u16);
+
+    let mut clip_guard = CLIP.lock().unwrap();
+    player.load(spotify_uri, true, start_ms);
+    *clip_guard = Some(Clip { uri, end_ms: start_ms + CLIP_LENGTH_MS, resume_main });
+    0
+}
+
+/// Stops the preview playing now, resuming main playback if it was paused for it.
+/// Does nothing if no preview is playing.
+#[no_mangle]
+pub extern "C" fn spotifly_stop_preview() {
+    finish(None, false, true);
+}
+
+/// Returns true while a preview is playing.
+#[no_mangle]
+pub extern "C" fn spotifly_is_preview_playing() -> bool {
+    CLIP.lock().unwrap().is_some()
+}
@@ -0,0 +1,67 @@
+// Private session ("incognito") mode.
+//
+// While a private session is active, plays are kept out of the local play statistics
+// (and everything derived from them), the recently played history and scrobbling. Like
+// the official client's Private Session, it expires automatically, notifying the host
+// with a PrivateSessionExpired event.
+//
+// Limitation: plays are NOT hidden from Spotify. librespot can't mark a device's session
+// as private (the Connect device info's is_private_session is always false, and
+// ConnectConfig has no setting for it), and Spirc keeps publishing the playback state to
+// Spotify while a private session is active. Spotify may therefore still count these
+// plays in its Recently Played, listening history and recommendations.
+
+use crate::{current_timestamp_ms, events, RUNTIME};
+use serde_json::json;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Matches the official client
+const DEFAULT_DURATION_MINUTES: u32 = 6 * 60;
+
+// Expiry time in ms since the epoch, 0 when no private session is active
+static PRIVATE_UNTIL_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns true while a private session is active.
+pub(crate) fn is_active() -> bool {
+    current_timestamp_ms() < PRIVATE_UNTIL_MS.load(Ordering::SeqCst)
+}
+
+/// Starts or ends a private session.
+/// While active, plays are not recorded in the local play statistics or history and
+/// are not scrobbled. Spotify itself still sees them: librespot can't mark the session
+/// private, so Spotify's Recently Played and recommendations may include them.
+/// The session ends automatically after `duration_minutes`, sending a
+/// PrivateSessionExpired event.
+///
+/// # Parameters
+/// - enabled: true to start (or extend) a private session, false to end it
+/// - duration_minutes: how long the session lasts, 0 = default (6 hours)
+#[no_mangle]
+pub extern "C" fn spotifly_set_private_session(enabled: bool, duration_minutes: u32) {
+    if !enabled {
+        PRIVATE_UNTIL_MS.store(0, Ordering::SeqCst);
+        return;
+    }
+
+    let minutes = if duration_minutes == 0 { DEFAULT_DURATION_MINUTES } else { duration_minutes };
+    let duration_ms = u64::from(minutes) * 60_000;
+    let until_ms = current_timestamp_ms() + duration_ms;
+    PRIVATE_UNTIL_MS.store(until_ms, Ordering::SeqCst);
+
+    RUNTIME.spawn(async move {
+        tokio::time::sleep(Duration::from_millis(duration_ms)).await;
+
+        // Only expire if the session wasn't ended or extended in the meantime
+        if PRIVATE_UNTIL_MS.compare_exchange(until_ms, 0, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            log::info!("Private session expired");
+            events::emit(events::EVENT_PRIVATE_SESSION_EXPIRED, json!({}));
+        }
+    });
+}
+
+/// Returns true while a private session is active.
+#[no_mangle]
+pub extern "C" fn spotifly_is_private_session() -> bool {
+    is_active()
+}
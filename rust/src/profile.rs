@@ -0,0 +1,132 @@
+// The logged-in user's profile and account tier.
+//
+// Comes from the Web API's /me. When that fails (e.g. the token lacks the
+// user-read-private scope) the profile falls back to what the session learned from the
+// access point at login: the user ID, country and account type, without name or avatar.
+//
+// Playback needs Premium. librespot only finds out about a free account after login and
+// then ends the process, so the tier is checked with the access token before a session
+// is created, and a free account gets a NotPremium error and a PremiumRequired event.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{events, network, power, to_c_string, webapi, RUNTIME};
+use bytes::Bytes;
+use http::header::AUTHORIZATION;
+use http::{Method, Request};
+use librespot_core::http_client::HttpClient;
+use librespot_core::session::Session;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::ffi::c_char;
+use std::ptr;
+
+#[derive(Serialize)]
+struct UserProfile {
+    user_id: String,
+    display_name: Option<String>,
+    country: Option<String>,
+    /// Account tier: "premium", "free", "open"... (None if unknown)
+    product: Option<String>,
+    avatar_url: Option<String>,
+    is_premium: bool,
+}
+
+/// The account type the access point reported at login ("premium", "free"...).
+pub(crate) fn session_product(session: &Session) -> Option<String> {
+    session.get_user_attribute("type").filter(|product| !product.is_empty())
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value.filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+/// The Web API's /me for an access token, asked before any session (and its HTTP client) exists.
+pub(crate) async fn token_me(access_token: &str) -> Result<Value, Failure> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(format!("{}/me", webapi::WEB_API_BASE))
+        .header(AUTHORIZATION, format!("Bearer {}", access_token))
+        .body(Bytes::new())
+        .map_err(|e| Failure::new(SpotiflyError::InvalidArgument, format!("Invalid Web API request: {}", e)))?;
+    let response = HttpClient::new(network::proxy().as_ref()).request_body(request).await
+        .map_err(|e| error::librespot("Web API request failed", &e))?;
+    serde_json::from_slice(&response)
+        .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Failed to parse Web API response: {:?}", e)))
+}
+
+// The account tier for an access token
+async fn token_product(access_token: &str) -> Result<Option<String>, Failure> {
+    let me = token_me(access_token).await?;
+    Ok(non_empty(me.get("product").and_then(Value::as_str)))
+}
+
+/// Tells the host that playback needs a Premium account and returns the NotPremium code.
+pub(crate) fn premium_required(action: &str, product: Option<&str>) -> i32 {
+    events::emit(events::EVENT_PREMIUM_REQUIRED, json!({ "product": product }));
+    error::fail(
+        SpotiflyError::NotPremium,
+        format!("{} error: Spotify Premium is required for playback (account type: {})",
+            action, product.unwrap_or("unknown")),
+    )
+}
+
+/// Checks that the account behind an access token is Premium.
+/// Returns Err with the account type if it isn't; an unknown tier passes.
+pub(crate) async fn require_premium(access_token: &str) -> Result<(), Option<String>> {
+    match token_product(access_token).await {
+        Ok(Some(product)) if product != "premium" => Err(Some(product)),
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::warn!("Could not check the account tier: {}", e);
+            Ok(())
+        }
+    }
+}
+
+async fn fetch_profile(session: &Session) -> UserProfile {
+    let me = match webapi::get(session, "/me").await {
+        Ok(me) => me,
+        Err(e) => {
+            log::warn!("Profile from Web API unavailable ({}), using session data", e);
+            Value::Null
+        }
+    };
+
+    let product = non_empty(me.get("product").and_then(Value::as_str)).or_else(|| session_product(session));
+    // Images are listed largest first; the first is the best avatar
+    let avatar_url = non_empty(me.pointer("/images/0/url").and_then(Value::as_str));
+    let country = non_empty(me.get("country").and_then(Value::as_str))
+        .or_else(|| Some(session.country()).filter(|country| !country.is_empty()));
+    UserProfile {
+        user_id: non_empty(me.get("id").and_then(Value::as_str)).unwrap_or_else(|| session.username()),
+        display_name: non_empty(me.get("display_name").and_then(Value::as_str)),
+        country,
+        is_premium: product.as_deref() == Some("premium"),
+        product,
+        avatar_url,
+    }
+}
+
+/// Returns the logged-in user's profile as JSON:
+/// {user_id, display_name, country, product, avatar_url, is_premium}
+/// product is the account tier ("premium", "free"...). display_name and avatar_url are null
+/// when the Web API can't be reached; the rest then comes from the session.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error (e.g. player not initialized).
+#[no_mangle]
+pub extern "C" fn spotifly_get_user_profile_json() -> *mut c_char {
+    power::note_activity();
+    let session = match webapi::current_session() {
+        Ok(session) => session,
+        Err(e) => {
+            error::report("Get user profile", e);
+            return ptr::null_mut();
+        }
+    };
+
+    let profile = RUNTIME.block_on(fetch_profile(&session));
+    match serde_json::to_string(&profile) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
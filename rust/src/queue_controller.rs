@@ -0,0 +1,786 @@
+// Queue progression.
+//
+// Next, previous, jumps, auto-advance at the end of a track, preloading, repeat and
+// shuffle are all decided here, on a `spotifly-queue` thread that takes commands from a
+// channel. The player's event loop only sends commands, so it never waits on the queue
+// lock, and moves can't interleave: a Next from the host and an end of track arriving
+// together are applied one after the other. Requests made on the controller's own thread
+// (e.g. from an event callback) run in place.
+//
+// Queue edits (adding, removing and moving items, replacing the queue, stations and
+// paged playlists topping it up) go through the same channel with edit(), so an edit
+// lands either before or after a move, never halfway through one, and an item removed
+// or moved while a track ends can't throw the current index off.
+//
+// Shuffle doesn't reorder the queue up front. Upcoming items are picked at random as they
+// are needed (when playback moves on, or when the host asks what's up next) and moved up
+// to follow the current item. The played part of the queue stays in play order, so
+// previous goes back through it, and turning shuffle off carries on with the upcoming
+// items in their original order.
+//
+// Like most players, previous restarts the current item once playback is more than a few
+// seconds into it, and only goes back an item within that window.
+
+use crate::error::{Failure, SpotiflyError};
+use crate::{
+    availability, connect, crossfade, end_track_early, explicit_filter, load_track, load_track_from, loading, paging, parse_spotify_uri,
+    sleep_timer, spotifly_get_position_ms, station, to_c_string, trim, update_position, QueueItem,
+    CURRENT_INDEX, IS_PLAYING, PLAY_STATE_PARTIAL, QUEUE, REPEAT_MODE, REPEAT_OFF, REPEAT_TRACK,
+};
+use librespot_playback::player::Player;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::ffi::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::oneshot;
+
+const DEFAULT_RESTART_MS: u32 = 3_000;
+
+enum Command {
+    /// A track played to its end (or its trimmed end)
+    TrackEnded { uri: String, trimmed: bool, player: Arc<Player> },
+    /// `uri` reached its crossfade point
+    CrossfadeFrom { uri: String, player: Arc<Player> },
+    /// The player is ready to preload the track after `uri`
+    Preload { uri: String, player: Arc<Player> },
+    /// The player couldn't load `uri`
+    Unavailable { uri: String, player: Arc<Player> },
+    /// Move on from `uri` if it is still the current item
+    SkipFrom { uri: String, player: Arc<Player>, reply: Option<Sender<bool>> },
+    Next { player: Arc<Player>, reply: Sender<Result<(), Failure>> },
+    Previous { player: Arc<Player>, reply: Sender<Result<(), Failure>> },
+    Jump { index: usize, resume: bool, player: Arc<Player>, reply: Sender<Result<(), Failure>> },
+    SetShuffle { enabled: bool, reply: Sender<()> },
+    UpNext { count: usize, reply: Sender<Vec<(usize, QueueItem)>> },
+    /// Runs a queue edit (see edit())
+    Edit(Box<dyn FnOnce() + Send>),
+}
+
+static SHUFFLE: AtomicBool = AtomicBool::new(false);
+// Past this far into the current item, previous restarts it (0 = never)
+static RESTART_MS: AtomicU32 = AtomicU32::new(DEFAULT_RESTART_MS);
+// The play order shuffle has settled on, from the current item on
+static SETTLED: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+thread_local! {
+    static ON_CONTROLLER: Cell<bool> = const { Cell::new(false) };
+}
+
+// None if the thread couldn't be started; commands then run on the caller's thread
+static COMMANDS: Lazy<Option<Sender<Command>>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("spotifly-queue".to_string())
+        .spawn(move || {
+            ON_CONTROLLER.set(true);
+            for command in receiver {
+                handle(command);
+            }
+        })
+        .map_err(|e| log::error!("Queue controller error: {}", e))
+        .ok()
+        .map(|_| sender)
+});
+
+fn submit(command: Command) {
+    let command = match COMMANDS.as_ref() {
+        Some(sender) if !ON_CONTROLLER.get() => match sender.send(command) {
+            Ok(()) => return,
+            // The thread is gone (it panicked); carry on without it
+            Err(mpsc::SendError(command)) => command,
+        },
+        _ => command,
+    };
+    handle(command);
+}
+
+// Sends a command and waits for its reply. None if the command was dropped unanswered.
+fn request<T>(command: impl FnOnce(Sender<T>) -> Command) -> Option<T> {
+    let (reply, response) = mpsc::channel();
+    submit(command(reply));
+    response.recv().ok()
+}
+
+/// The failure of requests the controller dropped.
+pub(crate) fn stopped() -> Failure {
+    Failure::new(SpotiflyError::Unknown, "queue controller stopped")
+}
+
+fn handle(command: Command) {
+    match command {
+        Command::TrackEnded { uri, trimmed, player } => {
+            if sleep_timer::on_track_end() {
+                player.stop();
+            } else if !finish_track(&uri, &player) {
+                if trimmed {
+                    player.stop();
+                }
+                station::on_queue_ended(&uri);
+            }
+        }
+        Command::CrossfadeFrom { uri, player } => crossfade_into_next(&uri, &player),
+        Command::Preload { uri, player } => preload_next(&uri, &player),
+        Command::Unavailable { uri, player } => availability::on_unavailable(&uri, &player),
+        Command::SkipFrom { uri, player, reply } => {
+            let advanced = advance_from(&uri, &player);
+            if let Some(reply) = reply {
+                let _ = reply.send(advanced);
+            }
+        }
+        Command::Next { player, reply } => {
+            let _ = reply.send(next(&player));
+        }
+        Command::Previous { player, reply } => {
+            let _ = reply.send(previous(&player));
+        }
+        Command::Jump { index, resume, player, reply } => {
+            let _ = reply.send(jump(index, resume, &player));
+        }
+        Command::SetShuffle { enabled, reply } => {
+            SHUFFLE.store(enabled, Ordering::SeqCst);
+            SETTLED.lock().unwrap().clear();
+            let _ = reply.send(());
+        }
+        Command::UpNext { count, reply } => {
+            let _ = reply.send(up_next(count));
+        }
+        Command::Edit(edit) => edit(),
+    }
+}
+
+/// Handles the end of a track: repeats it, advances, or lets the queue end (handing over
+/// to the sleep timer and autoplay). A trimmed track is stopped if nothing follows it.
+pub(crate) fn track_ended(uri: String, trimmed: bool, player: &Arc<Player>) {
+    submit(Command::TrackEnded { uri, trimmed, player: Arc::clone(player) });
+}
+
+/// Moves on from `uri` at its crossfade point, ahead of its end, if another track follows it.
+pub(crate) fn crossfade_from(uri: String, player: &Arc<Player>) {
+    submit(Command::CrossfadeFrom { uri, player: Arc::clone(player) });
+}
+
+/// Preloads the track that will follow `uri`.
+pub(crate) fn preload(uri: String, player: &Arc<Player>) {
+    submit(Command::Preload { uri, player: Arc::clone(player) });
+}
+
+/// Handles a track the player couldn't load (see availability::on_unavailable()).
+pub(crate) fn track_unavailable(uri: String, player: &Arc<Player>) {
+    submit(Command::Unavailable { uri, player: Arc::clone(player) });
+}
+
+/// Moves on from `uri` if it is still the current item, without waiting.
+pub(crate) fn skip(uri: String, player: &Arc<Player>) {
+    submit(Command::SkipFrom { uri, player: Arc::clone(player), reply: None });
+}
+
+/// Moves on from `uri` if it is still the current item. Returns true if a track was loaded.
+pub(crate) fn skip_from(uri: &str, player: &Arc<Player>) -> bool {
+    request(|reply| Command::SkipFrom { uri: uri.to_string(), player: Arc::clone(player), reply: Some(reply) })
+        .unwrap_or(false)
+}
+
+/// Skips to the next item (a random upcoming one when shuffling).
+pub(crate) fn request_next(player: &Arc<Player>) -> Result<(), Failure> {
+    request(|reply| Command::Next { player: Arc::clone(player), reply }).unwrap_or_else(|| Err(stopped()))
+}
+
+/// Goes back to the previous item.
+pub(crate) fn request_previous(player: &Arc<Player>) -> Result<(), Failure> {
+    request(|reply| Command::Previous { player: Arc::clone(player), reply }).unwrap_or_else(|| Err(stopped()))
+}
+
+/// Plays the item at `index`, where it was left off if `resume` is set and it was partly played.
+pub(crate) fn request_jump(index: usize, resume: bool, player: &Arc<Player>) -> Result<(), Failure> {
+    request(|reply| Command::Jump { index, resume, player: Arc::clone(player), reply })
+        .unwrap_or_else(|| Err(stopped()))
+}
+
+// Runs `edit` on the queue and the current index under the queue lock, then hands its
+// result to `reply`
+fn edit_command<T: 'static>(
+    edit: impl FnOnce(&mut Vec<QueueItem>, &mut usize) -> T + Send + 'static,
+    reply: impl FnOnce(T) + Send + 'static,
+) -> Command {
+    Command::Edit(Box::new(move || {
+        let mut queue_guard = QUEUE.lock().unwrap();
+        let mut current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+        let result = edit(&mut queue_guard, &mut current_idx);
+        CURRENT_INDEX.store(current_idx, Ordering::SeqCst);
+        drop(queue_guard);
+        reply(result);
+    }))
+}
+
+/// Edits the queue between moves. The edit gets the queue and the current index, and
+/// updates the index if it shifts the current item. Returns the edit's result, or None
+/// if the controller dropped it. Async code uses edit_async().
+pub(crate) fn edit<T: Send + 'static>(
+    edit: impl FnOnce(&mut Vec<QueueItem>, &mut usize) -> T + Send + 'static,
+) -> Option<T> {
+    request(|reply| {
+        edit_command(edit, move |result| {
+            let _ = reply.send(result);
+        })
+    })
+}
+
+/// Like edit(), without waiting for the edit to be made.
+pub(crate) fn post_edit(edit: impl FnOnce(&mut Vec<QueueItem>, &mut usize) + Send + 'static) {
+    submit(edit_command(edit, |()| ()));
+}
+
+/// Like edit(), without blocking the runtime while the controller gets to it.
+pub(crate) async fn edit_async<T: Send + 'static>(
+    edit: impl FnOnce(&mut Vec<QueueItem>, &mut usize) -> T + Send + 'static,
+) -> Option<T> {
+    let (reply, response) = oneshot::channel();
+    submit(edit_command(edit, move |result| {
+        let _ = reply.send(result);
+    }));
+    response.await.ok()
+}
+
+/// Replaces the queue with `items`, making the first one current.
+pub(crate) async fn replace(items: Vec<QueueItem>) -> Result<(), Failure> {
+    edit_async(|queue, current_idx| {
+        *queue = items;
+        *current_idx = 0;
+    })
+    .await
+    .ok_or_else(stopped)
+}
+
+// With shuffle on, settles the play order of the `count` items after `current_idx`: each
+// one is picked at random from the rest of the queue and moved up into place. Items already
+// settled stay put, so preloading, "up next" and moving on agree.
+// Returns true if the queue was reordered.
+fn settle_shuffled(queue: &mut [QueueItem], current_idx: usize, count: usize) -> bool {
+    if !SHUFFLE.load(Ordering::SeqCst) || current_idx >= queue.len() {
+        return false;
+    }
+
+    let mut settled = SETTLED.lock().unwrap();
+    // Forget what has been played, then keep what still matches the queue (it may have been edited)
+    match settled.iter().position(|uri| *uri == queue[current_idx].uri) {
+        Some(start) => drop(settled.drain(..start)),
+        None => settled.clear(),
+    }
+    let matching = settled.iter().zip(&queue[current_idx..]).take_while(|(uri, item)| **uri == item.uri).count();
+    settled.truncate(matching);
+    if settled.is_empty() {
+        settled.push(queue[current_idx].uri.clone());
+    }
+
+    // settled[0] is the current item
+    let mut reordered = false;
+    while settled.len() <= count && current_idx + settled.len() < queue.len() {
+        let slot = current_idx + settled.len();
+        let pick = rand::rng().random_range(slot..queue.len());
+        queue[slot..=pick].rotate_right(1);
+        reordered |= pick != slot;
+        settled.push(queue[slot].uri.clone());
+    }
+    reordered
+}
+
+// What follows the current item
+struct NextUp {
+    index: Option<usize>,
+    // Items the explicit filter passed over on the way
+    skipped: Vec<String>,
+    // Shuffle moved items up
+    reordered: bool,
+}
+
+impl NextUp {
+    // Tells the host what moving on changed; call without the queue lock
+    fn announce(&self) {
+        if self.reordered {
+            loading::emit_queue_updated(false);
+        }
+        for uri in &self.skipped {
+            explicit_filter::on_skipped(uri);
+        }
+    }
+}
+
+// The item to play after `current_idx` (picked at random when shuffling), passing over
+// items the explicit filter keeps out
+fn next_up(queue: &mut [QueueItem], current_idx: usize) -> NextUp {
+    let mut next_up = NextUp { index: None, skipped: Vec::new(), reordered: false };
+    let mut idx = current_idx;
+    for _ in 0..queue.len() {
+        // Past a wrap-around, the order is the queue's
+        if idx >= current_idx {
+            next_up.reordered |= settle_shuffled(queue, current_idx, idx - current_idx + 1);
+        }
+        match next_index(idx, queue.len()) {
+            Some(next) if explicit_filter::blocks(&queue[next]) => {
+                next_up.skipped.push(queue[next].uri.clone());
+                idx = next;
+            }
+            index => {
+                next_up.index = index;
+                break;
+            }
+        }
+    }
+    next_up
+}
+
+/// Index of the queue item after `current_idx`, wrapping around to the start
+/// when the queue is on repeat. None at the end of the queue.
+fn next_index(current_idx: usize, len: usize) -> Option<usize> {
+    if current_idx + 1 < len {
+        Some(current_idx + 1)
+    } else if len > 0 && REPEAT_MODE.load(Ordering::SeqCst) != REPEAT_OFF {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+// Loads the item now at CURRENT_INDEX and tops up stations and paged playlists
+fn play_current(player: &Player, item: &QueueItem, start_ms: Option<u32>) -> Result<(), Failure> {
+    load_track_from(player, parse_spotify_uri(&item.uri)?, start_ms);
+    IS_PLAYING.store(true, Ordering::SeqCst);
+    station::maybe_extend();
+    paging::maybe_extend();
+    Ok(())
+}
+
+/// Restart `track_uri` from the beginning if it is still the current item (repeat-one).
+fn replay_current(track_uri: &str, player: &Player) -> bool {
+    let queue_guard = QUEUE.lock().unwrap();
+    let is_current = queue_guard.get(CURRENT_INDEX.load(Ordering::SeqCst))
+        .is_some_and(|item| item.uri == track_uri);
+    drop(queue_guard);
+    if !is_current {
+        return false;
+    }
+
+    match parse_spotify_uri(track_uri) {
+        Ok(spotify_uri) => {
+            load_track(player, spotify_uri);
+            IS_PLAYING.store(true, Ordering::SeqCst);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Repeat the track that just ended, or auto-advance to the next one if available.
+/// Returns true if a track was loaded.
+fn finish_track(track_uri: &str, player: &Player) -> bool {
+    // A Connect client advances its own context
+    if connect::is_remote_controlled() {
+        false
+    } else if REPEAT_MODE.load(Ordering::SeqCst) == REPEAT_TRACK {
+        replay_current(track_uri, player)
+    } else {
+        advance_from(track_uri, player)
+    }
+}
+
+// Whether another track follows `track_uri` straight away: it is still the current item
+// and the queue doesn't end with it
+fn has_next(track_uri: &str) -> bool {
+    let mut queue_guard = QUEUE.lock().unwrap();
+    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+    if REPEAT_MODE.load(Ordering::SeqCst) == REPEAT_TRACK {
+        return queue_guard.get(current_idx).is_some_and(|item| item.uri == track_uri);
+    }
+    let Some(next_up) = next_after(&mut queue_guard, current_idx, track_uri) else {
+        return false;
+    };
+    drop(queue_guard);
+
+    // Items passed over are reported once playback actually moves on
+    if next_up.reordered {
+        loading::emit_queue_updated(false);
+    }
+    next_up.index.is_some()
+}
+
+/// Moves on from `track_uri` at its crossfade point, with its tail mixed into the track
+/// that follows. Only if another track follows straight away: at the end of the queue, or
+/// when an end-of-track sleep timer is set, the track plays to its end.
+fn crossfade_into_next(track_uri: &str, player: &Player) {
+    let follows = !connect::is_remote_controlled() && !sleep_timer::ends_with_track() && has_next(track_uri);
+    if !follows {
+        crossfade::disarm(track_uri);
+        return;
+    }
+    if !crossfade::arm(track_uri) {
+        return;
+    }
+    end_track_early(track_uri, spotifly_get_position_ms());
+    if !finish_track(track_uri, player) {
+        crossfade::disarm(track_uri);
+    }
+}
+
+/// Prime the track that will follow `track_uri` so the transition has no gap.
+/// Mirrors finish_track(): on repeat-one the same track is preloaded again.
+fn preload_next(track_uri: &str, player: &Player) {
+    // A Connect client preloads its own context
+    if connect::is_remote_controlled() {
+        return;
+    }
+
+    let mut queue_guard = QUEUE.lock().unwrap();
+    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+    if queue_guard.get(current_idx).is_none_or(|item| item.uri != track_uri) {
+        return;
+    }
+    let mut reordered = false;
+    let next_uri = if REPEAT_MODE.load(Ordering::SeqCst) == REPEAT_TRACK {
+        track_uri.to_string()
+    } else {
+        // Items passed over are reported once playback actually moves on
+        let next_up = next_up(&mut queue_guard, current_idx);
+        reordered = next_up.reordered;
+        match next_up.index {
+            Some(idx) => queue_guard[idx].uri.clone(),
+            None => return,
+        }
+    };
+    drop(queue_guard);
+
+    if reordered {
+        loading::emit_queue_updated(false);
+    }
+    if let Ok(spotify_uri) = parse_spotify_uri(&next_uri) {
+        player.preload(spotify_uri);
+    }
+}
+
+// What follows `track_uri`, or None if it is no longer the current item
+fn next_after(queue: &mut [QueueItem], current_idx: usize, track_uri: &str) -> Option<NextUp> {
+    // Ignore stale events for a track that is no longer current
+    // (e.g. the queue was replaced while the old track was ending)
+    let is_current = queue.get(current_idx).is_some_and(|item| item.uri == track_uri);
+    is_current.then(|| next_up(queue, current_idx))
+}
+
+/// Advance to the queue item after `track_uri` if it is still the current item.
+/// Index is read and advanced under the queue lock so concurrent queue edits
+/// can't make us skip or repeat a track. Returns true if a new track was loaded.
+fn advance_from(track_uri: &str, player: &Player) -> bool {
+    let mut queue_guard = QUEUE.lock().unwrap();
+    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+
+    let Some(next_up) = next_after(&mut queue_guard, current_idx, track_uri) else {
+        return false;
+    };
+    let Some(next_idx) = next_up.index else {
+        drop(queue_guard);
+        next_up.announce();
+        return false;
+    };
+
+    let next_track = queue_guard[next_idx].clone();
+    CURRENT_INDEX.store(next_idx, Ordering::SeqCst);
+    drop(queue_guard);
+
+    next_up.announce();
+    play_current(player, &next_track, None).is_ok()
+}
+
+fn next(player: &Player) -> Result<(), Failure> {
+    let mut queue_guard = QUEUE.lock().unwrap();
+    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+
+    let next_up = next_up(&mut queue_guard, current_idx);
+    let Some(next_idx) = next_up.index else {
+        drop(queue_guard);
+        next_up.announce();
+        return Err(Failure::new(SpotiflyError::InvalidArgument, "already at last track"));
+    };
+
+    let next_track = queue_guard[next_idx].clone();
+    CURRENT_INDEX.store(next_idx, Ordering::SeqCst);
+    drop(queue_guard);
+
+    next_up.announce();
+    play_current(player, &next_track, None)
+}
+
+fn previous(player: &Player) -> Result<(), Failure> {
+    let queue_guard = QUEUE.lock().unwrap();
+    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+
+    // Outside the jump-back window, restart the current item (at its trimmed start)
+    let restart_ms = RESTART_MS.load(Ordering::SeqCst);
+    if let Some(current) = queue_guard.get(current_idx).filter(|_| restart_ms > 0) {
+        let start_ms = trim::start_ms(&current.uri);
+        if spotifly_get_position_ms() > start_ms.saturating_add(restart_ms) {
+            drop(queue_guard);
+            player.seek(start_ms);
+            update_position(start_ms);
+            return Ok(());
+        }
+    }
+
+    let repeating = REPEAT_MODE.load(Ordering::SeqCst) != REPEAT_OFF;
+    let prev_idx = if current_idx > 0 && current_idx <= queue_guard.len() {
+        current_idx - 1
+    } else if current_idx == 0 && repeating && !queue_guard.is_empty() {
+        // Wrap around to the end of the queue
+        queue_guard.len() - 1
+    } else {
+        return Err(Failure::new(SpotiflyError::InvalidArgument, "already at first track"));
+    };
+
+    let prev_track = queue_guard[prev_idx].clone();
+    CURRENT_INDEX.store(prev_idx, Ordering::SeqCst);
+    drop(queue_guard);
+
+    play_current(player, &prev_track, None)
+}
+
+fn jump(index: usize, resume: bool, player: &Player) -> Result<(), Failure> {
+    // Validate before touching the current index, so a bad request leaves the queue as it was
+    let queue_guard = QUEUE.lock().unwrap();
+    if index >= queue_guard.len() {
+        return Err(Failure::new(
+            SpotiflyError::InvalidArgument,
+            format!("index {} out of bounds (queue length: {})", index, queue_guard.len()),
+        ));
+    }
+    let target_track = queue_guard[index].clone();
+    parse_spotify_uri(&target_track.uri)?;
+    CURRENT_INDEX.store(index, Ordering::SeqCst);
+    drop(queue_guard);
+
+    let start_ms = (resume && target_track.play_state == PLAY_STATE_PARTIAL).then_some(target_track.last_position_ms);
+    play_current(player, &target_track, start_ms)
+}
+
+// The next `count` items in the order they will play (if each plays to its end), with their
+// queue indices. Settles the shuffle order that far ahead.
+fn up_next(count: usize) -> Vec<(usize, QueueItem)> {
+    let mut queue_guard = QUEUE.lock().unwrap();
+    let current_idx = CURRENT_INDEX.load(Ordering::SeqCst);
+    let Some(current) = queue_guard.get(current_idx).filter(|_| count > 0) else { return Vec::new() };
+    if REPEAT_MODE.load(Ordering::SeqCst) == REPEAT_TRACK {
+        return vec![(current_idx, current.clone())];
+    }
+
+    let mut items = Vec::new();
+    let mut reordered = false;
+    let mut idx = current_idx;
+    while items.len() < count {
+        if idx >= current_idx {
+            reordered |= settle_shuffled(&mut queue_guard, current_idx, idx - current_idx + 1);
+        }
+        // A repeating queue comes round to the current item again
+        match next_index(idx, queue_guard.len()).filter(|&next| next != current_idx) {
+            Some(next) => {
+                if !explicit_filter::blocks(&queue_guard[next]) {
+                    items.push((next, queue_guard[next].clone()));
+                }
+                idx = next;
+            }
+            None => break,
+        }
+    }
+    drop(queue_guard);
+
+    if reordered {
+        loading::emit_queue_updated(false);
+    }
+    items
+}
+
+/// Turns shuffle on or off. While shuffle is on, each next item (on skipping or when a
+/// track ends) is picked at random from the upcoming ones and moved up to follow the
+/// current item, sending QueueUpdated. Turning it off continues with the upcoming items
+/// in their queue order. Works together with repeat: a repeating queue starts over from
+/// its first item.
+///
+/// # Parameters
+/// - enabled: true to shuffle
+#[no_mangle]
+pub extern "C" fn spotifly_set_shuffle(enabled: bool) {
+    request(|reply| Command::SetShuffle { enabled, reply });
+}
+
+/// Returns true if shuffle is on.
+#[no_mangle]
+pub extern "C" fn spotifly_get_shuffle() -> bool {
+    SHUFFLE.load(Ordering::SeqCst)
+}
+
+/// Returns the next items to play after the current one, in play order, as a JSON array
+/// of queue items (as in spotifly_get_queue_json()) with their queue "index" added.
+/// Takes repeat, shuffle and the explicit filter into account: a repeating queue wraps
+/// around (up to the current item), on repeat-one the current item is the only one, and
+/// when shuffling the next items are picked now (moving them up the queue, with a
+/// QueueUpdated event) so that they really are what plays next.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - count: Maximum number of items
+#[no_mangle]
+pub extern "C" fn spotifly_get_next_up_json(count: usize) -> *mut c_char {
+    let items = request(|reply| Command::UpNext { count, reply }).unwrap_or_default();
+    let items: Vec<Value> = items.into_iter()
+        .filter_map(|(index, item)| {
+            let mut item = serde_json::to_value(item).ok()?;
+            item["index"] = json!(index);
+            Some(item)
+        })
+        .collect();
+    match serde_json::to_string(&items) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Sets the jump-back window for spotifly_previous(): further than this into the current
+/// track, previous restarts it; within it, previous goes to the previous track. 0 always
+/// goes to the previous track. Defaults to 3000 ms.
+///
+/// # Parameters
+/// - window_ms: Jump-back window in milliseconds
+#[no_mangle]
+pub extern "C" fn spotifly_set_previous_restart_ms(window_ms: u32) {
+    RESTART_MS.store(window_ms, Ordering::SeqCst);
+}
+
+/// Returns the jump-back window for spotifly_previous() in milliseconds (0 = off).
+#[no_mangle]
+pub extern "C" fn spotifly_get_previous_restart_ms() -> u32 {
+    RESTART_MS.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    // Shuffle, repeat and the explicit filter are left at their defaults (off), so these
+    // cover the plain play order
+    use super::*;
+    use crate::artwork::ArtworkUrls;
+    use crate::{move_item, remove_item, ITEM_TYPE_TRACK, PLAY_STATE_UNPLAYED};
+
+    fn queue(uris: &[&str]) -> Vec<QueueItem> {
+        uris.iter()
+            .map(|uri| QueueItem {
+                uri: uri.to_string(),
+                item_type: ITEM_TYPE_TRACK,
+                track_name: String::new(),
+                artist_name: String::new(),
+                album_art_url: String::new(),
+                album_art_urls: ArtworkUrls::default(),
+                duration_ms: 0,
+                album_id: None,
+                artist_id: None,
+                external_url: None,
+                play_state: PLAY_STATE_UNPLAYED,
+                last_position_ms: 0,
+                album_name: None,
+                track_number: 0,
+                disc_number: 0,
+                explicit: false,
+                release_date: None,
+            })
+            .collect()
+    }
+
+    // URI of the item that plays once `track_uri` ends
+    fn next_uri(queue: &mut [QueueItem], current_idx: usize, track_uri: &str) -> Option<String> {
+        let index = next_after(queue, current_idx, track_uri)?.index?;
+        Some(queue[index].uri.clone())
+    }
+
+    #[test]
+    fn next_up_follows_queue_order() {
+        let mut queue = queue(&["a", "b", "c"]);
+        assert_eq!(next_up(&mut queue, 0).index, Some(1));
+        assert_eq!(next_up(&mut queue, 1).index, Some(2));
+        assert_eq!(next_up(&mut queue, 2).index, None);
+    }
+
+    #[test]
+    fn end_of_track_ignores_items_no_longer_current() {
+        let mut queue = queue(&["a", "b", "c"]);
+        assert!(next_after(&mut queue, 1, "a").is_none());
+        assert!(next_after(&mut queue, 5, "b").is_none());
+        assert_eq!(next_uri(&mut queue, 1, "b").as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn end_of_track_after_removing_a_played_item() {
+        let mut queue = queue(&["a", "b", "c", "d"]);
+        let mut current_idx = 1;
+        remove_item(&mut queue, &mut current_idx, 0).unwrap();
+        assert_eq!(current_idx, 0);
+        assert_eq!(next_uri(&mut queue, current_idx, "b").as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn end_of_track_after_removing_the_next_item() {
+        let mut queue = queue(&["a", "b", "c", "d"]);
+        let mut current_idx = 1;
+        remove_item(&mut queue, &mut current_idx, 2).unwrap();
+        assert_eq!(current_idx, 1);
+        assert_eq!(next_uri(&mut queue, current_idx, "b").as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn remove_rejects_the_current_item_and_bad_indices() {
+        let mut queue = queue(&["a", "b", "c"]);
+        let mut current_idx = 1;
+        assert!(remove_item(&mut queue, &mut current_idx, 1).is_err());
+        assert!(remove_item(&mut queue, &mut current_idx, 3).is_err());
+        assert_eq!(queue.len(), 3);
+        assert_eq!(current_idx, 1);
+    }
+
+    #[test]
+    fn end_of_track_after_moving_the_current_item() {
+        let mut queue = queue(&["a", "b", "c", "d"]);
+        let mut current_idx = 1;
+        move_item(&mut queue, &mut current_idx, 1, 3).unwrap();
+        assert_eq!(current_idx, 3);
+        // b is now last: the queue ends instead of replaying c or d
+        assert_eq!(next_uri(&mut queue, current_idx, "b"), None);
+
+        move_item(&mut queue, &mut current_idx, 3, 0).unwrap();
+        assert_eq!(current_idx, 0);
+        assert_eq!(next_uri(&mut queue, current_idx, "b").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn end_of_track_after_moving_items_around_the_current_one() {
+        let mut queue = queue(&["a", "b", "c", "d"]);
+        let mut current_idx = 1;
+
+        // Upcoming item moved in front of the current one
+        move_item(&mut queue, &mut current_idx, 3, 0).unwrap();
+        assert_eq!(current_idx, 2);
+        assert_eq!(next_uri(&mut queue, current_idx, "b").as_deref(), Some("c"));
+
+        // Played item moved behind the current one plays next
+        move_item(&mut queue, &mut current_idx, 0, 2).unwrap();
+        assert_eq!(current_idx, 1);
+        assert_eq!(next_uri(&mut queue, current_idx, "b").as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn move_rejects_bad_indices() {
+        let mut queue = queue(&["a", "b"]);
+        let mut current_idx = 0;
+        assert!(move_item(&mut queue, &mut current_idx, 0, 2).is_err());
+        assert!(move_item(&mut queue, &mut current_idx, 2, 0).is_err());
+        assert_eq!(current_idx, 0);
+    }
+}
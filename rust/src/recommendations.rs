@@ -0,0 +1,194 @@
+// Seed-based track recommendations via the Web API.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{power, webapi};
+use crate::{to_c_string, QueueItem, RUNTIME};
+use librespot_core::session::Session;
+use serde_json::Value;
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+// The Web API accepts at most 5 seeds and 100 results per request
+const MAX_SEEDS: usize = 5;
+const MAX_LIMIT: u32 = 100;
+
+// Audio attributes that can be tuned with min_/max_/target_ prefixes
+const TUNABLE_ATTRIBUTES: &[&str] = &[
+    "acousticness",
+    "danceability",
+    "energy",
+    "instrumentalness",
+    "liveness",
+    "loudness",
+    "popularity",
+    "speechiness",
+    "tempo",
+    "valence",
+];
+
+fn invalid(message: impl Into<String>) -> Failure {
+    Failure::new(SpotiflyError::InvalidArgument, message)
+}
+
+/// Converts a tuning JSON object (e.g. {"target_tempo": 128, "min_energy": 0.7})
+/// into query pairs, rejecting unknown attributes and non-numeric values.
+fn tuning_pairs(tuning: &Value) -> Result<Vec<(String, String)>, Failure> {
+    let object = match tuning {
+        Value::Null => return Ok(Vec::new()),
+        Value::Object(object) => object,
+        _ => return Err(invalid("Tuning must be a JSON object")),
+    };
+
+    let mut pairs = Vec::new();
+    for (key, value) in object {
+        let attribute = key.strip_prefix("min_")
+            .or_else(|| key.strip_prefix("max_"))
+            .or_else(|| key.strip_prefix("target_"))
+            .ok_or_else(|| invalid(format!("Unknown tuning parameter: {}", key)))?;
+        if !TUNABLE_ATTRIBUTES.contains(&attribute) {
+            return Err(invalid(format!("Unknown tuning parameter: {}", key)));
+        }
+        let number = value.as_f64()
+            .ok_or_else(|| invalid(format!("Tuning parameter {} must be a number", key)))?;
+        pairs.push((key.clone(), number.to_string()));
+    }
+    Ok(pairs)
+}
+
+/// Fetches recommendations for up to 5 seeds.
+/// Seeds are track or artist URIs; anything else is treated as a genre name.
+pub(crate) async fn fetch_recommendations(
+    session: &Session,
+    seeds: &[String],
+    limit: u32,
+    tuning: &Value,
+) -> Result<Vec<QueueItem>, Failure> {
+    if seeds.is_empty() {
+        return Err(invalid("At least one seed is required"));
+    }
+    if seeds.len() > MAX_SEEDS {
+        return Err(invalid(format!("At most {} seeds are allowed", MAX_SEEDS)));
+    }
+
+    let mut seed_tracks = Vec::new();
+    let mut seed_artists = Vec::new();
+    let mut seed_genres = Vec::new();
+    for seed in seeds {
+        if let Some(id) = seed.strip_prefix("spotify:track:") {
+            seed_tracks.push(id);
+        } else if let Some(id) = seed.strip_prefix("spotify:artist:") {
+            seed_artists.push(id);
+        } else if seed.starts_with("spotify:") {
+            return Err(Failure::new(SpotiflyError::InvalidUri, format!("Unsupported seed: {}", seed)));
+        } else {
+            seed_genres.push(seed.as_str());
+        }
+    }
+
+    let mut pairs = vec![("limit".to_string(), limit.clamp(1, MAX_LIMIT).to_string())];
+    for (key, ids) in [
+        ("seed_tracks", seed_tracks),
+        ("seed_artists", seed_artists),
+        ("seed_genres", seed_genres),
+    ] {
+        if !ids.is_empty() {
+            pairs.push((key.to_string(), ids.join(",")));
+        }
+    }
+    pairs.extend(tuning_pairs(tuning)?);
+
+    let query = webapi::query_string(pairs.iter().map(|(k, v)| (k.as_str(), v.clone())));
+    let response = webapi::get(session, &format!("/recommendations?{}", query)).await?;
+
+    let tracks = response.get("tracks")
+        .and_then(Value::as_array)
+        .ok_or_else(|| Failure::new(SpotiflyError::Unknown, "Recommendations response has no tracks"))?;
+
+    Ok(tracks.iter().filter_map(webapi::queue_item_from_json).collect())
+}
+
+/// Gets track recommendations for the given seeds, tuned by audio attributes.
+/// Returns a JSON array of queue items, or NULL on error.
+/// Caller must free the string with spotifly_free_string().
+///
+/// # Parameters
+/// - seed_uris_json: JSON array of up to 5 seeds (track URIs, artist URIs or genre names)
+/// - limit: Maximum number of tracks (1-100)
+/// - tuning_json: JSON object of min_/max_/target_ audio attributes
+///   (e.g. {"target_tempo": 128, "target_energy": 0.8, "min_popularity": 40}), may be NULL
+#[no_mangle]
+pub extern "C" fn spotifly_get_tuned_recommendations(
+    seed_uris_json: *const c_char,
+    limit: u32,
+    tuning_json: *const c_char,
+) -> *mut c_char {
+    power::note_activity();
+    if seed_uris_json.is_null() {
+        error::fail(SpotiflyError::InvalidArgument, "Get recommendations error: seed_uris_json is null");
+        return ptr::null_mut();
+    }
+
+    let seeds_str = unsafe {
+        match CStr::from_ptr(seed_uris_json).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => {
+                error::fail(SpotiflyError::InvalidArgument, "Get recommendations error: invalid seed_uris_json string");
+                return ptr::null_mut();
+            }
+        }
+    };
+
+    let tuning_str = if tuning_json.is_null() {
+        None
+    } else {
+        unsafe {
+            match CStr::from_ptr(tuning_json).to_str() {
+                Ok(s) => Some(s.to_string()),
+                Err(_) => {
+                    error::fail(
+                        SpotiflyError::InvalidArgument,
+                        "Get recommendations error: invalid tuning_json string",
+                    );
+                    return ptr::null_mut();
+                }
+            }
+        }
+    };
+
+    let result: Result<Vec<QueueItem>, Failure> = (|| {
+        let seeds: Vec<String> = serde_json::from_str(&seeds_str)
+            .map_err(|e| invalid(format!("failed to parse seeds JSON: {:?}", e)))?;
+        let tuning: Value = match &tuning_str {
+            Some(s) => serde_json::from_str(s)
+                .map_err(|e| invalid(format!("failed to parse tuning JSON: {:?}", e)))?,
+            None => Value::Null,
+        };
+        let session = webapi::current_session()?;
+
+        RUNTIME.block_on(fetch_recommendations(&session, &seeds, limit, &tuning))
+    })();
+
+    match result {
+        Ok(items) => match serde_json::to_string(&items) {
+            Ok(json_string) => to_c_string(&json_string),
+            Err(_) => ptr::null_mut(),
+        },
+        Err(e) => {
+            error::report("Get recommendations", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Gets track recommendations for the given seeds.
+/// Same as spotifly_get_tuned_recommendations() without tuning.
+/// Returns a JSON array of queue items, or NULL on error.
+/// Caller must free the string with spotifly_free_string().
+///
+/// # Parameters
+/// - seed_uris_json: JSON array of up to 5 seeds (track URIs, artist URIs or genre names)
+/// - limit: Maximum number of tracks (1-100)
+#[no_mangle]
+pub extern "C" fn spotifly_get_recommendations(seed_uris_json: *const c_char, limit: u32) -> *mut c_char {
+    spotifly_get_tuned_recommendations(seed_uris_json, limit, ptr::null())
+}
@@ -0,0 +1,301 @@
+// Scrobbling to Last.fm and ListenBrainz.
+//
+// A track is scrobbled once it has been listened to for half its length or four minutes,
+// whichever comes first (the rule both services use); tracks under 30 seconds and podcast
+// episodes are never scrobbled. Listening time is measured from position updates, so
+// seeking ahead doesn't count. Services also get a "now playing" notice when a track
+// starts. Nothing is sent during a private session.
+
+use crate::error::{self, SpotiflyError};
+use crate::{current_timestamp_ms, private_session, webapi, RUNTIME};
+use bytes::Bytes;
+use http::header::{AUTHORIZATION, CONTENT_TYPE};
+use http::{Method, Request};
+use librespot_core::session::Session;
+use librespot_metadata::audio::{AudioItem, UniqueFields};
+use md5::{Digest, Md5};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::ffi::{c_char, CStr};
+use std::sync::Mutex;
+
+const LASTFM_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+const LISTENBRAINZ_API_URL: &str = "https://api.listenbrainz.org/1/submit-listens";
+const CLIENT_NAME: &str = "Spotifly";
+// Shorter tracks are never scrobbled
+const MIN_TRACK_MS: u32 = 30_000;
+// Listening this long always scrobbles, even if it's less than half the track
+const MAX_THRESHOLD_MS: u32 = 4 * 60 * 1000;
+// Position jumps larger than this are seeks and don't count as listening time
+const MAX_POSITION_STEP_MS: u32 = 2_000;
+
+#[derive(Clone, Default)]
+struct ScrobblerConfig {
+    lastfm: Option<LastfmCredentials>,
+    listenbrainz_token: Option<String>,
+}
+
+#[derive(Clone)]
+struct LastfmCredentials {
+    api_key: String,
+    api_secret: String,
+    session_key: String,
+}
+
+#[derive(Clone)]
+struct Scrobble {
+    uri: String,
+    track_name: String,
+    /// All artists, in credit order
+    artists: Vec<String>,
+    album_name: String,
+    duration_ms: u32,
+    started_at_ms: u64,
+}
+
+// The track being listened to and how far towards a scrobble it got
+struct CurrentTrack {
+    scrobble: Scrobble,
+    listened_ms: u32,
+    last_position_ms: u32,
+    scrobbled: bool,
+}
+
+static CONFIG: Lazy<Mutex<ScrobblerConfig>> = Lazy::new(|| Mutex::new(ScrobblerConfig::default()));
+static CURRENT: Lazy<Mutex<Option<CurrentTrack>>> = Lazy::new(|| Mutex::new(None));
+
+fn is_configured() -> bool {
+    let config = CONFIG.lock().unwrap();
+    config.lastfm.is_some() || config.listenbrainz_token.is_some()
+}
+
+/// Starts tracking a new track and sends "now playing" notices.
+pub(crate) fn on_track_changed(audio_item: &AudioItem) {
+    let mut current = CURRENT.lock().unwrap();
+    *current = None;
+    if !is_configured() || private_session::is_active() || audio_item.duration_ms < MIN_TRACK_MS {
+        return;
+    }
+
+    let (artists, album_name) = match &audio_item.unique_fields {
+        UniqueFields::Track { artists, album, .. } => (
+            artists.iter().map(|artist| artist.name.clone()).collect::<Vec<_>>(),
+            album.clone(),
+        ),
+        // Episodes and local files aren't scrobbled
+        _ => return,
+    };
+    if artists.is_empty() {
+        return;
+    }
+
+    let scrobble = Scrobble {
+        uri: audio_item.uri.clone(),
+        track_name: audio_item.name.clone(),
+        artists,
+        album_name,
+        duration_ms: audio_item.duration_ms,
+        started_at_ms: current_timestamp_ms(),
+    };
+    submit(scrobble.clone(), false);
+    *current = Some(CurrentTrack {
+        scrobble,
+        listened_ms: 0,
+        last_position_ms: 0,
+        scrobbled: false,
+    });
+}
+
+/// Accumulates listening time from a position update and scrobbles the track
+/// once it crosses the threshold. `continuous` is false for seeks.
+pub(crate) fn on_position(position_ms: u32, continuous: bool) {
+    let mut guard = CURRENT.lock().unwrap();
+    let current = match guard.as_mut() {
+        Some(current) => current,
+        None => return,
+    };
+
+    let step = position_ms.saturating_sub(current.last_position_ms);
+    if continuous && step <= MAX_POSITION_STEP_MS {
+        current.listened_ms = current.listened_ms.saturating_add(step);
+    }
+    current.last_position_ms = position_ms;
+
+    let threshold_ms = (current.scrobble.duration_ms / 2).min(MAX_THRESHOLD_MS);
+    if !current.scrobbled && current.listened_ms >= threshold_ms && !private_session::is_active() {
+        current.scrobbled = true;
+        submit(current.scrobble.clone(), true);
+    }
+}
+
+// Sends a scrobble (or "now playing" notice) to every configured service in the background
+fn submit(scrobble: Scrobble, completed: bool) {
+    let config = CONFIG.lock().unwrap().clone();
+    let session = match webapi::current_session() {
+        Ok(session) => session,
+        Err(_) => return,
+    };
+
+    RUNTIME.spawn(async move {
+        if let Some(credentials) = &config.lastfm {
+            let request = lastfm_request(credentials, &scrobble, completed);
+            if let Err(e) = send(&session, request).await {
+                log::warn!("Last.fm scrobble failed: {}", e);
+            }
+        }
+        if let Some(token) = &config.listenbrainz_token {
+            let request = listenbrainz_request(token, &scrobble, completed);
+            if let Err(e) = send(&session, request).await {
+                log::warn!("ListenBrainz scrobble failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn send(session: &Session, request: Result<Request<Bytes>, http::Error>) -> Result<(), String> {
+    let request = request.map_err(|e| format!("invalid request: {}", e))?;
+    session.http_client().request_body(request).await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn lastfm_request(
+    credentials: &LastfmCredentials,
+    scrobble: &Scrobble,
+    completed: bool,
+) -> Result<Request<Bytes>, http::Error> {
+    let method = if completed { "track.scrobble" } else { "track.updateNowPlaying" };
+    let mut params = vec![
+        ("method", method.to_string()),
+        ("api_key", credentials.api_key.clone()),
+        ("sk", credentials.session_key.clone()),
+        ("artist", scrobble.artists[0].clone()),
+        ("track", scrobble.track_name.clone()),
+        ("duration", (scrobble.duration_ms / 1000).to_string()),
+    ];
+    if !scrobble.album_name.is_empty() {
+        params.push(("album", scrobble.album_name.clone()));
+    }
+    if completed {
+        params.push(("timestamp", (scrobble.started_at_ms / 1000).to_string()));
+    }
+
+    // The signature covers all parameters, sorted by name, followed by the secret
+    params.sort_by(|a, b| a.0.cmp(b.0));
+    let mut hasher = Md5::new();
+    for (key, value) in &params {
+        hasher.update(key.as_bytes());
+        hasher.update(value.as_bytes());
+    }
+    hasher.update(credentials.api_secret.as_bytes());
+    params.push(("api_sig", format!("{:x}", hasher.finalize())));
+    params.push(("format", "json".to_string()));
+
+    let body = webapi::query_string(params.iter().map(|(key, value)| (*key, value.clone())));
+    Request::builder()
+        .method(Method::POST)
+        .uri(LASTFM_API_URL)
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Bytes::from(body))
+}
+
+fn listenbrainz_request(token: &str, scrobble: &Scrobble, completed: bool) -> Result<Request<Bytes>, http::Error> {
+    let mut listen = json!({
+        "track_metadata": {
+            "artist_name": scrobble.artists.join(", "),
+            "track_name": scrobble.track_name,
+            "additional_info": {
+                "duration_ms": scrobble.duration_ms,
+                "artist_names": scrobble.artists,
+                "spotify_id": scrobble.uri.strip_prefix("spotify:track:")
+                    .map(|id| format!("https://open.spotify.com/track/{}", id)),
+                "media_player": CLIENT_NAME,
+                "submission_client": CLIENT_NAME,
+                "music_service": "spotify.com",
+            },
+        },
+    });
+    if !scrobble.album_name.is_empty() {
+        listen["track_metadata"]["release_name"] = Value::from(scrobble.album_name.clone());
+    }
+    if completed {
+        listen["listened_at"] = Value::from(scrobble.started_at_ms / 1000);
+    }
+    let body = json!({
+        "listen_type": if completed { "single" } else { "playing_now" },
+        "payload": [listen],
+    });
+
+    Request::builder()
+        .method(Method::POST)
+        .uri(LISTENBRAINZ_API_URL)
+        .header(AUTHORIZATION, format!("Token {}", token))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Bytes::from(body.to_string()))
+}
+
+/// Configures scrobbling. Services without credentials are disabled; passing NULL
+/// for everything turns scrobbling off. Tracks are scrobbled after half their length
+/// or four minutes of listening, whichever comes first, except during a private session.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - lastfm_api_key: Last.fm API key, or NULL
+/// - lastfm_api_secret: Last.fm API shared secret, or NULL
+/// - lastfm_session_key: Last.fm session key from the host's auth flow, or NULL
+/// - listenbrainz_token: ListenBrainz user token, or NULL
+#[no_mangle]
+pub extern "C" fn spotifly_configure_scrobbler(
+    lastfm_api_key: *const c_char,
+    lastfm_api_secret: *const c_char,
+    lastfm_session_key: *const c_char,
+    listenbrainz_token: *const c_char,
+) -> i32 {
+    let read = |s: *const c_char| -> Result<Option<String>, ()> {
+        if s.is_null() {
+            return Ok(None);
+        }
+        unsafe {
+            CStr::from_ptr(s).to_str()
+                .map(|s| Some(s.to_string()).filter(|s| !s.is_empty()))
+                .map_err(|_| ())
+        }
+    };
+
+    let strings = (
+        read(lastfm_api_key),
+        read(lastfm_api_secret),
+        read(lastfm_session_key),
+        read(listenbrainz_token),
+    );
+    let (api_key, api_secret, session_key, token) = match strings {
+        (Ok(a), Ok(b), Ok(c), Ok(d)) => (a, b, c, d),
+        _ => {
+            return error::fail(SpotiflyError::InvalidArgument, "Configure scrobbler error: invalid string");
+        }
+    };
+
+    let lastfm = match (api_key, api_secret, session_key) {
+        (Some(api_key), Some(api_secret), Some(session_key)) => Some(LastfmCredentials {
+            api_key,
+            api_secret,
+            session_key,
+        }),
+        (None, None, None) => None,
+        _ => {
+            return error::fail(
+                SpotiflyError::InvalidArgument,
+                "Configure scrobbler error: Last.fm needs an API key, secret and session key",
+            );
+        }
+    };
+
+    *CONFIG.lock().unwrap() = ScrobblerConfig {
+        lastfm,
+        listenbrainz_token: token,
+    };
+    if !is_configured() {
+        CURRENT.lock().unwrap().take();
+    }
+    0
+}
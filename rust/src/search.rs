@@ -0,0 +1,174 @@
+// Catalog search via the Web API.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::webapi::{self, first_image_url, str_field};
+use crate::{power, to_c_string, RUNTIME};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+const SEARCH_TYPES: &[&str] = &["track", "album", "artist", "playlist"];
+// The Web API returns at most 50 results per type
+const MAX_LIMIT: u32 = 50;
+
+/// An album as listed in search results and on artist pages.
+#[derive(Serialize)]
+pub(crate) struct AlbumResult {
+    uri: String,
+    name: String,
+    artist_name: String,
+    album_art_url: String,
+    release_date: Option<String>,
+    total_tracks: u64,
+}
+
+/// An artist as listed in search results.
+#[derive(Serialize)]
+pub(crate) struct ArtistResult {
+    uri: String,
+    name: String,
+    image_url: String,
+    genres: Vec<String>,
+}
+
+fn artist_names(value: &Value) -> String {
+    value.get("artists")
+        .and_then(Value::as_array)
+        .map(|artists| {
+            artists.iter()
+                .filter_map(|a| a.get("name").and_then(Value::as_str))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn album_result(album: &Value) -> Option<AlbumResult> {
+    Some(AlbumResult {
+        uri: album.get("uri")?.as_str()?.to_string(),
+        name: str_field(album, "name"),
+        artist_name: artist_names(album),
+        album_art_url: first_image_url(album),
+        release_date: album.get("release_date").and_then(Value::as_str).map(str::to_string),
+        total_tracks: album.get("total_tracks").and_then(Value::as_u64).unwrap_or(0),
+    })
+}
+
+pub(crate) fn artist_result(artist: &Value) -> Option<ArtistResult> {
+    Some(ArtistResult {
+        uri: artist.get("uri")?.as_str()?.to_string(),
+        name: str_field(artist, "name"),
+        image_url: first_image_url(artist),
+        genres: artist.get("genres")
+            .and_then(Value::as_array)
+            .map(|genres| genres.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            .unwrap_or_default(),
+    })
+}
+
+// Converts one type's page of results ({"items": [...], "total": n})
+fn convert_page(search_type: &str, page: &Value) -> Value {
+    // Items can be null for content that is no longer available
+    let items = page.get("items").and_then(Value::as_array).cloned().unwrap_or_default();
+    let items = items.iter().filter(|item| !item.is_null());
+    let converted = match search_type {
+        "track" => json!(items.filter_map(webapi::queue_item_from_json).collect::<Vec<_>>()),
+        "album" => json!(items.filter_map(album_result).collect::<Vec<_>>()),
+        "artist" => json!(items.filter_map(artist_result).collect::<Vec<_>>()),
+        _ => json!(items.filter_map(webapi::playlist_summary_from_json).collect::<Vec<_>>()),
+    };
+    json!({
+        "items": converted,
+        "total": page.get("total").and_then(Value::as_u64).unwrap_or(0),
+    })
+}
+
+/// Searches the catalog. Returns a JSON object with one entry per requested type
+/// ("tracks", "albums", "artists", "playlists"), each {"items": [...], "total": n}.
+async fn search(query: &str, types: &[&str], limit: u32, offset: u32) -> Result<Value, Failure> {
+    let session = webapi::current_session()?;
+    let query_string = webapi::query_string([
+        ("q", query.to_string()),
+        ("type", types.join(",")),
+        ("limit", limit.clamp(1, MAX_LIMIT).to_string()),
+        ("offset", offset.to_string()),
+    ]);
+    let response = webapi::get(&session, &format!("/search?{}", query_string)).await?;
+
+    let mut results = Map::new();
+    for search_type in types {
+        let key = format!("{}s", search_type);
+        if let Some(page) = response.get(&key) {
+            results.insert(key, convert_page(search_type, page));
+        }
+    }
+    Ok(Value::Object(results))
+}
+
+/// Searches for tracks, albums, artists and/or playlists.
+/// Returns a JSON object with one entry per requested type ("tracks", "albums",
+/// "artists", "playlists"), each {"items": [...], "total": n}. Tracks use the
+/// queue item format. Returns NULL on error.
+/// Caller must free the string with spotifly_free_string().
+///
+/// # Parameters
+/// - query: Search query
+/// - types: Comma-separated types ("track,album,artist,playlist"), NULL = all
+/// - limit: Results per type (1-50)
+/// - offset: Index of the first result, for paging
+#[no_mangle]
+pub extern "C" fn spotifly_search(
+    query: *const c_char,
+    types: *const c_char,
+    limit: u32,
+    offset: u32,
+) -> *mut c_char {
+    power::note_activity();
+    if query.is_null() {
+        error::fail(SpotiflyError::InvalidArgument, "Search error: query is null");
+        return ptr::null_mut();
+    }
+
+    let query_str = unsafe {
+        match CStr::from_ptr(query).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => {
+                error::fail(SpotiflyError::InvalidArgument, "Search error: invalid query string");
+                return ptr::null_mut();
+            }
+        }
+    };
+
+    let types_str = if types.is_null() {
+        SEARCH_TYPES.join(",")
+    } else {
+        unsafe {
+            match CStr::from_ptr(types).to_str() {
+                Ok(s) => s.to_string(),
+                Err(_) => {
+                    error::fail(SpotiflyError::InvalidArgument, "Search error: invalid types string");
+                    return ptr::null_mut();
+                }
+            }
+        }
+    };
+
+    let search_types: Vec<&str> = types_str.split(',').map(str::trim).filter(|t| !t.is_empty()).collect();
+    if let Some(unknown) = search_types.iter().find(|t| !SEARCH_TYPES.contains(t)) {
+        error::fail(SpotiflyError::InvalidArgument, format!("Search error: unsupported type {}", unknown));
+        return ptr::null_mut();
+    }
+    if search_types.is_empty() || query_str.trim().is_empty() {
+        error::fail(SpotiflyError::InvalidArgument, "Search error: empty query or types");
+        return ptr::null_mut();
+    }
+
+    match RUNTIME.block_on(search(&query_str, &search_types, limit, offset)) {
+        Ok(results) => to_c_string(&results.to_string()),
+        Err(e) => {
+            error::report("Search", e);
+            ptr::null_mut()
+        }
+    }
+}
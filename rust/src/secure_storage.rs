@@ -0,0 +1,175 @@
+// Secure storage for tokens and credentials.
+//
+// Once the host enables it, the OAuth token set and the session's reusable credential
+// blob are written to the platform's secure store (the Keychain on macOS, the Credential
+// Manager on Windows, the kernel keyring on Linux) whenever they change, instead of only
+// being held in memory. On the next launch spotifly_init_player(NULL) logs in with them:
+// with the token set (refreshed first if it has expired), or else with the credentials.
+
+use crate::auth::{self, OAuthResult, OAUTH_RESULT};
+use crate::error::{self, SpotiflyError};
+use crate::{current_timestamp_ms, spotifly_init_player, stored_credentials, token_manager, RUNTIME};
+use keyring::Entry;
+use librespot_core::authentication::Credentials;
+use librespot_core::session::Session;
+use librespot_protocol::authentication::AuthenticationType;
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, CStr, CString};
+use std::sync::Mutex;
+
+const TOKENS_ACCOUNT: &str = "oauth_tokens";
+const CREDENTIALS_ACCOUNT: &str = "credentials";
+// A stored access token this close to expiry is refreshed before use
+const EXPIRY_MARGIN_MS: u64 = 60_000;
+
+#[derive(Serialize, Deserialize)]
+struct StoredTokens {
+    // Needed to refresh the token set on the next launch
+    client_id: Option<String>,
+    #[serde(flatten)]
+    result: OAuthResult,
+}
+
+// Service name the entries are stored under; None = secure storage off
+static SERVICE: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+fn entry(account: &str) -> Option<Entry> {
+    let service = SERVICE.lock().unwrap().clone()?;
+    match Entry::new(&service, account) {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            log::warn!("Secure storage error: {}", e);
+            None
+        }
+    }
+}
+
+fn load<T: DeserializeOwned>(account: &str) -> Option<T> {
+    match entry(account)?.get_password() {
+        Ok(json) => serde_json::from_str(&json).ok(),
+        Err(keyring::Error::NoEntry) => None,
+        Err(e) => {
+            log::warn!("Could not read {} from secure storage: {}", account, e);
+            None
+        }
+    }
+}
+
+fn save<T: Serialize>(account: &str, value: &T) {
+    let Some(entry) = entry(account) else { return };
+    let Ok(json) = serde_json::to_string(value) else { return };
+    if let Err(e) = entry.set_password(&json) {
+        log::warn!("Could not write {} to secure storage: {}", account, e);
+    }
+}
+
+/// Stores a new token set, if secure storage is enabled.
+pub(crate) fn save_tokens(client_id: Option<&str>, result: &OAuthResult) {
+    save(TOKENS_ACCOUNT, &StoredTokens {
+        client_id: client_id.map(str::to_string),
+        result: result.clone(),
+    });
+}
+
+/// Stores the reusable credentials of a newly connected session, if secure storage is enabled.
+pub(crate) fn on_session_connected(session: &Session) {
+    let auth_data = session.auth_data();
+    if auth_data.is_empty() || SERVICE.lock().unwrap().is_none() {
+        return;
+    }
+    save(CREDENTIALS_ACCOUNT, &Credentials {
+        username: Some(session.username()),
+        auth_type: AuthenticationType::AUTHENTICATION_STORED_SPOTIFY_CREDENTIALS,
+        auth_data,
+    });
+}
+
+/// Reusable credentials from secure storage, if enabled and stored.
+pub(crate) fn load_credentials() -> Option<Credentials> {
+    load(CREDENTIALS_ACCOUNT)
+}
+
+/// Initializes the player from what secure storage holds (spotifly_init_player(NULL)).
+pub(crate) fn init_player() -> i32 {
+    if SERVICE.lock().unwrap().is_none() {
+        return error::fail(SpotiflyError::InvalidArgument, "Player init error: access_token is null");
+    }
+
+    if let Some(mut tokens) = load::<StoredTokens>(TOKENS_ACCOUNT) {
+        let expires_at_ms = tokens.result.obtained_at_ms + tokens.result.expires_in * 1000;
+        if current_timestamp_ms() + EXPIRY_MARGIN_MS >= expires_at_ms {
+            if let (Some(client_id), Some(refresh_token)) = (&tokens.client_id, &tokens.result.refresh_token) {
+                match RUNTIME.block_on(auth::refresh(client_id, refresh_token)) {
+                    Ok(result) => tokens.result = result,
+                    Err(e) => log::warn!("Could not refresh stored token: {}", e),
+                }
+            }
+        }
+
+        let expires_at_ms = tokens.result.obtained_at_ms + tokens.result.expires_in * 1000;
+        if current_timestamp_ms() + EXPIRY_MARGIN_MS < expires_at_ms {
+            let Ok(access_token) = CString::new(tokens.result.access_token.clone()) else {
+                return error::fail(SpotiflyError::NotAuthenticated, "Player init error: invalid stored token");
+            };
+            let result = spotifly_init_player(access_token.as_ptr());
+            if result == 0 {
+                token_manager::restore(tokens.client_id, tokens.result);
+                log::info!("Logged in with the stored token");
+            }
+            return result;
+        }
+    }
+
+    if stored_credentials::spotifly_has_stored_credentials() {
+        return stored_credentials::spotifly_login_with_stored_credentials();
+    }
+    error::fail(SpotiflyError::NotAuthenticated, "Player init error: nothing usable in secure storage")
+}
+
+/// Keeps the OAuth token set and the reusable credential blob in the platform's secure
+/// store (the Keychain on macOS), updating them whenever they change. On the next launch,
+/// enable secure storage again and call spotifly_init_player(NULL) to log in with them.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - service_name: Service the entries are stored under (e.g. the app's bundle identifier)
+#[no_mangle]
+pub extern "C" fn spotifly_enable_secure_storage(service_name: *const c_char) -> i32 {
+    if service_name.is_null() {
+        return error::fail(SpotiflyError::InvalidArgument, "Enable secure storage error: service_name is null");
+    }
+    let service = match unsafe { CStr::from_ptr(service_name) }.to_str() {
+        Ok(s) if !s.is_empty() => s.to_string(),
+        _ => return error::fail(SpotiflyError::InvalidArgument, "Enable secure storage error: invalid service_name"),
+    };
+    *SERVICE.lock().unwrap() = Some(service);
+
+    // Store what this session already has
+    let result = OAUTH_RESULT.lock().unwrap().clone();
+    if let Some(result) = result {
+        save_tokens(token_manager::client_id().as_deref(), &result);
+    }
+    0
+}
+
+/// Deletes the stored token set and credentials (e.g. on logout) and turns secure
+/// storage off. Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_clear_secure_storage() -> i32 {
+    let mut failed = None;
+    for account in [TOKENS_ACCOUNT, CREDENTIALS_ACCOUNT] {
+        let Some(entry) = entry(account) else { continue };
+        match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => failed = Some(e),
+        }
+    }
+    SERVICE.lock().unwrap().take();
+
+    match failed {
+        Some(e) => error::fail(SpotiflyError::Unknown, format!("Clear secure storage error: {}", e)),
+        None => 0,
+    }
+}
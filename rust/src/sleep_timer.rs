@@ -0,0 +1,164 @@
+// Sleep timer.
+//
+// Stops playback after a set time or at the end of the current track, optionally fading
+// out over the final stretch. It runs in the crate rather than the host UI, so it keeps
+// working while the app is in the background. Sends a SleepTimerExpired event when it fires.
+
+use crate::{
+    current_timestamp_ms, events, fades, spotifly_get_position_ms, spotifly_stop, DURATION_MS, IS_PLAYING, RUNTIME,
+};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Longest fade before the timer fires
+const MAX_FADE: Duration = Duration::from_secs(30);
+// How often an end-of-track timer checks whether to start fading
+const END_OF_TRACK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+struct Timer {
+    id: u64,
+    // When to stop, in ms since the epoch; None = at the end of the current track
+    deadline_ms: Option<u64>,
+    // The fade-out has started
+    fading: bool,
+}
+
+static TIMER: Lazy<Mutex<Option<Timer>>> = Lazy::new(|| Mutex::new(None));
+static NEXT_TIMER_ID: AtomicU64 = AtomicU64::new(1);
+
+fn is_current(id: u64) -> bool {
+    TIMER.lock().unwrap().as_ref().is_some_and(|timer| timer.id == id)
+}
+
+fn track_remaining_ms() -> u64 {
+    u64::from(DURATION_MS.load(Ordering::SeqCst).saturating_sub(spotifly_get_position_ms()))
+}
+
+// Fades out over `duration` on a blocking thread, unless the timer was cancelled
+async fn fade_out(id: u64, duration: Duration) {
+    {
+        let mut timer_guard = TIMER.lock().unwrap();
+        match timer_guard.as_mut() {
+            Some(timer) if timer.id == id => timer.fading = true,
+            _ => return,
+        }
+    }
+    let interrupted = tokio::task::spawn_blocking(move || !fades::fade_out_over(duration))
+        .await
+        .unwrap_or(true);
+    // A resume or a track change stopped the fade; an end-of-track timer fades the next track
+    if interrupted {
+        if let Some(timer) = TIMER.lock().unwrap().as_mut().filter(|timer| timer.id == id) {
+            timer.fading = false;
+        }
+    }
+}
+
+fn emit_expired() {
+    log::info!("Sleep timer expired");
+    events::emit(events::EVENT_SLEEP_TIMER_EXPIRED, json!({}));
+}
+
+async fn run_until(id: u64, deadline_ms: u64, fade: bool) {
+    let remaining = Duration::from_millis(deadline_ms.saturating_sub(current_timestamp_ms()));
+    let fade_duration = if fade { remaining.min(MAX_FADE) } else { Duration::ZERO };
+    tokio::time::sleep(remaining - fade_duration).await;
+
+    if !fade_duration.is_zero() && IS_PLAYING.load(Ordering::SeqCst) {
+        fade_out(id, fade_duration).await;
+    }
+    tokio::time::sleep(Duration::from_millis(deadline_ms.saturating_sub(current_timestamp_ms()))).await;
+
+    let expired = {
+        let mut timer_guard = TIMER.lock().unwrap();
+        timer_guard.take_if(|timer| timer.id == id).is_some()
+    };
+    if expired {
+        spotifly_stop();
+        emit_expired();
+    }
+}
+
+// An end-of-track timer fires from on_track_end(); this only starts the fade
+async fn fade_before_track_end(id: u64) {
+    while is_current(id) {
+        tokio::time::sleep(END_OF_TRACK_POLL_INTERVAL).await;
+        let fading = TIMER.lock().unwrap().as_ref().is_some_and(|timer| timer.fading);
+        let remaining_ms = track_remaining_ms();
+        if !fading && IS_PLAYING.load(Ordering::SeqCst) && remaining_ms <= MAX_FADE.as_millis() as u64 {
+            fade_out(id, Duration::from_millis(remaining_ms)).await;
+        }
+    }
+}
+
+/// Whether an end-of-track sleep timer will stop playback when the current track ends.
+pub(crate) fn ends_with_track() -> bool {
+    TIMER.lock().unwrap().as_ref().is_some_and(|timer| timer.deadline_ms.is_none())
+}
+
+/// Called when a track ends. Returns true if an end-of-track sleep timer fired, in which
+/// case playback should stop instead of moving on.
+pub(crate) fn on_track_end() -> bool {
+    let fired = TIMER.lock().unwrap()
+        .take_if(|timer| timer.deadline_ms.is_none())
+        .is_some();
+    if fired {
+        emit_expired();
+    }
+    fired
+}
+
+/// Starts a sleep timer, replacing any running one. When it fires, playback stops and
+/// a SleepTimerExpired event is sent.
+///
+/// # Parameters
+/// - seconds: Time until playback stops, 0 = at the end of the current track
+/// - fade: true to fade out over the final 30 seconds (or the whole time, if shorter)
+#[no_mangle]
+pub extern "C" fn spotifly_set_sleep_timer(seconds: u32, fade: bool) {
+    spotifly_cancel_sleep_timer();
+
+    let id = NEXT_TIMER_ID.fetch_add(1, Ordering::SeqCst);
+    let deadline_ms = (seconds > 0).then(|| current_timestamp_ms() + u64::from(seconds) * 1000);
+    *TIMER.lock().unwrap() = Some(Timer {
+        id,
+        deadline_ms,
+        fading: false,
+    });
+
+    match deadline_ms {
+        Some(deadline_ms) => {
+            RUNTIME.spawn(run_until(id, deadline_ms, fade));
+        }
+        None if fade => {
+            RUNTIME.spawn(fade_before_track_end(id));
+        }
+        None => {}
+    }
+}
+
+/// Cancels the sleep timer, restoring the volume if it had started fading.
+#[no_mangle]
+pub extern "C" fn spotifly_cancel_sleep_timer() {
+    if let Some(timer) = TIMER.lock().unwrap().take() {
+        if timer.fading {
+            fades::reset();
+        }
+    }
+}
+
+/// Returns the time left on the sleep timer in milliseconds (for an end-of-track timer,
+/// the time left in the current track), or -1 if no timer is set.
+#[no_mangle]
+pub extern "C" fn spotifly_get_sleep_timer_remaining_ms() -> i64 {
+    match TIMER.lock().unwrap().as_ref() {
+        Some(timer) => match timer.deadline_ms {
+            Some(deadline_ms) => deadline_ms.saturating_sub(current_timestamp_ms()) as i64,
+            None => track_remaining_ms() as i64,
+        },
+        None => -1,
+    }
+}
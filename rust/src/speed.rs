@@ -0,0 +1,186 @@
+// Playback speed.
+//
+// Episodes (podcasts and audiobooks) and music each have their own playback rate,
+// remembered in the data directory; music stays at 1.0x unless the host changes it.
+// The output is time-stretched with WSOLA (overlapping windows of the input, each nudged
+// to line up with the previous one) so voices keep their pitch. The player decodes
+// faster or slower to keep up, so positions and durations stay in track time.
+
+use crate::error::{self, SpotiflyError};
+use crate::storage;
+use librespot_metadata::audio::{AudioItem, UniqueFields};
+use librespot_playback::decoder::AudioPacket;
+use librespot_playback::NUM_CHANNELS;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+
+const RATES_FILE: &str = "playback_rates.json";
+const MIN_RATE: f32 = 0.5;
+const MAX_RATE: f32 = 3.0;
+// Output frames per window hop (about 17 ms); windows are twice as long
+const HOP: usize = 768;
+// How far a window may move to line up with the previous one (about 6 ms either way)
+const SEEK_FRAMES: usize = 256;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct PlaybackRates {
+    music: f32,
+    episodes: f32,
+}
+
+static RATES: Lazy<Mutex<PlaybackRates>> = Lazy::new(|| Mutex::new(PlaybackRates { music: 1.0, episodes: 1.0 }));
+static PLAYING_EPISODE: AtomicBool = AtomicBool::new(false);
+// Rate of what is playing now, as f32 bits
+static ACTIVE_RATE: AtomicU32 = AtomicU32::new(1.0f32.to_bits());
+
+/// Loads the stored playback rates from the data directory.
+pub(crate) fn load() {
+    if let Some(rates) = storage::load_json::<PlaybackRates>(RATES_FILE) {
+        *RATES.lock().unwrap() = rates;
+        apply_rate();
+    }
+}
+
+// Makes the rate of the current content type the active one
+fn apply_rate() {
+    let rates = *RATES.lock().unwrap();
+    let rate = if PLAYING_EPISODE.load(Ordering::SeqCst) { rates.episodes } else { rates.music };
+    ACTIVE_RATE.store(rate.to_bits(), Ordering::SeqCst);
+}
+
+/// Switches to the rate of the newly loaded item's content type.
+pub(crate) fn on_track_changed(audio_item: &AudioItem) {
+    let is_episode = matches!(audio_item.unique_fields, UniqueFields::Episode { .. });
+    PLAYING_EPISODE.store(is_episode, Ordering::SeqCst);
+    apply_rate();
+}
+
+/// Time-stretches the output to the active playback rate.
+pub(crate) struct Stretcher {
+    // Input frames not yet consumed
+    input: Vec<[f64; 2]>,
+    // Nominal start of the next window in `input`, advanced by HOP * rate per window
+    next_pos: f64,
+    // Second half of the previous window, faded out over the next hop
+    tail: Vec<[f64; 2]>,
+}
+
+impl Stretcher {
+    pub(crate) fn new() -> Self {
+        Stretcher {
+            input: Vec::new(),
+            next_pos: 0.0,
+            tail: Vec::new(),
+        }
+    }
+
+    /// Drops buffered audio, e.g. when the output stops.
+    pub(crate) fn reset(&mut self) {
+        self.input.clear();
+        self.next_pos = 0.0;
+        self.tail.clear();
+    }
+
+    /// Stretches a block of samples. At 1.0x the block passes through untouched; otherwise
+    /// some input is held back until a whole window is available.
+    pub(crate) fn process(&mut self, packet: AudioPacket) -> AudioPacket {
+        let rate = f32::from_bits(ACTIVE_RATE.load(Ordering::Relaxed)) as f64;
+        let samples = match &packet {
+            AudioPacket::Samples(samples) if rate != 1.0 => samples,
+            _ => {
+                if !self.input.is_empty() {
+                    self.reset();
+                }
+                return packet;
+            }
+        };
+
+        self.input.extend(samples.chunks_exact(NUM_CHANNELS as usize).map(|frame| [frame[0], frame[1]]));
+
+        let mut output = Vec::new();
+        while self.next_pos.round() as usize + SEEK_FRAMES + 2 * HOP <= self.input.len() {
+            let nominal = self.next_pos.round() as usize;
+            let start = if self.tail.is_empty() { nominal } else { self.best_start(nominal) };
+
+            for i in 0..HOP {
+                let frame = self.input[start + i];
+                let (left, right) = match self.tail.get(i) {
+                    Some(tail) => {
+                        let weight = 0.5 - 0.5 * (PI * i as f64 / HOP as f64).cos();
+                        (tail[0] * (1.0 - weight) + frame[0] * weight, tail[1] * (1.0 - weight) + frame[1] * weight)
+                    }
+                    None => (frame[0], frame[1]),
+                };
+                output.push(left);
+                output.push(right);
+            }
+            self.tail = self.input[start + HOP..start + 2 * HOP].to_vec();
+            self.next_pos += HOP as f64 * rate;
+
+            // Keep only what the next window could still start at
+            let consumed = (self.next_pos as usize).saturating_sub(SEEK_FRAMES).min(self.input.len());
+            self.input.drain(..consumed);
+            self.next_pos -= consumed as f64;
+        }
+        AudioPacket::Samples(output)
+    }
+
+    // The start near `nominal` whose first half best continues the previous window's tail
+    fn best_start(&self, nominal: usize) -> usize {
+        let mono = |frame: &[f64; 2]| frame[0] + frame[1];
+        let mut best = (nominal, f64::MIN);
+        // Every other offset and every fourth frame is plenty to find the best alignment
+        for start in (nominal.saturating_sub(SEEK_FRAMES)..=nominal + SEEK_FRAMES).step_by(2) {
+            let (mut correlation, mut energy) = (0.0, 0.0);
+            for i in (0..HOP).step_by(4) {
+                let x = mono(&self.input[start + i]);
+                correlation += mono(&self.tail[i]) * x;
+                energy += x * x;
+            }
+            let score = correlation / (energy + 1e-9).sqrt();
+            if score > best.1 {
+                best = (start, score);
+            }
+        }
+        best.0
+    }
+}
+
+/// Sets the playback rate for the kind of content playing now: episodes (podcasts and
+/// audiobooks) and music each keep their own rate, which is remembered in the data
+/// directory. Pitch is preserved.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - rate: Playback rate, 0.5-3.0 (1.0 = normal speed)
+#[no_mangle]
+pub extern "C" fn spotifly_set_playback_rate(rate: f32) -> i32 {
+    if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+        return error::fail(
+            SpotiflyError::InvalidArgument,
+            format!("Set playback rate error: rate must be between {} and {}", MIN_RATE, MAX_RATE),
+        );
+    }
+
+    let rates = {
+        let mut rates = RATES.lock().unwrap();
+        if PLAYING_EPISODE.load(Ordering::SeqCst) {
+            rates.episodes = rate;
+        } else {
+            rates.music = rate;
+        }
+        *rates
+    };
+    apply_rate();
+    storage::save_json(RATES_FILE, &rates);
+    0
+}
+
+/// Returns the playback rate of the content playing now (1.0 = normal speed).
+#[no_mangle]
+pub extern "C" fn spotifly_get_playback_rate() -> f32 {
+    f32::from_bits(ACTIVE_RATE.load(Ordering::SeqCst))
+}
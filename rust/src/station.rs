@@ -0,0 +1,414 @@
+// Stations ("Liked Songs radio", playlist, album, track, artist and genre radio) and autoplay.
+//
+// A station keeps a pool of seed tracks from the source collection. Whenever the queue
+// gets close to its end, a fresh batch of recommendations seeded by a random sample of
+// the pool is appended, so playback continues indefinitely. A station from a single seed
+// (a track, artist or genre) uses that seed in every batch, alongside a sample of the
+// tracks it has queued so far, so it drifts a little without losing its character.
+//
+// With autoplay enabled, a queue that nears its end without a station starts one seeded
+// by the queue's own tracks (the album or playlist that was playing), like the official
+// client's Autoplay. If the queue already ran out, playback resumes with the new tracks.
+
+use crate::error::{self, Failure, SpotiflyError};
+use crate::recommendations::fetch_recommendations;
+use crate::{explicit_filter, library, loading, power, queue_controller, stats, webapi};
+use crate::{
+    album_track_uris, connect, links, load_track, parse_spotify_uri, with_metadata_timeout, QueueItem,
+    CURRENT_INDEX, IS_PLAYING, PLAYER, QUEUE, REPEAT_MODE, REPEAT_OFF, RUNTIME,
+};
+use librespot_core::session::Session;
+use librespot_core::SpotifyUri;
+use librespot_metadata::{Metadata, Playlist};
+use once_cell::sync::Lazy;
+use rand::seq::IndexedRandom;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::ffi::{c_char, CStr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const LIKED_SONGS_URI: &str = "spotify:collection";
+// Source of stations started by autoplay
+const AUTOPLAY_SOURCE: &str = "autoplay";
+// Source prefix of genre stations, e.g. "genre:jazz"
+const GENRE_PREFIX: &str = "genre:";
+const LIKED_SONGS_PAGE_SIZE: usize = 50;
+// Upper bound on seed tracks kept from the source collection
+const SEED_POOL_SIZE: usize = 200;
+const SEEDS_PER_BATCH: usize = 5;
+const STATION_BATCH_SIZE: usize = 20;
+// Candidates fetched per batch, so down-weighted picks can be passed over
+const STATION_CANDIDATES: u32 = 50;
+// Lowest sampling weight for a candidate, however often its artist was skipped
+const MIN_CANDIDATE_WEIGHT: f64 = 0.05;
+// Extend the queue once this many (or fewer) upcoming tracks remain
+const REFILL_THRESHOLD: usize = 3;
+
+struct Station {
+    source_uri: String,
+    seed_pool: Vec<String>,
+    // Seed used in every batch (single-seed stations), with the pool growing as tracks are queued
+    anchor: Option<String>,
+    // Track URIs already queued by this station, to avoid repeats
+    queued: HashSet<String>,
+    // Load that started the station (0 for autoplay)
+    load_id: u64,
+}
+
+static STATION: Lazy<Mutex<Option<Station>>> = Lazy::new(|| Mutex::new(None));
+static REFILLING: AtomicBool = AtomicBool::new(false);
+static AUTOPLAY: AtomicBool = AtomicBool::new(false);
+// Last track of a queue that ran out while autoplay was fetching more
+static ENDED_URI: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+/// Stops the active station (e.g. because the user started playing something else).
+pub(crate) fn stop_station() {
+    STATION.lock().unwrap().take();
+    ENDED_URI.lock().unwrap().take();
+}
+
+/// Returns true if a station is active.
+pub(crate) fn is_active() -> bool {
+    STATION.lock().unwrap().is_some()
+}
+
+/// Returns true if the track was queued by the active station.
+pub(crate) fn queued_by_station(uri: &str) -> bool {
+    STATION.lock().unwrap().as_ref().is_some_and(|s| s.queued.contains(uri))
+}
+
+/// Loads the seeds for a station source: a seed pool from the user's liked songs, a
+/// playlist or an album, or an anchor seed for a track, artist or genre.
+async fn load_seeds(session: &Session, source_uri: &str) -> Result<(Vec<String>, Option<String>), Failure> {
+    let mut pool = Vec::new();
+
+    if let Some(genre) = source_uri.strip_prefix(GENRE_PREFIX) {
+        return Ok((pool, Some(genre.to_string())));
+    }
+    if source_uri == LIKED_SONGS_URI {
+        let mut offset = 0;
+        while pool.len() < SEED_POOL_SIZE {
+            let (tracks, _) = library::fetch_saved_tracks(session, offset, LIKED_SONGS_PAGE_SIZE).await?;
+            offset += LIKED_SONGS_PAGE_SIZE;
+            let page_len = tracks.len();
+            pool.extend(tracks.into_iter().map(|t| t.item.uri));
+            if page_len < LIKED_SONGS_PAGE_SIZE {
+                break;
+            }
+        }
+    } else {
+        let source = parse_spotify_uri(source_uri)?;
+        match source {
+            SpotifyUri::Track { .. } | SpotifyUri::Artist { .. } => {
+                return Ok((pool, Some(source_uri.to_string())));
+            }
+            SpotifyUri::Playlist { .. } => {
+                let playlist = with_metadata_timeout("playlist", Playlist::get(session, &source)).await?;
+                pool.extend(playlist.tracks()
+                    .filter(|uri| matches!(uri, SpotifyUri::Track { .. }))
+                    .map(|uri| uri.to_string())
+                    .take(SEED_POOL_SIZE));
+            }
+            SpotifyUri::Album { .. } => {
+                pool.extend(album_track_uris(session, &source).await?.into_iter().take(SEED_POOL_SIZE));
+            }
+            _ => {
+                return Err(Failure::new(
+                    SpotiflyError::InvalidUri,
+                    format!("Stations can't be seeded from {} (not a track, artist, album or playlist)", source_uri),
+                ));
+            }
+        }
+    }
+
+    if pool.is_empty() {
+        return Err(Failure::new(SpotiflyError::NotFound, format!("No seed tracks found in {}", source_uri)));
+    }
+    Ok((pool, None))
+}
+
+// The failure of work for a station that was stopped (or replaced) meanwhile
+fn station_stopped() -> Failure {
+    Failure::new(SpotiflyError::Cancelled, "Station stopped")
+}
+
+/// Fetches the next batch of station tracks, skipping anything already queued.
+/// Tracks the user skipped quickly are left out, and artists whose station tracks
+/// tend to get skipped are picked less often.
+async fn next_batch(session: &Session) -> Result<Vec<QueueItem>, Failure> {
+    let seeds: Vec<String> = {
+        let station_guard = STATION.lock().unwrap();
+        let station = station_guard.as_ref().ok_or_else(station_stopped)?;
+        let sample_size = SEEDS_PER_BATCH - usize::from(station.anchor.is_some());
+        station.anchor.iter()
+            .chain(station.seed_pool.choose_multiple(&mut rand::rng(), sample_size))
+            .cloned()
+            .collect()
+    };
+
+    let items = fetch_recommendations(session, &seeds, STATION_CANDIDATES, &Value::Null).await?;
+    let signals = stats::autoplay_skip_signals();
+
+    let mut station_guard = STATION.lock().unwrap();
+    let station = station_guard.as_mut().ok_or_else(station_stopped)?;
+    let candidates: Vec<QueueItem> = items.into_iter()
+        .filter(|item| !station.queued.contains(&item.uri))
+        .filter(|item| !signals.skipped_tracks.contains(&item.uri))
+        .filter(|item| !explicit_filter::blocks(item))
+        .collect();
+
+    let weight = |item: &QueueItem| {
+        let penalty = item.artist_id.as_ref()
+            .and_then(|id| signals.artist_penalties.get(&format!("spotify:artist:{}", id)))
+            .copied()
+            .unwrap_or(0.0);
+        (1.0 - penalty).max(MIN_CANDIDATE_WEIGHT)
+    };
+    let batch: Vec<QueueItem> = candidates
+        .choose_multiple_weighted(&mut rand::rng(), STATION_BATCH_SIZE, weight)
+        .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Failed to pick station tracks: {}", e)))?
+        .cloned()
+        .collect();
+
+    let grows_pool = station.anchor.is_some();
+    for item in &batch {
+        station.queued.insert(item.uri.clone());
+        if grows_pool && station.seed_pool.len() < SEED_POOL_SIZE && item.uri.starts_with("spotify:track:") {
+            station.seed_pool.push(item.uri.clone());
+        }
+    }
+    Ok(batch)
+}
+
+// Autoplay applies to queues that would otherwise end
+fn autoplay_applies() -> bool {
+    AUTOPLAY.load(Ordering::SeqCst)
+        && REPEAT_MODE.load(Ordering::SeqCst) == REPEAT_OFF
+        && !connect::is_remote_controlled()
+}
+
+// Starts an autoplay station seeded by the tracks in the queue
+fn start_autoplay() -> Result<(), Failure> {
+    let queue_guard = QUEUE.lock().unwrap();
+    let seed_pool: Vec<String> = queue_guard.iter().rev()
+        .filter(|item| item.uri.starts_with("spotify:track:"))
+        .take(SEED_POOL_SIZE)
+        .map(|item| item.uri.clone())
+        .collect();
+    if seed_pool.is_empty() {
+        return Err(Failure::new(SpotiflyError::NotFound, "No tracks in the queue to seed autoplay"));
+    }
+
+    *STATION.lock().unwrap() = Some(Station {
+        source_uri: AUTOPLAY_SOURCE.to_string(),
+        seed_pool,
+        anchor: None,
+        queued: queue_guard.iter().map(|item| item.uri.clone()).collect(),
+        load_id: 0,
+    });
+    Ok(())
+}
+
+/// Called when the queue ran out after `track_uri`. With autoplay, fetches more
+/// tracks and continues playback once they arrive.
+pub(crate) fn on_queue_ended(track_uri: &str) {
+    if !autoplay_applies() {
+        return;
+    }
+    *ENDED_URI.lock().unwrap() = Some(track_uri.to_string());
+    maybe_extend();
+}
+
+/// Extends the queue with more station tracks if it's about to run out.
+/// Called after the queue advances; does nothing without an active station,
+/// unless autoplay is enabled.
+pub(crate) fn maybe_extend() {
+    if !is_active() && !autoplay_applies() {
+        return;
+    }
+
+    let remaining = {
+        let queue_guard = QUEUE.lock().unwrap();
+        queue_guard.len().saturating_sub(CURRENT_INDEX.load(Ordering::SeqCst) + 1)
+    };
+    if remaining > REFILL_THRESHOLD || REFILLING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    RUNTIME.spawn(async {
+        let result = async {
+            let session = webapi::current_session()?;
+            if !is_active() {
+                start_autoplay()?;
+            }
+            let source_uri = STATION.lock().unwrap().as_ref()
+                .map(|s| s.source_uri.clone())
+                .ok_or_else(station_stopped)?;
+            let items = next_batch(&session).await?;
+
+            // The user may have switched to other content while we were fetching
+            let still_active = STATION.lock().unwrap().as_ref()
+                .is_some_and(|s| s.source_uri == source_uri);
+            if still_active {
+                queue_controller::edit_async(|queue, _| queue.extend(items)).await
+                    .ok_or_else(queue_controller::stopped)?;
+                loading::emit_queue_updated(false);
+
+                // Resume a queue that ran out while we were fetching
+                let ended_uri = ENDED_URI.lock().unwrap().take();
+                let player = PLAYER.lock().unwrap().clone();
+                if let (Some(uri), Some(player)) = (ended_uri, player) {
+                    queue_controller::skip(uri, &player);
+                }
+            }
+            Ok::<(), Failure>(())
+        }.await;
+
+        if let Err(e) = result {
+            log::error!("Station refill error: {}", e);
+        }
+        REFILLING.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Starts a station seeded from the user's liked songs or a playlist,
+/// replacing the queue and starting playback. The queue is extended
+/// automatically as it nears the end, until other content is played.
+/// Also takes the seeds spotifly_play_station() does.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - source_uri: a playlist URI/URL, or NULL / "spotify:collection" for liked songs
+#[no_mangle]
+pub extern "C" fn spotifly_start_station(source_uri: *const c_char) -> i32 {
+    power::note_activity();
+    let source_str = if source_uri.is_null() {
+        LIKED_SONGS_URI.to_string()
+    } else {
+        unsafe {
+            match CStr::from_ptr(source_uri).to_str() {
+                Ok(s) => s.to_string(),
+                Err(_) => return error::fail(
+                    SpotiflyError::InvalidArgument,
+                    "Start station error: invalid source_uri string",
+                ),
+            }
+        }
+    };
+    start_station(source_str, "Start station")
+}
+
+/// Starts a station of tracks similar to a seed, replacing the queue and starting
+/// playback. Unlike a playlist or album, the queue never runs out: more similar tracks
+/// are appended as it nears the end, until other content is played.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - seed_uri: URI/URL of a track, artist, album or playlist, "spotify:collection" for
+///   liked songs, or a genre name (e.g. "jazz", as in spotifly_get_recommendations())
+#[no_mangle]
+pub extern "C" fn spotifly_play_station(seed_uri: *const c_char) -> i32 {
+    power::note_activity();
+    match (!seed_uri.is_null()).then(|| unsafe { CStr::from_ptr(seed_uri) }.to_str()) {
+        Some(Ok(seed)) => start_station(seed.to_string(), "Play station"),
+        _ => error::fail(SpotiflyError::InvalidArgument, "Play station error: invalid seed_uri"),
+    }
+}
+
+// Replaces the queue with a new station from `source_str` and starts playing it
+fn start_station(source_str: String, action: &str) -> i32 {
+    let player = match PLAYER.lock().unwrap().clone() {
+        Some(p) => p,
+        None => return error::fail(SpotiflyError::NotInitialized, format!("{} error: player not initialized", action)),
+    };
+
+    let load_id = loading::begin_load();
+    let result: Result<(), Failure> = RUNTIME.block_on(loading::unless_cancelled(load_id, async {
+        let session = webapi::current_session()?;
+
+        let source_str = source_str.trim();
+        let source_uri = if source_str == LIKED_SONGS_URI || source_str.ends_with(":collection") {
+            LIKED_SONGS_URI.to_string()
+        } else if links::is_short_link(source_str) || links::parse_link(source_str).is_some() {
+            links::resolve_link(&session, source_str).await?.uri
+        } else if !source_str.is_empty() && !source_str.contains([':', '/']) {
+            format!("{}{}", GENRE_PREFIX, source_str.to_lowercase())
+        } else {
+            return Err(Failure::new(SpotiflyError::InvalidUri, format!("Not a station seed: {}", source_str)));
+        };
+
+        let (seed_pool, anchor) = load_seeds(&session, &source_uri).await?;
+        *STATION.lock().unwrap() = Some(Station {
+            source_uri,
+            seed_pool,
+            anchor,
+            queued: HashSet::new(),
+            load_id,
+        });
+
+        let items = next_batch(&session).await?;
+        if items.is_empty() {
+            stop_station();
+            return Err(Failure::new(SpotiflyError::NotFound, "Station returned no tracks"));
+        }
+
+        let first_uri = parse_spotify_uri(&items[0].uri)?;
+        queue_controller::replace(items).await?;
+        load_track(&player, first_uri);
+        Ok(())
+    }));
+
+    match result {
+        Ok(_) => {
+            IS_PLAYING.store(true, Ordering::SeqCst);
+            0
+        }
+        Err(e) => {
+            // A newer load may already have started another station
+            let mut station_guard = STATION.lock().unwrap();
+            if station_guard.as_ref().is_some_and(|s| s.load_id == load_id) {
+                station_guard.take();
+            }
+            drop(station_guard);
+            error::report(action, e)
+        }
+    }
+}
+
+/// Stops the active station. The current queue is kept but no longer extended.
+#[no_mangle]
+pub extern "C" fn spotifly_stop_station() {
+    stop_station();
+}
+
+/// Returns true if a station is active.
+#[no_mangle]
+pub extern "C" fn spotifly_is_station_active() -> bool {
+    is_active()
+}
+
+/// Enables or disables autoplay (off by default). When enabled and the queue is
+/// about to end, similar tracks seeded by the queue's content are appended, so
+/// playback keeps going. Has no effect while repeat is on.
+///
+/// # Parameters
+/// - enabled: true to continue playback with recommendations when the queue ends
+#[no_mangle]
+pub extern "C" fn spotifly_set_autoplay(enabled: bool) {
+    AUTOPLAY.store(enabled, Ordering::SeqCst);
+    if !enabled {
+        ENDED_URI.lock().unwrap().take();
+        // Autoplay stations end with autoplay; explicitly started ones keep going
+        let mut station_guard = STATION.lock().unwrap();
+        if station_guard.as_ref().is_some_and(|s| s.source_uri == AUTOPLAY_SOURCE) {
+            station_guard.take();
+        }
+    }
+}
+
+/// Returns true if autoplay is enabled.
+#[no_mangle]
+pub extern "C" fn spotifly_get_autoplay() -> bool {
+    AUTOPLAY.load(Ordering::SeqCst)
+}
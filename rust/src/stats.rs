@@ -0,0 +1,776 @@
+// Local play statistics.
+//
+// Every track that starts playing is recorded together with how long it was actually
+// listened to and whether it was skipped. Records are fed by player events, kept in
+// memory and persisted to the data directory, and can be summarized per day or week.
+// Saving is debounced and happens in the background, as the log runs to several MB.
+// The log keeps the latest plays only, so all-time statistics come from running totals
+// per track, artist and day that are kept alongside it and never trimmed.
+
+use crate::error::{self, SpotiflyError};
+use crate::{current_timestamp_ms, private_session, station, storage, to_c_string, RUNTIME};
+use librespot_metadata::audio::{AudioItem, UniqueFields};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const STATS_FILE: &str = "play_stats.json";
+const TOTALS_FILE: &str = "play_totals.json";
+// Oldest records are dropped beyond this many plays
+const MAX_RECORDS: usize = 20_000;
+// How long after a play is recorded the log is saved, so a run of skips saves once
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+// A play that ends before this fraction of the track was heard counts as a skip
+const SKIP_FRACTION: f64 = 0.8;
+// Position jumps larger than this are seeks and don't count as listening time
+const MAX_POSITION_STEP_MS: u32 = 2_000;
+// Autoplay tracks abandoned within this time count as quick skips
+const QUICK_SKIP_MS: u32 = 30_000;
+// Skip signals older than this are ignored
+const SKIP_SIGNAL_WINDOW_MS: u64 = 30 * 24 * 60 * 60 * 1000;
+const SUMMARY_LIST_SIZE: usize = 10;
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+// Windows of spotifly_get_top_tracks_local(), in days (None = all time)
+const TOP_TRACK_WINDOWS: [Option<u64>; 4] = [Some(7), Some(30), Some(365), None];
+
+/// A single play of a track.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct PlayRecord {
+    pub uri: String,
+    pub track_name: String,
+    /// Primary artist (or show, for episodes)
+    pub artist_name: String,
+    pub artist_uri: Option<String>,
+    pub started_at_ms: u64,
+    pub duration_ms: u32,
+    pub listened_ms: u32,
+    pub skipped: bool,
+    /// Queued by a station rather than picked by the user
+    #[serde(default)]
+    pub autoplay: bool,
+}
+
+/// What the user's skips say about autoplay picks.
+pub(crate) struct SkipSignals {
+    /// Artist URI -> penalty between 0 (always kept) and 1 (always skipped quickly)
+    pub artist_penalties: HashMap<String, f64>,
+    /// Autoplay tracks the user skipped quickly
+    pub skipped_tracks: HashSet<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct TrackTotal {
+    track_name: String,
+    artist_name: String,
+    play_count: u32,
+    listened_ms: u64,
+    last_played_ms: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ArtistTotal {
+    artist_name: String,
+    artist_uri: Option<String>,
+    play_count: u32,
+    listened_ms: u64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct DayTotal {
+    play_count: u32,
+    listened_ms: u64,
+}
+
+/// All-time totals, kept up to date with every recorded play.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Totals {
+    play_count: u32,
+    skip_count: u32,
+    listened_ms: u64,
+    first_played_ms: Option<u64>,
+    /// By track URI
+    tracks: HashMap<String, TrackTotal>,
+    /// By artist URI, or name if there is none
+    artists: HashMap<String, ArtistTotal>,
+    /// By UTC day (days since the Unix epoch)
+    days: BTreeMap<u64, DayTotal>,
+}
+
+impl Totals {
+    fn add(&mut self, record: &PlayRecord) {
+        let listened_ms = u64::from(record.listened_ms);
+        self.play_count += 1;
+        self.skip_count += u32::from(record.skipped);
+        self.listened_ms += listened_ms;
+        self.first_played_ms = Some(self.first_played_ms.map_or(record.started_at_ms, |first| {
+            first.min(record.started_at_ms)
+        }));
+
+        let track = self.tracks.entry(record.uri.clone()).or_insert_with(|| TrackTotal {
+            track_name: record.track_name.clone(),
+            artist_name: record.artist_name.clone(),
+            play_count: 0,
+            listened_ms: 0,
+            last_played_ms: 0,
+        });
+        track.play_count += 1;
+        track.listened_ms += listened_ms;
+        track.last_played_ms = track.last_played_ms.max(record.started_at_ms);
+
+        if !record.artist_name.is_empty() {
+            let key = record.artist_uri.clone().unwrap_or_else(|| record.artist_name.clone());
+            let artist = self.artists.entry(key).or_insert_with(|| ArtistTotal {
+                artist_name: record.artist_name.clone(),
+                artist_uri: record.artist_uri.clone(),
+                play_count: 0,
+                listened_ms: 0,
+            });
+            artist.play_count += 1;
+            artist.listened_ms += listened_ms;
+        }
+
+        let day = self.days.entry(record.started_at_ms / DAY_MS as u64).or_default();
+        day.play_count += 1;
+        day.listened_ms += listened_ms;
+    }
+}
+
+// The play in progress and the last position reported for it
+struct CurrentPlay {
+    record: PlayRecord,
+    last_position_ms: u32,
+}
+
+static RECORDS: Lazy<Mutex<Vec<PlayRecord>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static CURRENT: Lazy<Mutex<Option<CurrentPlay>>> = Lazy::new(|| Mutex::new(None));
+static TOTALS: Lazy<Mutex<Totals>> = Lazy::new(|| Mutex::new(Totals::default()));
+static SAVE_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Loads stored records and totals from the data directory, replacing the in-memory ones.
+pub(crate) fn load() {
+    if let Some(records) = storage::load_json::<Vec<PlayRecord>>(STATS_FILE) {
+        *RECORDS.lock().unwrap() = records;
+    }
+    // Data directories from before the totals existed start them from the log
+    let totals = storage::load_json::<Totals>(TOTALS_FILE).unwrap_or_else(|| {
+        let mut totals = Totals::default();
+        RECORDS.lock().unwrap().iter().for_each(|record| totals.add(record));
+        totals
+    });
+    *TOTALS.lock().unwrap() = totals;
+}
+
+// Writes the log and totals, serializing copies so the locks aren't held while writing
+fn save() {
+    SAVE_PENDING.store(false, Ordering::SeqCst);
+    let records = RECORDS.lock().unwrap().clone();
+    storage::save_json(STATS_FILE, &records);
+    let totals = TOTALS.lock().unwrap().clone();
+    storage::save_json(TOTALS_FILE, &totals);
+}
+
+// Saves the log in the background after SAVE_DELAY, unless a save is already scheduled
+fn schedule_save() {
+    if SAVE_PENDING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    RUNTIME.spawn(async {
+        tokio::time::sleep(SAVE_DELAY).await;
+        if SAVE_PENDING.load(Ordering::SeqCst) {
+            let _ = tokio::task::spawn_blocking(save).await;
+        }
+    });
+}
+
+/// Saves the log now if a save is scheduled (e.g. before the player is torn down).
+pub(crate) fn flush() {
+    if SAVE_PENDING.load(Ordering::SeqCst) {
+        save();
+    }
+}
+
+/// Starts recording a new play, finishing the previous one (which was cut short).
+/// Nothing is recorded during a private session.
+pub(crate) fn on_track_changed(audio_item: &AudioItem) {
+    if private_session::is_active() {
+        finish_current(false);
+        return;
+    }
+
+    let (artist_name, artist_uri) = match &audio_item.unique_fields {
+        UniqueFields::Track { artists, .. } => match artists.first() {
+            Some(artist) => (artist.name.clone(), Some(artist.id.to_string())),
+            None => (String::new(), None),
+        },
+        UniqueFields::Episode { show_name, .. } => (show_name.clone(), None),
+        UniqueFields::Local { artists, .. } => (artists.clone().unwrap_or_default(), None),
+    };
+
+    let record = PlayRecord {
+        uri: audio_item.uri.clone(),
+        track_name: audio_item.name.clone(),
+        artist_name,
+        artist_uri,
+        started_at_ms: current_timestamp_ms(),
+        duration_ms: audio_item.duration_ms,
+        listened_ms: 0,
+        skipped: false,
+        autoplay: station::queued_by_station(&audio_item.uri),
+    };
+
+    finish_current(false);
+    *CURRENT.lock().unwrap() = Some(CurrentPlay {
+        record,
+        last_position_ms: 0,
+    });
+}
+
+/// Accumulates listening time from a position update.
+/// `continuous` is false for seeks, which only move the reference point.
+pub(crate) fn on_position(position_ms: u32, continuous: bool) {
+    if let Some(current) = CURRENT.lock().unwrap().as_mut() {
+        let step = position_ms.saturating_sub(current.last_position_ms);
+        if continuous && step <= MAX_POSITION_STEP_MS {
+            current.record.listened_ms = current.record.listened_ms.saturating_add(step);
+        }
+        current.last_position_ms = position_ms;
+    }
+}
+
+/// Finishes the play in progress. `completed` is true when the track played to its end.
+pub(crate) fn on_playback_ended(completed: bool) {
+    finish_current(completed);
+}
+
+fn finish_current(completed: bool) {
+    let current = match CURRENT.lock().unwrap().take() {
+        Some(current) => current,
+        None => return,
+    };
+
+    let mut record = current.record;
+    if record.listened_ms == 0 && !completed {
+        // Never actually played (e.g. skipped while loading)
+        return;
+    }
+    record.skipped = !completed
+        && (record.listened_ms as f64) < record.duration_ms as f64 * SKIP_FRACTION;
+
+    let mut totals = TOTALS.lock().unwrap();
+    let mut records = RECORDS.lock().unwrap();
+    add_play(&mut records, &mut totals, record);
+    drop(records);
+    drop(totals);
+    schedule_save();
+}
+
+// Counts a finished play in the totals and appends it to the log, dropping the oldest
+// records beyond MAX_RECORDS
+fn add_play(records: &mut Vec<PlayRecord>, totals: &mut Totals, record: PlayRecord) {
+    totals.add(&record);
+    records.push(record);
+    if records.len() > MAX_RECORDS {
+        let excess = records.len() - MAX_RECORDS;
+        records.drain(..excess);
+    }
+}
+
+/// Collects recent quick skips of autoplay tracks, so stations can avoid similar picks.
+pub(crate) fn autoplay_skip_signals() -> SkipSignals {
+    let since = current_timestamp_ms().saturating_sub(SKIP_SIGNAL_WINDOW_MS);
+    // Artist URI -> (quick skips, plays kept)
+    let mut artist_counts: HashMap<String, (u32, u32)> = HashMap::new();
+    let mut skipped_tracks = HashSet::new();
+
+    let records = RECORDS.lock().unwrap();
+    for record in records.iter().filter(|r| r.autoplay && r.started_at_ms >= since) {
+        let quick_skip = record.skipped && record.listened_ms < QUICK_SKIP_MS;
+        if quick_skip {
+            skipped_tracks.insert(record.uri.clone());
+        }
+        if let Some(artist_uri) = &record.artist_uri {
+            let counts = artist_counts.entry(artist_uri.clone()).or_default();
+            if quick_skip {
+                counts.0 += 1;
+            } else if !record.skipped {
+                counts.1 += 1;
+            }
+        }
+    }
+
+    // The +1 keeps a single skip from ruling out an artist entirely
+    let artist_penalties = artist_counts.into_iter()
+        .filter(|(_, (skips, _))| *skips > 0)
+        .map(|(uri, (skips, kept))| (uri, f64::from(skips) / f64::from(skips + kept + 1)))
+        .collect();
+
+    SkipSignals {
+        artist_penalties,
+        skipped_tracks,
+    }
+}
+
+#[derive(Serialize)]
+struct ArtistSummary {
+    artist_name: String,
+    artist_uri: Option<String>,
+    play_count: u32,
+    minutes_listened: f64,
+}
+
+#[derive(Serialize)]
+struct SkippedTrack {
+    uri: String,
+    track_name: String,
+    artist_name: String,
+    skip_count: u32,
+}
+
+#[derive(Serialize)]
+struct TrackSummary {
+    uri: String,
+    track_name: String,
+    artist_name: String,
+    play_count: u32,
+    minutes_listened: f64,
+    last_played_ms: u64,
+}
+
+#[derive(Serialize)]
+struct ListeningStats {
+    minutes_listened: f64,
+    play_count: u32,
+    skip_count: u32,
+    track_count: usize,
+    artist_count: usize,
+    /// Days with at least one play
+    days_listened: usize,
+    first_played_ms: Option<u64>,
+    top_tracks: Vec<TrackSummary>,
+    top_artists: Vec<ArtistSummary>,
+}
+
+#[derive(Serialize)]
+struct DaySummary {
+    /// Local date, YYYY-MM-DD
+    date: String,
+    minutes_listened: f64,
+    play_count: u32,
+}
+
+#[derive(Serialize)]
+struct ListeningSummary {
+    period: &'static str,
+    start_ms: i64,
+    end_ms: i64,
+    minutes_listened: f64,
+    play_count: u32,
+    skip_count: u32,
+    top_artists: Vec<ArtistSummary>,
+    most_skipped: Vec<SkippedTrack>,
+    days: Vec<DaySummary>,
+}
+
+fn minutes(ms: u64) -> f64 {
+    (ms as f64 / 60_000.0 * 10.0).round() / 10.0
+}
+
+// Converts days since the Unix epoch to a YYYY-MM-DD date (proleptic Gregorian calendar)
+fn date_string(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Adds a play to its artist's totals (with the milliseconds listened)
+fn count_artist(artists: &mut HashMap<String, (ArtistSummary, u64)>, record: &PlayRecord) {
+    if record.artist_name.is_empty() {
+        return;
+    }
+    let key = record.artist_uri.clone().unwrap_or_else(|| record.artist_name.clone());
+    let (artist, artist_ms) = artists.entry(key).or_insert_with(|| (ArtistSummary {
+        artist_name: record.artist_name.clone(),
+        artist_uri: record.artist_uri.clone(),
+        play_count: 0,
+        minutes_listened: 0.0,
+    }, 0));
+    artist.play_count += 1;
+    *artist_ms += u64::from(record.listened_ms);
+}
+
+// The most listened artists
+fn top_artists(artists: HashMap<String, (ArtistSummary, u64)>) -> Vec<ArtistSummary> {
+    let mut top_artists: Vec<(ArtistSummary, u64)> = artists.into_values().collect();
+    top_artists.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.play_count.cmp(&a.0.play_count)));
+    top_artists.into_iter()
+        .take(SUMMARY_LIST_SIZE)
+        .map(|(mut artist, ms)| {
+            artist.minutes_listened = minutes(ms);
+            artist
+        })
+        .collect()
+}
+
+// Tracks played since `since_ms` (all time, from the totals, if None), most played first
+// (ties go to the longer listened)
+fn top_tracks(records: &[PlayRecord], totals: &Totals, since_ms: Option<u64>) -> Vec<TrackSummary> {
+    let mut tracks: Vec<(TrackSummary, u64)> = match since_ms {
+        None => totals.tracks.iter()
+            .map(|(uri, total)| (TrackSummary {
+                uri: uri.clone(),
+                track_name: total.track_name.clone(),
+                artist_name: total.artist_name.clone(),
+                play_count: total.play_count,
+                minutes_listened: 0.0,
+                last_played_ms: total.last_played_ms,
+            }, total.listened_ms))
+            .collect(),
+        Some(since_ms) => {
+            let mut tracks: HashMap<&str, (TrackSummary, u64)> = HashMap::new();
+            for record in records.iter().filter(|r| r.started_at_ms >= since_ms) {
+                let (track, track_ms) = tracks.entry(&record.uri).or_insert_with(|| (TrackSummary {
+                    uri: record.uri.clone(),
+                    track_name: record.track_name.clone(),
+                    artist_name: record.artist_name.clone(),
+                    play_count: 0,
+                    minutes_listened: 0.0,
+                    last_played_ms: 0,
+                }, 0));
+                track.play_count += 1;
+                track.last_played_ms = track.last_played_ms.max(record.started_at_ms);
+                *track_ms += u64::from(record.listened_ms);
+            }
+            tracks.into_values().collect()
+        }
+    };
+
+    tracks.sort_by(|a, b| b.0.play_count.cmp(&a.0.play_count).then(b.1.cmp(&a.1)));
+    tracks.into_iter()
+        .map(|(mut track, ms)| {
+            track.minutes_listened = minutes(ms);
+            track
+        })
+        .collect()
+}
+
+// Start of a spotifly_get_top_tracks_local() period as of `now_ms` (None inside for all time),
+// or None for an unknown period
+fn top_track_window(period: u8, now_ms: u64) -> Option<Option<u64>> {
+    let window_days = TOP_TRACK_WINDOWS.get(period as usize)?;
+    Some(window_days.map(|days| now_ms.saturating_sub(days * DAY_MS as u64)))
+}
+
+// All-time statistics from the running totals
+fn listening_stats(totals: &Totals) -> ListeningStats {
+    let artists: HashMap<String, (ArtistSummary, u64)> = totals.artists.iter()
+        .map(|(key, total)| (key.clone(), (ArtistSummary {
+            artist_name: total.artist_name.clone(),
+            artist_uri: total.artist_uri.clone(),
+            play_count: total.play_count,
+            minutes_listened: 0.0,
+        }, total.listened_ms)))
+        .collect();
+    let top_tracks = top_tracks(&[], totals, None);
+
+    ListeningStats {
+        minutes_listened: minutes(totals.listened_ms),
+        play_count: totals.play_count,
+        skip_count: totals.skip_count,
+        track_count: top_tracks.len(),
+        artist_count: artists.len(),
+        days_listened: totals.days.len(),
+        first_played_ms: totals.first_played_ms,
+        top_tracks: top_tracks.into_iter().take(SUMMARY_LIST_SIZE).collect(),
+        top_artists: top_artists(artists),
+    }
+}
+
+// Summarizes the plays in `records` over a day or week, as of `now_ms`
+fn summarize(
+    records: &[PlayRecord],
+    now_ms: u64,
+    period: u8,
+    periods_ago: u32,
+    utc_offset_minutes: i32,
+) -> ListeningSummary {
+    let offset_ms = i64::from(utc_offset_minutes) * 60_000;
+    let today = (now_ms as i64 + offset_ms).div_euclid(DAY_MS);
+
+    let (period_name, first_day, day_count) = if period == 1 {
+        // Weeks start on Monday (the epoch was a Thursday)
+        let weekday = (today + 3).rem_euclid(7);
+        ("week", today - weekday - 7 * i64::from(periods_ago), 7)
+    } else {
+        ("day", today - i64::from(periods_ago), 1)
+    };
+    let start_ms = first_day * DAY_MS - offset_ms;
+    let end_ms = start_ms + day_count * DAY_MS;
+
+    let mut listened_ms = 0u64;
+    let mut play_count = 0u32;
+    let mut skip_count = 0u32;
+    let mut artists: HashMap<String, (ArtistSummary, u64)> = HashMap::new();
+    let mut skipped: HashMap<String, SkippedTrack> = HashMap::new();
+    let mut days: Vec<(u64, u32)> = vec![(0, 0); day_count as usize];
+
+    for record in records {
+        let started = record.started_at_ms as i64;
+        if started < start_ms || started >= end_ms {
+            continue;
+        }
+
+        listened_ms += u64::from(record.listened_ms);
+        play_count += 1;
+
+        let day = &mut days[((started - start_ms) / DAY_MS) as usize];
+        day.0 += u64::from(record.listened_ms);
+        day.1 += 1;
+
+        count_artist(&mut artists, record);
+
+        if record.skipped {
+            skip_count += 1;
+            skipped.entry(record.uri.clone())
+                .or_insert_with(|| SkippedTrack {
+                    uri: record.uri.clone(),
+                    track_name: record.track_name.clone(),
+                    artist_name: record.artist_name.clone(),
+                    skip_count: 0,
+                })
+                .skip_count += 1;
+        }
+    }
+
+    let top_artists = top_artists(artists);
+
+    let mut most_skipped: Vec<SkippedTrack> = skipped.into_values().collect();
+    most_skipped.sort_by(|a, b| b.skip_count.cmp(&a.skip_count).then(a.track_name.cmp(&b.track_name)));
+    most_skipped.truncate(SUMMARY_LIST_SIZE);
+
+    ListeningSummary {
+        period: period_name,
+        start_ms,
+        end_ms,
+        minutes_listened: minutes(listened_ms),
+        play_count,
+        skip_count,
+        top_artists,
+        most_skipped,
+        days: days.into_iter()
+            .enumerate()
+            .map(|(i, (ms, plays))| DaySummary {
+                date: date_string(first_day + i as i64),
+                minutes_listened: minutes(ms),
+                play_count: plays,
+            })
+            .collect(),
+    }
+}
+
+/// Returns a listening summary for a day or week as JSON: minutes listened,
+/// play and skip counts, top artists, most-skipped tracks and per-day minutes.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - period: 0 = day, 1 = week (weeks start on Monday)
+/// - periods_ago: 0 = the current day/week, 1 = the previous one, ...
+/// - utc_offset_minutes: the user's UTC offset, used to find local day boundaries
+#[no_mangle]
+pub extern "C" fn spotifly_get_listening_summary_json(
+    period: u8,
+    periods_ago: u32,
+    utc_offset_minutes: i32,
+) -> *mut c_char {
+    if period > 1 {
+        error::fail(
+            SpotiflyError::InvalidArgument,
+            format!("Get listening summary error: invalid period {}", period),
+        );
+        return ptr::null_mut();
+    }
+
+    let records = RECORDS.lock().unwrap();
+    let summary = summarize(&records, current_timestamp_ms(), period, periods_ago, utc_offset_minutes);
+    drop(records);
+    match serde_json::to_string(&summary) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(e) => {
+            error::fail(SpotiflyError::Unknown, format!("Get listening summary error: {:?}", e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns the most played tracks over a period as a JSON array of
+/// {uri, track_name, artist_name, play_count, minutes_listened, last_played_ms},
+/// most played first. Only plays recorded by this library count (see spotifly_set_data_dir()).
+/// All-time counts come from running totals; the other periods from the play log, which
+/// keeps the latest 20000 plays.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+///
+/// # Parameters
+/// - period: 0 = last 7 days, 1 = last 30 days, 2 = last 365 days, 3 = all time
+/// - limit: Maximum number of tracks, 0 = all
+#[no_mangle]
+pub extern "C" fn spotifly_get_top_tracks_local(period: u8, limit: usize) -> *mut c_char {
+    let Some(since_ms) = top_track_window(period, current_timestamp_ms()) else {
+        error::fail(SpotiflyError::InvalidArgument, format!("Get top tracks error: invalid period {}", period));
+        return ptr::null_mut();
+    };
+
+    let totals = TOTALS.lock().unwrap();
+    let records = RECORDS.lock().unwrap();
+    let mut tracks = top_tracks(&records, &totals, since_ms);
+    drop(records);
+    drop(totals);
+    if limit != 0 {
+        tracks.truncate(limit);
+    }
+    match serde_json::to_string(&tracks) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(e) => {
+            error::fail(SpotiflyError::Unknown, format!("Get top tracks error: {:?}", e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns all-time listening statistics as JSON: {minutes_listened, play_count, skip_count,
+/// track_count, artist_count, days_listened, first_played_ms, top_tracks, top_artists}.
+/// track_count and artist_count count distinct tracks and artists; days_listened counts
+/// (UTC) days with at least one play. The top lists hold up to 10 entries.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+#[no_mangle]
+pub extern "C" fn spotifly_get_listening_stats_json() -> *mut c_char {
+    let stats = listening_stats(&TOTALS.lock().unwrap());
+    match serde_json::to_string(&stats) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(e) => {
+            error::fail(SpotiflyError::Unknown, format!("Get listening stats error: {:?}", e));
+            ptr::null_mut()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wednesday 2024-01-10 12:00 UTC
+    const NOW_MS: u64 = 1_704_888_000_000;
+    const HOUR_MS: u64 = 60 * 60 * 1000;
+
+    fn play(uri: &str, started_at_ms: u64, listened_ms: u32) -> PlayRecord {
+        PlayRecord {
+            uri: uri.to_string(),
+            track_name: uri.to_string(),
+            artist_name: "Artist".to_string(),
+            artist_uri: Some("spotify:artist:a".to_string()),
+            started_at_ms,
+            duration_ms: 200_000,
+            listened_ms,
+            skipped: false,
+            autoplay: false,
+        }
+    }
+
+    #[test]
+    fn day_boundaries_follow_the_utc_offset() {
+        // Tuesday 23:30 UTC: still Tuesday in London and New York, Wednesday in Berlin
+        let records = [play("spotify:track:late", NOW_MS - 12 * HOUR_MS - 30 * 60 * 1000, 60_000)];
+
+        let today = summarize(&records, NOW_MS, 0, 0, 0);
+        assert_eq!(today.play_count, 0);
+        assert_eq!(today.days[0].date, "2024-01-10");
+        let yesterday = summarize(&records, NOW_MS, 0, 1, 0);
+        assert_eq!(yesterday.play_count, 1);
+        assert_eq!(yesterday.days[0].date, "2024-01-09");
+
+        let berlin = summarize(&records, NOW_MS, 0, 0, 60);
+        assert_eq!(berlin.play_count, 1);
+        assert_eq!(berlin.start_ms, (NOW_MS - 13 * HOUR_MS) as i64);
+        assert_eq!(berlin.end_ms, berlin.start_ms + DAY_MS);
+
+        assert_eq!(summarize(&records, NOW_MS, 0, 0, -300).play_count, 0);
+        assert_eq!(summarize(&records, NOW_MS, 0, 1, -300).play_count, 1);
+    }
+
+    #[test]
+    fn weeks_run_from_monday_to_sunday() {
+        // Sunday 2024-01-07 12:00 UTC, the last day of the previous week
+        let records = [play("spotify:track:sunday", NOW_MS - 72 * HOUR_MS, 120_000)];
+
+        let this_week = summarize(&records, NOW_MS, 1, 0, 0);
+        assert_eq!(this_week.period, "week");
+        assert_eq!(this_week.days.len(), 7);
+        assert_eq!(this_week.days[0].date, "2024-01-08");
+        assert_eq!(this_week.days[6].date, "2024-01-14");
+        assert_eq!(this_week.play_count, 0);
+
+        let last_week = summarize(&records, NOW_MS, 1, 1, 0);
+        assert_eq!(last_week.days[0].date, "2024-01-01");
+        assert_eq!(last_week.play_count, 1);
+        assert_eq!(last_week.days[6].play_count, 1);
+        assert_eq!(last_week.minutes_listened, 2.0);
+    }
+
+    #[test]
+    fn top_track_periods_reach_back_their_window() {
+        assert_eq!(top_track_window(0, NOW_MS), Some(Some(NOW_MS - 7 * DAY_MS as u64)));
+        assert_eq!(top_track_window(2, NOW_MS), Some(Some(NOW_MS - 365 * DAY_MS as u64)));
+        assert_eq!(top_track_window(3, NOW_MS), Some(None));
+        assert_eq!(top_track_window(4, NOW_MS), None);
+
+        let mut records = Vec::new();
+        let mut totals = Totals::default();
+        add_play(&mut records, &mut totals, play("spotify:track:old", NOW_MS - 10 * DAY_MS as u64, 60_000));
+        add_play(&mut records, &mut totals, play("spotify:track:new", NOW_MS - HOUR_MS, 60_000));
+        add_play(&mut records, &mut totals, play("spotify:track:old", NOW_MS - 20 * DAY_MS as u64, 60_000));
+
+        let week = top_tracks(&records, &totals, top_track_window(0, NOW_MS).unwrap());
+        assert_eq!(week.iter().map(|t| t.uri.as_str()).collect::<Vec<_>>(), ["spotify:track:new"]);
+        let month = top_tracks(&records, &totals, top_track_window(1, NOW_MS).unwrap());
+        assert_eq!(month[0].uri, "spotify:track:old");
+        assert_eq!(month[0].play_count, 2);
+    }
+
+    #[test]
+    fn trimming_the_log_keeps_the_totals() {
+        let mut records = Vec::new();
+        let mut totals = Totals::default();
+        let plays = MAX_RECORDS + 5;
+        for i in 0..plays {
+            let uri = if i < 5 { "spotify:track:first" } else { "spotify:track:later" };
+            add_play(&mut records, &mut totals, play(uri, NOW_MS + i as u64, 1_000));
+        }
+
+        assert_eq!(records.len(), MAX_RECORDS);
+        assert!(records.iter().all(|record| record.uri == "spotify:track:later"));
+
+        let stats = listening_stats(&totals);
+        assert_eq!(stats.play_count as usize, plays);
+        assert_eq!(stats.track_count, 2);
+        assert_eq!(stats.first_played_ms, Some(NOW_MS));
+        assert_eq!(totals.tracks["spotify:track:first"].play_count, 5);
+        assert_eq!(totals.artists["spotify:artist:a"].play_count as usize, plays);
+        assert_eq!(totals.days.values().map(|day| day.play_count as usize).sum::<usize>(), plays);
+
+        let all_time = top_tracks(&records, &totals, None);
+        assert!(all_time.iter().any(|track| track.uri == "spotify:track:first" && track.play_count == 5));
+    }
+}
@@ -0,0 +1,83 @@
+// Local persistence for library-managed state (play statistics, etc).
+//
+// The host sets a data directory with spotifly_set_data_dir(). Each store is a JSON
+// file inside it. Without a data directory, stores live in memory only.
+
+use crate::error::{self, SpotiflyError};
+use crate::{episode_progress, history, offline, pins, speed, stats, trim};
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ffi::{c_char, CStr};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+static DATA_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+/// Loads a JSON store from the data directory.
+/// Returns None if there is no data directory, no file, or the file can't be parsed.
+pub(crate) fn load_json<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let path = DATA_DIR.lock().unwrap().as_ref()?.join(file_name);
+    let data = fs::read(&path).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log::warn!("Ignoring unreadable {}: {:?}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Saves a JSON store to the data directory (no-op without a data directory).
+/// Writes to a temporary file first so a crash can't leave a truncated store behind.
+pub(crate) fn save_json<T: Serialize>(file_name: &str, value: &T) {
+    let dir = match DATA_DIR.lock().unwrap().clone() {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    let result = serde_json::to_vec(value)
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            let tmp_path = dir.join(format!("{}.tmp", file_name));
+            fs::write(&tmp_path, data).map_err(|e| e.to_string())?;
+            fs::rename(&tmp_path, dir.join(file_name)).map_err(|e| e.to_string())
+        });
+
+    if let Err(e) = result {
+        log::warn!("Failed to save {}: {}", file_name, e);
+    }
+}
+
+/// Sets the directory used to persist local data (play statistics, trim points, etc.)
+/// and loads any data already stored there. The directory is created if needed.
+/// Returns 0 on success, a negative error code on error.
+#[no_mangle]
+pub extern "C" fn spotifly_set_data_dir(path: *const c_char) -> i32 {
+    if path.is_null() {
+        return error::fail(SpotiflyError::InvalidArgument, "Set data dir error: path is null");
+    }
+
+    let path_str = unsafe {
+        match CStr::from_ptr(path).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => return error::fail(SpotiflyError::InvalidArgument, "Set data dir error: invalid path string"),
+        }
+    };
+
+    let dir = PathBuf::from(path_str);
+    if let Err(e) = fs::create_dir_all(&dir) {
+        return error::fail(SpotiflyError::Unknown, format!("Set data dir error: {}", e));
+    }
+
+    *DATA_DIR.lock().unwrap() = Some(dir);
+    stats::load();
+    trim::load();
+    history::load();
+    speed::load();
+    episode_progress::load();
+    pins::load();
+    offline::load();
+    0
+}
@@ -0,0 +1,139 @@
+// Stored-credentials login.
+//
+// Once a session has been established (with an OAuth token, or by a Connect client
+// handing over playback), librespot saves a reusable credential blob in the cache
+// directory. Logging in with that blob needs neither the browser nor the host's own
+// client ID. Web API requests then use a token the session obtains for itself, which
+// is renewed the same way instead of through the accounts service.
+
+use crate::auth::{OAuthResult, OAUTH_RESULT};
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{
+    build_cache, current_timestamp_ms, init_player_async, secure_storage, to_c_string, webapi, ACCESS_TOKEN, RUNTIME,
+    SESSION,
+};
+use librespot_core::authentication::Credentials;
+use librespot_core::session::Session;
+use librespot_protocol::authentication::AuthenticationType;
+use std::ffi::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set while the session was logged in from the stored credentials
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the current session was logged in from the stored credentials.
+pub(crate) fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Forgets the login mode (on player cleanup).
+pub(crate) fn reset() {
+    ACTIVE.store(false, Ordering::SeqCst);
+}
+
+// Credentials from secure storage, or else from the cache directory
+fn stored() -> Option<Credentials> {
+    secure_storage::load_credentials().or_else(|| build_cache().ok()?.credentials())
+}
+
+/// Credentials for a new session: the stored blob after a stored-credentials login,
+/// otherwise the current access token.
+pub(crate) fn session_credentials() -> Result<Credentials, Failure> {
+    if is_active() {
+        return stored().ok_or_else(|| Failure::new(SpotiflyError::NotAuthenticated, "No stored credentials"));
+    }
+    let token = ACCESS_TOKEN.lock().unwrap().clone()
+        .ok_or_else(webapi::no_access_token)?;
+    Ok(Credentials::with_access_token(token))
+}
+
+/// The reusable credentials a connected session was given, if any.
+pub(crate) fn reusable(session: &Session) -> Option<Credentials> {
+    let auth_data = session.auth_data();
+    (!auth_data.is_empty()).then(|| Credentials {
+        username: Some(session.username()),
+        auth_type: AuthenticationType::AUTHENTICATION_STORED_SPOTIFY_CREDENTIALS,
+        auth_data,
+    })
+}
+
+/// Obtains a Web API token from the session and makes it the current access token.
+pub(crate) async fn renew_token(session: &Session) -> Result<OAuthResult, Failure> {
+    let token = session.login5().auth_token().await
+        .map_err(|e| error::librespot("Session token error", &e))?;
+
+    let result = OAuthResult {
+        access_token: token.access_token,
+        refresh_token: None,
+        expires_in: token.expires_in.as_secs(),
+        obtained_at_ms: current_timestamp_ms(),
+    };
+    *ACCESS_TOKEN.lock().unwrap() = Some(result.access_token.clone());
+    *OAUTH_RESULT.lock().unwrap() = Some(result.clone());
+    secure_storage::save_tokens(None, &result);
+    Ok(result)
+}
+
+/// Returns true if reusable credentials are stored in secure storage or the cache
+/// directory (see spotifly_set_cache_dir()).
+#[no_mangle]
+pub extern "C" fn spotifly_has_stored_credentials() -> bool {
+    stored().is_some()
+}
+
+/// Returns the username of the stored credentials.
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL if no credentials are stored.
+#[no_mangle]
+pub extern "C" fn spotifly_get_stored_username() -> *mut c_char {
+    match stored().and_then(|credentials| credentials.username) {
+        Some(username) => to_c_string(&username),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Initializes the player with the credentials stored (in secure storage or the cache
+/// directory) by an earlier login or by a Connect client handing over playback, instead of an access
+/// token. No browser round-trip or client ID is needed. Web API requests use a token
+/// from the session, renewed automatically (TokenRefreshed events carry the new one).
+/// Enable secure storage or set the cache directory first. Returns 0 on success, a negative error code on error
+/// (NotAuthenticated if nothing is stored or Spotify rejects the credentials).
+#[no_mangle]
+pub extern "C" fn spotifly_login_with_stored_credentials() -> i32 {
+    if SESSION.lock().unwrap().is_some() {
+        // Already initialized
+        return 0;
+    }
+    let credentials = match stored() {
+        Some(credentials) => credentials,
+        None => {
+            return error::fail(
+                SpotiflyError::NotAuthenticated,
+                "Stored credentials login error: no credentials stored in the cache directory",
+            );
+        }
+    };
+
+    ACTIVE.store(true, Ordering::SeqCst);
+    let result = RUNTIME.block_on(async {
+        init_player_async(credentials, None).await?;
+        let session = webapi::current_session()?;
+        if let Err(e) = renew_token(&session).await {
+            // Playback works without it; only Web API requests fail
+            log::warn!("{}", e);
+        }
+        Ok::<(), Failure>(())
+    });
+
+    match result {
+        Ok(()) => {
+            log::info!("Logged in with stored credentials");
+            0
+        }
+        Err(e) => {
+            ACTIVE.store(false, Ordering::SeqCst);
+            error::report("Stored credentials login", e)
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// Connection supervisor.
+//
+// Watches the session and brings it back when the network drops or the access point
+// resets the connection, so calls don't keep failing until the host re-inits the player.
+// Playback is paused and remembered (as for system sleep), the session is re-established
+// with exponentially growing pauses between attempts, and the current track is reloaded
+// at its last position. Progress is reported as ConnectionStateChanged events.
+//
+// Each connected session gets its own supervisor thread. Cleaning up the player, or
+// starting to initialize it again, bumps a generation counter that retires the running
+// thread, so it never mistakes a session still being set up for a lost one.
+
+use crate::{
+    power, set_connection_state, CONNECTION_DISCONNECTED, CONNECTION_IDLE, CONNECTION_RECONNECTING,
+    CONNECTION_STATE, CONNECTION_SUSPENDED, PLAYER, POSITION_MS, SESSION,
+};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// How often a backoff pause checks for an early retry
+const WAIT_STEP: Duration = Duration::from_millis(250);
+
+// Generation of the supervisor thread that is in charge; older threads exit
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+// Set to cut the current backoff pause short (e.g. a fresh token arrived)
+static RETRY_NOW: AtomicBool = AtomicBool::new(false);
+
+fn player_alive() -> bool {
+    PLAYER.lock().unwrap().is_some()
+}
+
+fn is_current(generation: u64) -> bool {
+    GENERATION.load(Ordering::SeqCst) == generation
+}
+
+fn needs_recovery() -> bool {
+    // Sleep/wake and idle handling (power.rs) take care of suspended and idle sessions
+    let state = CONNECTION_STATE.load(Ordering::SeqCst);
+    if matches!(state, CONNECTION_SUSPENDED | CONNECTION_IDLE | CONNECTION_RECONNECTING) {
+        return false;
+    }
+    // Disconnected with a live player means an earlier reconnect gave up
+    state == CONNECTION_DISCONNECTED || SESSION.lock().unwrap().as_ref().is_some_and(|s| s.is_invalid())
+}
+
+// Sleeps for `duration`, returning early on retry_now(), player cleanup or a new init
+fn wait(duration: Duration, generation: u64) {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if RETRY_NOW.swap(false, Ordering::SeqCst) || !player_alive() || !is_current(generation) {
+            return;
+        }
+        thread::sleep(WAIT_STEP);
+    }
+}
+
+fn recover(generation: u64) {
+    log::info!("Connection lost, reconnecting");
+    RETRY_NOW.store(false, Ordering::SeqCst);
+    power::suspend(POSITION_MS.load(Ordering::SeqCst));
+
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1.. {
+        // A new init takes over the connection
+        if !is_current(generation) {
+            return;
+        }
+        if !player_alive() {
+            set_connection_state(CONNECTION_DISCONNECTED);
+            return;
+        }
+        match power::try_resume() {
+            Ok(()) => {
+                log::info!("Reconnected (attempt {})", attempt);
+                return;
+            }
+            Err(e) => {
+                log::warn!("Reconnect attempt {} failed: {} (retrying in {}s)", attempt, e, backoff.as_secs());
+            }
+        }
+        wait(backoff, generation);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Starts supervising the session that was just connected, replacing any running supervisor.
+/// The thread exits once the player is cleaned up or initialized again (see stop()).
+pub(crate) fn start() {
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+
+    thread::Builder::new()
+        .name("spotifly-supervisor".to_string())
+        .spawn(move || loop {
+            thread::sleep(CHECK_INTERVAL);
+            if !is_current(generation) || !player_alive() {
+                break;
+            }
+            if needs_recovery() {
+                recover(generation);
+            }
+        })
+        .map_err(|e| log::error!("Connection supervisor error: {}", e))
+        .ok();
+}
+
+/// Retires the running supervisor: the player is being cleaned up or initialized, and the
+/// connection is left alone until start() is called for the new session.
+pub(crate) fn stop() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Makes a reconnect that is waiting out its backoff try again right away.
+pub(crate) fn retry_now() {
+    RETRY_NOW.store(true, Ordering::SeqCst);
+}
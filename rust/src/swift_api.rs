@@ -0,0 +1,708 @@
+// Typed Swift API generated with UniFFI (the `uniffi` feature).
+//
+// The C functions hand out strings the host must free and report errors as codes plus a
+// separate message lookup. This layer wraps the same engine in a SpotiflyPlayer object
+// with typed records and thrown errors; UniFFI generates the Swift classes from it and
+// takes care of memory management (see build-swift-bindings.sh). It covers the player
+// (setup, transport, volume, repeat, queue, now playing, token updates and events),
+// search, the user's library and playlists, Spotify Connect, the equalizer and the
+// listening statistics; the remaining features (lyrics, podcasts, stations and so on)
+// are reached through the C API. It calls the C functions underneath and decodes their
+// JSON into records, so both APIs behave the same and can be used side by side, except
+// that setting an event listener here replaces a callback registered through the C API.
+
+use crate::error::{self, spotifly_get_last_error_code, SpotiflyError};
+use crate::events::{self, spotifly_register_event_callback};
+use crate::{
+    connect, devices, eq, library, loading, now_playing, paging, playlists, search, spotifly_add_next_to_queue,
+    spotifly_add_to_queue, spotifly_cleanup_player, spotifly_clear_upcoming_queue, spotifly_enqueue,
+    spotifly_free_string, spotifly_get_connection_state, spotifly_get_duration_ms, spotifly_get_position_ms,
+    spotifly_get_repeat_mode, spotifly_get_volume, spotifly_init_player, spotifly_next, spotifly_pause,
+    spotifly_play_track, spotifly_play_tracks, spotifly_previous, spotifly_resume, spotifly_seek,
+    spotifly_set_repeat_mode, spotifly_set_volume, spotifly_stop, stats, token_manager, CURRENT_INDEX, IS_PLAYING,
+    QUEUE,
+};
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+/// A failed player call: the SpotiflyError code and the error message.
+#[derive(Debug, uniffi::Error)]
+pub enum PlayerError {
+    Failed { code: i32, message: String },
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let PlayerError::Failed { code, message } = self;
+        write!(f, "{} ({})", message, code)
+    }
+}
+
+impl std::error::Error for PlayerError {}
+
+/// A queue item (also used for tracks in search results and the library).
+#[derive(Deserialize, uniffi::Record)]
+pub struct QueueEntry {
+    pub uri: String,
+    /// 0 = track, 1 = episode
+    pub item_type: u8,
+    pub track_name: String,
+    pub artist_name: String,
+    pub album_art_url: String,
+    pub duration_ms: u32,
+    pub album_id: Option<String>,
+    pub artist_id: Option<String>,
+    pub external_url: Option<String>,
+    /// 0 = unplayed, 1 = partially played, 2 = completed
+    pub play_state: u8,
+    pub last_position_ms: u32,
+    pub album_name: Option<String>,
+    /// 0 if unknown
+    pub track_number: u32,
+    /// 0 if unknown
+    pub disc_number: u32,
+    pub explicit: bool,
+    /// "YYYY-MM-DD"
+    pub release_date: Option<String>,
+}
+
+/// An album in search results.
+#[derive(Deserialize, uniffi::Record)]
+pub struct AlbumResult {
+    pub uri: String,
+    pub name: String,
+    pub artist_name: String,
+    pub album_art_url: String,
+    pub release_date: Option<String>,
+    pub total_tracks: u64,
+}
+
+/// An artist in search results.
+#[derive(Deserialize, uniffi::Record)]
+pub struct ArtistResult {
+    pub uri: String,
+    pub name: String,
+    pub image_url: String,
+    pub genres: Vec<String>,
+}
+
+/// A playlist in search results or the user's library.
+#[derive(Deserialize, uniffi::Record)]
+pub struct PlaylistSummary {
+    pub uri: String,
+    pub name: String,
+    pub owner_name: String,
+    pub owner_id: String,
+    pub image_url: String,
+    pub track_count: u64,
+    pub collaborative: bool,
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+    total: u64,
+}
+
+#[derive(Deserialize)]
+struct SearchPages {
+    tracks: Option<Page<QueueEntry>>,
+    albums: Option<Page<AlbumResult>>,
+    artists: Option<Page<ArtistResult>>,
+    playlists: Option<Page<PlaylistSummary>>,
+}
+
+/// Search results. Types that weren't searched for are empty with a total of 0.
+#[derive(uniffi::Record)]
+pub struct SearchResults {
+    pub tracks: Vec<QueueEntry>,
+    pub total_tracks: u64,
+    pub albums: Vec<AlbumResult>,
+    pub total_albums: u64,
+    pub artists: Vec<ArtistResult>,
+    pub total_artists: u64,
+    pub playlists: Vec<PlaylistSummary>,
+    pub total_playlists: u64,
+}
+
+// Splits a page into its items and total (empty if the type wasn't searched for)
+fn page_parts<T>(page: Option<Page<T>>) -> (Vec<T>, u64) {
+    page.map_or((Vec::new(), 0), |page| (page.items, page.total))
+}
+
+/// A saved ("liked") track.
+#[derive(Deserialize, uniffi::Record)]
+pub struct SavedTrack {
+    #[serde(flatten)]
+    pub track: QueueEntry,
+    /// When the track was saved (ISO 8601)
+    pub added_at: String,
+}
+
+/// A page of the user's saved tracks.
+#[derive(Deserialize, uniffi::Record)]
+pub struct SavedTracksPage {
+    pub items: Vec<SavedTrack>,
+    pub total: u64,
+    pub offset: u64,
+}
+
+/// A Spotify Connect device.
+#[derive(Deserialize, uniffi::Record)]
+pub struct ConnectDevice {
+    pub id: String,
+    pub name: String,
+    /// "Computer", "Smartphone", "Speaker", ...
+    pub device_type: String,
+    pub is_active: bool,
+    /// Restricted devices can't be controlled through the Web API
+    pub is_restricted: bool,
+    pub volume_percent: Option<u64>,
+    /// True for this player
+    pub is_this_device: bool,
+}
+
+/// An equalizer band.
+#[derive(Deserialize, uniffi::Record)]
+pub struct EqBand {
+    /// Center frequency in Hz
+    pub frequency: f64,
+    pub gain_db: f64,
+}
+
+/// The equalizer settings and the names of the built-in presets.
+#[derive(Deserialize, uniffi::Record)]
+pub struct EqSettings {
+    pub enabled: bool,
+    pub bands: Vec<EqBand>,
+    pub presets: Vec<String>,
+}
+
+/// An artist's share of listening.
+#[derive(Deserialize, uniffi::Record)]
+pub struct ArtistListening {
+    pub artist_name: String,
+    pub artist_uri: Option<String>,
+    pub play_count: u32,
+    pub minutes_listened: f64,
+}
+
+/// A track's share of listening.
+#[derive(Deserialize, uniffi::Record)]
+pub struct TrackListening {
+    pub uri: String,
+    pub track_name: String,
+    pub artist_name: String,
+    pub play_count: u32,
+    pub minutes_listened: f64,
+    pub last_played_ms: u64,
+}
+
+/// A track the user kept skipping.
+#[derive(Deserialize, uniffi::Record)]
+pub struct SkippedTrack {
+    pub uri: String,
+    pub track_name: String,
+    pub artist_name: String,
+    pub skip_count: u32,
+}
+
+/// Listening on one day.
+#[derive(Deserialize, uniffi::Record)]
+pub struct DayListening {
+    /// Local date, YYYY-MM-DD
+    pub date: String,
+    pub minutes_listened: f64,
+    pub play_count: u32,
+}
+
+/// A listening summary for a day or week.
+#[derive(Deserialize, uniffi::Record)]
+pub struct ListeningSummary {
+    /// "day" or "week"
+    pub period: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub minutes_listened: f64,
+    pub play_count: u32,
+    pub skip_count: u32,
+    pub top_artists: Vec<ArtistListening>,
+    pub most_skipped: Vec<SkippedTrack>,
+    pub days: Vec<DayListening>,
+}
+
+/// All-time listening statistics.
+#[derive(Deserialize, uniffi::Record)]
+pub struct ListeningStats {
+    pub minutes_listened: f64,
+    pub play_count: u32,
+    pub skip_count: u32,
+    pub track_count: u64,
+    pub artist_count: u64,
+    /// (UTC) days with at least one play
+    pub days_listened: u64,
+    pub first_played_ms: Option<u64>,
+    pub top_tracks: Vec<TrackListening>,
+    pub top_artists: Vec<ArtistListening>,
+}
+
+/// The current track and playback state.
+#[derive(uniffi::Record)]
+pub struct NowPlaying {
+    pub uri: String,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album_name: Option<String>,
+    pub artwork_url: String,
+    pub duration_ms: u32,
+    pub position_ms: u32,
+    pub is_playing: bool,
+    pub queue_index: u64,
+    pub queue_length: u64,
+}
+
+/// A player event: its code and name (as in spotifly_rust.h) and its JSON payload.
+#[derive(uniffi::Record)]
+pub struct PlayerEvent {
+    pub code: i32,
+    pub name: String,
+    pub payload_json: String,
+}
+
+/// Receives player events, on a background thread.
+#[uniffi::export(with_foreign)]
+pub trait PlayerEventListener: Send + Sync {
+    fn on_event(&self, event: PlayerEvent);
+}
+
+static LISTENER: Lazy<Mutex<Option<Arc<dyn PlayerEventListener>>>> = Lazy::new(|| Mutex::new(None));
+
+extern "C" fn forward_event(code: i32, payload: *const c_char, _user_data: *mut c_void) {
+    let Some(listener) = LISTENER.lock().unwrap().clone() else { return };
+    let payload_json = unsafe { CStr::from_ptr(payload) }.to_string_lossy().into_owned();
+    listener.on_event(PlayerEvent { code, name: events::name(code).to_string(), payload_json });
+}
+
+fn failed(code: i32) -> PlayerError {
+    let message = error::last_message().unwrap_or_else(|| "unknown error".to_string());
+    PlayerError::Failed { code, message }
+}
+
+fn check(result: i32) -> Result<(), PlayerError> {
+    if result == 0 {
+        return Ok(());
+    }
+    Err(failed(result))
+}
+
+// For the C functions that return 1, 0 or a negative error code
+fn check_flag(result: i32) -> Result<bool, PlayerError> {
+    if result < 0 {
+        return Err(failed(result));
+    }
+    Ok(result == 1)
+}
+
+// Decodes and frees a JSON string returned by a C function (NULL on error)
+fn take_json<T: DeserializeOwned>(json: *mut c_char) -> Result<T, PlayerError> {
+    if json.is_null() {
+        let code = match spotifly_get_last_error_code() {
+            0 => SpotiflyError::Unknown as i32,
+            code => code,
+        };
+        return Err(failed(code));
+    }
+    let parsed = serde_json::from_str(&unsafe { CStr::from_ptr(json) }.to_string_lossy());
+    spotifly_free_string(json);
+    parsed.map_err(|e| PlayerError::Failed {
+        code: SpotiflyError::Unknown as i32,
+        message: format!("unexpected JSON from the player: {}", e),
+    })
+}
+
+fn c_string(s: &str) -> Result<CString, PlayerError> {
+    CString::new(s).map_err(|_| PlayerError::Failed {
+        code: SpotiflyError::InvalidArgument as i32,
+        message: format!("string contains a NUL byte: {:?}", s),
+    })
+}
+
+fn with_c_string(s: &str, call: extern "C" fn(*const c_char) -> i32) -> Result<(), PlayerError> {
+    let s = c_string(s)?;
+    check(call(s.as_ptr()))
+}
+
+/// The player. There is one engine per process: creating a player initializes it and
+/// dropping the last reference doesn't tear it down (call cleanup()).
+#[derive(uniffi::Object)]
+pub struct SpotiflyPlayer {}
+
+#[uniffi::export]
+impl SpotiflyPlayer {
+    /// Initializes the player with an access token, or with the stored login when
+    /// secure storage is enabled and the token is nil.
+    #[uniffi::constructor]
+    pub fn new(access_token: Option<String>) -> Result<Arc<Self>, PlayerError> {
+        let token = access_token.as_deref().map(c_string).transpose()?;
+        check(spotifly_init_player(token.as_ref().map_or(ptr::null(), |token| token.as_ptr())))?;
+        Ok(Arc::new(SpotiflyPlayer {}))
+    }
+
+    /// Stops playback and tears down the player and session.
+    pub fn cleanup(&self) -> Result<(), PlayerError> {
+        check(spotifly_cleanup_player())
+    }
+
+    /// Plays a track, album, playlist, artist, episode or show by URI or URL.
+    pub fn play(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, spotifly_play_track)
+    }
+
+    /// Plays tracks and episodes in sequence.
+    pub fn play_tracks(&self, uris: Vec<String>) -> Result<(), PlayerError> {
+        let json = serde_json::to_string(&uris).expect("a list of strings serializes");
+        with_c_string(&json, spotifly_play_tracks)
+    }
+
+    /// Plays a page of a large playlist, loading later pages as playback gets there.
+    pub fn play_playlist_page(&self, uri: String, offset: u64, limit: u64) -> Result<(), PlayerError> {
+        let uri = c_string(&uri)?;
+        check(paging::spotifly_load_playlist_page(uri.as_ptr(), offset as usize, limit as usize))
+    }
+
+    /// Cancels a play call that is still loading.
+    pub fn cancel_pending_load(&self) {
+        loading::spotifly_cancel_pending_load();
+    }
+
+    pub fn pause(&self) -> Result<(), PlayerError> {
+        check(spotifly_pause())
+    }
+
+    pub fn resume(&self) -> Result<(), PlayerError> {
+        check(spotifly_resume())
+    }
+
+    pub fn stop(&self) -> Result<(), PlayerError> {
+        check(spotifly_stop())
+    }
+
+    pub fn next(&self) -> Result<(), PlayerError> {
+        check(spotifly_next())
+    }
+
+    pub fn previous(&self) -> Result<(), PlayerError> {
+        check(spotifly_previous())
+    }
+
+    pub fn seek(&self, position_ms: u32) -> Result<(), PlayerError> {
+        check(spotifly_seek(position_ms))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        IS_PLAYING.load(Ordering::SeqCst)
+    }
+
+    pub fn position_ms(&self) -> u32 {
+        spotifly_get_position_ms()
+    }
+
+    pub fn duration_ms(&self) -> u32 {
+        spotifly_get_duration_ms()
+    }
+
+    /// Volume from 0 to 65535.
+    pub fn volume(&self) -> u16 {
+        spotifly_get_volume()
+    }
+
+    pub fn set_volume(&self, volume: u16) -> Result<(), PlayerError> {
+        check(spotifly_set_volume(volume))
+    }
+
+    /// 0 = off, 1 = repeat the queue, 2 = repeat the current track
+    pub fn repeat_mode(&self) -> u8 {
+        spotifly_get_repeat_mode()
+    }
+
+    pub fn set_repeat_mode(&self, mode: u8) -> Result<(), PlayerError> {
+        check(spotifly_set_repeat_mode(mode))
+    }
+
+    /// 0 = disconnected, 1 = connected, 2 = suspended, 3 = reconnecting, 4 = idle
+    pub fn connection_state(&self) -> u8 {
+        spotifly_get_connection_state()
+    }
+
+    /// Gives the player a fresh access token (e.g. after a TokenNeeded event).
+    pub fn update_access_token(&self, access_token: String, expires_in: u64) -> Result<(), PlayerError> {
+        let token = c_string(&access_token)?;
+        check(token_manager::spotifly_update_access_token(token.as_ptr(), expires_in))
+    }
+
+    /// The current track and playback state, nil if the queue is empty.
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        now_playing::current().map(|now_playing| NowPlaying {
+            uri: now_playing.uri,
+            title: now_playing.title,
+            artists: now_playing.artists.into_iter().map(|artist| artist.name).collect(),
+            album_name: now_playing.album.name,
+            artwork_url: now_playing.artwork.large,
+            duration_ms: now_playing.duration_ms,
+            position_ms: now_playing.position_ms,
+            is_playing: now_playing.is_playing,
+            queue_index: now_playing.queue_index as u64,
+            queue_length: now_playing.queue_length as u64,
+        })
+    }
+
+    pub fn queue(&self) -> Vec<QueueEntry> {
+        QUEUE.lock().unwrap().iter()
+            .map(|item| QueueEntry {
+                uri: item.uri.clone(),
+                item_type: item.item_type,
+                track_name: item.track_name.clone(),
+                artist_name: item.artist_name.clone(),
+                album_art_url: item.album_art_url.clone(),
+                duration_ms: item.duration_ms,
+                album_id: item.album_id.clone(),
+                artist_id: item.artist_id.clone(),
+                external_url: item.external_url.clone(),
+                play_state: item.play_state,
+                last_position_ms: item.last_position_ms,
+                album_name: item.album_name.clone(),
+                track_number: item.track_number,
+                disc_number: item.disc_number,
+                explicit: item.explicit,
+                release_date: item.release_date.clone(),
+            })
+            .collect()
+    }
+
+    pub fn current_index(&self) -> u64 {
+        CURRENT_INDEX.load(Ordering::SeqCst) as u64
+    }
+
+    /// Adds a track or episode to the end of the queue.
+    pub fn add_to_queue(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, spotifly_add_to_queue)
+    }
+
+    /// Inserts a track or episode right after the current one.
+    pub fn play_next(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, spotifly_add_next_to_queue)
+    }
+
+    /// Appends a track, album, playlist, artist, episode or show to the end of the queue.
+    pub fn enqueue(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, spotifly_enqueue)
+    }
+
+    pub fn clear_upcoming(&self) -> Result<(), PlayerError> {
+        check(spotifly_clear_upcoming_queue())
+    }
+
+    /// Sends player events to the listener (nil to stop). Replaces a callback registered
+    /// with spotifly_register_event_callback().
+    pub fn set_event_listener(&self, listener: Option<Arc<dyn PlayerEventListener>>) {
+        let registered = listener.is_some();
+        *LISTENER.lock().unwrap() = listener;
+        spotifly_register_event_callback(registered.then_some(forward_event as events::EventCallback), ptr::null_mut());
+    }
+
+    /// Searches the catalog.
+    /// types: "track", "album", "artist" and/or "playlist", nil = all.
+    /// limit: results per type (1-50).
+    pub fn search(
+        &self,
+        query: String,
+        types: Option<Vec<String>>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<SearchResults, PlayerError> {
+        let query = c_string(&query)?;
+        let types = types.map(|types| c_string(&types.join(","))).transpose()?;
+        let types_ptr = types.as_ref().map_or(ptr::null(), |types| types.as_ptr());
+        let pages: SearchPages = take_json(search::spotifly_search(query.as_ptr(), types_ptr, limit, offset))?;
+        let (tracks, total_tracks) = page_parts(pages.tracks);
+        let (albums, total_albums) = page_parts(pages.albums);
+        let (artists, total_artists) = page_parts(pages.artists);
+        let (playlists, total_playlists) = page_parts(pages.playlists);
+        Ok(SearchResults {
+            tracks,
+            total_tracks,
+            albums,
+            total_albums,
+            artists,
+            total_artists,
+            playlists,
+            total_playlists,
+        })
+    }
+
+    /// A page of the user's saved tracks, most recently saved first.
+    /// limit: 1-50.
+    pub fn saved_tracks(&self, offset: u32, limit: u32) -> Result<SavedTracksPage, PlayerError> {
+        take_json(library::spotifly_get_saved_tracks(offset, limit))
+    }
+
+    /// Replaces the queue with the user's saved tracks and starts playing.
+    pub fn play_saved_tracks(&self) -> Result<(), PlayerError> {
+        check(library::spotifly_play_saved_tracks())
+    }
+
+    pub fn save_track(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, library::spotifly_save_track)
+    }
+
+    pub fn remove_saved_track(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, library::spotifly_remove_saved_track)
+    }
+
+    pub fn is_track_saved(&self, uri: String) -> Result<bool, PlayerError> {
+        let uri = c_string(&uri)?;
+        check_flag(library::spotifly_is_track_saved(uri.as_ptr()))
+    }
+
+    pub fn follow_artist(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, library::spotifly_follow_artist)
+    }
+
+    pub fn unfollow_artist(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, library::spotifly_unfollow_artist)
+    }
+
+    pub fn is_artist_followed(&self, uri: String) -> Result<bool, PlayerError> {
+        let uri = c_string(&uri)?;
+        check_flag(library::spotifly_is_artist_followed(uri.as_ptr()))
+    }
+
+    /// The user's playlists, owned and followed.
+    pub fn user_playlists(&self) -> Result<Vec<PlaylistSummary>, PlayerError> {
+        take_json(library::spotifly_get_user_playlists())
+    }
+
+    pub fn follow_playlist(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, library::spotifly_follow_playlist)
+    }
+
+    /// Unfollowing a playlist the user owns deletes it from their profile.
+    pub fn unfollow_playlist(&self, uri: String) -> Result<(), PlayerError> {
+        with_c_string(&uri, library::spotifly_unfollow_playlist)
+    }
+
+    pub fn is_playlist_followed(&self, uri: String) -> Result<bool, PlayerError> {
+        let uri = c_string(&uri)?;
+        check_flag(library::spotifly_is_playlist_followed(uri.as_ptr()))
+    }
+
+    /// Creates a playlist in the user's library.
+    pub fn create_playlist(&self, name: String, public: bool) -> Result<PlaylistSummary, PlayerError> {
+        let name = c_string(&name)?;
+        take_json(playlists::spotifly_create_playlist(name.as_ptr(), public))
+    }
+
+    /// Adds tracks or episodes to a playlist, at position or (nil) at the end.
+    pub fn playlist_add_tracks(
+        &self,
+        playlist_uri: String,
+        uris: Vec<String>,
+        position: Option<u32>,
+    ) -> Result<(), PlayerError> {
+        let playlist_uri = c_string(&playlist_uri)?;
+        let uris = c_string(&serde_json::to_string(&uris).expect("a list of strings serializes"))?;
+        let position = position.map_or(-1, |position| position.min(i32::MAX as u32) as i32);
+        check(playlists::spotifly_playlist_add_tracks(playlist_uri.as_ptr(), uris.as_ptr(), position))
+    }
+
+    /// Removes all occurrences of the given tracks or episodes from a playlist.
+    pub fn playlist_remove_tracks(&self, playlist_uri: String, uris: Vec<String>) -> Result<(), PlayerError> {
+        let playlist_uri = c_string(&playlist_uri)?;
+        let uris = c_string(&serde_json::to_string(&uris).expect("a list of strings serializes"))?;
+        check(playlists::spotifly_playlist_remove_tracks(playlist_uri.as_ptr(), uris.as_ptr()))
+    }
+
+    /// Moves range_length items starting at range_start in front of insert_before
+    /// (an index in the playlist before the move).
+    pub fn playlist_reorder(
+        &self,
+        playlist_uri: String,
+        range_start: u32,
+        range_length: u32,
+        insert_before: u32,
+    ) -> Result<(), PlayerError> {
+        let playlist_uri = c_string(&playlist_uri)?;
+        check(playlists::spotifly_playlist_reorder(playlist_uri.as_ptr(), range_start, range_length, insert_before))
+    }
+
+    /// Advertises the player on the local network as a Spotify Connect device.
+    /// device_name: nil = "Spotifly"; device_type: "computer", "speaker", ..., nil = "computer".
+    pub fn enable_connect(&self, device_name: Option<String>, device_type: Option<String>) -> Result<(), PlayerError> {
+        let device_name = device_name.as_deref().map(c_string).transpose()?;
+        let device_type = device_type.as_deref().map(c_string).transpose()?;
+        check(connect::spotifly_enable_connect(
+            device_name.as_ref().map_or(ptr::null(), |name| name.as_ptr()),
+            device_type.as_ref().map_or(ptr::null(), |device_type| device_type.as_ptr()),
+        ))
+    }
+
+    /// Stops advertising the player; a Connect client already in control stays connected.
+    pub fn disable_connect(&self) {
+        connect::spotifly_disable_connect();
+    }
+
+    /// The user's available Spotify Connect devices.
+    pub fn devices(&self) -> Result<Vec<ConnectDevice>, PlayerError> {
+        take_json(devices::spotifly_list_devices())
+    }
+
+    /// Transfers playback to another Connect device and pauses this player.
+    pub fn transfer_playback(&self, device_id: String) -> Result<(), PlayerError> {
+        with_c_string(&device_id, devices::spotifly_transfer_playback)
+    }
+
+    pub fn set_eq_enabled(&self, enabled: bool) {
+        eq::spotifly_set_eq_enabled(enabled);
+    }
+
+    /// index: 0-9; gain_db is clamped to -12.0..12.0.
+    pub fn set_eq_band(&self, index: u32, gain_db: f32) -> Result<(), PlayerError> {
+        check(eq::spotifly_set_eq_band(index, gain_db))
+    }
+
+    /// Sets all bands from a built-in preset (see eq().presets).
+    pub fn set_eq_preset(&self, preset_name: String) -> Result<(), PlayerError> {
+        with_c_string(&preset_name, eq::spotifly_set_eq_preset)
+    }
+
+    pub fn eq(&self) -> Result<EqSettings, PlayerError> {
+        take_json(eq::spotifly_get_eq_json())
+    }
+
+    /// A listening summary. period: 0 = day, 1 = week (from Monday);
+    /// periods_ago: 0 = the current one.
+    pub fn listening_summary(
+        &self,
+        period: u8,
+        periods_ago: u32,
+        utc_offset_minutes: i32,
+    ) -> Result<ListeningSummary, PlayerError> {
+        take_json(stats::spotifly_get_listening_summary_json(period, periods_ago, utc_offset_minutes))
+    }
+
+    /// The most played tracks. period: 0 = 7 days, 1 = 30 days, 2 = 365 days,
+    /// 3 = all time; limit: 0 = all.
+    pub fn top_tracks(&self, period: u8, limit: u64) -> Result<Vec<TrackListening>, PlayerError> {
+        take_json(stats::spotifly_get_top_tracks_local(period, limit as usize))
+    }
+
+    pub fn listening_stats(&self) -> Result<ListeningStats, PlayerError> {
+        take_json(stats::spotifly_get_listening_stats_json())
+    }
+}
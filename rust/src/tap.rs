@@ -0,0 +1,66 @@
+// Raw PCM tap for visualizers.
+//
+// The host can register a callback that receives every block of decoded samples on its
+// way to the audio output, so waveforms and spectrum analysers need no second decode path.
+// Samples are the ones actually played: after volume, normalisation and fades.
+
+use librespot_playback::decoder::AudioPacket;
+use librespot_playback::{NUM_CHANNELS, SAMPLE_RATE};
+use once_cell::sync::Lazy;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Audio tap callback: (interleaved f32 samples, sample count, channels, sample rate, user data).
+/// The samples are only valid for the duration of the call.
+pub type AudioTapCallback = extern "C" fn(*const f32, usize, u32, u32, *mut c_void);
+
+struct Registration {
+    callback: AudioTapCallback,
+    // Opaque host pointer, passed back untouched
+    user_data: usize,
+}
+
+static TAP: Lazy<Mutex<Option<Registration>>> = Lazy::new(|| Mutex::new(None));
+// Lets the output skip the lock while nothing is registered
+static TAP_REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Hands a block of samples to the registered tap, if any.
+pub(crate) fn deliver(packet: &AudioPacket) {
+    if !TAP_REGISTERED.load(Ordering::Relaxed) {
+        return;
+    }
+    let registration = match TAP.lock().unwrap().as_ref() {
+        Some(r) => (r.callback, r.user_data),
+        None => return,
+    };
+    let samples: Vec<f32> = match packet.samples() {
+        Ok(samples) => samples.iter().map(|&sample| sample as f32).collect(),
+        Err(_) => return,
+    };
+
+    let (callback, user_data) = registration;
+    callback(
+        samples.as_ptr(),
+        samples.len(),
+        NUM_CHANNELS as u32,
+        SAMPLE_RATE,
+        user_data as *mut c_void,
+    );
+}
+
+/// Registers a callback that receives decoded audio as it is played, replacing any
+/// previous one. Pass NULL to unregister.
+///
+/// Samples are interleaved f32 in -1.0..1.0 (currently always stereo at 44.1 kHz).
+/// The callback runs on the audio thread: copy the samples out and return quickly,
+/// or playback will stutter.
+#[no_mangle]
+pub extern "C" fn spotifly_register_audio_tap(callback: Option<AudioTapCallback>, user_data: *mut c_void) {
+    let mut tap = TAP.lock().unwrap();
+    *tap = callback.map(|callback| Registration {
+        callback,
+        user_data: user_data as usize,
+    });
+    TAP_REGISTERED.store(tap.is_some(), Ordering::Relaxed);
+}
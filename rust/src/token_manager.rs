@@ -0,0 +1,199 @@
+// Access token lifecycle.
+//
+// A background task watches the access token's expiry and renews it shortly before it
+// runs out: from the session after a stored-credentials login, through the accounts
+// service when the host registered a client ID and refresh token, otherwise by sending a
+// TokenNeeded event so the host can supply one with spotifly_update_access_token(). A
+// session that died waiting for a fresh token is reconnected with it right away (see
+// supervisor.rs), so the host never has to tear down and re-init the player.
+
+use crate::auth::{self, OAuthResult, OAUTH_RESULT};
+use crate::error::{self, SpotiflyError};
+use crate::{secure_storage, stored_credentials, supervisor};
+use crate::{current_timestamp_ms, events, ACCESS_TOKEN, PLAYER, RUNTIME, SESSION};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::ffi::{c_char, CStr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// Renew this long before the token expires
+const REFRESH_MARGIN_MS: u64 = 5 * 60 * 1000;
+
+static CLIENT_ID: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+static MANAGER_RUNNING: AtomicBool = AtomicBool::new(false);
+// Issue time of the token we already asked the host to replace
+static TOKEN_NEEDED_SENT_FOR: AtomicU64 = AtomicU64::new(0);
+
+/// Forgets the client ID and token set (on player cleanup).
+pub(crate) fn reset() {
+    CLIENT_ID.lock().unwrap().take();
+    OAUTH_RESULT.lock().unwrap().take();
+    TOKEN_NEEDED_SENT_FOR.store(0, Ordering::SeqCst);
+}
+
+/// The client ID registered for refreshing, if any.
+pub(crate) fn client_id() -> Option<String> {
+    CLIENT_ID.lock().unwrap().clone()
+}
+
+/// Resumes renewing a token set restored from secure storage.
+pub(crate) fn restore(client_id: Option<String>, result: OAuthResult) {
+    *CLIENT_ID.lock().unwrap() = client_id;
+    *OAUTH_RESULT.lock().unwrap() = Some(result);
+}
+
+/// Starts the token manager task if it isn't running yet.
+/// The task exits once the player has been cleaned up.
+pub(crate) fn start() {
+    if MANAGER_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    RUNTIME.spawn(async {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            if PLAYER.lock().unwrap().is_none() {
+                break;
+            }
+            check().await;
+        }
+        MANAGER_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+async fn check() {
+    let result = OAUTH_RESULT.lock().unwrap().clone();
+    if let Some(result) = result {
+        let expires_at_ms = result.obtained_at_ms + result.expires_in * 1000;
+        if current_timestamp_ms() + REFRESH_MARGIN_MS >= expires_at_ms {
+            renew(&result, expires_at_ms).await;
+        }
+    }
+
+}
+
+// Refreshes the token ourselves if we can, otherwise asks the host for a new one
+async fn renew(result: &OAuthResult, expires_at_ms: u64) {
+    if stored_credentials::is_active() {
+        let session = SESSION.lock().unwrap().clone();
+        if let Some(session) = session {
+            match stored_credentials::renew_token(&session).await {
+                Ok(new_result) => {
+                    log::info!("Access token renewed from the session");
+                    events::emit(events::EVENT_TOKEN_REFRESHED, json!(new_result));
+                    supervisor::retry_now();
+                    return;
+                }
+                Err(e) => log::error!("Token refresh error: {}", e),
+            }
+        }
+    }
+
+    let client_id = CLIENT_ID.lock().unwrap().clone();
+    if let (Some(client_id), Some(refresh_token)) = (client_id, result.refresh_token.as_deref()) {
+        match auth::refresh(&client_id, refresh_token).await {
+            Ok(new_result) => {
+                log::info!("Access token refreshed");
+                events::emit(events::EVENT_TOKEN_REFRESHED, json!(new_result));
+                supervisor::retry_now();
+                return;
+            }
+            Err(e) => log::error!("Token refresh error: {}", e),
+        }
+    }
+
+    if TOKEN_NEEDED_SENT_FOR.swap(result.obtained_at_ms, Ordering::SeqCst) != result.obtained_at_ms {
+        events::emit(events::EVENT_TOKEN_NEEDED, json!({ "expires_at_ms": expires_at_ms }));
+    }
+}
+
+/// Enables automatic renewal of the access token the player was initialized with.
+/// With a client ID and refresh token the token is refreshed through the accounts
+/// service (sending a TokenRefreshed event with the new token set, which the host should
+/// persist). Without them, a TokenNeeded event asks the host for a new token.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - client_id: The Spotify app's client ID, may be NULL
+/// - refresh_token: Refresh token from the OAuth flow, may be NULL
+/// - expires_in: Lifetime of the current access token in seconds
+#[no_mangle]
+pub extern "C" fn spotifly_set_token_refresh(
+    client_id: *const c_char,
+    refresh_token: *const c_char,
+    expires_in: u64,
+) -> i32 {
+    let read = |s: *const c_char| -> Result<Option<String>, ()> {
+        if s.is_null() {
+            return Ok(None);
+        }
+        unsafe { CStr::from_ptr(s).to_str().map(|s| Some(s.to_string())).map_err(|_| ()) }
+    };
+
+    let (client_id_str, refresh_token_str) = match (read(client_id), read(refresh_token)) {
+        (Ok(c), Ok(r)) => (c, r),
+        _ => {
+            return error::fail(SpotiflyError::InvalidArgument, "Set token refresh error: invalid string");
+        }
+    };
+
+    let access_token = match ACCESS_TOKEN.lock().unwrap().clone() {
+        Some(token) => token,
+        None => return error::fail(SpotiflyError::NotInitialized, "Set token refresh error: player not initialized"),
+    };
+
+    let result = OAuthResult {
+        access_token,
+        refresh_token: refresh_token_str,
+        expires_in,
+        obtained_at_ms: current_timestamp_ms(),
+    };
+    secure_storage::save_tokens(client_id_str.as_deref(), &result);
+    *CLIENT_ID.lock().unwrap() = client_id_str;
+    *OAUTH_RESULT.lock().unwrap() = Some(result);
+    0
+}
+
+/// Supplies a new access token (e.g. in response to a TokenNeeded event).
+/// It is used for Web API requests right away, and the session is reconnected
+/// with it if it was lost. Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - access_token: The new access token
+/// - expires_in: Lifetime of the token in seconds
+#[no_mangle]
+pub extern "C" fn spotifly_update_access_token(access_token: *const c_char, expires_in: u64) -> i32 {
+    if access_token.is_null() {
+        return error::fail(SpotiflyError::InvalidArgument, "Update access token error: access_token is null");
+    }
+
+    let token_str = unsafe {
+        match CStr::from_ptr(access_token).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => return error::fail(
+                SpotiflyError::InvalidArgument,
+                "Update access token error: invalid access_token string",
+            ),
+        }
+    };
+
+    *ACCESS_TOKEN.lock().unwrap() = Some(token_str.clone());
+    let mut result_guard = OAUTH_RESULT.lock().unwrap();
+    let refresh_token = result_guard.as_ref().and_then(|r| r.refresh_token.clone());
+    let result = OAuthResult {
+        access_token: token_str,
+        refresh_token,
+        expires_in,
+        obtained_at_ms: current_timestamp_ms(),
+    };
+    *result_guard = Some(result.clone());
+    drop(result_guard);
+    secure_storage::save_tokens(client_id().as_deref(), &result);
+
+    // A reconnect waiting for this token shouldn't sit out its backoff
+    supervisor::retry_now();
+    0
+}
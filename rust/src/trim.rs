@@ -0,0 +1,120 @@
+// Per-track start/end trim points.
+//
+// The host registers offsets for specific track URIs (e.g. to skip long intros or
+// outros). Trimmed tracks start playing at their start offset and move on to the next
+// track once they reach their end offset. Trim points are stored in the data directory.
+
+use crate::error::{self, SpotiflyError};
+use crate::{links, storage, to_c_string};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+const TRIM_FILE: &str = "trim_points.json";
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct TrimPoints {
+    pub start_ms: u32,
+    /// None plays to the end of the track
+    pub end_ms: Option<u32>,
+}
+
+static TRIMS: Lazy<Mutex<HashMap<String, TrimPoints>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// Play request whose trimmed end has already been handled
+static END_HANDLED_FOR: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Loads stored trim points from the data directory.
+pub(crate) fn load() {
+    if let Some(trims) = storage::load_json::<HashMap<String, TrimPoints>>(TRIM_FILE) {
+        *TRIMS.lock().unwrap() = trims;
+    }
+}
+
+/// Position to start the track at (0 if it isn't trimmed).
+pub(crate) fn start_ms(track_uri: &str) -> u32 {
+    TRIMS.lock().unwrap().get(track_uri).map_or(0, |t| t.start_ms)
+}
+
+/// The track's trimmed end, if it has one.
+pub(crate) fn end_ms(track_uri: &str) -> Option<u32> {
+    TRIMS.lock().unwrap().get(track_uri).and_then(|t| t.end_ms)
+}
+
+/// Returns true the first time a play request reaches its track's trimmed end.
+pub(crate) fn end_reached(play_request_id: u64, track_uri: &str, position_ms: u32) -> bool {
+    let Some(end_ms) = end_ms(track_uri) else {
+        return false;
+    };
+    position_ms >= end_ms && END_HANDLED_FOR.swap(play_request_id, Ordering::SeqCst) != play_request_id
+}
+
+/// Sets start/end trim points for a track. They apply every time the track is played.
+/// Setting both offsets to 0 removes the track's trim points.
+/// Returns 0 on success, a negative error code on error.
+///
+/// # Parameters
+/// - track_uri: Spotify track URI or URL
+/// - start_ms: Position to start playback at
+/// - end_ms: Position to move on to the next track at, 0 = play to the end
+#[no_mangle]
+pub extern "C" fn spotifly_set_trim_points(track_uri: *const c_char, start_ms: u32, end_ms: u32) -> i32 {
+    if track_uri.is_null() {
+        return error::fail(SpotiflyError::InvalidArgument, "Set trim points error: track_uri is null");
+    }
+
+    let uri_str = unsafe {
+        match CStr::from_ptr(track_uri).to_str() {
+            Ok(s) => s.to_string(),
+            Err(_) => return error::fail(
+                SpotiflyError::InvalidArgument,
+                "Set trim points error: invalid track_uri string",
+            ),
+        }
+    };
+
+    let uri = match links::parse_link(&uri_str) {
+        Some(link) if link.uri.starts_with("spotify:track:") || link.uri.starts_with("spotify:episode:") => link.uri,
+        _ => {
+            return error::fail(
+                SpotiflyError::InvalidUri,
+                format!("Set trim points error: not a track URI: {}", uri_str),
+            );
+        }
+    };
+
+    if end_ms != 0 && end_ms <= start_ms {
+        return error::fail(
+            SpotiflyError::InvalidArgument,
+            format!("Set trim points error: end ({}ms) must be after start ({}ms)", end_ms, start_ms),
+        );
+    }
+
+    let mut trims = TRIMS.lock().unwrap();
+    if start_ms == 0 && end_ms == 0 {
+        trims.remove(&uri);
+    } else {
+        trims.insert(uri, TrimPoints {
+            start_ms,
+            end_ms: (end_ms != 0).then_some(end_ms),
+        });
+    }
+    storage::save_json(TRIM_FILE, &*trims);
+    0
+}
+
+/// Returns all trim points as a JSON object keyed by track URI:
+/// {"spotify:track:xxx": {"start_ms": 15000, "end_ms": null}, ...}
+/// Caller must free the string with spotifly_free_string().
+/// Returns NULL on error.
+#[no_mangle]
+pub extern "C" fn spotifly_get_trim_points_json() -> *mut c_char {
+    let trims = TRIMS.lock().unwrap();
+    match serde_json::to_string(&*trims) {
+        Ok(json_string) => to_c_string(&json_string),
+        Err(_) => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,183 @@
+// Minimal Spotify Web API client.
+//
+// Requests go through the session's HTTP client and are authorized with the access token
+// the player was initialized with (the host's OAuth token, which carries the Web API scopes),
+// or with the session's own token after a stored-credentials login.
+
+use crate::artwork::ArtworkUrls;
+use crate::error::{self, Failure, SpotiflyError};
+use crate::{QueueItem, ACCESS_TOKEN, ITEM_TYPE_EPISODE, ITEM_TYPE_TRACK, PLAY_STATE_UNPLAYED, SESSION};
+use bytes::Bytes;
+use http::header::{AUTHORIZATION, CONTENT_TYPE};
+use http::{Method, Request};
+use librespot_core::session::Session;
+use serde::Serialize;
+use serde_json::Value;
+
+pub(crate) const WEB_API_BASE: &str = "https://api.spotify.com/v1";
+
+/// A playlist as listed in search results and the user's library.
+#[derive(Serialize)]
+pub(crate) struct PlaylistSummary {
+    pub uri: String,
+    pub name: String,
+    pub owner_name: String,
+    pub owner_id: String,
+    pub image_url: String,
+    pub track_count: u64,
+    pub collaborative: bool,
+}
+
+/// Returns the current session, or an error if the player isn't initialized.
+pub(crate) fn current_session() -> Result<Session, Failure> {
+    SESSION.lock().unwrap().clone()
+        .ok_or_else(|| Failure::new(SpotiflyError::NotInitialized, "session not initialized"))
+}
+
+/// Sends a Web API request and returns the parsed JSON response
+/// (`Value::Null` for empty responses).
+/// `path` is relative to the API base, e.g. "/me/tracks?limit=20".
+pub(crate) async fn request(
+    session: &Session,
+    method: Method,
+    path: &str,
+    body: Option<Value>,
+) -> Result<Value, Failure> {
+    let token = ACCESS_TOKEN.lock().unwrap().clone()
+        .ok_or_else(no_access_token)?;
+
+    let body = match body {
+        Some(json) => Bytes::from(json.to_string()),
+        None => Bytes::new(),
+    };
+
+    let request = Request::builder()
+        .method(method.clone())
+        .uri(format!("{}{}", WEB_API_BASE, path))
+        .header(AUTHORIZATION, format!("Bearer {}", token))
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .map_err(|e| Failure::new(SpotiflyError::InvalidArgument, format!("Invalid Web API request: {}", e)))?;
+
+    let response = session.http_client().request_body(request).await
+        .map_err(|e| error::librespot(format_args!("Web API {} {} failed", method, path), &e))?;
+
+    if response.is_empty() {
+        return Ok(Value::Null);
+    }
+
+    serde_json::from_slice(&response)
+        .map_err(|e| Failure::new(SpotiflyError::Unknown, format!("Failed to parse Web API response: {:?}", e)))
+}
+
+/// The failure of a request made before the host provided an access token.
+pub(crate) fn no_access_token() -> Failure {
+    Failure::new(SpotiflyError::NotAuthenticated, "No access token available")
+}
+
+/// Sends a GET request to the Web API.
+pub(crate) async fn get(session: &Session, path: &str) -> Result<Value, Failure> {
+    request(session, Method::GET, path, None).await
+}
+
+/// Builds a query string from key/value pairs (values are percent-encoded).
+pub(crate) fn query_string<'a>(pairs: impl IntoIterator<Item = (&'a str, String)>) -> String {
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    for (key, value) in pairs {
+        serializer.append_pair(key, &value);
+    }
+    serializer.finish()
+}
+
+/// Returns a string field of a JSON object, or "" if missing.
+pub(crate) fn str_field(value: &Value, key: &str) -> String {
+    value.get(key).and_then(Value::as_str).unwrap_or_default().to_string()
+}
+
+/// Returns the URL of the first (largest) image in an object's "images" array, or "".
+pub(crate) fn first_image_url(value: &Value) -> String {
+    value.get("images")
+        .and_then(Value::as_array)
+        .and_then(|images| images.first())
+        .map(|image| str_field(image, "url"))
+        .unwrap_or_default()
+}
+
+/// Builds a playlist summary from a Web API playlist object.
+pub(crate) fn playlist_summary_from_json(playlist: &Value) -> Option<PlaylistSummary> {
+    let owner = playlist.get("owner");
+    Some(PlaylistSummary {
+        uri: playlist.get("uri")?.as_str()?.to_string(),
+        name: str_field(playlist, "name"),
+        owner_name: owner.map(|o| str_field(o, "display_name")).unwrap_or_default(),
+        owner_id: owner.map(|o| str_field(o, "id")).unwrap_or_default(),
+        image_url: first_image_url(playlist),
+        track_count: playlist.get("tracks")
+            .and_then(|t| t.get("total"))
+            .and_then(Value::as_u64)
+            .unwrap_or(0),
+        collaborative: playlist.get("collaborative").and_then(Value::as_bool).unwrap_or(false),
+    })
+}
+
+/// Builds a queue item from a Web API track (or episode) object.
+pub(crate) fn queue_item_from_json(track: &Value) -> Option<QueueItem> {
+    let uri = track.get("uri")?.as_str()?.to_string();
+    let id = track.get("id").and_then(Value::as_str);
+
+    let artists = track.get("artists").and_then(Value::as_array);
+    let artist_name = artists
+        .map(|artists| {
+            artists.iter()
+                .filter_map(|a| a.get("name").and_then(Value::as_str))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+    let artist_id = artists
+        .and_then(|artists| artists.first())
+        .and_then(|a| a.get("id"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    let album = track.get("album");
+    let album_art_urls = ArtworkUrls::from_json(album.and_then(|a| a.get("images")));
+    let album_id = album
+        .and_then(|a| a.get("id"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    let external_url = track.get("external_urls")
+        .and_then(|urls| urls.get("spotify"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .or_else(|| id.map(|id| format!("https://open.spotify.com/track/{}", id)));
+
+    let item_type = match track.get("type").and_then(Value::as_str) {
+        Some("episode") => ITEM_TYPE_EPISODE,
+        _ => ITEM_TYPE_TRACK,
+    };
+
+    Some(QueueItem {
+        uri,
+        item_type,
+        track_name: track.get("name").and_then(Value::as_str).unwrap_or_default().to_string(),
+        artist_name,
+        album_art_url: album_art_urls.large.clone(),
+        album_art_urls,
+        duration_ms: track.get("duration_ms").and_then(Value::as_u64).unwrap_or(0) as u32,
+        album_id,
+        artist_id,
+        external_url,
+        play_state: PLAY_STATE_UNPLAYED,
+        last_position_ms: 0,
+        album_name: album.and_then(|a| a.get("name")).and_then(Value::as_str).map(str::to_string),
+        track_number: track.get("track_number").and_then(Value::as_u64).unwrap_or(0) as u32,
+        disc_number: track.get("disc_number").and_then(Value::as_u64).unwrap_or(0) as u32,
+        explicit: track.get("explicit").and_then(Value::as_bool).unwrap_or(false),
+        // Albums carry a release date, episodes their own
+        release_date: album.and_then(|a| a.get("release_date")).or_else(|| track.get("release_date"))
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    })
+}